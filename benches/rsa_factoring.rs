@@ -0,0 +1,37 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use discrete_log_server::algo::{PollardsRSAFact, PollardsRSAFactBrent, RSA_BENCH_SAMPLES};
+
+/// Times Pollard's rho with Floyd's and Brent's cycle detection on each of the sample semiprimes.
+fn floyd_vs_brent(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rsa_factoring");
+    for &n in RSA_BENCH_SAMPLES {
+        // Both walks are deterministic on the samples, so the work done is the same on every iteration
+        let mut floyd = PollardsRSAFact::new(n);
+        Iterator::count(&mut floyd);
+        let mut brent = PollardsRSAFactBrent::new(n);
+        Iterator::count(&mut brent);
+        println!(
+            "{n}: floyd {} steps, {} evaluations, {} gcds; brent {} steps, {} evaluations, {} gcds",
+            floyd.steps(), floyd.evaluations(), floyd.gcds(), brent.steps(), brent.evaluations(), brent.gcds()
+        );
+
+        group.bench_with_input(BenchmarkId::new("floyd", n), &n, |b, &n| {
+            b.iter(|| {
+                let mut pollards = PollardsRSAFact::new(n);
+                Iterator::count(&mut pollards);
+                pollards.factor()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("brent", n), &n, |b, &n| {
+            b.iter(|| {
+                let mut pollards = PollardsRSAFactBrent::new(n);
+                Iterator::count(&mut pollards);
+                pollards.factor()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, floyd_vs_brent);
+criterion_main!(benches);
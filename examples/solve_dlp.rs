@@ -0,0 +1,73 @@
+//! Solves a discrete logarithm with Pollard's rho, straight through the `algo` API without a server.
+//!
+//! ```text
+//! cargo run --example solve_dlp -- 2 2495 5011
+//! ```
+use std::env;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::str::FromStr;
+use discrete_log_server::algo::PollardsLog;
+
+/// Solves `g^x = h (mod p)` for `x`, writing every step of the walk to `out` followed by the outcome.
+fn solve<W: Write>(g: u64, h: u64, p: u64, out: &mut W) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    let mut pollards = PollardsLog::try_new(p, g, h)?;
+    writeln!(
+        out, "{:<11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|",
+        "i", "x", "alpha", "beta", "y", "gamma", "delta",
+    )?;
+    writeln!(out, "{}", "-".repeat(84))?;
+    for item in &mut pollards {
+        writeln!(
+            out, "{:<11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|",
+            item.i, item.xi, item.ai, item.bi, item.yi, item.gi, item.di
+        )?;
+    }
+    writeln!(out, "{}", "-".repeat(84))?;
+
+    let log = pollards.solve()?;
+    match log {
+        Some(log) => writeln!(
+            out, "discrete log solved: {g}^{log} = {h} in the field F{p}, {} steps, ratio of steps to sqrt({p}) = {:.10}, {} run",
+            pollards.steps(), pollards.steps_to_sqrt_mod_ratio(), pollards.performance_class()
+        )?,
+        None => writeln!(out, "discrete log unable to be solved for g: {g}, h: {h}, p: {p}")?,
+    }
+    Ok(log)
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).map(|arg| u64::from_str(&arg)).collect::<Result<Vec<u64>, _>>();
+    let (g, h, p) = match args.as_deref() {
+        Ok(&[g, h, p]) => (g, h, p),
+        _ => {
+            eprintln!("usage: solve_dlp <g> <h> <p>");
+            return ExitCode::FAILURE;
+        }
+    };
+    match solve(g, h, p, &mut io::stdout().lock()) {
+        Ok(Some(_)) => ExitCode::SUCCESS,
+        Ok(None) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn solve_dlp_prints_the_log() {
+        let mut out = Vec::new();
+        assert_eq!(solve(2, 2495, 5011, &mut out).unwrap(), Some(3351));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("i          |     x     |"));
+        assert!(out.contains("discrete log solved: 2^3351 = 2495 in the field F5011"));
+
+        // Input the walk cannot start from is reported rather than panicking
+        assert!(solve(2, 6000, 5011, &mut Vec::new()).is_err());
+    }
+}
@@ -1,9 +1,12 @@
+use std::collections::HashMap;
 use std::iter::Iterator;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use rand::prelude::*;
 use futures::stream::{FusedStream, Stream};
 use futures::StreamExt;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::task::{self, JoinHandle};
 
 pub mod prelude {
     pub use super::*;
@@ -11,6 +14,115 @@ pub mod prelude {
 
 pub use utils::*;
 
+/// An error produced when an algorithm cannot be run on the given input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlgoError {
+    /// The modulus is too large, intermediate products would overflow a `u64`
+    Overflow,
+
+    /// An argument that must be nonzero is zero
+    ZeroArgument,
+
+    /// The input or the state of a computation broke an invariant the algorithm relies on, such as a modulus below 2,
+    /// an element that is not reduced or a walk whose exponents no longer agree with its values
+    InvariantViolation(String),
+
+    /// The modulus must be prime, but is not
+    NotPrime(u64),
+}
+
+impl std::fmt::Display for AlgoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlgoError::Overflow => write!(f, "modulus too large, overflow may occur"),
+            AlgoError::ZeroArgument => write!(f, "argument must be nonzero"),
+            AlgoError::InvariantViolation(reason) => write!(f, "{reason}"),
+            AlgoError::NotPrime(n) => write!(f, "modulus {n} is not prime"),
+        }
+    }
+}
+
+impl std::error::Error for AlgoError {}
+
+/// Checks that `(n - 1)^2` can be computed without overflowing, as required by the Pollard's rho walks.
+fn check_modulus(n: u64) -> Result<(), AlgoError> {
+    if n < 2 {
+        return Err(AlgoError::InvariantViolation(format!("modulus {n} too small")));
+    }
+    (n - 1).checked_mul(n - 1).ok_or(AlgoError::Overflow)?;
+    Ok(())
+}
+
+/// Checks that `p` is a valid modulus for `PollardsLog` and that `g` and `h` are reduced modulo it.
+fn check_log_input(p: u64, g: u64, h: u64) -> Result<(), AlgoError> {
+    check_modulus(p)?;
+    if let Some(&value) = [g, h].iter().find(|&&x| x >= p) {
+        return Err(AlgoError::InvariantViolation(format!("element {value} not reduced modulo {p}")));
+    }
+    Ok(())
+}
+
+/// Runs finishing in fewer than this many steps relative to the square root of the modulus are `Performance::Fast`.
+pub const FAST_RATIO: f64 = 0.5;
+
+/// Runs needing more than this many steps relative to the square root of the modulus are `Performance::Slow`.
+pub const SLOW_RATIO: f64 = 2.0;
+
+/// How a run of Pollard's rho compares to the `sqrt(n)` steps expected of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Performance {
+    /// A ratio of steps to `sqrt(n)` below `FAST_RATIO`
+    Fast,
+
+    /// A ratio of steps to `sqrt(n)` between `FAST_RATIO` and `SLOW_RATIO` inclusive
+    Typical,
+
+    /// A ratio of steps to `sqrt(n)` above `SLOW_RATIO`
+    Slow,
+}
+
+impl Performance {
+    /// Classifies a ratio of steps taken to the square root of the modulus.
+    pub fn from_ratio(ratio: f64) -> Self {
+        if ratio < FAST_RATIO {
+            Performance::Fast
+        } else if ratio <= SLOW_RATIO {
+            Performance::Typical
+        } else {
+            Performance::Slow
+        }
+    }
+
+    /// The byte used to transmit the classification.
+    pub fn as_byte(self) -> u8 {
+        match self {
+            Performance::Fast => 0,
+            Performance::Typical => 1,
+            Performance::Slow => 2,
+        }
+    }
+
+    /// The classification transmitted as `b`, or `None` if `b` is not a valid classification.
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(Performance::Fast),
+            1 => Some(Performance::Typical),
+            2 => Some(Performance::Slow),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Performance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Performance::Fast => write!(f, "fast"),
+            Performance::Typical => write!(f, "typical"),
+            Performance::Slow => write!(f, "slow"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct PollardsLogItem {
     pub i: usize,
@@ -34,12 +146,34 @@ pub struct PollardsLog {
     bi: u64,
     gi: u64,
     di: u64,
+    /// The walk multiplies by `g` below this threshold, the smallest `x` with `3x >= p`.
+    first_third: u64,
+    /// The walk squares below this threshold and multiplies by `h` from it on, the smallest `x` with `3x >= 2p`.
+    second_third: u64,
+    max_steps: u64,
     finished: bool,
 }
 
 impl PollardsLog {
+    /// Creates a new `PollardsLog`, panicking if `g` or `h` is not reduced modulo `p` or `p` is too large to walk
+    /// without overflow.
     pub fn new(p: u64, g: u64, h: u64) -> PollardsLog {
-        PollardsLog {
+        PollardsLog::try_new(p, g, h).expect("invalid discrete logarithm input")
+    }
+
+    /// Creates a new `PollardsLog`, returning an `AlgoError` if `g` or `h` is not reduced modulo `p` or `p` is too
+    /// large to walk without overflow.
+    pub fn try_new(p: u64, g: u64, h: u64) -> Result<PollardsLog, AlgoError> {
+        check_log_input(p, g, h)?;
+        let max_steps = 4 * (f64::sqrt(p as f64).ceil() as u64);
+        PollardsLog::with_max_steps(p, g, h, max_steps)
+    }
+
+    /// Creates a new `PollardsLog` that gives up after `max_steps` iterations without a collision, returning an
+    /// `AlgoError` on the same inputs as `try_new`.
+    pub fn with_max_steps(p: u64, g: u64, h: u64, max_steps: u64) -> Result<PollardsLog, AlgoError> {
+        check_log_input(p, g, h)?;
+        Ok(PollardsLog {
             p, g, h,
             i: 0,
             xi: 1,
@@ -48,22 +182,58 @@ impl PollardsLog {
             bi: 0,
             gi: 0,
             di: 0,
+            // Rounding up keeps the partition at exactly p/3 and 2p/3, so for small p such as 5 the starting
+            // point 1 lands in the first region instead of squaring to itself forever
+            first_third: p.div_ceil(3),
+            second_third: (2 * p).div_ceil(3),
+            max_steps,
             finished: false,
-        }
+        })
+    }
+
+    /// Creates a new `PollardsLog` whose walk starts from `x0 = g^a0 h^b0 (mod p)` rather than 1, returning an
+    /// `AlgoError` on the same inputs as `try_new`. A start that falls into an unlucky cycle can be retried from
+    /// different exponents.
+    pub fn with_start(p: u64, g: u64, h: u64, a0: u64, b0: u64) -> Result<PollardsLog, AlgoError> {
+        let mut pollards = PollardsLog::try_new(p, g, h)?;
+        let (a0, b0) = (a0 % (p - 1), b0 % (p - 1));
+        let x0 = mulmod(fast_power(g, a0, p), fast_power(h, b0, p), p);
+        pollards.xi = x0;
+        pollards.yi = x0;
+        pollards.ai = a0;
+        pollards.gi = a0;
+        pollards.bi = b0;
+        pollards.di = b0;
+        Ok(pollards)
     }
 
     fn mix(&self, x: u64, a: u64, b: u64) -> (u64, u64, u64) {
-        if 0 <= x && x <  self.p / 3 {
+        if x < self.first_third {
             ((self.g * x) % self.p, (a + 1) % (self.p - 1), b)
-        } else if self.p / 3 <= x && x < (2 * self.p) / 3 {
+        } else if x < self.second_third {
             (u64::pow(x, 2) % self.p, (2 * a) % (self.p - 1), (2 * b) % (self.p - 1))
         } else {
             ((self.h * x) % self.p, a, (b + 1) % (self.p - 1))
         }
     }
 
-    pub fn solve(&self) -> Option<u64> {
-        assert!(self.xi == self.yi);
+    /// Solves for the logarithm from the collision the walk ended on.
+    ///
+    /// # Returns
+    /// The logarithm, or `None` if the walk was cut off before a collision or the collision does not determine it.
+    /// An `AlgoError::InvariantViolation` is returned if the exponents tracked by the walk no longer agree with the
+    /// values it reached, rather than panicking.
+    pub fn solve(&self) -> Result<Option<u64>, AlgoError> {
+        // The walk was cut off by the step cap before a collision was found
+        if self.xi != self.yi {
+            return Ok(None);
+        }
+        let inconsistent = AlgoError::InvariantViolation(format!("walk reached an inconsistent state at step {}", self.i));
+        // Both sides of the collision must still be the powers g^a h^b recorded alongside them
+        let power = |a, b| mulmod(fast_power(self.g, a, self.p), fast_power(self.h, b, self.p), self.p);
+        if power(self.ai, self.bi) != self.xi || power(self.gi, self.di) != self.yi {
+            return Err(inconsistent);
+        }
         // Compute the exponents after combining like terms
         let u = if self.ai >= self.gi {
             (self.ai - self.gi) % (self.p - 1)
@@ -75,16 +245,27 @@ impl PollardsLog {
         } else {
             (self.di + (self.p - 1) - self.bi) % (self.p - 1)
         };
-        // Compute gcd of v and p - 1
-        let d = gcd(v, self.p - 1);
-        let (s, t) = gcd_weights(v, self.p - 1);
+        // A degenerate collision carries no information about the log
+        if v == 0 {
+            return Ok(None);
+        }
+        // Compute gcd of v and p - 1, along with weights satisfying v * s + (p - 1) * t = d
+        let (d, s, _t) = ext_gcd(v, self.p - 1);
+        if d == 1 {
+            // v is invertible, so the log is determined uniquely
+            let v_inv = mod_inverse(v, self.p - 1).ok_or(inconsistent)?;
+            let e = mulmod(u, v_inv, self.p - 1);
+            return Ok((fast_power(self.g, e, self.p) == self.h).then_some(e));
+        }
 
-        // Find correct combination of weights that sum to d
-        let v_inv = gcd_mul_inverse(self.p - 1, v, d, s, t);
-        assert_eq!((v * v_inv) % (self.p - 1), d);
+        // Reducing s gives v * v_inv = d (mod p - 1)
+        let v_inv = s.rem_euclid((self.p - 1) as i128) as u64;
+        if mulmod(v, v_inv, self.p - 1) != d {
+            return Err(inconsistent);
+        }
 
         // Finally solve
-        let r = ((u * v_inv) % (self.p - 1)) / d;
+        let r = mulmod(u, v_inv, self.p - 1) / d;
         let mut found = None;
         for k in 0..d {
             let e = ((self.p - 1) / d) * k + r;
@@ -95,12 +276,27 @@ impl PollardsLog {
             }
         }
 
-        found
+        Ok(found)
     }
 
     pub fn steps_to_sqrt_mod_ratio(&self) -> f64 {
         (self.i as f64) / (f64::sqrt(self.p as f64))
     }
+
+    /// The number of steps taken so far.
+    pub fn steps(&self) -> u64 {
+        self.i as u64
+    }
+
+    /// The step at which the tortoise and hare met and the value `x_i = y_i` they met at, if they have.
+    pub fn collision(&self) -> Option<(u64, u64)> {
+        (self.i > 0 && self.xi == self.yi).then_some((self.i as u64, self.xi))
+    }
+
+    /// Classifies the run by its ratio of steps to `sqrt(p)`.
+    pub fn performance_class(&self) -> Performance {
+        Performance::from_ratio(self.steps_to_sqrt_mod_ratio())
+    }
 }
 
 impl Iterator for PollardsLog {
@@ -119,7 +315,7 @@ impl Iterator for PollardsLog {
         self.di = next_di;
         self.gi = next_gi;
         self.i += 1;
-        if self.xi == self.yi {
+        if self.xi == self.yi || self.i as u64 >= self.max_steps {
             self.finished = true;
         }
         Some(PollardsLogItem {
@@ -156,33 +352,115 @@ impl FusedStream for PollardsLog {
 
 // impl StreamExt for PollardsLog {}
 
+/// The number of steps of a walk driven by `log_item_channel` that may be waiting to be received.
+pub const LOG_ITEM_CHANNEL_SIZE: usize = 64;
+
+/// Runs Pollard's rho for the discrete logarithm of `h` to the base `g` modulo `p` on a blocking task, sending
+/// each step of the walk through a bounded channel.
+///
+/// The walk waits whenever the channel is full, so a slow consumer holds it back rather than letting steps pile
+/// up in memory. Must be called from within a tokio runtime.
+///
+/// # Returns
+/// The handle of the blocking task, which resolves to the logarithm once the walk finishes, and the receiving
+/// half of the channel. The walk is abandoned, resolving to `Ok(None)`, if the receiver is dropped.
+pub fn log_item_channel(p: u64, g: u64, h: u64) -> (JoinHandle<Result<Option<u64>, AlgoError>>, Receiver<PollardsLogItem>) {
+    let (items_send, items) = channel(LOG_ITEM_CHANNEL_SIZE);
+    let handle = task::spawn_blocking(move || {
+        let mut pollards = PollardsLog::new(p, g, h);
+        while let Some(item) = Iterator::next(&mut pollards) {
+            if items_send.blocking_send(item).is_err() {
+                return Ok(None);
+            }
+        }
+        pollards.solve()
+    });
+    (handle, items)
+}
+
+/// Runs Pollard's rho for the discrete logarithm of `h` to the base `g` modulo `p` to completion.
+///
+/// # Returns
+/// Every step of the walk, ending on the collision or once the step cap of `PollardsLog::new` is reached, along
+/// with the result of `PollardsLog::solve`.
+pub fn pollards_log_trace(p: u64, g: u64, h: u64) -> (Vec<PollardsLogItem>, Result<Option<u64>, AlgoError>) {
+    let mut pollards = PollardsLog::new(p, g, h);
+    let trace = Iterator::collect(&mut pollards);
+    (trace, pollards.solve())
+}
+
 #[derive(Debug, PartialEq)]
 pub struct PollardsRSAFactItem {
     pub i: usize,
     pub xi: u64,
     pub yi: u64,
     pub g: u64,
-    pub n: u64
+    pub n: u64,
+    /// The constant `c` of the polynomial `x^2 + c` the walk was using when this step was taken.
+    pub c: u64,
 }
 
+/// The number of times `PollardsRSAFact` restarts with a new polynomial after its walk fails to split `n`.
+const RHO_RETRIES: u32 = 8;
+
 #[derive(Debug, PartialEq)]
 pub struct PollardsRSAFact {
     pub n: u64,
     i: usize,
     xi: u64,
     yi: u64,
+    c: u64,
+    retries: u32,
     factor: Option<u64>,
+    max_steps: u64,
     finished: bool,
 }
 
 impl PollardsRSAFact {
+    /// Creates a new `PollardsRSAFact`, panicking if `n` is too large to factor without overflow.
     pub fn new(n: u64) -> Self {
-        assert!((n - 1).checked_mul(n - 1).is_some(), "modulus too large, overflow may occur");
-        Self { n, i: 0, xi: 1, yi: 1,  factor: None, finished: false }
+        Self::try_new(n).expect("invalid modulus")
+    }
+
+    /// Creates a new `PollardsRSAFact`, returning an `AlgoError` if `n` cannot be factored without overflow.
+    pub fn try_new(n: u64) -> Result<Self, AlgoError> {
+        check_modulus(n)?;
+        let max_steps = 8 * (f64::powf(n as f64, 0.25).ceil() as u64);
+        Self::with_max_steps(n, max_steps)
+    }
+
+    /// Creates a new `PollardsRSAFact` that gives up after `max_steps` iterations without finding a factor,
+    /// returning an `AlgoError` on the same moduli as `try_new`.
+    ///
+    /// The walk starts from 1 with the polynomial `x^2 + 1`. Whenever it fails to split `n` it is restarted, up to
+    /// `RHO_RETRIES` times, with the next constant `c` and a random starting point.
+    pub fn with_max_steps(n: u64, max_steps: u64) -> Result<Self, AlgoError> {
+        check_modulus(n)?;
+        Ok(Self { n, i: 0, xi: 1, yi: 1, c: 1, retries: RHO_RETRIES, factor: None, max_steps, finished: false })
+    }
+
+    /// Creates a new `PollardsRSAFact` walking `x -> x^2 + c (mod n)` from `x0`, without retrying on failure,
+    /// returning an `AlgoError` on the same moduli as `try_new`.
+    pub fn with_poly(n: u64, c: u64, x0: u64) -> Result<Self, AlgoError> {
+        let mut pollards = Self::try_new(n)?;
+        pollards.c = c % n;
+        pollards.xi = x0 % n;
+        pollards.yi = x0 % n;
+        pollards.retries = 0;
+        Ok(pollards)
     }
 
     fn mix(&self, x: u64) -> u64 {
-        (((x * x) % self.n) + 1) % self.n
+        (((x * x) % self.n) + self.c) % self.n
+    }
+
+    /// Starts the walk over with the next polynomial constant from a random point.
+    fn restart(&mut self) {
+        self.retries -= 1;
+        self.c = self.c % (self.n - 1) + 1;
+        let x0 = rand::thread_rng().gen_range(0..self.n);
+        self.xi = x0;
+        self.yi = x0;
     }
 
     pub fn factor(&mut self) -> Option<u64> {
@@ -192,6 +470,26 @@ impl PollardsRSAFact {
     pub fn steps_to_sqrt_mod_ratio(&self) -> f64 {
         (self.i as f64) / f64::sqrt(self.n as f64)
     }
+
+    /// The number of steps taken so far.
+    pub fn steps(&self) -> u64 {
+        self.i as u64
+    }
+
+    /// The number of times the polynomial `x^2 + c` has been evaluated, three per step.
+    pub fn evaluations(&self) -> u64 {
+        3 * self.i as u64
+    }
+
+    /// The number of gcd computations performed, one per step.
+    pub fn gcds(&self) -> u64 {
+        self.i as u64
+    }
+
+    /// Classifies the run by its ratio of steps to `sqrt(n)`.
+    pub fn performance_class(&self) -> Performance {
+        Performance::from_ratio(self.steps_to_sqrt_mod_ratio())
+    }
 }
 
 impl Iterator for PollardsRSAFact {
@@ -204,12 +502,21 @@ impl Iterator for PollardsRSAFact {
         self.xi = self.mix(self.xi);
         self.yi = self.mix(self.yi);
         self.yi = self.mix(self.yi);
-        let g = gcd(self.xi.abs_diff(self.yi), self.n);
-        if g != 1 && self.n % g == 0 {
+        // The sequences colliding means the gcd is the full modulus, which fails to split `n`
+        let g = if self.xi == self.yi { self.n } else { gcd(self.xi.abs_diff(self.yi), self.n) };
+        // Taken before a restart, so the item reports the walk that collided rather than the one replacing it
+        let item = PollardsRSAFactItem { i: self.i, xi: self.xi, yi: self.yi, g, n: self.n, c: self.c };
+        if g == self.n && self.retries > 0 && (self.i as u64) < self.max_steps {
+            self.restart();
+        } else if g == self.n {
+            self.finished = true;
+        } else if g != 1 && self.n % g == 0 {
             self.finished = true;
             self.factor = Some(g);
+        } else if self.i as u64 >= self.max_steps {
+            self.finished = true;
         }
-        Some(PollardsRSAFactItem { i: self.i, xi: self.xi, yi: self.yi, g, n: self.n })
+        Some(item)
     }
 }
 
@@ -232,83 +539,703 @@ impl FusedStream for PollardsRSAFact {
     }
 }
 
+/// Semiprimes with factors of similar size, used to compare `PollardsRSAFact` against `PollardsRSAFactBrent`.
+pub const RSA_BENCH_SAMPLES: &[u64] = &[1782886219, 1991010911, 2313646417, 2465929391, 2657601671, 3447944263];
+
+/// The number of products accumulated by `PollardsRSAFactBrent` before taking a gcd.
+const BRENT_BATCH_SIZE: u64 = 100;
+
+/// Pollard's rho factorization using Brent's cycle detection.
+///
+/// Instead of moving two sequences at different speeds, the tortoise `xi` teleports to the position of
+/// the hare `yi` at every power of two. The differences `|xi - yi|` are multiplied together and a single gcd
+/// is taken per batch, backtracking one step at a time if a batch gcd turns out to be the full modulus.
+#[derive(Debug, PartialEq)]
+pub struct PollardsRSAFactBrent {
+    pub n: u64,
+    i: usize,
+    xi: u64,
+    yi: u64,
+    c: u64,
+    ys: u64,
+    q: u64,
+    r: u64,
+    k: u64,
+    advance: u64,
+    batch: u64,
+    backtracking: bool,
+    evaluations: u64,
+    gcds: u64,
+    factor: Option<u64>,
+    max_steps: u64,
+    finished: bool,
+}
+
+impl PollardsRSAFactBrent {
+    /// Creates a new `PollardsRSAFactBrent`, panicking if `n` is too large to factor without overflow.
+    pub fn new(n: u64) -> Self {
+        Self::try_new(n).expect("invalid modulus")
+    }
+
+    /// Creates a new `PollardsRSAFactBrent`, returning an `AlgoError` if `n` cannot be factored without overflow.
+    pub fn try_new(n: u64) -> Result<Self, AlgoError> {
+        check_modulus(n)?;
+        let max_steps = 8 * (f64::powf(n as f64, 0.25).ceil() as u64) + BRENT_BATCH_SIZE;
+        Self::with_max_steps(n, max_steps)
+    }
+
+    /// Creates a new `PollardsRSAFactBrent` that gives up after `max_steps` iterations without finding a factor,
+    /// returning an `AlgoError` on the same moduli as `try_new`.
+    pub fn with_max_steps(n: u64, max_steps: u64) -> Result<Self, AlgoError> {
+        check_modulus(n)?;
+        Ok(Self {
+            n,
+            i: 0,
+            xi: 1,
+            yi: 1,
+            c: 1,
+            ys: 1,
+            q: 1,
+            r: 1,
+            k: 0,
+            advance: 1,
+            batch: 0,
+            backtracking: false,
+            evaluations: 0,
+            gcds: 0,
+            factor: None,
+            max_steps,
+            finished: false,
+        })
+    }
+
+    /// Creates a new `PollardsRSAFactBrent` walking `x -> x^2 + c (mod n)` from `x0`, returning an `AlgoError` on the
+    /// same moduli as `try_new`.
+    pub fn with_poly(n: u64, c: u64, x0: u64) -> Result<Self, AlgoError> {
+        let mut pollards = Self::try_new(n)?;
+        pollards.c = c % n;
+        pollards.xi = x0 % n;
+        pollards.yi = x0 % n;
+        pollards.ys = x0 % n;
+        Ok(pollards)
+    }
+
+    fn mix(&mut self, x: u64) -> u64 {
+        self.evaluations += 1;
+        (((x * x) % self.n) + self.c) % self.n
+    }
+
+    fn gcd_with_modulus(&mut self, x: u64) -> u64 {
+        self.gcds += 1;
+        if x == 0 { self.n } else { gcd(x, self.n) }
+    }
+
+    pub fn factor(&mut self) -> Option<u64> {
+        self.factor.take()
+    }
+
+    pub fn steps_to_sqrt_mod_ratio(&self) -> f64 {
+        (self.i as f64) / f64::sqrt(self.n as f64)
+    }
+
+    /// The number of steps taken so far.
+    pub fn steps(&self) -> u64 {
+        self.i as u64
+    }
+
+    /// The number of times the polynomial `x^2 + c` has been evaluated, one per step.
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    /// The number of gcd computations performed, one per batch plus any backtracking steps.
+    pub fn gcds(&self) -> u64 {
+        self.gcds
+    }
+
+    /// Classifies the run by its ratio of steps to `sqrt(n)`.
+    pub fn performance_class(&self) -> Performance {
+        Performance::from_ratio(self.steps_to_sqrt_mod_ratio())
+    }
+}
+
+impl Iterator for PollardsRSAFactBrent {
+    type Item = PollardsRSAFactItem;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        self.i += 1;
+        let mut g = 1;
+        if self.backtracking {
+            // A batch gcd was the full modulus, so retrace the batch one difference at a time
+            self.ys = self.mix(self.ys);
+            g = self.gcd_with_modulus(self.xi.abs_diff(self.ys));
+            if g != 1 {
+                self.finished = true;
+                if g != self.n {
+                    self.factor = Some(g);
+                }
+            }
+            let item = PollardsRSAFactItem { i: self.i, xi: self.xi, yi: self.ys, g, n: self.n, c: self.c };
+            if !self.finished && self.i as u64 >= self.max_steps {
+                self.finished = true;
+            }
+            return Some(item);
+        }
+        if self.advance > 0 {
+            // Move the hare ahead without comparing it to the tortoise
+            self.yi = self.mix(self.yi);
+            self.advance -= 1;
+        } else {
+            if self.batch == 0 {
+                self.ys = self.yi;
+            }
+            self.yi = self.mix(self.yi);
+            self.q = (self.q * self.xi.abs_diff(self.yi)) % self.n;
+            self.batch += 1;
+            self.k += 1;
+            if self.batch == BRENT_BATCH_SIZE || self.k == self.r {
+                self.batch = 0;
+                g = self.gcd_with_modulus(self.q);
+                if g == self.n {
+                    self.backtracking = true;
+                } else if g != 1 {
+                    self.finished = true;
+                    self.factor = Some(g);
+                } else if self.k == self.r {
+                    // Teleport the tortoise to the hare and double the length of the next block
+                    self.xi = self.yi;
+                    self.r *= 2;
+                    self.k = 0;
+                    self.advance = self.r;
+                }
+            }
+        }
+        if !self.finished && self.i as u64 >= self.max_steps {
+            self.finished = true;
+        }
+        Some(PollardsRSAFactItem { i: self.i, xi: self.xi, yi: self.yi, g, n: self.n, c: self.c })
+    }
+}
+
+impl Stream for PollardsRSAFactBrent {
+    type Item = PollardsRSAFactItem;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match Iterator::next(&mut *self) {
+            Some(item) => Poll::Ready(Some(item)),
+            _ => {
+                self.finished = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl FusedStream for PollardsRSAFactBrent {
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
+
+/// The most baby steps baby-step giant-step stores, bounding its table to a million or so entries.
+pub const MAX_BABY_STEPS: u64 = 1 << 20;
+
+/// Solves `g^x = h (mod p)` deterministically using Shanks's baby-step giant-step algorithm.
+///
+/// Stores the baby steps `g^j` for `j` in `0..m` where `m = ceil(sqrt(p - 1))`, then takes giant steps
+/// `h * g^(-i * m)` until one lands in the table. Returns `None` when no logarithm exists, or when `m` exceeds
+/// `MAX_BABY_STEPS` and the table would be too large to build.
+pub fn bsgs(g: u64, h: u64, p: u64) -> Option<u64> {
+    if p < 2 {
+        return None;
+    }
+    bsgs_with_order(g, h, p, p - 1).0
+}
+
+/// Baby-step giant-step inside a subgroup, where `order` is a multiple of the order of `g` modulo `p`.
+///
+/// Returns the logarithm, if found, along with the number of baby and giant steps taken.
+fn bsgs_with_order(g: u64, h: u64, p: u64, order: u64) -> (Option<u64>, u64) {
+    if g % p == 0 || order == 0 {
+        return (None, 0);
+    }
+    let m = f64::sqrt(order as f64).ceil() as u64;
+    if m > MAX_BABY_STEPS {
+        return (None, 0);
+    }
+
+    // Baby steps, keeping the smallest exponent for each value
+    let mut baby_steps = HashMap::with_capacity(m as usize);
+    let mut gj = 1 % p;
+    for j in 0..m {
+        baby_steps.entry(gj).or_insert(j);
+        gj = mulmod(gj, g, p);
+    }
+
+    // Giant steps, g^(-m) computed as g^(order - m) since g^order = 1
+    let giant = fast_power(g, (order - (m % order)) % order, p);
+    let mut gamma = h % p;
+    for i in 0..m {
+        if let Some(j) = baby_steps.get(&gamma) {
+            let log = i * m + j;
+            // `p` is not guaranteed to be prime, so confirm the candidate
+            if fast_power(g, log, p) == h % p {
+                return (Some(log), m + i + 1);
+            }
+            return (None, m + i + 1);
+        }
+        gamma = mulmod(gamma, giant, p);
+    }
+    (None, 2 * m)
+}
+
+/// Solves `g^x = h (mod p)` using the Pohlig-Hellman algorithm.
+///
+/// Factors `p - 1` to find the order `N` of `g`, then solves the logarithm in each prime power subgroup of order
+/// `q^e` dividing `N` one digit at a time with baby-step giant-step, and combines the results with the Chinese
+/// remainder theorem. `g` need not be a primitive root. This is fast whenever `p - 1` only has small prime factors.
+/// Returns `None` if `p` is not prime or no logarithm exists.
+pub fn pohlig_hellman(g: u64, h: u64, p: u64) -> Option<u64> {
+    pohlig_hellman_with_steps(g, h, p).map(|(log, _)| log)
+}
+
+/// Solves `g^x = h (mod p)` like `pohlig_hellman`, also returning the total number of baby-step giant-step steps
+/// taken across the prime power subgroups.
+pub fn pohlig_hellman_with_steps(g: u64, h: u64, p: u64) -> Option<(u64, u64)> {
+    if !is_prime(p) || g % p == 0 || h % p == 0 {
+        return None;
+    }
+    let factors = try_factorize(p - 1).ok()?;
+    // The order of g divides p - 1, and is what the subgroups have to be taken from, since a g that is not a
+    // primitive root leaves some of the subgroups of p - 1 with nothing to search
+    let mut n = p - 1;
+    for &(q, _) in &factors {
+        while n % q == 0 && fast_power(g, n / q, p) == 1 {
+            n /= q;
+        }
+    }
+    let mut steps = 0;
+    // The combined solution x and the modulus it is known to
+    let (mut x, mut modulus) = (0u64, 1u64);
+
+    for &(q, _) in &factors {
+        let (mut e, mut rest) = (0, n);
+        while rest % q == 0 {
+            rest /= q;
+            e += 1;
+        }
+        if e == 0 {
+            continue;
+        }
+        let qe = q.pow(e);
+        // Move g and h into the subgroup of order q^e, which g^(n / q^e) generates
+        let gi = fast_power(g, n / qe, p);
+        let hi = fast_power(h, n / qe, p);
+        // An element of order q used to find each base q digit of the logarithm
+        let gamma = fast_power(gi, qe / q, p);
+
+        let mut xi = 0;
+        let mut qk = 1;
+        for k in 0..e {
+            // Strip the digits found so far and project onto the subgroup of order q
+            let gi_inv_xi = fast_power(gi, (qe - xi % qe) % qe, p);
+            let hk = fast_power(mulmod(gi_inv_xi, hi, p), qe / (qk * q), p);
+            let (digit, digit_steps) = bsgs_with_order(gamma, hk, p, q);
+            steps += digit_steps;
+            xi += digit? * qk;
+            if k + 1 < e {
+                qk *= q;
+            }
+        }
+
+        // Combine x = xi (mod q^e) with the previous congruences, the inverse of modulus mod q^e
+        // comes from Euler's theorem since phi(q^e) = q^(e - 1) * (q - 1)
+        let phi = (qe / q) * (q - 1);
+        let inv = fast_power(modulus % qe, phi - 1, qe);
+        let t = mulmod((xi + qe - x % qe) % qe, inv, qe);
+        x += modulus * t;
+        modulus *= qe;
+    }
+
+    if fast_power(g, x, p) == h % p {
+        Some((x, steps))
+    } else {
+        None
+    }
+}
+
+/// The largest divisor tried by trial division in `factorize` before switching to Pollard's rho.
+const FACTORIZE_TRIAL_LIMIT: u64 = 1000;
+
+/// Factors any `n` into `(prime, exponent)` pairs, sorted by prime, panicking on the same inputs as
+/// `try_factorize` fails on.
+pub fn factorize(n: u64) -> Vec<(u64, u32)> {
+    try_factorize(n).expect("unable to factor")
+}
+
+/// Factors any `n` into `(prime, exponent)` pairs, sorted by prime.
+///
+/// Small factors are removed by trial division, then the remaining cofactor is split recursively with Pollard's
+/// rho until every part passes `is_prime`. Returns an empty vec for `n = 0` and `n = 1`, and an
+/// `AlgoError::InvariantViolation` if a composite part cannot be split.
+pub fn try_factorize(mut n: u64) -> Result<Vec<(u64, u32)>, AlgoError> {
+    let mut primes = vec![];
+    if n == 0 {
+        return Ok(vec![]);
+    }
+    for &d in small_primes().iter().take_while(|&&d| d <= FACTORIZE_TRIAL_LIMIT) {
+        if d > n / d {
+            break;
+        }
+        while n % d == 0 {
+            n /= d;
+            primes.push(d);
+        }
+    }
+    let mut composites = if n > 1 { vec![n] } else { vec![] };
+    while let Some(m) = composites.pop() {
+        if is_prime(m) {
+            primes.push(m);
+        } else {
+            let d = rho_split(m)?;
+            composites.push(d);
+            composites.push(m / d);
+        }
+    }
+    primes.sort_unstable();
+
+    let mut factors: Vec<(u64, u32)> = vec![];
+    for q in primes {
+        match factors.last_mut() {
+            Some((last, e)) if *last == q => *e += 1,
+            _ => factors.push((q, 1)),
+        }
+    }
+    Ok(factors)
+}
+
+/// Finds a nontrivial factor of the composite `n` with Pollard's rho.
+///
+/// Unlike `PollardsRSAFact` the walk uses `mulmod`, so any `u64` can be split. Each polynomial `x^2 + c` that
+/// only finds the full modulus is abandoned for the next value of `c`. Returns an `AlgoError::InvariantViolation`
+/// if no polynomial splits `n`, which only happens if `n` is prime after all.
+fn rho_split(n: u64) -> Result<u64, AlgoError> {
+    for c in 1..n {
+        let mix = |x: u64| ((mulmod(x, x, n) as u128 + c as u128) % n as u128) as u64;
+        let (mut x, mut y, mut g) = (2, 2, 1);
+        while g == 1 {
+            x = mix(x);
+            y = mix(mix(y));
+            g = if x == y { n } else { gcd(x.abs_diff(y), n) };
+        }
+        if g != n {
+            return Ok(g);
+        }
+    }
+    Err(AlgoError::InvariantViolation(format!("{n} is not split by any polynomial")))
+}
+
 pub mod utils {
+    use std::sync::OnceLock;
+    use super::AlgoError;
+
+    /// The limit of the primes cached by `small_primes`.
+    const SMALL_PRIMES_LIMIT: u64 = 1 << 16;
+
+    static SMALL_PRIMES: OnceLock<Vec<u64>> = OnceLock::new();
+
+    /// Computes the greatest common divisor of `a` and `b`, with `gcd(a, 0) = a` so that zero is never rejected.
     pub fn gcd(mut a: u64, mut b: u64) -> u64 {
-        assert!(a != 0 && b != 0);
+        while b > 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    }
+
+    /// Computes the greatest common divisor of `a` and `b`, returning `AlgoError::ZeroArgument` if either is zero.
+    pub fn try_gcd(mut a: u64, mut b: u64) -> Result<u64, AlgoError> {
+        if a == 0 || b == 0 {
+            return Err(AlgoError::ZeroArgument);
+        }
         let mut r = a % b;
         while r > 0 {
             a = b;
             b = r;
             r = a % b;
         }
-        b
+        Ok(b)
+    }
+
+    /// Computes `a * b (mod m)` without overflowing by widening to `u128`.
+    pub fn mulmod(a: u64, b: u64, m: u64) -> u64 {
+        ((a as u128 * b as u128) % m as u128) as u64
+    }
+
+    /// Computes `base^exp (mod modulus)` by repeated squaring.
+    ///
+    /// Intermediate products are reduced with `mulmod`, so any `u64` modulus is safe from overflow.
+    /// Clients can use this to check a reported discrete log against its `g`, `h` and `p`.
+    pub fn mod_pow(base: u64, exp: u64, modulus: u64) -> u64 {
+        fast_power(base, exp, modulus)
     }
 
     pub fn fast_power(mut g: u64, mut e: u64, n: u64) -> u64 {
         let mut r = 1;
         while e > 0 {
             if e % 2 == 1 {
-                r *= g;
-                r %= n;
+                r = mulmod(r, g, n);
             }
-            g *= g;
-            g %= n;
+            g = mulmod(g, g, n);
             e /= 2;
         }
         r
     }
 
-    pub fn gcd_weights(mut a: u64, mut b: u64) -> (u64, u64) {
-        let mut p_vec = vec![1];
-        let mut q_vec = vec![0, 1];
-        let mut q = a / b;
-        p_vec.push(q);
-        let mut r = a % b;
-        while r > 0 {
-            a = b;
-            b = r;
-            q = a / b;
-            let (p1, p2) = (p_vec[p_vec.len() - 1], p_vec[p_vec.len() - 2]);
-            let (q1, q2) = (q_vec[q_vec.len() - 1], q_vec[q_vec.len() - 2]);
-            p_vec.push(p1 * q + p2);
-            q_vec.push(q1 * q + q2);
-            r = a % b;
+    /// Computes the inverse of `a` modulo `m` with the extended Euclidean algorithm.
+    ///
+    /// Returns `None` when `gcd(a, m) != 1`, since `a` then has no inverse.
+    pub fn mod_inverse(a: u64, m: u64) -> Option<u64> {
+        if m == 0 {
+            return None;
         }
-        (p_vec[p_vec.len() - 2], q_vec[q_vec.len() - 2])
+        let (d, s, _) = ext_gcd(a % m, m);
+        if d != 1 {
+            return None;
+        }
+        Some(s.rem_euclid(m as i128) as u64)
+    }
+
+    /// The extended Euclidean algorithm.
+    ///
+    /// Returns `(d, s, t)` where `d = gcd(a, b)` and `a * s + b * t = d`.
+    pub fn ext_gcd(a: u64, b: u64) -> (u64, i128, i128) {
+        let (mut old_r, mut r) = (a as i128, b as i128);
+        let (mut old_s, mut s) = (1i128, 0i128);
+        let (mut old_t, mut t) = (0i128, 1i128);
+        while r != 0 {
+            let q = old_r / r;
+            (old_r, r) = (r, old_r - q * r);
+            (old_s, s) = (s, old_s - q * s);
+            (old_t, t) = (t, old_t - q * t);
+        }
+        debug_assert_eq!(a as i128 * old_s + b as i128 * old_t, old_r);
+        (old_r as u64, old_s, old_t)
     }
 
-    pub fn gcd_mul_inverse(m: u64, v: u64, d: u64, s: u64, t: u64) -> u64 {
-        let mut m = m;
-        if m * s > v * t && m * s - v * t == d {
-            while m < t {
-                m += m;
+    /// Searches for the smallest factor of `n` that is at most `limit` by trial division.
+    ///
+    /// Divides by the cached `small_primes` first, then by odd numbers past them if `limit` is larger still.
+    /// Returns `None` if `n` is prime or all of its factors are larger than `limit`.
+    pub fn trial_divide(n: u64, limit: u64) -> Option<u64> {
+        for &d in small_primes() {
+            if d > limit || d > n / d {
+                return None;
             }
-            // println!("gcd_mul_inverse, branch1");
-            let v_inv = (m - t) % m;
-            assert_eq!((v * v_inv) % m, d);
-            (m - t) % m
-        } else if m * t > v * s && m * t - v * s == d {
-            while m < s {
-                m += m;
+            if n % d == 0 {
+                return Some(d);
             }
-            // println!("gcd_mul_inverse, branch2");
-            let v_inv = (m - s) % m;
-            assert_eq!((v * v_inv) % m, d);
-            (m - s) % m
-        } else if v * t > m * s && t * v - m * s == d {
-            // println!("gcd_mul_inverse, branch3");
-            let v_inv = t % m;
-            assert_eq!((v * v_inv) % m, d);
-            t % m
-        } else {
-            // println!("gcd_mul_inverse, branch4");
-            let v_inv = s % m;
-            assert_eq!((v * v_inv) % m, d);
-            s % m
         }
+        let mut d = SMALL_PRIMES_LIMIT + 1;
+        while d <= limit && d <= n / d {
+            if n % d == 0 {
+                return Some(d);
+            }
+            d += 2;
+        }
+        None
+    }
+
+    /// The Sieve of Eratosthenes, returning every prime up to and including `limit` in increasing order.
+    pub fn sieve(limit: u64) -> Vec<u64> {
+        if limit < 2 {
+            return vec![];
+        }
+        let mut composite = vec![false; limit as usize + 1];
+        let mut p = 2;
+        while p * p <= limit {
+            if !composite[p as usize] {
+                for m in (p * p..=limit).step_by(p as usize) {
+                    composite[m as usize] = true;
+                }
+            }
+            p += 1;
+        }
+        (2..=limit).filter(|&n| !composite[n as usize]).collect()
+    }
+
+    /// The primes below `2^16`, sieved on first use and shared for the lifetime of the process.
+    pub fn small_primes() -> &'static [u64] {
+        SMALL_PRIMES.get_or_init(|| sieve(SMALL_PRIMES_LIMIT))
+    }
+
+    /// Deterministic primality test for any `u64`, running Miller-Rabin against the first twelve primes.
+    pub fn is_prime(n: u64) -> bool {
+        const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+        if n < 2 {
+            return false;
+        }
+        for p in BASES {
+            if n % p == 0 {
+                return n == p;
+            }
+        }
+        BASES.iter().all(|&a| !miller_rabin(n, a))
+    }
+
+    /// Euler's totient function `φ(n)`, the number of integers in `1..=n` coprime to `n`.
+    ///
+    /// Computed from the prime factorization of `n` as the product of `q^(e - 1) * (q - 1)` over its prime powers
+    /// `q^e`, so `φ(1) = 1` and `φ(p) = p - 1` for a prime `p`. Returns 0 for `n = 0`.
+    pub fn euler_totient(n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        super::factorize(n)
+            .into_iter()
+            .map(|(q, e)| q.pow(e - 1) * (q - 1))
+            .product()
+    }
+
+    /// The multiplicative order of `g` modulo the prime `p`, the smallest `k > 0` with `g^k = 1 (mod p)`.
+    ///
+    /// The order divides `p - 1`, so it is found by dividing the prime factors of `p - 1` out of it for as long as
+    /// `g` raised to the quotient is still 1. Returns `None` if `gcd(g, p) != 1`, or if `g^(p - 1) != 1 (mod p)`
+    /// which means `p` is not prime.
+    pub fn mult_order(g: u64, p: u64) -> Option<u64> {
+        if p < 2 {
+            return None;
+        }
+        let g = g % p;
+        if g == 0 || gcd(g, p) != 1 {
+            return None;
+        }
+        let n = p - 1;
+        if fast_power(g, n, p) != 1 {
+            return None;
+        }
+        let mut order = n;
+        for (q, _) in super::try_factorize(n).ok()? {
+            while order % q == 0 && fast_power(g, order / q, p) == 1 {
+                order /= q;
+            }
+        }
+        Some(order)
+    }
+
+    /// The smallest primitive root modulo the prime `p`, a generator of the multiplicative group `F_p^*`.
+    ///
+    /// Candidates `g = 2, 3, ...` are tested until one has order exactly `p - 1`, that is until `g^((p - 1) / q)`
+    /// is not 1 for any prime factor `q` of `p - 1`. Returns `None` if `p` is not prime.
+    pub fn primitive_root(p: u64) -> Option<u64> {
+        try_primitive_root(p).ok()
+    }
+
+    /// The smallest primitive root modulo `p` like `primitive_root`, returning `AlgoError::NotPrime` if `p` is not
+    /// prime.
+    pub fn try_primitive_root(p: u64) -> Result<u64, AlgoError> {
+        if !is_prime(p) {
+            return Err(AlgoError::NotPrime(p));
+        }
+        if p == 2 {
+            return Ok(1);
+        }
+        let n = p - 1;
+        let factors = super::try_factorize(n)?;
+        // Every prime has a primitive root, so not finding one means p is not prime after all
+        (2..p).find(|&g| factors.iter().all(|&(q, _)| fast_power(g, n / q, p) != 1))
+            .ok_or(AlgoError::NotPrime(p))
+    }
+
+    /// Solves the system of congruences `x = r_i (mod m_i)` with the Chinese Remainder Theorem.
+    ///
+    /// The congruences are merged one at a time using `ext_gcd`. Moduli need not be pairwise coprime, as long as
+    /// the residues agree modulo the gcd of each pair. Returns `(x, m)` where `m` is the lcm of the moduli and
+    /// `x < m`, or `None` if the residues conflict, a modulus is 0, or `m` does not fit in a `u64`.
+    pub fn crt(residues: &[(u64, u64)]) -> Option<(u64, u64)> {
+        let (mut x, mut m) = (0u64, 1u64);
+        for &(r, n) in residues {
+            if n == 0 {
+                return None;
+            }
+            let r = r % n;
+            let (d, s, _) = ext_gcd(m, n);
+            let diff = r as i128 - x as i128;
+            if diff % d as i128 != 0 {
+                return None;
+            }
+            // x + m * k solves both congruences when m * k = r - x (mod n), i.e. k = (r - x) / d * s (mod n / d)
+            let step = (n / d) as i128;
+            let k = (diff / d as i128).rem_euclid(step) as u128 * s.rem_euclid(step) as u128 % step as u128;
+            let lcm = m as u128 * step as u128;
+            if lcm > u64::MAX as u128 {
+                return None;
+            }
+            x = ((x as u128 + m as u128 * k) % lcm) as u64;
+            m = lcm as u64;
+        }
+        Some((x, m))
+    }
+
+    /// The Jacobi symbol `(a / n)` for an odd `n`, which is the Legendre symbol when `n` is prime.
+    ///
+    /// Evaluated with quadratic reciprocity, pulling factors of 2 out of `a` and swapping `a` and `n` until `a`
+    /// is 0. Returns 0 when `gcd(a, n) != 1`, and also when `n` is even or 0, where the symbol is not defined.
+    pub fn jacobi(a: u64, n: u64) -> i8 {
+        if n % 2 == 0 {
+            return 0;
+        }
+        let (mut a, mut n) = (a % n, n);
+        let mut t = 1;
+        while a != 0 {
+            while a % 2 == 0 {
+                a /= 2;
+                if n % 8 == 3 || n % 8 == 5 {
+                    t = -t;
+                }
+            }
+            (a, n) = (n, a);
+            if a % 4 == 3 && n % 4 == 3 {
+                t = -t;
+            }
+            a %= n;
+        }
+        if n == 1 { t } else { 0 }
+    }
+
+    /// The smallest base for which `miller_rabin` proves `n` composite, or `None` if `n` is prime or below 2.
+    pub fn composite_witness(n: u64) -> Option<u64> {
+        if n < 2 || is_prime(n) {
+            return None;
+        }
+        (2..n).find(|&a| miller_rabin(n, a))
+    }
+
+    /// One round of the Solovay-Strassen test, returning `true` if the base `a` proves `n` composite.
+    ///
+    /// A prime `n` satisfies Euler's criterion `a^((n - 1) / 2) = (a / n) (mod n)` for every base `a` coprime to it,
+    /// where `(a / n)` is the Jacobi symbol. Like `miller_rabin`, an even `n` or a base sharing a factor with `n`
+    /// is reported as composite.
+    pub fn solovay_strassen(n: u64, a: u64) -> bool {
+        if n < 2 {
+            return true;
+        }
+        let d = gcd(a, n);
+        if n % 2 == 0 || (1 < d && d < n) {
+            return true;
+        }
+        let expected = match jacobi(a, n) {
+            1 => 1,
+            -1 => n - 1,
+            _ => return true,
+        };
+        fast_power(a, (n - 1) / 2, n) != expected
     }
 
     pub fn miller_rabin(n: u64, a: u64) -> bool {
+        if n < 2 {
+            return true;
+        }
         let d = gcd(a, n);
         if n % 2 == 0 || (1 < d && d < n) {
             return true;
@@ -327,8 +1254,7 @@ pub mod utils {
             if a % n == n - 1 {
                 return false;
             }
-            a *= a;
-            a %= n;
+            a = mulmod(a, a, n);
         }
         true
     }
@@ -348,80 +1274,24 @@ mod test {
     }
 
     #[test]
-    fn gcd_weights_test() {
-        let (a, b) = (100, 80);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        if a * u > b * v && a * u - b * v == d {
-            println!("a * u - b * v = {}", d);
-        } else if a * v > b * u && a * v - b * u == d {
-            println!("a * v - b * u = {}", d);
-        } else if b * v > a * u && b * v - a * u == d {
-            println!("b * v - a * u = {}", d);
-        } else {
-            assert!(b * u > a * v);
-            assert_eq!(b * u - a * v, d);
-            println!("b * u - a * v = {}", d);
+    fn ext_gcd_test() {
+        for (a, b) in [(100, 80), (9409612, 666), (2200, 124), (1782886218, 34478)] {
+            let (d, s, t) = ext_gcd(a, b);
+            println!("a: {}, b: {}", a, b);
+            println!("d: {}, s: {}, t: {}", d, s, t);
+            assert_eq!(d, gcd(a, b));
+            assert_eq!(a as i128 * s + b as i128 * t, d as i128);
+
+            // Reducing t modulo a gives b * b_inv = d (mod a)
+            let b_inv = t.rem_euclid(a as i128) as u64;
+            println!("b_inv = {}", b_inv);
+            println!("b * b_inv mod a = {}", (b * b_inv) % a);
+            assert_eq!((b * b_inv) % a, d);
+            println!();
         }
 
-        println!();
-
-        let (a, b) = (9409612, 666);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        if a * u > b * v && a * u - b * v == d {
-            println!("a * u - b * v = {}", d);
-        } else if a * v > b * u && a * v - b * u == d {
-            println!("a * v - b * u = {}", d);
-        } else if b * v > a * u && b * v - a * u == d {
-            println!("b * v - a * u = {}", d);
-        } else {
-            assert!(b * u > a * v);
-            assert_eq!(b * u - a * v, d);
-            println!("b * u - a * v = {}", d);
-        }
-
-        println!();
-
-        let (a, b) = (2200, 124);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        if a * u > b * v && a * u - b * v == d {
-            println!("a * u - b * v = {}", d);
-        } else if a * v > b * u && a * v - b * u == d {
-            println!("a * v - b * u = {}", d);
-        } else if b * v > a * u && b * v - a * u == d {
-            println!("b * v - a * u = {}", d);
-        } else {
-            assert!(b * u > a * v);
-            assert_eq!(b * u - a * v, d);
-            println!("b * u - a * v = {}", d);
-        }
-
-        println!();
-
-        let (a, b) = (1782886218, 34478);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        if a * u > b * v && a * u - b * v == d {
-            println!("a * u - b * v = {}", d);
-        } else if a * v > b * u && a * v - b * u == d {
-            println!("a * v - b * u = {}", d);
-        } else if b * v > a * u && b * v - a * u == d {
-            println!("b * v - a * u = {}", d);
-        } else {
-            assert!(b * u > a * v);
-            assert_eq!(b * u - a * v, d);
-            println!("b * u - a * v = {}", d);
-        }
+        assert_eq!(ext_gcd(7, 0), (7, 1, 0));
+        assert_eq!(ext_gcd(0, 7), (7, 0, 1));
     }
 
     #[test]
@@ -464,52 +1334,6 @@ mod test {
         println!("{} is prime with probability: {:2.20}", n, 1.0 - f64::powi(0.25, k));
     }
 
-    #[test]
-    fn gcd_mul_inverse_test() {
-        let (a, b) = (100, 80);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        let b_inv = gcd_mul_inverse(a, b, d, u, v);
-        println!("b_inv = {}", b_inv);
-        println!("b * b_inv mod a = {}", (b * b_inv) % a);
-        assert_eq!((b * b_inv) % a, d);
-        println!();
-
-        let (a, b) = (9409612, 666);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        let b_inv = gcd_mul_inverse(a, b, d, u, v);
-        println!("b_inv = {}", b_inv);
-        println!("b * b_inv mod a = {}", (b * b_inv) % a);
-        assert_eq!((b * b_inv) % a, d);
-        println!();
-
-        let (a, b) = (2200, 124);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        let b_inv = gcd_mul_inverse(a, b, d, u, v);
-        println!("b_inv = {}", b_inv);
-        println!("b * b_inv mod a = {}", (b * b_inv) % a);
-        assert_eq!((b * b_inv) % a, d);
-        println!();
-
-        let (a, b) = (1782886218, 34478);
-        let d = gcd(a, b);
-        let (u, v) = gcd_weights(a, b);
-        println!("a: {}, b: {}", a, b);
-        println!("u: {}, v: {}", u, v);
-        let b_inv = gcd_mul_inverse(a, b, d, u, v);
-        println!("b_inv = {}", b_inv);
-        println!("b * b_inv mod a = {}", (b * b_inv) % a);
-        assert_eq!((b * b_inv) % a, d);
-    }
-
     #[test]
     fn pollards_log_solve_test() {
         let (p, g, h) = (5011, 2, 2495);
@@ -518,7 +1342,7 @@ mod test {
         for item in &mut pollards {
             println!("{:?}", item);
         }
-        let log = pollards.solve();
+        let log = pollards.solve().unwrap();
         println!("{:?}", log);
         assert!(log.is_some());
         let log = log.unwrap();
@@ -534,7 +1358,7 @@ mod test {
         for item in &mut pollards {
             println!("{:?}", item);
         }
-        let log = pollards.solve();
+        let log = pollards.solve().unwrap();
         println!("{:?}", log);
         assert!(log.is_some());
         let log = log.unwrap();
@@ -550,7 +1374,7 @@ mod test {
         for item in &mut pollards {
             println!("{:?}", item);
         }
-        let log = pollards.solve();
+        let log = pollards.solve().unwrap();
         println!("{:?}", log);
         assert!(log.is_some());
         let log = log.unwrap();
@@ -561,6 +1385,95 @@ mod test {
         println!();
     }
 
+    #[test]
+    fn pollards_log_max_steps_test() {
+        // g = p - 1 has order 2, so h is not in the subgroup generated by g and no log exists
+        let (p, g, h) = (2147483647, 2147483646, 5);
+        let mut pollards = PollardsLog::with_max_steps(p, g, h, 1000).unwrap();
+        let mut last = None;
+        for item in &mut pollards {
+            last = Some(item);
+        }
+        println!("{:?}", pollards);
+        let last = last.unwrap();
+        assert_eq!(last.i, 1000);
+        assert_ne!(last.xi, last.yi);
+        assert!(Iterator::next(&mut pollards).is_none());
+        assert_eq!(pollards.solve(), Ok(None));
+        assert_eq!(pollards.collision(), None);
+    }
+
+    #[test]
+    fn pollards_log_small_prime_test() {
+        // With p = 5 the walk partitions into {1}, {2, 3} and {4}, so it must not start by squaring 1
+        let (p, g, h) = (5, 2, 3);
+        let mut pollards = PollardsLog::new(p, g, h);
+        let items = Iterator::collect::<Vec<_>>(&mut pollards);
+        assert_eq!(items[0], PollardsLogItem { i: 1, xi: 2, ai: 1, bi: 0, yi: 4, gi: 2, di: 0 });
+        assert_eq!(items.len(), 2);
+        assert_eq!(pollards.solve(), Ok(Some(3)));
+    }
+
+    #[test]
+    fn pollards_log_with_start_test() {
+        // Starting from 1 the walk only finds a collision that says nothing about the log of 29
+        let (p, g, h) = (1213, 2, 29);
+        let mut pollards = PollardsLog::new(p, g, h);
+        for _ in &mut pollards {}
+        assert_eq!(pollards.solve(), Ok(None));
+
+        let mut pollards = PollardsLog::with_start(p, g, h, 1, 1).unwrap();
+        let first = Iterator::next(&mut pollards).unwrap();
+        // x0 = 2 * 29 = 58 lies in the first third, so the walk multiplies by g
+        assert_eq!((first.xi, first.ai, first.bi), (116, 2, 1));
+        for _ in &mut pollards {}
+        let log = pollards.solve().unwrap().unwrap();
+        assert_eq!(fast_power(g, log, p), h);
+
+        // The exponents are reduced modulo p - 1
+        assert_eq!(PollardsLog::with_start(p, g, h, p, 2 * p - 1).unwrap(), PollardsLog::with_start(p, g, h, 1, 1).unwrap());
+    }
+
+    #[test]
+    fn pollards_log_inconsistent_state_test() {
+        let (p, g, h) = (5011, 2, 2495);
+        let mut pollards = PollardsLog::new(p, g, h);
+        for _ in &mut pollards {}
+        let step = pollards.steps();
+        assert!(pollards.solve().unwrap().is_some());
+
+        // Exponents that no longer describe the collision are reported rather than solved with
+        pollards.ai = (pollards.ai + 1) % (p - 1);
+        assert_eq!(pollards.solve(), Err(AlgoError::InvariantViolation(format!("walk reached an inconsistent state at step {step}"))));
+        assert_eq!(pollards.solve().unwrap_err().to_string(), format!("walk reached an inconsistent state at step {step}"));
+    }
+
+    #[test]
+    fn pollards_log_try_new_test() {
+        assert!(PollardsLog::try_new(48611, 19, 24717).is_ok());
+        // (p - 1)^2 still fits in a u64 at p = 2^32, but not one past it
+        assert!(PollardsLog::try_new(1 << 32, 3, (1 << 32) - 1).is_ok());
+        assert_eq!(PollardsLog::try_new((1 << 32) + 1, 3, 5), Err(AlgoError::Overflow));
+        assert_eq!(PollardsLog::try_new(18446744073709551557, 2, 3), Err(AlgoError::Overflow));
+        assert_eq!(PollardsLog::try_new(1, 0, 0), Err(AlgoError::InvariantViolation("modulus 1 too small".to_string())));
+        assert!(PollardsLog::try_new(2, 1, 1).is_ok());
+        // g and h must be reduced modulo p
+        assert!(PollardsLog::try_new(5011, 5010, 0).is_ok());
+        assert_eq!(PollardsLog::try_new(5011, 5011, 2), Err(AlgoError::InvariantViolation("element 5011 not reduced modulo 5011".to_string())));
+        assert_eq!(PollardsLog::try_new(5011, 2, 5011), Err(AlgoError::InvariantViolation("element 5011 not reduced modulo 5011".to_string())));
+        assert_eq!(PollardsLog::try_new(5011, 2, u64::MAX), Err(AlgoError::InvariantViolation(format!("element {} not reduced modulo 5011", u64::MAX))));
+        // A step cap does not lift the checks
+        assert!(PollardsLog::with_max_steps(5011, 2, 5010, 10).is_ok());
+        assert_eq!(PollardsLog::with_max_steps(5011, 2, 5011, 10), Err(AlgoError::InvariantViolation("element 5011 not reduced modulo 5011".to_string())));
+        assert_eq!(PollardsLog::with_max_steps((1 << 32) + 1, 3, 5, 10), Err(AlgoError::Overflow));
+    }
+
+    #[test]
+    #[should_panic]
+    fn pollards_log_new_panics_on_invalid_input() {
+        PollardsLog::new(5011, 2, 5011);
+    }
+
     #[test]
     fn test_pollards_rsa_factor() {
         let mut pollards = PollardsRSAFact::new(1782886219);
@@ -619,6 +1532,524 @@ mod test {
         assert_eq!(factor1 * factor2, pollards.n);
     }
 
-}
+    #[test]
+    fn pollards_rsa_try_new_test() {
+        assert!(PollardsRSAFact::try_new(1782886219).is_ok());
+        assert!(PollardsRSAFact::try_new(1 << 32).is_ok());
+        assert_eq!(PollardsRSAFact::try_new((1 << 32) + 1), Err(AlgoError::Overflow));
+        assert_eq!(PollardsRSAFact::try_new(u64::MAX), Err(AlgoError::Overflow));
+        assert_eq!(PollardsRSAFact::try_new(0), Err(AlgoError::InvariantViolation("modulus 0 too small".to_string())));
+        assert_eq!(PollardsRSAFactBrent::try_new(u64::MAX), Err(AlgoError::Overflow));
+    }
+
+    #[test]
+    fn pollards_rsa_max_steps_test() {
+        // A prime modulus has no nontrivial factor, so the walk only stops because of the cap
+        let n = 1000000007;
+        let mut pollards = PollardsRSAFact::new(n);
+        let steps = Iterator::count(&mut pollards);
+        println!("steps: {}", steps);
+        assert!(steps as f64 <= 8.0 * f64::powf(n as f64, 0.25) + 8.0);
+        assert!(Iterator::next(&mut pollards).is_none());
+        assert_eq!(pollards.factor(), None);
+
+        let mut pollards = PollardsRSAFact::with_max_steps(n, 10).unwrap();
+        assert_eq!(Iterator::count(&mut pollards), 10);
+        assert_eq!(pollards.factor(), None);
+    }
+
+    #[test]
+    fn pollards_rsa_full_modulus_collision_test() {
+        // With x -> x^2 + 1 the walk modulo 143 = 11 * 13 closes its cycle modulo both primes at once
+        let mut pollards = PollardsRSAFact::with_poly(143, 1, 1).unwrap();
+        let mut last = None;
+        for item in &mut pollards {
+            println!("{:?}", item);
+            last = Some(item);
+        }
+        let last = last.unwrap();
+        assert_eq!(last.xi, last.yi);
+        assert_eq!(last.g, 143);
+        assert_eq!(last.c, 1);
+        assert_eq!(pollards.factor(), None);
+
+        // Retrying with another polynomial splits it, the collision reporting the old constant and the items after
+        // it the new one
+        let mut pollards = PollardsRSAFact::new(143);
+        let items: Vec<_> = Iterator::collect(&mut pollards);
+        let collision = items.iter().position(|item| item.g == 143).unwrap();
+        assert_eq!(items[collision].c, 1);
+        assert_eq!(items[collision].xi, items[collision].yi);
+        assert_eq!(items[collision + 1].c, 2);
+        let last = items.last().unwrap();
+        assert!(last.c > 1, "{last:?}");
+        let factor = pollards.factor();
+        assert!(factor == Some(11) || factor == Some(13), "{factor:?}");
+
+        let mut pollards = PollardsRSAFact::with_poly(143, 2, 1).unwrap();
+        Iterator::count(&mut pollards);
+        assert!(pollards.factor().is_some());
+    }
+
+    #[test]
+    fn test_pollards_rsa_brent_factor() {
+        for n in [1782886219, 9409613, 2201, 143] {
+            let mut pollards = PollardsRSAFactBrent::new(n);
+            for item in &mut pollards {
+                println!("{:?}", item);
+            }
+            let factor1 = pollards.factor();
+            println!("{:?}", factor1);
+            assert!(factor1.is_some());
+
+            let factor1 = factor1.unwrap();
+            assert_ne!(factor1, 1);
+            assert_ne!(factor1, n);
+            assert_eq!(n % factor1, 0);
+            println!("Steps to modulus sqrt ratio: {:10.10}", pollards.steps_to_sqrt_mod_ratio());
+        }
+
+        // The items report the constant of the polynomial actually walked
+        let mut pollards = PollardsRSAFactBrent::with_poly(1782886219, 3, 2).unwrap();
+        assert!(Iterator::all(&mut pollards, |item| item.c == 3));
+        let factor = pollards.factor().unwrap();
+        assert!(factor == 7933 || factor == 224743, "{factor}");
+    }
+
+    #[test]
+    fn pollards_rsa_floyd_vs_brent_test() {
+        let n = 1782886219;
+        let mut floyd = PollardsRSAFact::new(n);
+        Iterator::count(&mut floyd);
+        let mut brent = PollardsRSAFactBrent::new(n);
+        Iterator::count(&mut brent);
+        println!("floyd: {} evaluations, {} gcds", floyd.evaluations(), floyd.gcds());
+        println!("brent: {} evaluations, {} gcds", brent.evaluations(), brent.gcds());
+        assert_eq!(floyd.factor(), brent.factor());
+
+        // Floyd needs 126 steps of three evaluations each, Brent finds the same factor after 254 evaluations
+        assert_eq!(floyd.evaluations(), 378);
+        assert_eq!(brent.evaluations(), 254);
+        assert!(brent.gcds() < floyd.gcds());
+    }
+
+    #[test]
+    fn pollards_rsa_floyd_vs_brent_samples_test() {
+        // The same semiprimes are timed by the `rsa_factoring` benchmark
+        let (mut floyd_total, mut brent_total) = (0, 0);
+        for &n in RSA_BENCH_SAMPLES {
+            let start = std::time::Instant::now();
+            let mut floyd = PollardsRSAFact::new(n);
+            Iterator::count(&mut floyd);
+            let floyd_time = start.elapsed();
+            let start = std::time::Instant::now();
+            let mut brent = PollardsRSAFactBrent::new(n);
+            Iterator::count(&mut brent);
+            let brent_time = start.elapsed();
+            println!(
+                "{n}: floyd {} evaluations in {:?}, brent {} evaluations in {:?}",
+                floyd.evaluations(), floyd_time, brent.evaluations(), brent_time
+            );
+
+            let factor = brent.factor().unwrap();
+            assert!(factor != 1 && factor != n && n % factor == 0);
+            floyd_total += floyd.evaluations();
+            brent_total += brent.evaluations();
+        }
+        // Brent can lose on a single input by overshooting to the next power of two, but not across the samples
+        println!("floyd {floyd_total} evaluations in total, brent {brent_total}");
+        assert!(brent_total < floyd_total);
+    }
+
+    #[test]
+    fn mulmod_test() {
+        assert_eq!(mulmod(3, 5, 7), 1);
+        assert_eq!(mulmod(u64::MAX, u64::MAX, 1000000007), ((u64::MAX as u128 * u64::MAX as u128) % 1000000007) as u64);
+        assert_eq!(fast_power(2, 64, 18446744073709551557), ((1u128 << 64) % 18446744073709551557) as u64);
+    }
+
+    #[test]
+    fn bsgs_test() {
+        let (p, g, h) = (5011, 2, 2495);
+        let log = bsgs(g, h, p);
+        println!("{:?}", log);
+        assert!(log.is_some());
+        assert_eq!(fast_power(g, log.unwrap(), p), h);
+
+        let (p, g, h) = (15239131, 29, 5953042);
+        let log = bsgs(g, h, p);
+        println!("{:?}", log);
+        assert_eq!(fast_power(g, log.unwrap(), p), h);
+
+        // 10 generates the subgroup of order 2 modulo 11, which does not contain 3
+        assert_eq!(bsgs(10, 3, 11), None);
+        assert_eq!(bsgs(0, 3, 11), None);
+
+        // The table for a modulus past MAX_BABY_STEPS squared is never built
+        let p = 18446744073709551557;
+        assert_eq!(bsgs(2, 4, p), None);
+        assert_eq!(bsgs_with_order(2, 4, p, p - 1), (None, 0));
+        let order = MAX_BABY_STEPS * MAX_BABY_STEPS;
+        assert_eq!(bsgs_with_order(2, 4, order + 1, order).0, Some(2));
+    }
 
+    #[test]
+    fn pohlig_hellman_test() {
+        // p - 1 = 7918 = 2 * 37 * 107
+        let (p, g, h) = (7919, 7, 7542);
+        let (log, steps) = pohlig_hellman_with_steps(g, h, p).unwrap();
+        println!("log: {}, steps: {}", log, steps);
+        assert_eq!(fast_power(g, log, p), h);
+        assert_eq!(pohlig_hellman(g, h, p), Some(log));
+
+        let mut pollards = PollardsLog::new(p, g, h);
+        let pollard_steps = Iterator::count(&mut pollards);
+        println!("pollard steps: {}", pollard_steps);
+        assert!(steps < pollard_steps as u64);
+
+        // Prime powers in p - 1 = 5010 = 2 * 3 * 5 * 167 and 17958 = 2 * 3 * 41 * 73
+        let (p, g, h) = (5011, 2, 2495);
+        assert_eq!(fast_power(g, pohlig_hellman(g, h, p).unwrap(), p), h);
+        let (p, g, h) = (17959, 17, 14226);
+        assert_eq!(fast_power(g, pohlig_hellman(g, h, p).unwrap(), p), h);
+        // p - 1 = 2^4 * 3^2 * 7 has repeated factors
+        let (p, g) = (1009, 11);
+        let h = fast_power(g, 777, p);
+        assert_eq!(fast_power(g, pohlig_hellman(g, h, p).unwrap(), p), h);
+
+        // Bases that are not primitive roots only generate a subgroup, 4 has order 6 modulo 13 and 9 has order
+        // 2^15 modulo 65537, so the logs are only determined modulo those orders
+        assert_eq!(pohlig_hellman(4, 10, 13), Some(5));
+        assert_eq!(pohlig_hellman(9, 59049, 65537), Some(5));
+        let (p, g) = (1009, 4);
+        for x in [1, 250, 503] {
+            let h = fast_power(g, x, p);
+            assert_eq!(fast_power(g, pohlig_hellman(g, h, p).unwrap(), p), h);
+        }
+        assert_eq!(pohlig_hellman(1, 1, 13), Some(0));
+
+        // Not prime, or h is not in the subgroup generated by g
+        assert_eq!(pohlig_hellman(2, 3, 7917), None);
+        assert_eq!(pohlig_hellman(10, 3, 11), None);
+    }
+
+    #[test]
+    fn is_prime_test() {
+        let primes = [2, 3, 5, 37, 41, 7919, 15239131, 1000000007, 2147483647, 18446744073709551557];
+        let composites = [0, 1, 4, 561, 1729, 172947529, 1782886219, 3215031751];
+        assert!(primes.iter().all(|&p| is_prime(p)));
+        assert!(composites.iter().all(|&n| !is_prime(n)));
+    }
+
+    #[test]
+    fn mod_inverse_test() {
+        assert_eq!(mod_inverse(3, 11), Some(4));
+        assert_eq!(mod_inverse(2, 4), None);
+        assert_eq!(mod_inverse(0, 7), None);
+        assert_eq!(mod_inverse(14, 11), Some(4));
+        assert_eq!(mod_inverse(1, 1), Some(0));
+        assert_eq!(mod_inverse(5, 0), None);
+
+        let m = 1782886218;
+        let a = 34477;
+        let a_inv = mod_inverse(a, m).unwrap();
+        assert_eq!(mulmod(a, a_inv, m), 1);
+        assert_eq!(mod_inverse(34478, m), None);
+    }
+
+    #[test]
+    fn mod_pow_test() {
+        assert_eq!(mod_pow(2, 11, 71), 60);
+        assert_eq!(mod_pow(7, 0, 7919), 1);
+        assert_eq!(mod_pow(5, 1000000006, 1000000007), 1);
+        assert_eq!(mod_pow(2, 64, 18446744073709551557), 59);
+    }
+
+    #[test]
+    fn trial_divide_test() {
+        assert_eq!(trial_divide(2201, 10_000), Some(31));
+        assert_eq!(trial_divide(1782886219, 10_000), Some(7933));
+        assert_eq!(trial_divide(1782886219, 1000), None);
+        // Even numbers, squares of small primes and primes
+        assert_eq!(trial_divide(1000, 10_000), Some(2));
+        assert_eq!(trial_divide(49, 10_000), Some(7));
+        assert_eq!(trial_divide(7919 * 7919, 10_000), Some(7919));
+        assert_eq!(trial_divide(2, 10_000), None);
+        assert_eq!(trial_divide(7919, 10_000), None);
+        assert_eq!(trial_divide(1000000007, 10_000), None);
+        // Factors past the cached small primes
+        assert_eq!(trial_divide(65537 * 65537, 100_000), Some(65537));
+        assert_eq!(trial_divide(65537 * 65537, 65536), None);
+    }
 
+    #[test]
+    fn euler_totient_test() {
+        assert_eq!(euler_totient(1), 1);
+        assert_eq!(euler_totient(10), 4);
+        assert_eq!(euler_totient(36), 12);
+        assert_eq!(euler_totient(7919), 7918);
+        // Prime powers and a product of two large primes
+        assert_eq!(euler_totient(1024), 512);
+        assert_eq!(euler_totient(1782886219), 1782886219 - 7933 - 224743 + 1);
+        assert_eq!(euler_totient(0), 0);
+    }
+
+    #[test]
+    fn mult_order_test() {
+        assert_eq!(mult_order(2, 7), Some(3));
+        assert_eq!(mult_order(3, 7), Some(6));
+        assert_eq!(mult_order(6, 7), Some(2));
+        assert_eq!(mult_order(1, 7), Some(1));
+        assert_eq!(mult_order(3, 11), Some(5));
+        assert_eq!(mult_order(10, 11), Some(2));
+        assert_eq!(mult_order(5, 71), Some(5));
+        assert_eq!(mult_order(7, 71), Some(70));
+        assert_eq!(mult_order(14, 71), Some(10));
+        // g reduced modulo p
+        assert_eq!(mult_order(7 + 71, 71), Some(70));
+        // no order when g is not invertible or p is not prime
+        assert_eq!(mult_order(0, 7), None);
+        assert_eq!(mult_order(14, 7), None);
+        assert_eq!(mult_order(2, 15), None);
+        assert_eq!(mult_order(2, 1), None);
+    }
+
+    #[test]
+    fn algo_error_test() {
+        assert_eq!(try_gcd(12, 18), Ok(6));
+        assert_eq!(try_gcd(0, 18), Err(AlgoError::ZeroArgument));
+        assert_eq!(try_gcd(12, 0), Err(AlgoError::ZeroArgument));
+        assert_eq!(try_primitive_root(7), Ok(3));
+        assert_eq!(try_primitive_root(15), Err(AlgoError::NotPrime(15)));
+        assert_eq!(try_primitive_root(1), Err(AlgoError::NotPrime(1)));
+
+        // Each error describes itself
+        let messages = [
+            (AlgoError::Overflow, "modulus too large, overflow may occur"),
+            (AlgoError::InvariantViolation("element 7 not reduced modulo 5".to_string()), "element 7 not reduced modulo 5"),
+            (AlgoError::ZeroArgument, "argument must be nonzero"),
+            (AlgoError::NotPrime(15), "modulus 15 is not prime"),
+        ];
+        for (e, message) in messages {
+            assert_eq!(e.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn zero_arguments_are_not_a_panic() {
+        // gcd takes gcd(a, 0) = a, only try_gcd rejects a zero
+        assert_eq!(gcd(0, 5), 5);
+        assert_eq!(gcd(12, 0), 12);
+        assert_eq!(gcd(0, 0), 0);
+        assert_eq!(try_gcd(0, 5), Err(AlgoError::ZeroArgument));
+        assert_eq!(try_gcd(0, 0), Err(AlgoError::ZeroArgument));
+
+        // A zero base or a modulus below 2 is reported as not prime rather than panicking or looping
+        for n in [0, 1] {
+            assert!(miller_rabin(n, 2));
+            assert!(solovay_strassen(n, 2));
+        }
+        assert!(miller_rabin(7919, 0));
+        assert!(solovay_strassen(7919, 0));
+    }
+
+    #[test]
+    fn constructors_report_invalid_input() {
+        assert_eq!(PollardsLog::with_start(5011, 2, 5011, 1, 1), Err(AlgoError::InvariantViolation("element 5011 not reduced modulo 5011".to_string())));
+        assert_eq!(PollardsLog::with_start((1 << 32) + 1, 2, 3, 1, 1), Err(AlgoError::Overflow));
+        assert_eq!(PollardsRSAFact::with_max_steps(u64::MAX, 10), Err(AlgoError::Overflow));
+        assert_eq!(PollardsRSAFact::with_poly(1, 1, 1), Err(AlgoError::InvariantViolation("modulus 1 too small".to_string())));
+        assert_eq!(PollardsRSAFactBrent::with_max_steps(0, 10), Err(AlgoError::InvariantViolation("modulus 0 too small".to_string())));
+        assert_eq!(PollardsRSAFactBrent::with_poly(u64::MAX, 1, 1), Err(AlgoError::Overflow));
+        assert!(PollardsRSAFact::with_poly(143, 2, 1).is_ok());
+
+        // Splitting a prime breaks the invariant that only composites are passed to Pollard's rho
+        assert_eq!(rho_split(7), Err(AlgoError::InvariantViolation("7 is not split by any polynomial".to_string())));
+        assert_eq!(try_factorize(1782886219), Ok(vec![(7933, 1), (224743, 1)]));
+    }
+
+    #[test]
+    fn primitive_root_test() {
+        assert_eq!(primitive_root(7), Some(3));
+        assert_eq!(primitive_root(11), Some(2));
+        assert_eq!(primitive_root(23), Some(5));
+        assert_eq!(primitive_root(71), Some(7));
+        assert_eq!(primitive_root(2), Some(1));
+        assert_eq!(primitive_root(3), Some(2));
+        assert_eq!(primitive_root(2147483647), Some(7));
+        assert_eq!(mult_order(primitive_root(5011).unwrap(), 5011), Some(5010));
+        assert_eq!(primitive_root(1), None);
+        assert_eq!(primitive_root(15), None);
+        assert_eq!(primitive_root(1782886219), None);
+    }
+
+    #[test]
+    fn jacobi_test() {
+        for (a, n, j) in [
+            (1001, 9907, -1), (19, 45, 1), (8, 21, -1), (5, 21, 1), (30, 7, 1), (2, 15, 1),
+            (123456789, 1000000007, 1), (6, 9, 0), (0, 9, 0), (0, 1, 1), (1, 1, 1),
+        ] {
+            assert_eq!(jacobi(a, n), j, "({a} / {n})");
+        }
+        // a larger than n is reduced first
+        assert_eq!(jacobi(1001 + 9907, 9907), -1);
+        // n must be odd
+        assert_eq!(jacobi(3, 10), 0);
+        assert_eq!(jacobi(3, 0), 0);
+        // agrees with Euler's criterion modulo a prime
+        for a in 1..71 {
+            let euler = if fast_power(a, 35, 71) == 1 { 1 } else { -1 };
+            assert_eq!(jacobi(a, 71), euler);
+        }
+    }
+
+    #[test]
+    fn crt_test() {
+        assert_eq!(crt(&[(2, 3), (3, 5), (2, 7)]), Some((23, 105)));
+        assert_eq!(crt(&[(1, 4), (2, 9)]), Some((29, 36)));
+        // residues are reduced and moduli may share factors if the residues agree
+        assert_eq!(crt(&[(5, 3), (3, 5)]), Some((8, 15)));
+        assert_eq!(crt(&[(3, 4), (5, 6)]), Some((11, 12)));
+        assert_eq!(crt(&[(0, 4), (1, 6)]), None);
+        // large coprime moduli do not overflow
+        let (p, q) = (4294967291, 4294967279);
+        let x = 12345678901234567890 % (p * q);
+        assert_eq!(crt(&[(x % p, p), (x % q, q)]), Some((x, p * q)));
+        assert_eq!(crt(&[(p - 1, p), (q - 2, q)]).map(|(x, _)| (x % p, x % q)), Some((p - 1, q - 2)));
+        assert_eq!(crt(&[(0, 1 << 40), (1, (1 << 40) - 1)]), None);
+        assert_eq!(crt(&[]), Some((0, 1)));
+        assert_eq!(crt(&[(1, 0)]), None);
+    }
+
+    #[test]
+    fn factorize_test() {
+        assert_eq!(factorize(12), vec![(2, 2), (3, 1)]);
+        assert_eq!(factorize(1782886219), vec![(7933, 1), (224743, 1)]);
+        assert_eq!(factorize(7919), vec![(7919, 1)]);
+        assert_eq!(factorize(1), vec![]);
+        assert_eq!(factorize(0), vec![]);
+        // factors past the trial division limit, including repeated ones
+        assert_eq!(factorize(7919 * 7919 * 2), vec![(2, 1), (7919, 2)]);
+        assert_eq!(factorize(4294967291 * 4294967279), vec![(4294967279, 1), (4294967291, 1)]);
+        assert_eq!(factorize(u64::MAX), vec![(3, 1), (5, 1), (17, 1), (257, 1), (641, 1), (65537, 1), (6700417, 1)]);
+        assert_eq!(factorize(18446744073709551557), vec![(18446744073709551557, 1)]);
+    }
+
+    #[test]
+    fn sieve_test() {
+        assert_eq!(sieve(30), vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert_eq!(sieve(29), sieve(30));
+        assert_eq!(sieve(2), vec![2]);
+        assert_eq!(sieve(1), vec![]);
+        assert_eq!(sieve(0), vec![]);
+        assert_eq!(sieve(10_000).len(), 1229);
+        assert!(sieve(10_000).into_iter().all(is_prime));
+        assert_eq!(small_primes().len(), 6542);
+        assert_eq!(small_primes().last(), Some(&65521));
+    }
+
+    #[test]
+    fn performance_class_test() {
+        assert_eq!(Performance::from_ratio(0.0), Performance::Fast);
+        assert_eq!(Performance::from_ratio(0.4999), Performance::Fast);
+        assert_eq!(Performance::from_ratio(FAST_RATIO), Performance::Typical);
+        assert_eq!(Performance::from_ratio(1.0), Performance::Typical);
+        assert_eq!(Performance::from_ratio(SLOW_RATIO), Performance::Typical);
+        assert_eq!(Performance::from_ratio(2.0001), Performance::Slow);
+        assert_eq!(Performance::from_ratio(f64::INFINITY), Performance::Slow);
+        for performance in [Performance::Fast, Performance::Typical, Performance::Slow] {
+            assert_eq!(Performance::from_byte(performance.as_byte()), Some(performance));
+        }
+        assert_eq!(Performance::from_byte(3), None);
+
+        let mut pollards = PollardsLog::new(5011, 2, 2495);
+        Iterator::count(&mut pollards);
+        assert_eq!(pollards.performance_class(), Performance::from_ratio(pollards.steps_to_sqrt_mod_ratio()));
+        // A capped walk on a prime modulus takes 8 * n^(1/4) steps, far fewer than sqrt(n)
+        let mut pollards = PollardsRSAFact::new(1000000007);
+        Iterator::count(&mut pollards);
+        assert_eq!(pollards.performance_class(), Performance::Fast);
+    }
+
+    #[test]
+    fn log_item_channel_test() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (p, g, h) = (5011, 2, 2495);
+        let (items, log) = rt.block_on(async {
+            let (handle, mut receiver) = log_item_channel(p, g, h);
+            let mut items = vec![];
+            while let Some(item) = receiver.recv().await {
+                items.push(item);
+            }
+            (items, handle.await.unwrap())
+        });
+
+        // The channel yields the same walk as iterating directly, ending on the collision
+        assert_eq!(items, Iterator::collect::<Vec<_>>(PollardsLog::new(p, g, h)));
+        let last = items.last().unwrap();
+        assert_eq!(last.xi, last.yi);
+        assert!(items[..items.len() - 1].iter().all(|item| item.xi != item.yi));
+        assert_eq!(fast_power(g, log.unwrap().unwrap(), p), h);
+    }
+
+    #[test]
+    fn pollards_log_trace_test() {
+        let (p, g, h) = (48611, 19, 24717);
+        let (trace, log) = pollards_log_trace(p, g, h);
+        assert_eq!(fast_power(g, log.unwrap().unwrap(), p), h);
+        assert_eq!(trace, Iterator::collect::<Vec<_>>(PollardsLog::new(p, g, h)));
+        // The walk takes on the order of sqrt(p) steps, numbered from 1
+        let sqrt_p = f64::sqrt(p as f64);
+        println!("{} steps, sqrt(p) = {:.1}", trace.len(), sqrt_p);
+        assert!((trace.len() as f64) > 0.1 * sqrt_p && (trace.len() as f64) < 4.0 * sqrt_p);
+        assert!(trace.iter().enumerate().all(|(i, item)| item.i == i + 1));
+        // The walk ends at the step where the tortoise and hare meet
+        let mut pollards = PollardsLog::new(p, g, h);
+        assert_eq!(pollards.collision(), None);
+        for _ in &mut pollards {}
+        let last = trace.last().unwrap();
+        assert_eq!(pollards.collision(), Some((last.i as u64, last.xi)));
+        assert_eq!(last.xi, last.yi);
+
+        // g = p - 1 has order 2 so there is no log to find, the trace still ends within the step cap
+        let (p, g, h) = (5011, 5010, 2);
+        let (trace, log) = pollards_log_trace(p, g, h);
+        assert!(trace.len() <= 4 * 71);
+        assert_eq!(log, Ok(None));
+    }
+
+    #[test]
+    fn composite_witness_test() {
+        // 561 is a Carmichael number, but not a strong pseudoprime to base 2
+        assert_eq!(composite_witness(561), Some(2));
+        // 2047 is a strong pseudoprime to base 2
+        assert_eq!(composite_witness(2047), Some(3));
+        assert_eq!(composite_witness(15), Some(2));
+        assert_eq!(composite_witness(4), Some(2));
+        assert_eq!(composite_witness(7919), None);
+        assert_eq!(composite_witness(2), None);
+        assert_eq!(composite_witness(1), None);
+    }
+
+    #[test]
+    fn solovay_strassen_test() {
+        // Primes are never reported composite
+        for n in [3, 5, 7919, 15239131, 1000000007] {
+            for a in 2..100.min(n) {
+                assert!(!solovay_strassen(n, a), "{a} falsely witnesses {n}");
+            }
+        }
+        // Every strong liar is an Euler liar, so Solovay-Strassen only finds witnesses Miller-Rabin also finds
+        for n in [9, 15, 91, 561, 1105, 1729, 2465, 2821, 6601, 2047, 1782886219] {
+            for a in 2..200.min(n) {
+                if solovay_strassen(n, a) {
+                    assert!(miller_rabin(n, a), "{a} witnesses {n} for Solovay-Strassen only");
+                }
+            }
+            assert!((2..200.min(n)).any(|a| solovay_strassen(n, a)), "no witness found for {n}");
+        }
+        // 1729 is an Euler pseudoprime to base 2, but not a strong one
+        assert!(!solovay_strassen(1729, 2));
+        assert!(miller_rabin(1729, 2));
+    }
+}
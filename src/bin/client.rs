@@ -1,13 +1,23 @@
-use std::io::{self, stdin, Read};
+use std::io::{self, stdin, stdout, BufReader, Read};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
 use std::fmt;
 use std::net::SocketAddr;
-use tokio::net::{TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+use clap::Parser;
+use tokio::net::{self as tokio_net, TcpStream};
 // use tokio::task;
 use tokio::runtime;
+use tokio::time;
 use tokio::io as tokio_io;
-use tokio_io::{AsyncReadExt, AsyncWriteExt};
-use tracing::instrument;
-use crate::interface::Interface;
+use tokio_io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_rustls::rustls::{self, pki_types::ServerName};
+use tokio_rustls::TlsConnector;
+use tracing::{info, instrument, warn};
+use tracing_subscriber::EnvFilter;
+use discrete_log_server::{AuthKey, Response};
+use crate::interface::{CsvWriter, Interface, Theme, ThemeName, DEFAULT_MAX_ROWS};
 
 mod interface;
 
@@ -17,9 +27,33 @@ pub enum ClientError {
     Write(io::Error),
     Read(io::Error),
     SendRequest(tokio_io::Error),
-    IllegalResponse,
-    InterfaceState,
+    IllegalResponse(Response),
+    InterfaceState(Interface),
     Connection(io::Error),
+    ServerBusy,
+    AuthFailed,
+}
+
+impl ClientError {
+    /// Whether the error was caused by losing the connection to the server, in which case reconnecting may help.
+    fn is_disconnect(&self) -> bool {
+        match self {
+            ClientError::Connection(_) | ClientError::SendRequest(_) => true,
+            ClientError::Response(e) => matches!(
+                e.kind(),
+                io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe
+            ),
+            _ => false,
+        }
+    }
+
+    /// Reports a request answered with `AuthFailed` as a failed handshake, rather than as an unexpected response.
+    fn or_auth_failed(self) -> Self {
+        match self {
+            ClientError::IllegalResponse(Response::AuthFailed { .. }) => ClientError::AuthFailed,
+            e => e,
+        }
+    }
 }
 
 impl fmt::Display for ClientError {
@@ -29,37 +63,212 @@ impl fmt::Display for ClientError {
             ClientError::Write(e) => write!(f, "{e}"),
             ClientError::Read(e) => write!(f, "{e}"),
             ClientError::SendRequest(e) => write!(f, "{e}"),
-            ClientError::IllegalResponse => write!(f, "illegal response received from server"),
-            ClientError::InterfaceState => write!(f, "interface entered illegal state"),
+            ClientError::IllegalResponse(response) => write!(f, "illegal response received from server: {response:?}"),
+            ClientError::InterfaceState(interface) => write!(f, "interface entered illegal state: {interface:?}"),
             ClientError::Connection(e) => write!(f, "{e}"),
+            ClientError::ServerBusy => write!(f, "server has too many connected clients, try again later"),
+            ClientError::AuthFailed => write!(f, "server rejected the shared key, check the key given with --auth-key"),
+        }
+    }
+}
+
+/// The address the client connects to when none is given.
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// The number of times the client tries to reach the server before giving up.
+const MAX_CONNECT_ATTEMPTS: u32 = 5;
+
+/// How long the client waits after its first failed attempt to reach the server, doubled after every attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Exponential backoff between attempts to reach the server.
+#[derive(Debug, Clone, Copy)]
+struct Backoff {
+    initial: Duration,
+    max_attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff { initial: INITIAL_BACKOFF, max_attempts: MAX_CONNECT_ATTEMPTS }
+    }
+}
+
+impl Backoff {
+    /// The delay after the failed attempt `attempt`, counting from 0.
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial * 2u32.saturating_pow(attempt)
+    }
+}
+
+/// How the client wraps its connection to the server in TLS.
+#[derive(Clone)]
+struct Tls {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl Tls {
+    /// Trusts the PEM encoded certificates in the file at `ca` to verify the server at `addr`, given as `host:port`.
+    ///
+    /// # Returns
+    /// `Result<Tls, ClientError>`, a `ClientError::Connection` if the certificates cannot be read or the host is not a
+    /// valid server name.
+    fn load(ca: &Path, addr: &str) -> Result<Self, ClientError> {
+        let invalid = |msg: String| ClientError::Connection(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        let file = File::open(ca)
+            .map_err(|e| ClientError::Connection(io::Error::new(e.kind(), format!("unable to open certificate file {}: {e}", ca.display()))))?;
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+            let cert = cert.map_err(|e| invalid(format!("unable to read certificate file {}: {e}", ca.display())))?;
+            roots.add(cert).map_err(|e| invalid(format!("invalid certificate in {}: {e}", ca.display())))?;
         }
+        if roots.is_empty() {
+            return Err(invalid(format!("no certificates found in {}", ca.display())));
+        }
+        let host = host(addr);
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| invalid(format!("invalid server name {host}: {e}")))?;
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Tls { connector: TlsConnector::from(Arc::new(config)), server_name })
     }
 }
 
+/// The read and write halves of a connection to the server, over TLS or not.
+type Halves = (Box<dyn AsyncRead + Send + Unpin>, Box<dyn AsyncWrite + Send + Unpin>);
+
 /// A struct for connecting to the server
 struct Client;
 
 impl Client {
 
-    /// Connects to the server at the address given by `addr`.
-    #[instrument(ret, err)]
-    async fn connect(addr: SocketAddr) -> Result<(), ClientError> {
-        // create interface
-        let mut interface = Interface::new();
-
+    /// Connects to the server at the first of the addresses in `addrs` that accepts the connection.
+    ///
+    /// If the connection is lost the client reconnects, resuming its session on the server and starting over from the
+    /// home screen. The steps of Pollard's
+    /// rho computations are appended to the CSV file at `out`, if given, and at most `max_rows` steps of each
+    /// computation are kept for scrolling back through.
+    ///
+    /// The connection is wrapped in TLS if `tls` is given, and the server's challenge answered with `auth_key` if given.
+    /// The interface is drawn in the colors of `theme`.
+    #[instrument(ret, err, skip(tls, auth_key))]
+    async fn connect(addrs: Vec<SocketAddr>, tls: Option<Tls>, auth_key: Option<AuthKey>, out: Option<PathBuf>, max_rows: usize, theme: Theme) -> Result<(), ClientError> {
         // handle to standard input
         let mut stdin = stdin();
 
-        // connect to server
-        let server_socket = TcpStream::connect(addr)
+        let mut csv = match out {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| ClientError::Write(io::Error::new(e.kind(), format!("unable to open output file {}: {e}", path.display()))))?;
+                Some(CsvWriter::new(file))
+            }
+            None => None,
+        };
+
+        // The session held over the last connection, resumed on reconnecting
+        let mut session = None;
+        loop {
+            let server_socket = Client::connect_with_backoff(&addrs, Backoff::default(), |attempt, max_attempts| {
+                let _ = Interface::display_reconnecting(attempt, max_attempts, theme);
+            }).await?;
+            let (mut from_server, mut to_server) = Client::split(server_socket, tls.as_ref()).await?;
+
+            match Client::run(&mut from_server, &mut to_server, &mut stdin, auth_key.as_ref(), &mut session, csv.as_mut(), max_rows, theme).await.map_err(ClientError::or_auth_failed) {
+                Err(e) if e.is_disconnect() => {
+                    warn!(error = %e, "lost connection to server, reconnecting");
+                    let _ = Interface::display_reconnecting(1, MAX_CONNECT_ATTEMPTS, theme);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Connects to the server at one of `addrs` and runs the queries in the file at `path` without the terminal interface,
+    /// printing one result line per query to standard output, as JSON if `json` is set.
+    #[instrument(ret, err, skip(tls, auth_key))]
+    async fn batch(addrs: Vec<SocketAddr>, tls: Option<Tls>, auth_key: Option<AuthKey>, path: PathBuf, json: bool) -> Result<(), ClientError> {
+        let input = File::open(&path)
+            .map_err(|e| ClientError::Read(io::Error::new(e.kind(), format!("unable to open batch file {}: {e}", path.display()))))?;
+        let server_socket = Client::connect_with_backoff(&addrs, Backoff::default(), |_, _| {}).await?;
+        let (from_server, to_server) = Client::split(server_socket, tls.as_ref()).await?;
+        interface::batch::run_batch(BufReader::new(input), from_server, to_server, stdout().lock(), json, auth_key.as_ref())
             .await
-            .map_err(|e| ClientError::Connection(e))?;
-        let (mut from_server, mut to_server) = server_socket.into_split();
+            .map_err(ClientError::or_auth_failed)
+    }
+
+    /// Tries to connect to the server at `addrs`, waiting according to `backoff` between failed attempts.
+    ///
+    /// Every attempt tries each address in turn, in the order they were resolved, so a dual-stack host is reached
+    /// over whichever of IPv6 and IPv4 accepts the connection first.
+    ///
+    /// `on_retry` is called with the number of the next attempt and the maximum number of attempts before waiting.
+    ///
+    /// # Returns
+    /// `Result<TcpStream, ClientError>`, the connected socket, or `ClientError::Connection` once every attempt failed.
+    #[instrument(ret, err, skip(on_retry))]
+    async fn connect_with_backoff(addrs: &[SocketAddr], backoff: Backoff, mut on_retry: impl FnMut(u32, u32)) -> Result<TcpStream, ClientError> {
+        let mut attempt = 0;
+        loop {
+            match TcpStream::connect(addrs).await {
+                Ok(socket) => return Ok(socket),
+                Err(e) if attempt + 1 < backoff.max_attempts => {
+                    let delay = backoff.delay(attempt);
+                    warn!(error = %e, attempt, ?delay, "unable to connect to server, retrying");
+                    attempt += 1;
+                    on_retry(attempt + 1, backoff.max_attempts);
+                    time::sleep(delay).await;
+                }
+                Err(e) => return Err(ClientError::Connection(e)),
+            }
+        }
+    }
+
+    /// Splits `socket` into its read and write halves, after completing the TLS handshake over it if `tls` is given.
+    ///
+    /// # Returns
+    /// `Result<Halves, ClientError>`, a `ClientError::Connection` if the handshake fails, e.g. because the server's
+    /// certificate is not trusted.
+    async fn split(socket: TcpStream, tls: Option<&Tls>) -> Result<Halves, ClientError> {
+        match tls {
+            Some(tls) => {
+                let stream = tls.connector.connect(tls.server_name.clone(), socket)
+                    .await
+                    .map_err(|e| ClientError::Connection(io::Error::new(e.kind(), format!("TLS handshake with server failed: {e}"))))?;
+                let (from_server, to_server) = tokio_io::split(stream);
+                Ok((Box::new(from_server), Box::new(to_server)))
+            }
+            None => {
+                let (from_server, to_server) = socket.into_split();
+                Ok((Box::new(from_server), Box::new(to_server)))
+            }
+        }
+    }
+
+    /// Runs the interface over an established connection until the user quits or an error occurs.
+    ///
+    /// The connection takes over the session `session` of an earlier connection, if any, which is then updated to the
+    /// session held over this one.
+    async fn run<R, W, C>(from_server: &mut R, to_server: &mut W, stdin: &mut C, auth_key: Option<&AuthKey>, session: &mut Option<u128>, mut csv: Option<&mut CsvWriter<File>>, max_rows: usize, theme: Theme) -> Result<(), ClientError>
+    where
+        R: AsyncReadExt + Unpin,
+        W: AsyncWriteExt + Unpin,
+        C: Read,
+    {
+        *session = Some(interface::handshake(&mut *from_server, &mut *to_server, auth_key, *session).await?);
+        info!("successfully connected to server");
+        // every connection starts from the `Home` state, which displays the home screen
+        let mut interface = Interface::Home;
+        let mut last_query = None;
 
         // main loop for the ui
         loop {
-            interface = interface.receive_response(&mut from_server).await?;
-            interface = match interface.parse_request(&mut to_server, &mut stdin).await {
+            interface = interface.receive_response(&mut *from_server, &mut *to_server, csv.as_deref_mut(), max_rows, theme).await?;
+            interface = match interface.parse_request(&mut *to_server, &mut *stdin, theme, &mut last_query).await {
                 Ok(Interface::Quit) => {
                     // TODO: log exiting application
                     break;
@@ -73,13 +282,325 @@ impl Client {
     }
 }
 
+/// The host part of `addr`, given as `host:port`, without the brackets around an IPv6 address.
+fn host(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host)
+}
+
+/// Resolves `addr`, given as `host:port`, to every socket address it refers to.
+///
+/// The host may be an IPv4 address, an IPv6 address in brackets such as `[::1]`, or a host name.
+///
+/// # Returns
+/// `Result<Vec<SocketAddr>, ClientError>`, a `ClientError::Connection` if `addr` is malformed or cannot be resolved.
+#[instrument(ret, err)]
+async fn resolve(addr: &str) -> Result<Vec<SocketAddr>, ClientError> {
+    let addrs: Vec<SocketAddr> = tokio_net::lookup_host(addr)
+        .await
+        .map_err(|e| ClientError::Connection(io::Error::new(e.kind(), format!("unable to resolve server address {addr}: {e}"))))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(ClientError::Connection(io::Error::new(io::ErrorKind::NotFound, format!("no addresses found for server address {addr}"))));
+    }
+    Ok(addrs)
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// The address of the server, as `host:port`
+    #[arg(value_name = "ADDR", conflicts_with = "addr")]
+    target: Option<String>,
+
+    /// The address of the server, as `host:port`
+    #[arg(short, long)]
+    addr: Option<String>,
+
+    /// Run the queries in FILE, one per line such as `log 2 2495 5011`, and print the results instead of starting
+    /// the interface
+    #[arg(long, value_name = "FILE")]
+    batch: Option<PathBuf>,
+
+    /// Print the results of `--batch` as JSON lines rather than tab separated fields
+    #[arg(long, requires = "batch")]
+    json: bool,
+
+    /// Append the steps of every discrete log and RSA computation to FILE as CSV
+    #[arg(short, long, value_name = "FILE")]
+    out: Option<PathBuf>,
+
+    /// The number of steps of a discrete log or RSA computation kept for scrolling back through
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_ROWS)]
+    max_rows: usize,
+
+    /// Connect over TLS, verifying the server against the PEM encoded certificates in FILE, such as the server's own
+    /// self-signed certificate
+    #[arg(long, value_name = "FILE")]
+    tls: Option<PathBuf>,
+
+    /// Answer the server's challenge with the shared key KEY, for a server started with `--auth-key`
+    #[arg(long, value_name = "KEY")]
+    auth_key: Option<String>,
+
+    /// Log the bytes of every frame sent and response received to stderr in hex, redirect stderr to keep them off
+    /// the interface, e.g. `client --verbose 2> wire.log`
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// The colors the interface is drawn in, `mono` for terminals without true color support
+    #[arg(long, value_enum, value_name = "NAME", default_value_t = ThemeName::Default)]
+    theme: ThemeName,
+}
+
+impl Cli {
+    /// The server address given either positionally or with `--addr`, defaulting to `DEFAULT_ADDR`.
+    fn server_addr(&self) -> &str {
+        self.addr.as_deref()
+            .or(self.target.as_deref())
+            .unwrap_or(DEFAULT_ADDR)
+    }
+}
+
 fn main() {
-    let addr = ([127, 0, 0, 1], 8080).into();
+    // Logs go to stderr so they don't corrupt the interface drawn on stdout, redirect it to keep them,
+    // e.g. `RUST_LOG=debug client 2> client.log`
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .with_ansi(false)
+        .init();
+
+    let cli = Cli::parse();
+    interface::set_verbose(cli.verbose);
     let mut rt = runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .expect("unable to build runtime");
-    if let Err(e) = rt.block_on(Client::connect(addr)) {
+    let res = rt.block_on(async {
+        let addrs = resolve(cli.server_addr()).await?;
+        let tls = cli.tls.as_deref().map(|ca| Tls::load(ca, cli.server_addr())).transpose()?;
+        let auth_key = cli.auth_key.as_deref().map(AuthKey::new);
+        match cli.batch {
+            Some(path) => Client::batch(addrs, tls, auth_key, path, cli.json).await,
+            None => Client::connect(addrs, tls, auth_key, cli.out, cli.max_rows, cli.theme.into()).await,
+        }
+    });
+    if let Err(e) = res {
         eprintln!("{e}");
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use discrete_log_server::{AsBytes, Frame, Response, AUTH_LEN};
+
+    #[test]
+    fn cli_server_addr() {
+        assert_eq!(Cli::try_parse_from(["client"]).unwrap().server_addr(), DEFAULT_ADDR);
+        assert_eq!(Cli::try_parse_from(["client", "--addr", "example.com:9000"]).unwrap().server_addr(), "example.com:9000");
+        assert_eq!(Cli::try_parse_from(["client", "10.0.0.2:8080"]).unwrap().server_addr(), "10.0.0.2:8080");
+        assert!(Cli::try_parse_from(["client", "10.0.0.2:8080", "--addr", "10.0.0.3:8080"]).is_err());
+
+        let cli = Cli::try_parse_from(["client", "--batch", "queries.txt"]).unwrap();
+        assert_eq!(cli.batch, Some(PathBuf::from("queries.txt")));
+        assert_eq!(cli.out, None);
+        assert_eq!(cli.max_rows, DEFAULT_MAX_ROWS);
+        assert!(!cli.json);
+        assert!(Cli::try_parse_from(["client", "--batch", "queries.txt", "--json"]).unwrap().json);
+        assert!(Cli::try_parse_from(["client", "--json"]).is_err());
+
+        let cli = Cli::try_parse_from(["client", "--out", "steps.csv"]).unwrap();
+        assert_eq!(cli.out, Some(PathBuf::from("steps.csv")));
+
+        let cli = Cli::try_parse_from(["client", "--max-rows", "500"]).unwrap();
+        assert_eq!(cli.max_rows, 500);
+        assert_eq!(cli.server_addr(), DEFAULT_ADDR);
+        assert_eq!(cli.tls, None);
+
+        let cli = Cli::try_parse_from(["client", "--tls", "server.pem"]).unwrap();
+        assert_eq!(cli.tls, Some(PathBuf::from("server.pem")));
+        assert_eq!(cli.auth_key, None);
+
+        let cli = Cli::try_parse_from(["client", "--auth-key", "shared secret"]).unwrap();
+        assert_eq!(cli.auth_key.as_deref(), Some("shared secret"));
+        assert!(!cli.verbose);
+        assert!(Cli::try_parse_from(["client", "--verbose"]).unwrap().verbose);
+        assert_eq!(cli.theme, ThemeName::Default);
+
+        let cli = Cli::try_parse_from(["client", "--theme", "highcontrast"]).unwrap();
+        assert_eq!(Theme::from(cli.theme), Theme::HIGH_CONTRAST);
+        assert!(Cli::try_parse_from(["client", "--theme", "neon"]).is_err());
+    }
+
+    #[test]
+    fn host_strips_port_and_brackets() {
+        assert_eq!(host("example.com:9000"), "example.com");
+        assert_eq!(host("127.0.0.1:8080"), "127.0.0.1");
+        assert_eq!(host("[::1]:9000"), "::1");
+        assert_eq!(host("localhost"), "localhost");
+    }
+
+    #[test]
+    fn client_connects_over_tls() {
+        let key = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca = std::env::temp_dir().join(format!("client_tls_ca_{}.pem", std::process::id()));
+        std::fs::write(&ca, key.cert.pem()).unwrap();
+        let tls = Tls::load(&ca, "localhost:9000");
+        assert!(matches!(Tls::load(&ca, "not a host:9000"), Err(ClientError::Connection(_))));
+        std::fs::remove_file(&ca).unwrap();
+        let tls = tls.unwrap();
+        assert!(matches!(Tls::load(&ca, "localhost:9000"), Err(ClientError::Connection(_))));
+
+        let cert = key.cert.der().clone();
+        let private_key = rustls::pki_types::PrivateKeyDer::Pkcs8(key.key_pair.serialize_der().into());
+        let config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], private_key)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let listener = tokio_net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = tokio::spawn(async move {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut stream = acceptor.accept(socket).await.unwrap();
+                stream.write_all(&Response::ConnectionOk { request_id: 0, version: 2, session: 7, challenge: [0; AUTH_LEN] }.as_bytes()).await.unwrap();
+                stream.flush().await.unwrap();
+                let mut quit = [0u8; 1];
+                stream.read_exact(&mut quit).await.unwrap();
+                quit[0]
+            });
+
+            let socket = TcpStream::connect(addr).await.unwrap();
+            let (mut from_server, mut to_server) = Client::split(socket, Some(&tls)).await.unwrap();
+            let res = Response::from_reader(&mut from_server).await.unwrap();
+            assert!(matches!(res, Response::ConnectionOk { session: 7, .. }));
+            to_server.write_all(&[4]).await.unwrap();
+            assert_eq!(server.await.unwrap(), 4);
+        });
+    }
+
+    #[test]
+    fn backoff_doubles_delay() {
+        let backoff = Backoff { initial: Duration::from_millis(100), max_attempts: 5 };
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn client_recovers_after_server_restart() {
+        let rt = runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let listener = tokio_net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let backoff = Backoff { initial: Duration::from_millis(50), max_attempts: 5 };
+
+            // The mock server greets the client, then dies along with the connection
+            let server = tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                socket.write_all(&Response::ConnectionOk { request_id: 0, version: 2, session: 0, challenge: [0; AUTH_LEN] }.as_bytes()).await.unwrap();
+            });
+            let mut socket = Client::connect_with_backoff(&[addr], backoff, |_, _| {}).await.unwrap();
+            assert!(Response::from_reader(&mut socket).await.unwrap().is_connection_ok());
+            server.await.unwrap();
+            let err = Response::from_reader(&mut socket).await.map_err(ClientError::Response).unwrap_err();
+            assert!(err.is_disconnect());
+
+            // The server comes back after the client has started retrying
+            let restarted = tokio::spawn(async move {
+                time::sleep(Duration::from_millis(120)).await;
+                let listener = tokio_net::TcpListener::bind(addr).await.unwrap();
+                let (mut socket, _) = listener.accept().await.unwrap();
+                socket.write_all(&Response::ConnectionOk { request_id: 0, version: 2, session: 0, challenge: [0; AUTH_LEN] }.as_bytes()).await.unwrap();
+            });
+            let mut retries = 0;
+            let mut socket = Client::connect_with_backoff(&[addr], backoff, |_, _| retries += 1).await.unwrap();
+            assert!(retries > 0);
+            assert!(Response::from_reader(&mut socket).await.unwrap().is_connection_ok());
+            restarted.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn client_resumes_its_session_after_reconnecting() {
+        let rt = runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let listener = tokio_net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let backoff = Backoff { initial: Duration::from_millis(10), max_attempts: 5 };
+
+            // The mock server opens session 7, then drops the connection. The client reconnects and is greeted with
+            // a fresh session 8, which it trades for session 7 before sending anything else
+            let server = tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                socket.write_all(&Response::ConnectionOk { request_id: 0, version: 2, session: 7, challenge: [0; AUTH_LEN] }.as_bytes()).await.unwrap();
+                drop(socket);
+
+                let (mut socket, _) = listener.accept().await.unwrap();
+                socket.write_all(&Response::ConnectionOk { request_id: 0, version: 2, session: 8, challenge: [0; AUTH_LEN] }.as_bytes()).await.unwrap();
+                let Frame::Resume { request_id, session } = Frame::from_reader(&mut socket).await.unwrap() else {
+                    panic!("expected `Frame::Resume`");
+                };
+                socket.write_all(&Response::ConnectionOk { request_id, version: 2, session, challenge: [0; AUTH_LEN] }.as_bytes()).await.unwrap();
+                session
+            });
+
+            let mut session = None;
+            let socket = Client::connect_with_backoff(&[addr], backoff, |_, _| {}).await.unwrap();
+            let (mut from_server, mut to_server) = Client::split(socket, None).await.unwrap();
+            session = Some(interface::handshake(&mut from_server, &mut to_server, None, session).await.unwrap());
+            assert_eq!(session, Some(7));
+            let err = Response::from_reader(&mut from_server).await.map_err(ClientError::Response).unwrap_err();
+            assert!(err.is_disconnect());
+
+            let socket = Client::connect_with_backoff(&[addr], backoff, |_, _| {}).await.unwrap();
+            let (mut from_server, mut to_server) = Client::split(socket, None).await.unwrap();
+            session = Some(interface::handshake(&mut from_server, &mut to_server, None, session).await.unwrap());
+            assert_eq!(session, Some(7));
+            assert_eq!(server.await.unwrap(), 7);
+        });
+    }
+
+    #[test]
+    fn client_gives_up_after_max_attempts() {
+        let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            // Bind and drop a listener to find a port nothing listens on
+            let addr = tokio_net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+            let backoff = Backoff { initial: Duration::from_millis(1), max_attempts: 3 };
+            let mut attempts = vec![];
+            let res = Client::connect_with_backoff(&[addr], backoff, |attempt, max| attempts.push((attempt, max))).await;
+            assert!(matches!(res, Err(ClientError::Connection(_))));
+            assert_eq!(attempts, vec![(2, 3), (3, 3)]);
+        });
+    }
+
+    #[test]
+    fn client_tries_each_resolved_address() {
+        let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let dead = tokio_net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+            let listener = tokio_net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let live = listener.local_addr().unwrap();
+            let backoff = Backoff { initial: Duration::from_millis(1), max_attempts: 1 };
+            let socket = Client::connect_with_backoff(&[dead, live], backoff, |_, _| {}).await.unwrap();
+            assert_eq!(socket.peer_addr().unwrap(), live);
+        });
+    }
+
+    #[test]
+    fn resolve_invalid_address_should_error() {
+        let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        assert_eq!(rt.block_on(resolve("127.0.0.1:8080")).unwrap(), vec![SocketAddr::from(([127, 0, 0, 1], 8080))]);
+        assert_eq!(rt.block_on(resolve("[::1]:9000")).unwrap(), vec!["[::1]:9000".parse::<SocketAddr>().unwrap()]);
+        let addrs = rt.block_on(resolve("localhost:9000")).unwrap();
+        assert!(!addrs.is_empty());
+        assert!(addrs.iter().all(|addr| addr.port() == 9000 && addr.ip().is_loopback()));
+        for addr in ["not an address", "127.0.0.1", "127.0.0.1:port", ""] {
+            assert!(matches!(rt.block_on(resolve(addr)), Err(ClientError::Connection(_))), "{addr}");
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Non-interactive batch mode, running queries read from a file without the terminal interface.
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, warn};
+
+use discrete_log_server::{AuthKey, Response};
+use super::{utils, ClientError, Query};
+
+/// Runs every query in `input`, one per line, writing one result line per query to `out`.
+///
+/// Blank lines and lines starting with `#` are skipped. Each result line is the query followed by tab separated
+/// fields, the first of which is the outcome:
+///
+/// - `prime <probability> <rounds>` or `composite <witness>`, a base proving the number composite
+/// - `solved <log>`, `unsolved` or `no-log <order>`, for a log that does not exist since `h` is not in the subgroup
+///   of order `order` generated by `g`
+/// - `factored <p> <q> <d>`, with `d` the recovered private exponent, or `unfactored`
+/// - `invalid-exponent <e>`, for a factored key whose public exponent has no private exponent
+/// - `stats <clients> <logs> <rsas> <primes> <iterations>`
+/// - `root <g>`, a primitive root of the prime in the query
+/// - `error <message>`, for a query that could not be parsed or that the server refused
+///
+/// With `json` set each result is instead written as a JSON object on its own line, holding the query's
+/// parameters and the outcome's fields, see `BatchResult`.
+///
+/// The server's challenge is answered with `auth_key` before the first query, if given.
+///
+/// # Returns
+/// `Result<(), ClientError>`, an error if the connection to the server fails or the server misbehaves. Errors on a
+/// single line are reported in `out` and do not stop the batch.
+pub async fn run_batch<I, R, W, O>(input: I, mut from_server: R, mut to_server: W, mut out: O, json: bool, auth_key: Option<&AuthKey>) -> Result<(), ClientError>
+where
+    I: BufRead,
+    R: AsyncReadExt + Unpin,
+    W: AsyncWriteExt + Unpin,
+    O: Write,
+{
+    utils::handshake(&mut from_server, &mut to_server, auth_key, None).await?;
+
+    for line in input.lines() {
+        let line = line.map_err(|e| ClientError::Read(e))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let query = match Query::from_str(line) {
+            Ok(query) => query,
+            Err(e) => {
+                warn!(line, error = %e, "skipping invalid query");
+                let result = BatchResult { line: Some(line), query: None, outcome: Outcome::Error { message: e } };
+                write_result(&mut out, line, &result, json)?;
+                continue;
+            }
+        };
+        debug!(?query, "sending batch query");
+        let request_id = query.send(&mut to_server).await?;
+
+        // Only the final response of a computation is reported, the streamed steps and collision are skipped, as is
+        // a factored key's `SuccessfulRSA` since its `RSAKey` follows
+        let response = loop {
+            let response = Response::from_reader(&mut from_server)
+                .await
+                .map_err(|e| ClientError::Response(e))
+                .inspect(utils::trace_response)?;
+            if !response.is_step() && !response.is_collision() && !response.is_successful_rsa() && response.request_id() == request_id {
+                break response;
+            }
+        };
+        let result = BatchResult { line: None, query: Some(query), outcome: Outcome::try_from(response)? };
+        write_result(&mut out, line, &result, json)?;
+    }
+
+    Ok(())
+}
+
+/// The result of one line of a batch, serialized as a JSON object with the fields of the query and its outcome,
+/// e.g. `{"query":"log","g":2,"h":2495,"p":5011,"outcome":"solved","log":1234,"iterations":9}`.
+///
+/// A line that is not a valid query keeps its text in `line` instead.
+#[derive(Debug, Serialize)]
+pub struct BatchResult<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<&'a str>,
+    #[serde(flatten)]
+    pub query: Option<Query>,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// The outcome of a query, from the final response the server sent for it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum Outcome {
+    Prime { prob: f32, rounds: u32 },
+    Composite { witness: u64 },
+    Solved { log: u64, iterations: u64 },
+    Unsolved,
+    NoLog { order: u64 },
+    Factored { p: u64, q: u64, d: u64 },
+    InvalidExponent { e: u64 },
+    Unfactored,
+    Stats { clients: u64, logs: u64, rsas: u64, primes: u64, iterations: u64 },
+    Root { g: u64 },
+    Error { message: String },
+}
+
+impl TryFrom<Response> for Outcome {
+    type Error = ClientError;
+
+    fn try_from(response: Response) -> Result<Self, ClientError> {
+        let outcome = match response {
+            Response::Prime { prob, rounds, .. } => Outcome::Prime { prob, rounds },
+            Response::NotPrime { witness, .. } => Outcome::Composite { witness },
+            Response::SuccessfulLog { log, iterations, .. } => Outcome::Solved { log, iterations },
+            Response::UnsuccessfulLog { .. } => Outcome::Unsolved,
+            Response::LogDoesNotExist { order, .. } => Outcome::NoLog { order },
+            Response::RSAKey { p, q, d, .. } => Outcome::Factored { p, q, d },
+            Response::InvalidExponent { e, .. } => Outcome::InvalidExponent { e },
+            Response::UnsuccessfulRSA { .. } => Outcome::Unfactored,
+            Response::Stats { clients, logs, rsas, primes, iterations, .. } => Outcome::Stats { clients, logs, rsas, primes, iterations },
+            Response::Timeout { millis, .. } => Outcome::Error { message: format!("timed out after {millis} ms") },
+            Response::InputTooLarge { value, .. } => Outcome::Error { message: format!("input {value} is too large") },
+            Response::InvalidParameters { reason, .. } => Outcome::Error { message: reason.to_string() },
+            Response::RateLimited { .. } => Outcome::Error { message: "rate limited".to_string() },
+            Response::Cancelled { .. } => Outcome::Error { message: "cancelled".to_string() },
+            Response::Error { message, .. } => Outcome::Error { message },
+            Response::PrimitiveRoot { g, .. } => Outcome::Root { g },
+            response => return Err(ClientError::IllegalResponse(response)),
+        };
+        Ok(outcome)
+    }
+}
+
+/// Formats the outcome as the tab separated fields of a result line.
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Prime { prob, rounds } => write!(f, "prime\t{prob}\t{rounds}"),
+            Outcome::Composite { witness } => write!(f, "composite\t{witness}"),
+            Outcome::Solved { log, .. } => write!(f, "solved\t{log}"),
+            Outcome::Unsolved => write!(f, "unsolved"),
+            Outcome::NoLog { order } => write!(f, "no-log\t{order}"),
+            Outcome::Factored { p, q, d } => write!(f, "factored\t{p}\t{q}\t{d}"),
+            Outcome::InvalidExponent { e } => write!(f, "invalid-exponent\t{e}"),
+            Outcome::Unfactored => write!(f, "unfactored"),
+            Outcome::Stats { clients, logs, rsas, primes, iterations } => write!(f, "stats\t{clients}\t{logs}\t{rsas}\t{primes}\t{iterations}"),
+            Outcome::Root { g } => write!(f, "root\t{g}"),
+            Outcome::Error { message } => write!(f, "error\t{message}"),
+        }
+    }
+}
+
+/// Writes `result` to `out` as a JSON line if `json` is set, otherwise as the query's `line` followed by the outcome.
+fn write_result<O: Write>(mut out: O, line: &str, result: &BatchResult, json: bool) -> Result<(), ClientError> {
+    if json {
+        let object = serde_json::to_string(result)
+            .map_err(|e| ClientError::Write(e.into()))?;
+        writeln!(out, "{object}")
+    } else {
+        writeln!(out, "{line}\t{}", result.outcome)
+    }.map_err(|e| ClientError::Write(e))?;
+    out.flush().map_err(|e| ClientError::Write(e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+    use discrete_log_server::{AsBytes, Frame};
+    use discrete_log_server::algo::{Performance, PollardsLogItem};
+
+    #[test]
+    fn run_batch_test() {
+        let path = std::env::temp_dir().join(format!("batch_{}.txt", std::process::id()));
+        std::fs::write(&path, "# queries\nlog 2 2495 5011\n\nrsa 1782865951\nrsa 3233 3\nprime 15239131\nfactor 15\nprime 15\nroot 71\nlog 5 2 71\nlog 2 5\n").unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (client, server) = tokio::io::duplex(4096);
+        let (from_server, to_server) = tokio::io::split(client);
+        let (mut server_reader, mut server_writer) = tokio::io::split(server);
+
+        let output = rt.block_on(async {
+            // A mock server answering every query with a canned response
+            let mock = async move {
+                server_writer.write_all(&Response::ConnectionOk { request_id: 0, version: super::super::PROTOCOL_VERSION, session: 0, challenge: [0; discrete_log_server::AUTH_LEN] }.as_bytes()).await.unwrap();
+                while let Ok(frame) = Frame::from_reader(&mut server_reader).await {
+                    let request_id = frame.request_id();
+                    let responses = match frame {
+                        Frame::Log { g: 5, h: 2, p: 71, .. } => vec![
+                            Response::LogDoesNotExist { request_id, g: 5, h: 2, p: 71, order: 5, batch_index: None },
+                        ],
+                        Frame::Log { g, h, p, .. } => vec![
+                            Response::LogItem { request_id, item: PollardsLogItem { i: 1, xi: 1, ai: 0, bi: 0, yi: 1, gi: 0, di: 0 } },
+                            Response::SuccessfulLog { request_id, log: 1234, g, h, p, ratio: 0.5, iterations: 9, performance: Performance::Typical, batch_index: None },
+                        ],
+                        Frame::RSA { n, e: 3, .. } => vec![
+                            Response::SuccessfulRSA { request_id, p: 53, q: 61, ratio: 0.5, iterations: 1, performance: Performance::Fast },
+                            Response::InvalidExponent { request_id, n, e: 3 },
+                        ],
+                        Frame::RSA { .. } => vec![
+                            Response::SuccessfulRSA { request_id, p: 42209, q: 42239, ratio: 0.5, iterations: 9, performance: Performance::Typical },
+                            Response::RSAKey { request_id, p: 42209, q: 42239, d: 1605556097 },
+                        ],
+                        Frame::Prime { p: 15, .. } => vec![Response::NotPrime { request_id, p: 15, witness: 2 }],
+                        Frame::PrimitiveRoot { p, .. } => vec![Response::PrimitiveRoot { request_id, p, g: 7 }],
+                        Frame::Prime { p, .. } => vec![Response::Prime { request_id, p, prob: 0.5, rounds: 1 }],
+                        _ => panic!("unexpected frame {frame:?}"),
+                    };
+                    for response in responses {
+                        server_writer.write_all(&response.as_bytes()).await.unwrap();
+                    }
+                }
+            };
+            let mut output = Vec::new();
+            let input = BufReader::new(File::open(&path).unwrap());
+            let (res, _) = futures::join!(run_batch(input, from_server, to_server, &mut output, false, None), mock);
+            res.unwrap();
+            output
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "\
+log 2 2495 5011\tsolved\t1234
+rsa 1782865951\tfactored\t42209\t42239\t1605556097
+rsa 3233 3\tinvalid-exponent\t3
+prime 15239131\tprime\t0.5\t1
+factor 15\terror\tunknown command `factor`
+prime 15\tcomposite\t2
+root 71\troot\t7
+log 5 2 71\tno-log\t5
+log 2 5\terror\twrong number of arguments for `log`
+");
+    }
+
+    #[test]
+    fn json_results_test() {
+        let response = Response::SuccessfulLog {
+            request_id: 1, log: 1234, g: 2, h: 2495, p: 5011, ratio: 0.5, iterations: 9, performance: Performance::Typical, batch_index: None
+        };
+        let result = BatchResult { line: None, query: Some(Query::Log { g: 2, h: 2495, p: 5011 }), outcome: Outcome::try_from(response).unwrap() };
+        let mut out = Vec::new();
+        write_result(&mut out, "log 2 2495 5011", &result, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"query\":\"log\",\"g\":2,\"h\":2495,\"p\":5011,\"outcome\":\"solved\",\"log\":1234,\"iterations\":9}\n"
+        );
+
+        // A line that is not a query is reported with its text
+        let result = BatchResult { line: Some("log 2 5"), query: None, outcome: Outcome::Error { message: "wrong number of arguments for `log`".to_string() } };
+        let mut out = Vec::new();
+        write_result(&mut out, "log 2 5", &result, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"line\":\"log 2 5\",\"outcome\":\"error\",\"message\":\"wrong number of arguments for `log`\"}\n"
+        );
+
+        // The message of a server error is reported as is
+        let response = Response::Error { request_id: 1, code: 1, message: "the modulus 1 is below 2".to_string(), batch_index: None };
+        let outcome = Outcome::try_from(response).unwrap();
+        assert_eq!(outcome, Outcome::Error { message: "the modulus 1 is below 2".to_string() });
+
+        let result = BatchResult { line: None, query: Some(Query::RSA { n: 3233, e: 17 }), outcome: Outcome::Factored { p: 53, q: 61, d: 2753 } };
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            "{\"query\":\"rsa\",\"n\":3233,\"e\":17,\"outcome\":\"factored\",\"p\":53,\"q\":61,\"d\":2753}"
+        );
+    }
+}
@@ -1,108 +1,331 @@
-use std::io::{Read, Write, BufRead, stdout, Stdin, Stdout};
+use std::io::{self, Read, Write, BufRead, stdout, Stdin, Stdout};
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::fs::File;
 use std::time::Duration;
 use std::str::FromStr;
 use tokio::io::{AsyncWrite, AsyncWriteExt, AsyncRead, AsyncReadExt};
+use futures::FutureExt;
+use serde::Serialize;
 use tracing::{error, info, debug, instrument};
 pub use termion::{raw::{IntoRawMode, RawTerminal}, color, screen::{AlternateScreen, IntoAlternateScreen}, terminal_size, scroll, style, cursor, input::TermRead, event::Key, clear};
 
-use discrete_log_server::{Response, BytesDeser, BytesSer, AsBytes, Frame};
+use discrete_log_server::{Response, BytesDeser, BytesSer, AsBytes, AuthKey, Frame, LogMethod, AUTH_LEN};
+use discrete_log_server::algo::{mod_pow, Performance, PollardsLogItem, PollardsRSAFactItem};
 use super::ClientError;
 
+pub mod batch;
+pub mod theme;
+
+pub use utils::{handshake, set_verbose};
+pub use theme::{Theme, ThemeColor, ThemeName};
+
+/// The version of the wire protocol this client speaks, must match the version sent by the server.
+const PROTOCOL_VERSION: u8 = 2;
+
+/// The default number of rows of a table of Pollard's rho steps kept for scrolling back through.
+pub const DEFAULT_MAX_ROWS: usize = 10_000;
+
+/// The number of lines kept free below a table, for the outcome of the computation and the prompt.
+const FOOTER_LINES: u16 = 4;
+
+/// A request the client can make of the server, shared by the interactive interface and batch mode.
+///
+/// Serialized with the command that parses it under `query`, e.g. `{"query":"log","g":2,"h":2495,"p":5011}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "query", rename_all = "lowercase")]
+pub enum Query {
+    /// Check whether `p` is prime, drawing the bases of the test from a generator seeded with `seed` if given
+    Prime {
+        p: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+
+    /// Solve the discrete logarithm of `h` to the base `g` modulo `p` with Pollard's rho
+    Log { g: u64, h: u64, p: u64 },
+
+    /// Solve the discrete logarithm of `h` to the base `g` modulo `p` with baby-step giant-step
+    Bsgs { g: u64, h: u64, p: u64 },
+
+    /// Factor the RSA public key `n` with public exponent `e`
+    RSA { n: u64, e: u64 },
+
+    /// Request the server's statistics
+    Stats,
+
+    /// Find a primitive root modulo the prime `p`
+    #[serde(rename = "root")]
+    PrimitiveRoot { p: u64 },
+}
+
+impl Query {
+    /// The public exponent used for RSA queries that do not specify one.
+    pub const DEFAULT_EXPONENT: u64 = 65537;
+
+    /// Builds the `Frame` for this query with the given request id.
+    pub fn to_frame(&self, request_id: u32) -> Frame {
+        match *self {
+            Query::Prime { p, seed } => Frame::Prime { request_id, p, seed },
+            Query::Log { g, h, p } => Frame::Log { request_id, g, h, p, method: LogMethod::PollardsRho },
+            Query::Bsgs { g, h, p } => Frame::Bsgs { request_id, g, h, p },
+            Query::RSA { n, e } => Frame::RSA { request_id, n, e },
+            Query::Stats => Frame::Stats { request_id },
+            Query::PrimitiveRoot { p } => Frame::PrimitiveRoot { request_id, p },
+        }
+    }
+
+    /// Checks the query for numbers the server is sure to reject, so the interface can ask for them again without
+    /// a round trip to the server.
+    ///
+    /// # Returns
+    /// `Result<(), String>`, an error describing the first number out of range.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Query::Prime { p: p @ (0 | 1), .. } => Err(format!("{p} is neither prime nor composite, please enter a number of at least 2")),
+            Query::Log { p, .. } | Query::Bsgs { p, .. } | Query::PrimitiveRoot { p } if p < 2 => {
+                Err(format!("the prime {p} is too small, please enter a prime of at least 2"))
+            }
+            Query::RSA { n, .. } if n < 4 => Err(format!("the modulus {n} is too small, please enter a modulus of at least 4")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends this query to the server under a fresh request id, which is returned.
+    pub async fn send<W: AsyncWriteExt + Unpin>(&self, mut to_server: W) -> Result<u32, ClientError> {
+        let request_id = utils::next_request_id();
+        let frame = self.to_frame(request_id);
+        utils::trace_frame(&frame);
+        frame.to_writer(&mut to_server)
+            .await
+            .map_err(|e| ClientError::SendRequest(e))?;
+        Ok(request_id)
+    }
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    /// Parses a query written as a command followed by its arguments, e.g. `log 2 2495 5011`, `rsa 1782886219`,
+    /// `prime 15239131`, `bsgs 2 2495 5011`, `root 5011` or `stats`. The exponent of an RSA query and the seed
+    /// of a prime query are optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().ok_or("empty query")?.to_lowercase();
+        let args = words
+            .map(|w| u64::from_str(w).map_err(|_| format!("invalid number `{w}`")))
+            .collect::<Result<Vec<u64>, String>>()?;
+        match (command.as_str(), args.as_slice()) {
+            ("prime", &[p]) => Ok(Query::Prime { p, seed: None }),
+            ("prime", &[p, seed]) => Ok(Query::Prime { p, seed: Some(seed) }),
+            ("log", &[g, h, p]) => Ok(Query::Log { g, h, p }),
+            ("bsgs", &[g, h, p]) => Ok(Query::Bsgs { g, h, p }),
+            ("rsa", &[n]) => Ok(Query::RSA { n, e: Query::DEFAULT_EXPONENT }),
+            ("rsa", &[n, e]) => Ok(Query::RSA { n, e }),
+            ("stats", &[]) => Ok(Query::Stats),
+            ("root", &[p]) => Ok(Query::PrimitiveRoot { p }),
+            ("prime" | "log" | "bsgs" | "rsa" | "stats" | "root", _) => Err(format!("wrong number of arguments for `{command}`")),
+            _ => Err(format!("unknown command `{command}`")),
+        }
+    }
+}
+
 /// The interface for client interactions with the server
 ///
 /// This struct will manage the parsing of requests from client input, sending requests to the server,
 /// and receiving responses from the server as well. The `Interface` type is a state machine, that will
 /// change state based on input received from the client as well as responses received from the server.
 pub enum Interface {
-    Init,
     Home,
     Quit,
-    Prime,
-    Log,
-    RSA,
-    ReturnHome { row: u16, alt_screen: Option<AlternateScreen<Stdout>> }
+    Prime { request_id: u32 },
+    PrimitiveRoot,
+    /// Displaying the steps of a discrete log modulo `p`
+    Log { request_id: u32, p: u64 },
+    /// Displaying the steps of factoring the public key `n`
+    RSA { request_id: u32, n: u64 },
+    Stats,
+    ReturnHome { row: u16, alt_screen: Option<AlternateScreen<Stdout>>, table: Option<ScrollTable> }
+}
+
+// The screen handle and table of `ReturnHome` cannot be formatted, so states are written by name, along with the
+// row the prompt of `ReturnHome` is on
+impl fmt::Debug for Interface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Interface::Home => "Home",
+            Interface::Quit => "Quit",
+            Interface::Prime { .. } => "Prime",
+            Interface::PrimitiveRoot => "PrimitiveRoot",
+            Interface::Log { .. } => "Log",
+            Interface::RSA { .. } => "RSA",
+            Interface::Stats => "Stats",
+            Interface::ReturnHome { row, .. } => return f.debug_struct("ReturnHome").field("row", row).finish_non_exhaustive(),
+        };
+        f.write_str(name)
+    }
 }
 
 impl Interface {
-    pub fn new() -> Interface {
-        Interface::Init
+    /// Informs the user that the connection to the server is being re-established.
+    pub fn display_reconnecting(attempt: u32, max_attempts: u32, theme: Theme) -> Result<(), ClientError> {
+        let mut out = stdout();
+        write!(
+            out, "{}{}{}{}reconnecting to server… (attempt {}/{}){}",
+            cursor::Goto(1, 1), clear::All, color::Fg(theme.warning), style::Bold, attempt, max_attempts, style::Reset,
+        ).map_err(|e| ClientError::Write(e))?;
+        out.flush().map_err(|e| ClientError::Write(e))
     }
 
     /// Transitions the state of the Interface based on the response received from the server.
-    pub async fn receive_response<R: AsyncReadExt + Unpin>(self, mut from_server: R) -> Result<Self, ClientError> {
-        let mut out = stdout().into_raw_mode().expect("stdout unable to be converted into raw mode");
+    ///
+    /// The steps of Pollard's rho computations are also written to `csv` as they are received, if given. At most
+    /// `max_rows` steps of each computation are kept for scrolling back through. Pressing `q` or Esc while the steps
+    /// are received sends a request to cancel the computation over `to_server`. Everything is drawn in the colors
+    /// of `theme`.
+    pub async fn receive_response<R, W>(self, mut from_server: R, mut to_server: W, mut csv: Option<&mut CsvWriter<File>>, max_rows: usize, theme: Theme) -> Result<Self, ClientError>
+    where
+        R: AsyncReadExt + Unpin,
+        W: AsyncWriteExt + Unpin,
+    {
         match self {
-            Interface::Init => {
-                debug!("interface is in `Init` state");
-                let response = Response::from_reader(&mut from_server)
-                    .await
-                    .map_err(|e| ClientError::Response(e))?;
-                assert!(response.is_connection_ok());
-                info!("successfully connected to server");
+            Interface::Home => {
+                debug!("interface is in `Home` state");
+                let mut out = stdout().into_raw_mode().expect("stdout unable to be converted into raw mode");
                 // Display home screen for client
                 write!(
                     out,
-                    "{}{}{}{}{}{}{:-^80}{}",
-                    cursor::Goto(1, 1), cursor::Hide, clear::BeforeCursor, clear::AfterCursor, style::Bold, color::Fg(color::Rgb(92, 209, 193)), "Pollards-Server", style::Reset,
+                    "{}{}{}{}{}{}{:-^80}{}{}",
+                    cursor::Goto(1, 1), cursor::Hide, clear::BeforeCursor, clear::AfterCursor, style::Bold, color::Fg(theme.header),
+                    "Pollards-Server", style::Reset, color::Fg(color::Reset)
                 ).map_err(|e| ClientError::Write(e))?;
                 out.flush().map_err(|e| ClientError::Write(e))?;
-
                 // Display menu of options
                 write!(
-                    out, "{}{}{}{}{}{}",
-                    cursor::Goto(1, 5), color::Fg(color::Rgb(225, 247, 244)),
-                    "[q] - Quit ", "[:p:] - Check if p is prime ", "[l] - Solve discrete logarithm ", "[r] - Factor RSA public key "
+                    out, "{}{}{}{}{}{}{}{}{}{}",
+                    cursor::Goto(1, 5), color::Fg(theme.text),
+                    "[q] - Quit ", "[:p:] - Check if p is prime ", "[l] - Solve discrete logarithm ", "[r] - Factor RSA public key ",
+                    "[b] - Solve discrete logarithm with baby-step giant-step ", "[g] - Find a primitive root ", "[s] - Server statistics ", "[.] - Re-run the last query "
                 ).map_err(|e| ClientError::Write(e))?;
                 out.flush().map_err(|e| ClientError::Write(e))?;
                 Ok(Interface::Home)
             }
-            Interface::Home => {
-                debug!("interface is in `Home` state");
-                // Display home screen for client
+            Interface::Prime { request_id } => {
+                // The rounds are shown on their own screen, like the steps of pollards method
+                let mut alt_out = stdout()
+                    .into_alternate_screen()
+                    .map_err(|e| ClientError::Write(e))?;
+                debug!("interface is in `Prime` state");
+
                 write!(
-                    out,
-                    "{}{}{}{}{}{}{:-^80}{}{}",
-                    cursor::Goto(1, 1), cursor::Hide, clear::BeforeCursor, clear::AfterCursor, style::Bold, color::Fg(color::Rgb(92, 209, 193)),
-                    "Pollards-Server", style::Reset, color::Fg(color::Reset)
+                    alt_out, "{}{}{}{}",
+                    cursor::Goto(1, 1), clear::BeforeCursor, clear::AfterCursor, color::Fg(theme.text)
                 ).map_err(|e| ClientError::Write(e))?;
-                out.flush().map_err(|e| ClientError::Write(e))?;
-                // Display menu of options
+
+                // display table headings
+                let header = [
+                    format!("{:<14}|{:^22}|{:^14}|", "round", "base", "result"),
+                    "-".repeat(53),
+                ];
+                write!(
+                    alt_out, "{}\n{}{}\n", header[0], cursor::Goto(1, 2), header[1]
+                ).map_err(|e| ClientError::Write(e))?;
+                alt_out.flush().map_err(|e| ClientError::Write(e))?;
+
+                let mut table = ScrollTable::for_terminal(header, max_rows);
+
+                // Raw mode delivers the keys that cancel the check as soon as they are pressed
+                let _raw = stdout().into_raw_mode().map_err(|e| ClientError::Write(e))?;
+                let mut cancel = Box::pin(utils::cancel_key().fuse());
+
+                // show each round as it arrives, until the verdict
+                let (row, fg, message) = loop {
+                    let row = table.end_row();
+                    match utils::next_response(&mut from_server, &mut to_server, &mut cancel, request_id).await? {
+                        Response::PrimeRound { round, base, passed, .. } => {
+                            if passed {
+                                table.push(format!("{:<14}|{:^22}|{:^14}|", round, base, "passed"));
+                            } else {
+                                table.push(format!(
+                                    "{:<14}|{:^22}|{}{:^14}{}|",
+                                    round, base, color::Fg(theme.error), "witness", color::Fg(theme.text)
+                                ));
+                            }
+                            table.draw_latest(&mut alt_out).map_err(|e| ClientError::Write(e))?;
+                        }
+                        response => {
+                            let (fg, message) = utils::prime_verdict(response, theme)?;
+                            break (row, fg, message);
+                        }
+                    }
+                };
+                write!(
+                    alt_out, "{}{}{}{}\n{}{}{}{}\n{}{}",
+                    cursor::Goto(1, row), style::Bold, "-".repeat(53), style::NoBold,
+                    cursor::Goto(1, row + 1), color::Fg(fg), message, color::Fg(theme.text),
+                    cursor::Goto(1, row + 2), "press enter to return to menu "
+                ).map_err(|e| ClientError::Write(e))?;
+                alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                Ok(Interface::ReturnHome { row: row + 3, alt_screen: Some(alt_out), table: Some(table) })
+            }
+            Interface::PrimitiveRoot => {
+                debug!("interface is in `PrimitiveRoot` state");
+                let mut out = stdout().into_raw_mode().expect("stdout unable to be converted into raw mode");
+                let (fg, message) = match Response::from_reader(&mut from_server)
+                    .await
+                    .map_err(|e| ClientError::Response(e))
+                    .inspect(utils::trace_response)?
+                {
+                    Response::PrimitiveRoot { p, g, .. } => (theme.text, format!("{g} is a primitive root modulo {p}")),
+                    Response::NotPrime { p, .. } => (theme.text, format!("{p} is not prime, so it has no primitive root")),
+                    Response::InputTooLarge { value, .. } => (theme.warning, format!("{value} is larger than the server accepts")),
+                    Response::RateLimited { .. } => (theme.warning, "too many requests, slow down and try again".to_string()),
+                    Response::Error { code, message, .. } => (theme.error, format!("server error {code}: {message}")),
+                    response => return Err(ClientError::IllegalResponse(response)),
+                };
                 write!(
-                    out, "{}{}{}{}{}{}",
-                    cursor::Goto(1, 5), color::Fg(color::Rgb(225, 247, 244)),
-                    "[q] - Quit ", "[:p:] - Check if p is prime ", "[l] - Solve discrete logarithm ", "[r] - Factor RSA public key "
+                    out, "{}{}{}{}, press enter to return to menu",
+                    cursor::Goto(1, 5), clear::CurrentLine, color::Fg(fg), message
                 ).map_err(|e| ClientError::Write(e))?;
                 out.flush().map_err(|e| ClientError::Write(e))?;
-                Ok(Interface::Home)
+                Ok(Interface::ReturnHome { row: 6, alt_screen: None, table: None })
             }
-            Interface::Prime => {
-                debug!("interface is in `Prime` state");
-                // match on the response returned from the server
+            Interface::Stats => {
+                debug!("interface is in `Stats` state");
+                let mut out = stdout().into_raw_mode().expect("stdout unable to be converted into raw mode");
                 match Response::from_reader(&mut from_server)
                     .await
-                    .map_err(|e| ClientError::Response(e))?
+                    .map_err(|e| ClientError::Response(e))
+                    .inspect(utils::trace_response)?
                 {
-                    Response::Prime { p, prob } => {
+                    Response::Stats { clients, logs, rsas, primes, iterations, timings, .. } => {
+                        // only a server started with `--benchmark` times its computations
+                        let timings = timings.map_or(String::new(), |timings| format!(
+                            ", discrete log times: {}, RSA times: {}, primality check times: {}",
+                            timings.logs, timings.rsas, timings.primes
+                        ));
                         write!(
                             out, "{}{}{}{}",
-                            cursor::Goto(1, 5), clear::CurrentLine, color::Fg(color::Rgb(225, 247, 244)),
-                            format!("{p} is prime with probability {prob:.10}, press enter to return to menu")
+                            cursor::Goto(1, 5), clear::CurrentLine, color::Fg(theme.text),
+                            format!(
+                                "clients: {clients}, discrete logs: {logs}, RSA keys: {rsas}, primality checks: {primes}, \
+                                iterations: {iterations}{timings}, press enter to return to menu"
+                            )
                         ).map_err(|e| ClientError::Write(e))?;
                         out.flush().map_err(|e| ClientError::Write(e))?;
                     }
-                    Response::NotPrime { p} => {
+                    Response::Error { code, message, .. } => {
                         write!(
-                            out, "{}{}{}{}",
-                            cursor::Goto(1, 5), clear::CurrentLine, color::Fg(color::Rgb(225, 247, 244)),
-                            format!("{p} is not prime, press enter to return to menu")
+                            out, "{}{}{}server error {}: {}, press enter to return to menu",
+                            cursor::Goto(1, 5), clear::CurrentLine, color::Fg(theme.error), code, message
                         ).map_err(|e| ClientError::Write(e))?;
                         out.flush().map_err(|e| ClientError::Write(e))?;
                     }
-                    _ => return Err(ClientError::IllegalResponse),
+                    response => return Err(ClientError::IllegalResponse(response)),
                 }
-                Ok(Interface::ReturnHome { row: 6, alt_screen: None })
+                Ok(Interface::ReturnHome { row: 6, alt_screen: None, table: None })
             }
-            Interface::Log => {
+            Interface::Log { request_id, p } => {
                 // For writing to a new screen, that way we don't pollute the main screen when output
                 // becomes long
                 let mut alt_out = stdout()
@@ -113,73 +336,183 @@ impl Interface {
                 // clear the console for displaying the results of pollards method
                 write!(
                     alt_out, "{}{}{}{}",
-                    cursor::Goto(1, 1), clear::BeforeCursor, clear::AfterCursor, color::Fg(color::Rgb(225, 247, 244))
+                    cursor::Goto(1, 1), clear::BeforeCursor, clear::AfterCursor, color::Fg(theme.text)
                 ).map_err(|e| ClientError::Write(e))?;
                 alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
-                // display table headings
+                // display table headings, with the columns fitted to the width of the terminal
+                let layout = TableLayout::for_terminal(7);
+                let rule = layout.rule();
+                let header = [
+                    layout.row(["i", "x", "alpha", "beta", "y", "gamma", "delta"]),
+                    rule.clone(),
+                ];
                 write!(
-                    alt_out, "{:<11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|\n",
-                    "i", "x", "alpha", "beta", "y", "gamma", "delta",
+                    alt_out, "{}\n{}{}\n", header[0], cursor::Goto(1, 2), header[1]
                 ).map_err(|e| ClientError::Write(e))?;
                 alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
-                write!(
-                    alt_out, "{}{}\n", cursor::Goto(1, 2), "-".repeat(84)
-                ).map_err(|e| ClientError::Write(e))?;
-                alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                if let Some(csv) = csv.as_mut() {
+                    csv.write_log_header().map_err(|e| ClientError::Write(e))?;
+                }
+
+                // Keep the rows, so they can be scrolled back through once the computation finishes
+                let mut table = ScrollTable::for_terminal(header, max_rows);
+                let progress = Progress::new(p);
 
-                // Keep track of what row we are on
-                let mut row = 3;
+                // Raw mode delivers the keys that cancel the computation as soon as they are pressed
+                let _raw = stdout().into_raw_mode().map_err(|e| ClientError::Write(e))?;
+                let mut cancel = Box::pin(utils::cancel_key().fuse());
 
                 // keep pulling responses from the server until they are finished
-                loop {
-                    match Response::from_reader(&mut from_server)
-                        .await
-                        .map_err(|e| ClientError::Response(e))?
-                    {
-                        Response::LogItem { item} => {
-                            if item.xi != item.yi {
-                                write!(
-                                    alt_out, "{}{:<11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|{:^11}|\n",
-                                    cursor::Goto(1, row), item.i, item.xi, item.ai, item.bi, item.yi, item.gi, item.di
-                                ).map_err(|e| ClientError::Write(e))?;
-                                alt_out.flush().map_err(|e| ClientError::Write(e))?;
-                            } else {
-                                write!(
-                                    alt_out, "{}{:<11}|{}{:^11}{}|{:^11}|{:^11}|{}{:^11}{}|{:^11}|{:^11}|\n",
-                                    cursor::Goto(1, row), item.i, color::Fg(color::Rgb(31, 207, 31)), item.xi,
-                                    color::Fg(color::Rgb(225, 247, 244)), item.ai, item.bi, color::Fg(color::Rgb(31, 207, 31)),
-                                    item.yi,  color::Fg(color::Rgb(225, 247, 244)), item.gi, item.di
-                                ).map_err(|e| ClientError::Write(e))?;
-                                alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                let row = loop {
+                    // the row below the table, where the progress and then the outcome are written
+                    let mut row = table.end_row();
+                    let response = utils::next_response(&mut from_server, &mut to_server, &mut cancel, request_id).await?;
+                    Progress::clear(&mut alt_out, row).map_err(|e| ClientError::Write(e))?;
+                    match response {
+                        Response::LogItem { item, .. } => {
+                            if let Some(csv) = csv.as_mut() {
+                                csv.write_log_item(&item).map_err(|e| ClientError::Write(e))?;
                             }
-                            row += 1;
+                            let mut cells = layout.cells([item.i as u64, item.xi, item.ai, item.bi, item.yi, item.gi, item.di]);
+                            if item.xi == item.yi {
+                                for i in [1, 4] {
+                                    cells[i] = format!("{}{}{}", color::Fg(theme.highlight), cells[i], color::Fg(theme.text));
+                                }
+                            }
+                            table.push(TableLayout::join(cells));
+                            table.draw_latest(&mut alt_out).map_err(|e| ClientError::Write(e))?;
+                            progress.draw(&mut alt_out, table.end_row(), item.i as u64).map_err(|e| ClientError::Write(e))?;
+                        }
+                        Response::Collision { i, value, .. } => {
+                            table.push(format!(
+                                "{}{}collision at step {i}: x = y = {value}{}{}",
+                                style::Bold, color::Fg(theme.highlight), style::Reset, color::Fg(theme.text)
+                            ));
+                            table.draw_latest(&mut alt_out).map_err(|e| ClientError::Write(e))?;
                         }
-                        Response::SuccessfulLog { log, g, h, p, ratio } => {
+                        Response::SuccessfulLog { log, g, h, p, ratio, iterations, performance, .. } => {
                             write!(
                                 alt_out, "{}{}{}{}\n",
-                                cursor::Goto(1, row), style::Bold, "-".repeat(85), style::Reset,
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
                                 // cursor::Goto(1, row + 1),
                                 // format!("discrete log solved: {g}^{log} = {h} in the field F{p}, ratio of iterations to sqrt({p}) = {ratio:.10}")
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
                             write!(
-                                alt_out, "{}{}{}\n",
-                                cursor::Goto(1, row + 1), color::Fg(color::Rgb(225, 247, 244)),
-                                format!("discrete log solved: {g}^{log} = {h} in the field F{p}, ratio of iterations to sqrt({p}) = {ratio:.10}")
+                                alt_out, "{}{}{}, {}{} run\n",
+                                cursor::Goto(1, row + 1), color::Fg(theme.text),
+                                format!("discrete log solved: {g}^{log} = {h} in the field F{p}, {iterations} iterations, ratio of iterations to sqrt({p}) = {ratio:.10}"),
+                                color::Fg(utils::performance_color(performance, theme)), performance
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            // Don't trust the server blindly, check the log actually solves the problem
+                            if !utils::verify_log(log, g, h, p) {
+                                error!(log, g, h, p, "discrete log received from server failed verification");
+                                write!(
+                                    alt_out, "{}{}{}{}\n",
+                                    cursor::Goto(1, row + 2), color::Fg(theme.error),
+                                    format!("VERIFICATION FAILED: {g}^{log} != {h} in the field F{p}"),
+                                    color::Fg(theme.text)
+                                ).map_err(|e| ClientError::Write(e))?;
+                                alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                                row += 1;
+                            }
                             write!(
                                 alt_out, "{}{}", cursor::Goto(1, row + 2), "press enter to return to menu "
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
-                            break;
+                            break row;
+                        }
+                        Response::RateLimited { .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
+                                cursor::Goto(1, row + 1),
+                                "too many requests, slow down and try again"
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            write!(
+                                alt_out, "{}", "press enter to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::InputTooLarge { value, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
+                                cursor::Goto(1, row + 1),
+                                format!("the prime {value} is larger than the server accepts")
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            write!(
+                                alt_out, "{}", "press enter to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::InvalidParameters { reason, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
+                                cursor::Goto(1, row + 1),
+                                format!("invalid parameters: {reason}")
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            write!(
+                                alt_out, "{}", "press enter to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::Timeout { millis, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
+                                cursor::Goto(1, row + 1),
+                                format!("discrete log computation timed out after {millis} ms")
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            write!(
+                                alt_out, "{}", "press enter to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
                         }
-                        Response::UnsuccessfulLog { g, h, p} => {
+                        Response::Cancelled { .. } => {
                             write!(
                                 alt_out, "{}{}{}{}\n{}{}\n",
-                                cursor::Goto(1, row), style::Bold, "-".repeat(84), style::NoBold,
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
+                                cursor::Goto(1, row + 1),
+                                "discrete log computation cancelled"
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            write!(
+                                alt_out, "{}", "press enter to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::LogDoesNotExist { g, h, p, order, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
+                                cursor::Goto(1, row + 1),
+                                format!("no discrete log exists for g: {g}, h: {h}, p: {p}, h is not in the subgroup of order {order} generated by g")
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            write!(
+                                alt_out, "{}", "press enter to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::UnsuccessfulLog { g, h, p, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
                                 cursor::Goto(1, row + 1),
                                 format!("discrete log unable to be solved for g: {g}, h: {h}, p: {p}")
                             ).map_err(|e| ClientError::Write(e))?;
@@ -188,14 +521,28 @@ impl Interface {
                                 alt_out, "{}", "press enter to return to menu "
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
-                            break;
+                            break row;
                         }
-                        _ => return Err(ClientError::IllegalResponse),
+                        Response::Error { code, message, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::NoBold,
+                                cursor::Goto(1, row + 1),
+                                format!("server error {code}: {message}")
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            write!(
+                                alt_out, "{}", "press enter to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        response => return Err(ClientError::IllegalResponse(response)),
                     }
-                }
-                Ok(Interface::ReturnHome { row: row + 3, alt_screen: Some(alt_out) })
+                };
+                Ok(Interface::ReturnHome { row: row + 3, alt_screen: Some(alt_out), table: Some(table) })
             }
-            Interface::RSA => {
+            Interface::RSA { request_id, n } => {
                 let mut alt_out = stdout().into_alternate_screen()
                     .map_err(|e| ClientError::Write(e))?;
 
@@ -204,48 +551,113 @@ impl Interface {
                 // clear the console for displaying the results of pollards method
                 write!(
                     alt_out, "{}{}{}",
-                    cursor::Goto(1, 1), clear::All, color::Fg(color::Rgb(225, 247, 244))
+                    cursor::Goto(1, 1), clear::All, color::Fg(theme.text)
                 ).map_err(|e| ClientError::Write(e))?;
                 alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
                 // display table headings
+                let layout = TableLayout::for_terminal(5);
+                let rule = layout.rule();
+                let header = [
+                    layout.row(["i", "x", "y", "g", "c"]),
+                    rule.clone(),
+                ];
                 write!(
-                    alt_out, "{:<14}|{:^14}|{:^14}|{:^14}|\n",
-                    "i", "x", "y", "g",
+                    alt_out, "{}\n{}{}\n", header[0], cursor::Goto(1, 2), header[1]
                 ).map_err(|e| ClientError::Write(e))?;
                 alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
-                write!(
-                    alt_out, "{}{}\n", cursor::Goto(1, 2), "-".repeat(60)
-                ).map_err(|e| ClientError::Write(e))?;
-                alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                let mut table = ScrollTable::for_terminal(header, max_rows);
+
+                // Raw mode delivers the keys that cancel the computation as soon as they are pressed
+                let _raw = stdout().into_raw_mode().map_err(|e| ClientError::Write(e))?;
+                let mut cancel = Box::pin(utils::cancel_key().fuse());
+
+                if let Some(csv) = csv.as_mut() {
+                    csv.write_rsa_header().map_err(|e| ClientError::Write(e))?;
+                }
+
+                let progress = Progress::new(n);
+                let row = loop {
+                    let row = table.end_row();
+                    let response = utils::next_response(&mut from_server, &mut to_server, &mut cancel, request_id).await?;
+                    Progress::clear(&mut alt_out, row).map_err(|e| ClientError::Write(e))?;
+                    match response {
+                        Response::RSAItem { item, .. } => {
+                            if let Some(csv) = csv.as_mut() {
+                                csv.write_rsa_item(&item).map_err(|e| ClientError::Write(e))?;
+                            }
+                            table.push(layout.row([item.i as u64, item.xi, item.yi, item.g, item.c]));
+                            table.draw_latest(&mut alt_out).map_err(|e| ClientError::Write(e))?;
+                            progress.draw(&mut alt_out, table.end_row(), item.i as u64).map_err(|e| ClientError::Write(e))?;
+                        }
+                        Response::SuccessfulRSA { p, q, ratio, iterations, performance, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
 
-                let mut row = 3;
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
-                loop {
-                    match Response::from_reader(&mut from_server)
-                        .await
-                        .map_err(|e| ClientError::Write(e))?
-                    {
-                        Response::RSAItem { item } => {
                             write!(
-                                alt_out, "{}{:<14}|{:^14}|{:^14}|{:^14}|\n",
-                                cursor::Goto(1, row), item.i, item.xi, item.yi, item.g
+                                alt_out, "{}{}{}, {}{} run\n", cursor::Goto(1, row + 1),
+                                color::Fg(theme.text),
+                                format!("public key factored successfully: n = {} * {}, {} iterations, ratio of iterations to sqrt({}) {:.10}", p, q, iterations, p * q, ratio),
+                                color::Fg(utils::performance_color(performance, theme)), performance
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
+
+                            // the private exponent recovered from the factors follows
+                            let key = match utils::next_response(&mut from_server, &mut to_server, &mut cancel, request_id).await? {
+                                Response::RSAKey { d, .. } => format!("private exponent recovered: d = {d}"),
+                                Response::InvalidExponent { e, .. } => format!("malformed key: public exponent {e} has no inverse modulo (p - 1)(q - 1)"),
+                                response => return Err(ClientError::IllegalResponse(response)),
+                            };
+                            write!(
+                                alt_out, "{}{}{}\n", cursor::Goto(1, row + 2),
+                                color::Fg(theme.text),
+                                key,
+                            ).map_err(|e| ClientError::Write(e))?;
+
+                            write!(
+                                alt_out, "{}{}", cursor::Goto(1, row + 3), "press any key to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row + 1;
                         }
-                        Response::SuccessfulRSA { p, q, ratio } => {
+                        Response::RateLimited { .. } => {
                             write!(
                                 alt_out, "{}{}{}{}\n",
-                                cursor::Goto(1, row), style::Bold, "-".repeat(60), style::Reset,
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
+                            write!(
+                                alt_out, "{}{}{}\n", cursor::Goto(1, row + 1),
+                                color::Fg(theme.text),
+                                "too many requests, slow down and try again",
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+
+                            write!(
+                                alt_out, "{}{}", cursor::Goto(1, row + 2), "press any key to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::InputTooLarge { value, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
                             write!(
                                 alt_out, "{}{}{}\n", cursor::Goto(1, row + 1),
-                                color::Fg(color::Rgb(225, 247, 244)),
-                                format!("public key factored successfully: n = {} * {}, ratio of iterations to sqrt({}) {:.10}", p, q,  p * q, ratio)
+                                color::Fg(theme.text),
+                                format!("public key: {value} is larger than the server accepts"),
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
@@ -254,19 +666,61 @@ impl Interface {
                             ).map_err(|e| ClientError::Write(e))?;
 
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
-                            break;
+                            break row;
                         }
-                        Response::UnsuccessfulRSA { n} => {
+                        Response::Timeout { millis, .. } => {
                             write!(
                                 alt_out, "{}{}{}{}\n",
-                                cursor::Goto(1, row), style::Bold, "-".repeat(60), style::Reset,
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
+                            write!(
+                                alt_out, "{}{}{}\n", cursor::Goto(1, row + 1),
+                                color::Fg(theme.text),
+                                format!("factoring the public key timed out after {millis} ms"),
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+
+                            write!(
+                                alt_out, "{}{}", cursor::Goto(1, row + 2), "press any key to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::Cancelled { .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
                             write!(
                                 alt_out, "{}{}{}\n", cursor::Goto(1, row + 1),
-                                color::Fg(color::Rgb(225, 247, 244)),
+                                color::Fg(theme.text),
+                                "factoring the public key cancelled",
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+
+                            write!(
+                                alt_out, "{}{}", cursor::Goto(1, row + 2), "press any key to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        Response::UnsuccessfulRSA { n, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
+
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+
+                            write!(
+                                alt_out, "{}{}{}\n", cursor::Goto(1, row + 1),
+                                color::Fg(theme.text),
                                 format!("public key: {n} was not factored successfully"),
                             ).map_err(|e| ClientError::Write(e))?;
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
@@ -276,93 +730,540 @@ impl Interface {
                             ).map_err(|e| ClientError::Write(e))?;
 
                             alt_out.flush().map_err(|e| ClientError::Write(e))?;
-                            break;
+                            break row;
                         }
-                        _ => return Err(ClientError::IllegalResponse),
-                    }
+                        Response::Error { code, message, .. } => {
+                            write!(
+                                alt_out, "{}{}{}{}\n",
+                                cursor::Goto(1, row), style::Bold, rule, style::Reset,
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
 
-                    row += 1;
-                }
-                Ok(Interface::ReturnHome { row: 6, alt_screen: Some(alt_out) })
+                            write!(
+                                alt_out, "{}{}{}\n", cursor::Goto(1, row + 1),
+                                color::Fg(theme.error),
+                                format!("server error {code}: {message}"),
+                            ).map_err(|e| ClientError::Write(e))?;
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+
+                            write!(
+                                alt_out, "{}{}{}", cursor::Goto(1, row + 2), color::Fg(theme.text), "press any key to return to menu "
+                            ).map_err(|e| ClientError::Write(e))?;
+
+                            alt_out.flush().map_err(|e| ClientError::Write(e))?;
+                            break row;
+                        }
+                        response => return Err(ClientError::IllegalResponse(response)),
+                    }
+                };
+                Ok(Interface::ReturnHome { row: row + 3, alt_screen: Some(alt_out), table: Some(table) })
             }
-            s => return Err(ClientError::InterfaceState),
+            s => return Err(ClientError::InterfaceState(s)),
         }
     }
 
     /// Transitions the state of the interface based on the input of the client
-    pub async fn parse_request<W: AsyncWriteExt + Unpin, C: Read>(self, mut to_server: W, mut from_client: C) -> Result<Self, ClientError> {
-        let mut stdout = stdout().into_raw_mode().expect("unable to convert terminal into raw mode");
+    ///
+    /// The query submitted from `Home` is kept in `last_query`, so entering `.` there sends it again.
+    pub async fn parse_request<W: AsyncWriteExt + Unpin, C: Read>(self, mut to_server: W, mut from_client: C, theme: Theme, last_query: &mut Option<Query>) -> Result<Self, ClientError> {
         match self {
             Interface::Home => {
                 debug!("interface is in `Home` state");
-                let next_state = loop {
+                let mut stdout = stdout().into_raw_mode().expect("unable to convert terminal into raw mode");
+                let query = loop {
                     // let mut buf = String::default();
                     // let _ = from_client.read_to_string(&mut buf)
                     //     .map_err(|e| ClientError::Read(e))?;
-                    let buf = utils::read_client_input(&mut stdout, 6, 1)?;
+                    let buf = utils::read_client_input(&mut stdout, 6, 1, None)?;
 
-                    match buf.to_lowercase().as_str() {
+                    let query = match buf.to_lowercase().as_str() {
                         "q" => {
                             info!("client exiting");
                             write!(stdout, "{}{}{}{}", cursor::Goto(1, 1), clear::BeforeCursor, clear::AfterCursor, cursor::Show)
                                 .map_err(|e| ClientError::Write(e))?;
                             stdout.flush().map_err(|e| ClientError::Write(e))?;
-                            break Interface::Quit;
+                            break None;
                         }
                         p if !p.starts_with('-') && u64::from_str(p).is_ok() => {
                             let p = u64::from_str(p).expect("conversion to `u64` should not fail");
-                            let frame = Frame::Prime { p };
-                            to_server.write_all(frame.as_bytes().as_slice())
-                                .await
-                                .map_err(|e| ClientError::SendRequest(e))?;
-                            break Interface::Prime;
+                            Query::Prime { p, seed: None }
                         }
                         "l" => {
-                            let base = utils::read_u64("base", &mut from_client, &mut stdout)?;
-                            let val = utils::read_u64("value", &mut from_client, &mut stdout)?;
-                            let prime = utils::read_u64("prime", &mut from_client, &mut stdout)?;
-
-                            // create frame and send to server
-                            let frame = Frame::Log { g: base, h: val, p: prime };
-                            to_server.write_all(&frame.as_bytes())
-                                .await
-                                .map_err(|e| ClientError::SendRequest(e))?;
-                            break Interface::Log;
+                            let base = utils::read_u64("base", &mut from_client, &mut stdout, theme)?;
+                            let val = utils::read_u64("value", &mut from_client, &mut stdout, theme)?;
+                            let prime = utils::read_u64("prime", &mut from_client, &mut stdout, theme)?;
+                            Query::Log { g: base, h: val, p: prime }
+                        }
+                        "b" => {
+                            let base = utils::read_u64("base", &mut from_client, &mut stdout, theme)?;
+                            let val = utils::read_u64("value", &mut from_client, &mut stdout, theme)?;
+                            let prime = utils::read_u64("prime", &mut from_client, &mut stdout, theme)?;
+                            Query::Bsgs { g: base, h: val, p: prime }
+                        }
+                        "s" => Query::Stats,
+                        "." => match *last_query {
+                            Some(query) => query,
+                            None => {
+                                utils::incorrect_input_prompt("no query to re-run yet, please enter a valid option", &mut stdout, theme)?;
+                                continue;
+                            }
+                        },
+                        "g" => {
+                            let prime = utils::read_u64("prime", &mut from_client, &mut stdout, theme)?;
+                            Query::PrimitiveRoot { p: prime }
                         }
                         "r" => {
-                            let modulus = utils::read_u64("modulus", &mut from_client, &mut stdout)?;
-                            let exponent = utils::read_u64("exponent", &mut from_client, &mut stdout)?;
-
-                            // create frame and send to server
-                            let frame = Frame::RSA { n: modulus, e: exponent };
-                            to_server.write_all(&frame.as_bytes())
-                                .await
-                                .map_err(|e| ClientError::SendRequest(e))?;
-                            break Interface::RSA;
+                            let modulus = utils::read_u64("modulus", &mut from_client, &mut stdout, theme)?;
+                            let exponent = utils::read_u64("exponent", &mut from_client, &mut stdout, theme)?;
+                            Query::RSA { n: modulus, e: exponent }
+                        }
+                        _ => {
+                            utils::incorrect_input_prompt("please enter a valid option", &mut stdout, theme)?;
+                            continue;
                         }
-                        _ => utils::incorrect_input_prompt("please enter a valid option", &mut stdout)?,
+                    };
+                    // numbers the server would only reject are asked for again
+                    match query.validate() {
+                        Ok(()) => break Some(query),
+                        Err(reason) => utils::incorrect_input_prompt(&reason, &mut stdout, theme)?,
                     }
                 };
-                Ok(next_state)
+
+                // create frame and send to server
+                match query {
+                    Some(query) => Interface::submit(query, &mut to_server, last_query).await,
+                    None => Ok(Interface::Quit),
+                }
             }
-            Interface::ReturnHome { row, alt_screen } => {
+            Interface::ReturnHome { row, alt_screen, mut table } => {
                 debug!("interface is in `ReturnHome` state");
+                let mut stdout = stdout().into_raw_mode().expect("unable to convert terminal into raw mode");
                 let _ = if let Some(mut alt_out) = alt_screen {
-                    utils::read_client_input(&mut alt_out, row, 1)
+                    utils::read_client_input(&mut alt_out, row, 1, table.as_mut())
                 } else {
-                    utils::read_client_input(&mut stdout, row, 1)
+                    utils::read_client_input(&mut stdout, row, 1, None)
                 };
                 Ok(Interface::Home)
             }
-            s => Err(ClientError::InterfaceState)
+            s => Err(ClientError::InterfaceState(s))
+        }
+    }
+
+    /// Sends `query` to the server, remembering it in `last_query` so it can be re-run.
+    ///
+    /// # Returns
+    /// `Result<Interface, ClientError>`, the state that displays the responses to `query`.
+    async fn submit<W: AsyncWriteExt + Unpin>(query: Query, to_server: W, last_query: &mut Option<Query>) -> Result<Interface, ClientError> {
+        let request_id = query.send(to_server).await?;
+        *last_query = Some(query);
+        // the computation is cancelled by its request id
+        let next_state = match query {
+            Query::Prime { .. } => Interface::Prime { request_id },
+            // the result is displayed like a Pollard's rho log
+            Query::Log { p, .. } | Query::Bsgs { p, .. } => Interface::Log { request_id, p },
+            Query::RSA { n, .. } => Interface::RSA { request_id, n },
+            Query::Stats => Interface::Stats,
+            Query::PrimitiveRoot { .. } => Interface::PrimitiveRoot,
+        };
+        Ok(next_state)
+    }
+}
+
+/// A table of the steps of a Pollard's rho computation, that keeps its rows so they can be scrolled back through
+/// once the computation finishes.
+///
+/// The rows are drawn below a two line header, in a window as tall as the terminal allows. Only the latest
+/// `max_rows` rows are kept, though never fewer than fill the window.
+pub struct ScrollTable {
+    header: [String; 2],
+    rows: VecDeque<String>,
+    max_rows: usize,
+    height: usize,
+    offset: usize,
+}
+
+impl ScrollTable {
+    pub fn new(header: [String; 2], max_rows: usize, height: usize) -> Self {
+        let height = height.max(1);
+        ScrollTable { header, rows: VecDeque::new(), max_rows: max_rows.max(height), height, offset: 0 }
+    }
+
+    /// Creates a table whose window fills the terminal, leaving room for the header and the outcome below it.
+    pub fn for_terminal(header: [String; 2], max_rows: usize) -> Self {
+        let (_, lines) = terminal_size().unwrap_or((80, 24));
+        ScrollTable::new(header, max_rows, lines.saturating_sub(2 + FOOTER_LINES) as usize)
+    }
+
+    /// Adds a row to the bottom of the table, dropping the oldest row once `max_rows` are kept. A window showing the
+    /// bottom of the table follows the new row.
+    pub fn push(&mut self, row: String) {
+        let following = self.offset == self.max_offset();
+        if self.rows.len() == self.max_rows {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(row);
+        if following {
+            self.offset = self.max_offset();
+        }
+    }
+
+    /// Moves the window `delta` rows down the table, or up if negative, stopping at either end.
+    ///
+    /// # Returns
+    /// `bool`, whether the window moved.
+    pub fn scroll(&mut self, delta: isize) -> bool {
+        let offset = self.offset.saturating_add_signed(delta).min(self.max_offset());
+        let moved = offset != self.offset;
+        self.offset = offset;
+        moved
+    }
+
+    /// The number of rows a page up or down moves the window.
+    pub fn page(&self) -> isize {
+        self.height as isize
+    }
+
+    /// The terminal row just below the window.
+    pub fn end_row(&self) -> u16 {
+        3 + self.rows.len().min(self.height) as u16
+    }
+
+    /// The rows currently shown in the window.
+    pub fn visible(&self) -> impl Iterator<Item = &String> {
+        self.rows.iter().skip(self.offset).take(self.height)
+    }
+
+    fn max_offset(&self) -> usize {
+        self.rows.len().saturating_sub(self.height)
+    }
+
+    /// Draws the newest row as the table is received. Once the window is full the screen is scrolled up a line
+    /// and the header redrawn, so the header stays in view.
+    pub fn draw_latest<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let Some(row) = self.rows.back() else { return Ok(()) };
+        if self.rows.len() <= self.height {
+            write!(out, "{}{}", cursor::Goto(1, 2 + self.rows.len() as u16), row)?;
+        } else {
+            write!(
+                out, "{}{}{}{}{}{}{}{}{}{}",
+                scroll::Up(1),
+                cursor::Goto(1, 1), clear::CurrentLine, self.header[0],
+                cursor::Goto(1, 2), clear::CurrentLine, self.header[1],
+                cursor::Goto(1, 2 + self.height as u16), clear::CurrentLine, row,
+            )?;
+        }
+        out.flush()
+    }
+
+    /// Redraws the rows in the window.
+    pub fn draw<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (i, row) in self.visible().enumerate() {
+            write!(out, "{}{}{}", cursor::Goto(1, 3 + i as u16), clear::CurrentLine, row)?;
+        }
+        out.flush()
+    }
+}
+
+/// The narrowest a column of a table is drawn, however narrow the terminal.
+const MIN_COLUMN_WIDTH: usize = 3;
+
+/// The widest a column of a table is drawn, room for any `u64` with a space either side.
+const MAX_COLUMN_WIDTH: usize = 22;
+
+/// The widths of the columns of a table of Pollard's rho steps, fitted to the width of the terminal.
+///
+/// Every column is drawn as wide as the terminal allows, followed by a `|`. Values too wide for their column are
+/// cut short and end in `…`, rather than wrapping onto the next line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableLayout {
+    columns: usize,
+    width: usize,
+}
+
+impl TableLayout {
+    /// Lays out `columns` columns across a terminal `terminal_width` characters wide.
+    pub fn new(columns: usize, terminal_width: u16) -> Self {
+        let width = (terminal_width as usize / columns.max(1))
+            .saturating_sub(1)
+            .clamp(MIN_COLUMN_WIDTH, MAX_COLUMN_WIDTH);
+        TableLayout { columns, width }
+    }
+
+    /// Lays out `columns` columns across the terminal, as wide as it currently is.
+    pub fn for_terminal(columns: usize) -> Self {
+        let (width, _) = terminal_size().unwrap_or((80, 24));
+        TableLayout::new(columns, width)
+    }
+
+    /// The rule drawn under the header and above the outcome, as wide as the table.
+    pub fn rule(&self) -> String {
+        "-".repeat(self.columns * (self.width + 1))
+    }
+
+    /// Pads `value` to the width of a column, left aligned in the first column and centered in the others.
+    pub fn cell<T: Display>(&self, column: usize, value: T) -> String {
+        let mut value = value.to_string();
+        if value.chars().count() > self.width {
+            value = value.chars().take(self.width - 1).collect();
+            value.push('…');
         }
+        if column == 0 {
+            format!("{value:<width$}", width = self.width)
+        } else {
+            format!("{value:^width$}", width = self.width)
+        }
+    }
+
+    /// Pads every one of `values` to the width of its column.
+    pub fn cells<T: Display>(&self, values: impl IntoIterator<Item = T>) -> Vec<String> {
+        values.into_iter().enumerate().map(|(column, value)| self.cell(column, value)).collect()
+    }
+
+    /// A row of the table holding `values`, one per column.
+    pub fn row<T: Display>(&self, values: impl IntoIterator<Item = T>) -> String {
+        TableLayout::join(self.cells(values))
+    }
+
+    /// Joins cells already padded by `cell` into a row.
+    pub fn join(cells: impl IntoIterator<Item = String>) -> String {
+        cells.into_iter().map(|cell| cell + "|").collect()
+    }
+}
+
+/// The line below a table of Pollard's rho steps that tracks how far the walk has come, relative to the
+/// `sqrt(modulus)` steps expected of it.
+///
+/// The line is drawn just below the window, so it is overwritten by the next row or the outcome rather than
+/// scrolling with the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    expected: u64,
+}
+
+impl Progress {
+    /// Tracks a walk modulo `modulus`, the prime of a discrete log or the public key being factored.
+    pub fn new(modulus: u64) -> Self {
+        Progress { expected: (f64::sqrt(modulus as f64).ceil() as u64).max(1) }
+    }
+
+    /// The percentage of the expected steps taken by step `i`, past 100 once the walk runs long.
+    pub fn percent(&self, i: u64) -> f64 {
+        100.0 * i as f64 / self.expected as f64
+    }
+
+    /// The text of the line at step `i`.
+    pub fn line(&self, i: u64) -> String {
+        format!("step {i} of ~{} ({:.1}%)", self.expected, self.percent(i))
+    }
+
+    /// Draws the line at step `i` on `row`.
+    pub fn draw<W: Write>(&self, out: &mut W, row: u16, i: u64) -> io::Result<()> {
+        write!(out, "{}{}{}{}{}", cursor::Goto(1, row), clear::CurrentLine, style::Italic, self.line(i), style::NoItalic)?;
+        out.flush()
+    }
+
+    /// Clears the line drawn on `row`, before a row of the table or the outcome is written over it.
+    pub fn clear<W: Write>(out: &mut W, row: u16) -> io::Result<()> {
+        write!(out, "{}{}", cursor::Goto(1, row), clear::CurrentLine)
+    }
+}
+
+/// Writes the steps of Pollard's rho computations as CSV, next to the tables rendered in the terminal.
+///
+/// Every table starts with a header row. The output is flushed after every row, so a crash still leaves the rows
+/// received so far.
+pub struct CsvWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(out: W) -> Self {
+        CsvWriter { out }
+    }
+
+    /// Writes the header of a discrete log table.
+    pub fn write_log_header(&mut self) -> io::Result<()> {
+        self.write_row(&["i", "x", "alpha", "beta", "y", "gamma", "delta"])
+    }
+
+    /// Writes one step of Pollard's rho for discrete logarithms.
+    pub fn write_log_item(&mut self, item: &PollardsLogItem) -> io::Result<()> {
+        self.write_row(&[item.i as u64, item.xi, item.ai, item.bi, item.yi, item.gi, item.di])
+    }
+
+    /// Writes the header of an RSA factoring table.
+    pub fn write_rsa_header(&mut self) -> io::Result<()> {
+        self.write_row(&["i", "x", "y", "g", "c"])
+    }
+
+    /// Writes one step of Pollard's rho for factoring RSA public keys.
+    pub fn write_rsa_item(&mut self, item: &PollardsRSAFactItem) -> io::Result<()> {
+        self.write_row(&[item.i as u64, item.xi, item.yi, item.g, item.c])
+    }
+
+    fn write_row<T: Display>(&mut self, fields: &[T]) -> io::Result<()> {
+        let row = fields.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(",");
+        writeln!(self.out, "{row}")?;
+        self.out.flush()
     }
 }
 
 mod utils {
     use super::*;
     use std::io::{stdin, Read};
-    pub fn read_u64<'a, C: Read>(label: &'a str, from_client: &mut C, out: &mut RawTerminal<Stdout>) -> Result<u64, ClientError> {
+    use std::os::unix::io::AsRawFd;
+    use futures::{select, future::FusedFuture};
+    use tokio::io::unix::AsyncFd;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+    /// Whether the bytes of every frame sent and response received are logged to stderr, set by `--verbose`.
+    static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+    /// Turns logging the bytes on the wire to stderr on or off.
+    pub fn set_verbose(verbose: bool) {
+        VERBOSE.store(verbose, Ordering::Relaxed);
+    }
+
+    /// Writes `bytes` to `out` as a single line of hex, prefixed with `direction`, `>` for sent and `<` for received.
+    ///
+    /// Lines end in `\r\n` when `raw` is set, since a terminal in raw mode does not return the cursor on a newline.
+    pub fn write_wire<W: Write>(out: &mut W, direction: char, bytes: &[u8], raw: bool) -> io::Result<()> {
+        let mut line = String::with_capacity(3 * bytes.len() + 4);
+        line.push(direction);
+        for byte in bytes {
+            line.push_str(&format!(" {byte:02x}"));
+        }
+        line.push_str(if raw { "\r\n" } else { "\n" });
+        // one write per line, so the line is not split by anything else written in between
+        out.write_all(line.as_bytes())?;
+        out.flush()
+    }
+
+    /// Logs the bytes of `frame` as sent to the server, if verbose.
+    pub fn trace_frame(frame: &Frame) {
+        trace_wire('>', &frame.as_bytes());
+    }
+
+    /// Logs the serialized tag of `response` as received from the server, if verbose.
+    pub fn trace_response(response: &Response) {
+        trace_wire('<', &response.serialize());
+    }
+
+    fn trace_wire(direction: char, bytes: &[u8]) {
+        if !VERBOSE.load(Ordering::Relaxed) {
+            return;
+        }
+        // stderr shares the terminal with the interface unless redirected
+        let stderr = io::stderr();
+        let raw = termion::is_tty(&stderr);
+        if let Err(e) = write_wire(&mut stderr.lock(), direction, bytes, raw) {
+            error!(error = %e, "unable to log wire bytes");
+        }
+    }
+
+    /// Checks the first response sent by the server, refusing to continue with a server that speaks a different
+    /// protocol or has no room for the client.
+    ///
+    /// # Returns
+    /// `Result<([u8; AUTH_LEN], u128), ClientError>`, the challenge the server sent for the client to authenticate
+    /// with, along with the token of the session it opened for the client.
+    pub fn check_greeting(response: Response) -> Result<([u8; AUTH_LEN], u128), ClientError> {
+        match response {
+            Response::ConnectionOk { version, challenge, session, .. } if version == PROTOCOL_VERSION => Ok((challenge, session)),
+            Response::ConnectionOk { version, .. } => {
+                error!(server_version = version, client_version = PROTOCOL_VERSION, "server protocol version does not match");
+                Err(ClientError::IllegalResponse(response))
+            }
+            Response::ServerBusy { .. } => Err(ClientError::ServerBusy),
+            response => Err(ClientError::IllegalResponse(response)),
+        }
+    }
+
+    /// Checks the first response sent by the server with `check_greeting`, then answers its challenge with the
+    /// HMAC of it under `auth_key`, if given.
+    ///
+    /// The server sends no answer to a successful handshake, a wrong key is only reported by the `AuthFailed` sent
+    /// in reply to the next request.
+    ///
+    /// # Returns
+    /// `Result<u128, ClientError>`, the token of the session the server opened for the client.
+    pub async fn greet<W: AsyncWriteExt + Unpin>(response: Response, to_server: &mut W, auth_key: Option<&AuthKey>) -> Result<u128, ClientError> {
+        let (challenge, session) = check_greeting(response)?;
+        if let Some(key) = auth_key {
+            let frame = Frame::Auth { request_id: next_request_id(), hmac: key.sign(&challenge) };
+            trace_frame(&frame);
+            frame.to_writer(to_server)
+                .await
+                .map_err(|e| ClientError::SendRequest(e))?;
+        }
+        Ok(session)
+    }
+
+    /// Reads the server's greeting and answers it with `greet`, then presents the token `resume` of a session held
+    /// over an earlier connection, if given, in a `Frame::Resume` to take that session over.
+    ///
+    /// A server that no longer knows the session keeps the client in the fresh session it opened for it.
+    ///
+    /// # Returns
+    /// `Result<u128, ClientError>`, the token of the session the client holds once connected, to be resumed should
+    /// the connection be lost.
+    pub async fn handshake<R, W>(from_server: &mut R, to_server: &mut W, auth_key: Option<&AuthKey>, resume: Option<u128>) -> Result<u128, ClientError>
+    where
+        R: AsyncReadExt + Unpin,
+        W: AsyncWriteExt + Unpin,
+    {
+        let response = Response::from_reader(&mut *from_server)
+            .await
+            .map_err(|e| ClientError::Response(e))
+            .inspect(trace_response)?;
+        let session = greet(response, &mut *to_server, auth_key).await?;
+        let Some(session) = resume else {
+            return Ok(session);
+        };
+
+        let frame = Frame::Resume { request_id: next_request_id(), session };
+        trace_frame(&frame);
+        frame.to_writer(&mut *to_server)
+            .await
+            .map_err(|e| ClientError::SendRequest(e))?;
+        // The server answers with another `ConnectionOk`, carrying the session the client holds afterwards
+        let response = Response::from_reader(&mut *from_server)
+            .await
+            .map_err(|e| ClientError::Response(e))
+            .inspect(trace_response)?;
+        match response {
+            Response::ConnectionOk { session, .. } => Ok(session),
+            response => Err(ClientError::IllegalResponse(response)),
+        }
+    }
+
+    /// Describes the response that ends a prime check, its verdict or the reason it has none, as the color and
+    /// text it is displayed in.
+    ///
+    /// # Returns
+    /// `Result<(ThemeColor, String), ClientError>`, a `ClientError::IllegalResponse` carrying `response` if it
+    /// does not end a prime check.
+    pub fn prime_verdict(response: Response, theme: Theme) -> Result<(ThemeColor, String), ClientError> {
+        let verdict = match response {
+            Response::Prime { p, prob, rounds, .. } => (theme.text, format!("{p} is prime with probability {prob:.10} after {rounds} rounds")),
+            Response::NotPrime { p, witness, .. } => (theme.text, format!("{p} is not prime, witnessed by the base {witness}")),
+            Response::RateLimited { .. } => (theme.warning, "too many requests, slow down and try again".to_string()),
+            Response::Timeout { millis, .. } => (theme.warning, format!("prime check timed out after {millis} ms")),
+            Response::Cancelled { .. } => (theme.warning, "prime check cancelled".to_string()),
+            Response::Error { code, message, .. } => (theme.error, format!("server error {code}: {message}")),
+            response => return Err(ClientError::IllegalResponse(response)),
+        };
+        Ok(verdict)
+    }
+
+    /// Returns a fresh id for the next request sent to the server, ids start at 1 since 0 is used by the server
+    /// for responses that do not answer a request.
+    pub fn next_request_id() -> u32 {
+        static NEXT_REQUEST_ID: AtomicU32 = AtomicU32::new(1);
+        NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn read_u64<'a, C: Read>(label: &'a str, from_client: &mut C, out: &mut RawTerminal<Stdout>, theme: Theme) -> Result<u64, ClientError> {
         let prompt = format!("enter {}: ", label);
         loop {
             write!(
@@ -374,19 +1275,75 @@ mod utils {
             // let mut buf = String::default();
             // from_client.read_to_string(&mut buf)
             //     .map_err(|e| ClientError::Read(e))?;
-            let buf = read_client_input(out, 5, prompt.len() as u16)?;
+            let buf = read_client_input(out, 5, prompt.len() as u16, None)?;
 
             match u64::from_str(buf.trim_end_matches('\n')) {
                 Ok(v) => return Ok(v),
-                Err(e) => incorrect_input_prompt("please enter a valid unsigned inter", out)?,
+                Err(e) => incorrect_input_prompt("please enter a valid unsigned inter", out, theme)?,
             }
         }
     }
 
-    pub fn incorrect_input_prompt(prompt: &str, out: &mut RawTerminal<Stdout>) -> Result<(), ClientError> {
+    /// Completes once `q` or Esc is pressed, without blocking the runtime while waiting for keys.
+    pub async fn cancel_key() -> io::Result<()> {
+        let stdin_fd = AsyncFd::new(io::stdin().as_raw_fd())?;
+        loop {
+            let mut guard = stdin_fd.readable().await?;
+            // a key is waiting, so reading it does not block
+            match io::stdin().keys().next() {
+                Some(Ok(Key::Char('q') | Key::Esc)) => return Ok(()),
+                Some(Err(e)) => return Err(e),
+                _ => guard.clear_ready(),
+            }
+        }
+    }
+
+    /// Reads the next response from the server. If `cancel` completes first, a request to cancel `request_id` is
+    /// sent and the response is still waited for.
+    pub async fn next_response<R, W, F>(from_server: &mut R, to_server: &mut W, cancel: &mut F, request_id: u32) -> Result<Response, ClientError>
+    where
+        R: AsyncReadExt + Unpin,
+        W: AsyncWriteExt + Unpin,
+        F: FusedFuture<Output = io::Result<()>> + Unpin,
+    {
+        // the read is kept across iterations, so no partially read response is lost
+        let mut response = Box::pin(Response::from_reader(&mut *from_server).fuse());
+        loop {
+            select! {
+                r = response => return r.map_err(|e| ClientError::Response(e)).inspect(trace_response),
+                r = &mut *cancel => match r {
+                    Ok(()) => {
+                        info!(request_id, "cancelling computation");
+                        let frame = Frame::Cancel { request_id };
+                        trace_frame(&frame);
+                        frame.to_writer(to_server)
+                            .await
+                            .map_err(|e| ClientError::SendRequest(e))?;
+                    }
+                    Err(e) => error!(error = %e, "unable to watch for the cancel key"),
+                },
+            }
+        }
+    }
+
+    /// The color a run's performance classification is displayed in.
+    pub fn performance_color(performance: Performance, theme: Theme) -> ThemeColor {
+        match performance {
+            Performance::Fast => theme.highlight,
+            Performance::Typical => theme.text,
+            Performance::Slow => theme.warning,
+        }
+    }
+
+    /// Checks that `log` is a solution to `g^log = h (mod p)`.
+    pub fn verify_log(log: u64, g: u64, h: u64, p: u64) -> bool {
+        p > 1 && mod_pow(g, log, p) == h
+    }
+
+    pub fn incorrect_input_prompt(prompt: &str, out: &mut RawTerminal<Stdout>, theme: Theme) -> Result<(), ClientError> {
         write!(
             out, "{}{}{}{}{}",
-            cursor::Goto(1, 4), color::Fg(color::Rgb(242, 217, 104)),
+            cursor::Goto(1, 4), color::Fg(theme.warning),
             clear::CurrentLine, prompt,
             color::Fg(color::Reset)
         ).map_err(|e| ClientError::Write(e))?;
@@ -394,7 +1351,8 @@ mod utils {
         Ok(())
     }
 
-    pub fn read_client_input<W: Write>(out: &mut W, row: u16, col: u16) -> Result<String, ClientError> {
+    /// Reads a line of input typed at `row` and `col`, the arrow and page keys scroll `table` if given.
+    pub fn read_client_input<W: Write>(out: &mut W, row: u16, col: u16, mut table: Option<&mut ScrollTable>) -> Result<String, ClientError> {
         let mut keys = stdin().keys();
         let mut buf = String::default();
 
@@ -422,6 +1380,22 @@ mod utils {
                     out.flush().map_err(|e| ClientError::Write(e))?;
                     buf.push(c);
                 }
+                Some(Ok(key @ (Key::Up | Key::Down | Key::PageUp | Key::PageDown))) => {
+                    let Some(table) = table.as_deref_mut() else { continue };
+                    let delta = match key {
+                        Key::Up => -1,
+                        Key::Down => 1,
+                        Key::PageUp => -table.page(),
+                        _ => table.page(),
+                    };
+                    if table.scroll(delta) {
+                        table.draw(out).map_err(|e| ClientError::Write(e))?;
+                        write!(
+                            out, "{}", cursor::Goto(col + buf.len() as u16, row)
+                        ).map_err(|e| ClientError::Write(e))?;
+                        out.flush().map_err(|e| ClientError::Write(e))?;
+                    }
+                }
                 Some(Err(e)) => return Err(ClientError::Write(e)),
                 _ => {}
             }
@@ -430,4 +1404,342 @@ mod utils {
 
         Ok(buf)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use discrete_log_server::{error_code, FramedCodec};
+
+    #[test]
+    fn handshake_rejects_protocol_version_mismatch() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let bytes = Response::ConnectionOk { request_id: 0, version: PROTOCOL_VERSION + 1, session: 0, challenge: [0; AUTH_LEN] }.as_bytes();
+        let res = rt.block_on(utils::handshake(&mut &bytes[..], &mut tokio::io::sink(), None, None));
+        assert!(matches!(res, Err(ClientError::IllegalResponse(Response::ConnectionOk { .. }))));
+
+        let bytes = Response::ServerBusy { request_id: 0 }.as_bytes();
+        let res = rt.block_on(utils::handshake(&mut &bytes[..], &mut tokio::io::sink(), None, None));
+        assert!(matches!(res, Err(ClientError::ServerBusy)));
+
+        // Any response other than `ConnectionOk` is rejected as well
+        let bytes = Response::NotPrime { request_id: 0, p: 15, witness: 2 }.as_bytes();
+        let res = rt.block_on(utils::handshake(&mut &bytes[..], &mut tokio::io::sink(), None, None));
+        assert!(matches!(res, Err(ClientError::IllegalResponse(Response::NotPrime { p: 15, .. }))));
+    }
+
+    #[test]
+    fn greeting_answers_challenge_with_auth_key() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let challenge = [9; AUTH_LEN];
+        let greeting = || Response::ConnectionOk { request_id: 0, version: PROTOCOL_VERSION, session: 1, challenge };
+
+        let mut sent = Vec::new();
+        assert_eq!(rt.block_on(utils::greet(greeting(), &mut sent, None)).unwrap(), 1);
+        assert!(sent.is_empty());
+
+        let key = AuthKey::new("shared secret");
+        assert_eq!(rt.block_on(utils::greet(greeting(), &mut sent, Some(&key))).unwrap(), 1);
+        let Frame::Auth { hmac, .. } = rt.block_on(Frame::from_reader(&mut &sent[..])).unwrap() else {
+            panic!("expected `Frame::Auth`");
+        };
+        assert!(key.verify(&challenge, &hmac));
+    }
+
+    #[test]
+    fn wire_bytes_are_written_in_hex() {
+        let mut out = vec![];
+        let bytes = FramedCodec { checksummed: false }.encode(&Frame::Stats { request_id: 1 }.serialize()).unwrap();
+        utils::write_wire(&mut out, '>', &bytes, false).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "> 02 00 06 01\n");
+
+        // In raw mode the cursor has to be returned to the start of the line explicitly
+        let mut out = vec![];
+        utils::write_wire(&mut out, '<', &[0x0c, 0xff], true).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "< 0c ff\r\n");
+    }
+
+    #[test]
+    fn interface_debug_names_the_state() {
+        let states = [
+            (Interface::Home, "Home"),
+            (Interface::Quit, "Quit"),
+            (Interface::Prime { request_id: 1 }, "Prime"),
+            (Interface::PrimitiveRoot, "PrimitiveRoot"),
+            (Interface::Log { request_id: 2, p: 5011 }, "Log"),
+            (Interface::RSA { request_id: 3, n: 1782886219 }, "RSA"),
+            (Interface::Stats, "Stats"),
+            (Interface::ReturnHome { row: 6, alt_screen: None, table: None }, "ReturnHome { row: 6, .. }"),
+        ];
+        for (state, expected) in states {
+            assert_eq!(format!("{state:?}"), expected);
+        }
+    }
+
+    #[test]
+    fn illegal_states_are_returned_in_the_error() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let res = rt.block_on(Interface::Prime { request_id: 7 }.parse_request(tokio::io::sink(), io::empty(), Theme::DEFAULT, &mut None));
+        assert!(matches!(res, Err(ClientError::InterfaceState(Interface::Prime { request_id: 7 }))));
+
+        let res = rt.block_on(Interface::Quit.receive_response(tokio::io::empty(), tokio::io::sink(), None, DEFAULT_MAX_ROWS, Theme::DEFAULT));
+        match res {
+            Err(e @ ClientError::InterfaceState(Interface::Quit)) => assert_eq!(e.to_string(), "interface entered illegal state: Quit"),
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+
+    #[test]
+    fn prime_verdict_rejects_other_responses() {
+        let (_, message) = utils::prime_verdict(Response::NotPrime { request_id: 1, p: 15, witness: 2 }, Theme::DEFAULT).unwrap();
+        assert_eq!(message, "15 is not prime, witnessed by the base 2");
+
+        // A failure on the server ends the check with its message, rather than tearing down the connection
+        let response = Response::Error { request_id: 1, code: error_code::INTERNAL, message: "the server failed to compute the result".to_string(), batch_index: None };
+        let (_, message) = utils::prime_verdict(response, Theme::DEFAULT).unwrap();
+        assert_eq!(message, "server error 2: the server failed to compute the result");
+
+        // An RSA result sent in answer to a prime check is handed back, along with its type byte
+        let response = Response::SuccessfulRSA { request_id: 1, p: 53, q: 61, ratio: 0.5, iterations: 1, performance: Performance::Fast };
+        match utils::prime_verdict(response, Theme::DEFAULT) {
+            Err(ClientError::IllegalResponse(response)) => assert_eq!(response.serialize()[0], 8),
+            res => panic!("unexpected result {res:?}"),
+        }
+    }
+
+    #[test]
+    fn mono_theme_has_no_rgb_escapes() {
+        let Theme { header, text, highlight, warning, error } = Theme::MONO;
+        for fg in [header, text, highlight, warning, error] {
+            assert_eq!(color::Fg(fg).to_string(), color::Fg(color::Reset).to_string());
+        }
+        let (fg, _) = utils::prime_verdict(Response::Cancelled { request_id: 1, batch_index: None }, Theme::MONO).unwrap();
+        assert!(!color::Fg(fg).to_string().contains("38;2;"));
+
+        // The default theme draws in true color
+        assert_eq!(color::Fg(Theme::DEFAULT.error).to_string(), color::Fg(color::Rgb(227, 61, 61)).to_string());
+        assert!(color::Fg(utils::performance_color(Performance::Slow, Theme::DEFAULT)).to_string().contains("38;2;"));
+    }
+
+    #[test]
+    fn next_response_sends_cancel() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (client, server) = tokio::io::duplex(4096);
+        let (mut from_server, mut to_server) = tokio::io::split(client);
+        let (mut server_reader, mut server_writer) = tokio::io::split(server);
+
+        let response = rt.block_on(async {
+            // The server only answers once the computation is cancelled
+            let mock = async move {
+                let frame = Frame::from_reader(&mut server_reader).await.unwrap();
+                assert_eq!(frame, Frame::Cancel { request_id: 7 });
+                server_writer.write_all(&Response::Cancelled { request_id: 7, batch_index: None }.as_bytes()).await.unwrap();
+            };
+            let mut cancel = futures::future::ready(Ok(())).fuse();
+            let (res, _) = futures::join!(utils::next_response(&mut from_server, &mut to_server, &mut cancel, 7), mock);
+            res
+        });
+        assert!(matches!(response, Ok(Response::Cancelled { request_id: 7, batch_index: None })));
+    }
+
+    #[test]
+    fn query_from_str_test() {
+        assert_eq!(Query::from_str("log 2 2495 5011"), Ok(Query::Log { g: 2, h: 2495, p: 5011 }));
+        assert_eq!(Query::from_str("  RSA 1782886219 "), Ok(Query::RSA { n: 1782886219, e: Query::DEFAULT_EXPONENT }));
+        assert_eq!(Query::from_str("rsa 1782886219 3"), Ok(Query::RSA { n: 1782886219, e: 3 }));
+        assert_eq!(Query::from_str("prime 15239131"), Ok(Query::Prime { p: 15239131, seed: None }));
+        assert_eq!(Query::from_str("prime 15239131 42"), Ok(Query::Prime { p: 15239131, seed: Some(42) }));
+        assert_eq!(Query::from_str("bsgs 2 2495 5011"), Ok(Query::Bsgs { g: 2, h: 2495, p: 5011 }));
+        assert_eq!(Query::from_str("stats"), Ok(Query::Stats));
+        assert_eq!(Query::from_str("root 5011"), Ok(Query::PrimitiveRoot { p: 5011 }));
+        assert!(Query::from_str("root").is_err());
+        assert!(Query::from_str("").is_err());
+        assert!(Query::from_str("log 2 2495").is_err());
+        assert!(Query::from_str("prime -7").is_err());
+        assert!(Query::from_str("factor 15").is_err());
+
+        assert_eq!(Query::Log { g: 2, h: 2495, p: 5011 }.to_frame(7), Frame::Log { request_id: 7, g: 2, h: 2495, p: 5011, method: LogMethod::PollardsRho });
+    }
+
+    #[test]
+    fn submit_remembers_the_query_to_rerun() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let query = Query::Log { g: 2, h: 2495, p: 5011 };
+        let mut last_query = None;
+
+        let mut first = Vec::new();
+        let Interface::Log { request_id: first_id, p: 5011 } = rt.block_on(Interface::submit(query, &mut first, &mut last_query)).unwrap() else {
+            panic!("expected the `Log` state");
+        };
+        assert_eq!(last_query, Some(query));
+
+        // Re-running sends the same frame again, under a fresh request id
+        let mut second = Vec::new();
+        let Interface::Log { request_id: second_id, p: 5011 } = rt.block_on(Interface::submit(last_query.unwrap(), &mut second, &mut last_query)).unwrap() else {
+            panic!("expected the `Log` state");
+        };
+        assert_ne!(first_id, second_id);
+        assert_eq!(rt.block_on(Frame::from_reader(&mut &first[..])).unwrap(), query.to_frame(first_id));
+        assert_eq!(rt.block_on(Frame::from_reader(&mut &second[..])).unwrap(), query.to_frame(second_id));
+        assert_eq!(last_query, Some(query));
+
+        let state = rt.block_on(Interface::submit(Query::Stats, tokio::io::sink(), &mut last_query)).unwrap();
+        assert!(matches!(state, Interface::Stats));
+        assert_eq!(last_query, Some(Query::Stats));
+    }
+
+    #[test]
+    fn query_validate_rejects_out_of_range_numbers() {
+        assert_eq!(Query::Log { g: 2, h: 2495, p: 1 }.validate(), Err("the prime 1 is too small, please enter a prime of at least 2".to_string()));
+        assert!(Query::Log { g: 2, h: 2495, p: 0 }.validate().is_err());
+        assert!(Query::Bsgs { g: 2, h: 2495, p: 1 }.validate().is_err());
+        assert!(Query::PrimitiveRoot { p: 0 }.validate().is_err());
+        assert_eq!(Query::RSA { n: 3, e: Query::DEFAULT_EXPONENT }.validate(), Err("the modulus 3 is too small, please enter a modulus of at least 4".to_string()));
+        assert_eq!(Query::Prime { p: 0, seed: None }.validate(), Err("0 is neither prime nor composite, please enter a number of at least 2".to_string()));
+        assert!(Query::Prime { p: 1, seed: Some(7) }.validate().is_err());
+
+        // Numbers only the server can judge, such as a composite `p`, are sent
+        assert_eq!(Query::Log { g: 2, h: 2495, p: 2 }.validate(), Ok(()));
+        assert_eq!(Query::Log { g: 2, h: 2495, p: 5010 }.validate(), Ok(()));
+        assert_eq!(Query::RSA { n: 4, e: 3 }.validate(), Ok(()));
+        assert_eq!(Query::Prime { p: 2, seed: None }.validate(), Ok(()));
+        assert_eq!(Query::Stats.validate(), Ok(()));
+    }
+
+    #[test]
+    fn scroll_table_test() {
+        let mut table = ScrollTable::new([String::new(), String::new()], 10, 3);
+        for i in 0..5 {
+            table.push(i.to_string());
+        }
+        // the window follows the newest rows
+        assert_eq!(table.visible().collect::<Vec<_>>(), ["2", "3", "4"]);
+        assert_eq!(table.end_row(), 6);
+
+        assert!(table.scroll(-1));
+        assert_eq!(table.visible().collect::<Vec<_>>(), ["1", "2", "3"]);
+        assert!(table.scroll(-table.page()));
+        assert_eq!(table.visible().collect::<Vec<_>>(), ["0", "1", "2"]);
+        assert!(!table.scroll(-1));
+        assert!(table.scroll(100));
+        assert_eq!(table.visible().collect::<Vec<_>>(), ["2", "3", "4"]);
+        assert!(!table.scroll(1));
+
+        // only the latest rows are kept
+        for i in 5..15 {
+            table.push(i.to_string());
+        }
+        assert!(table.scroll(-100));
+        assert_eq!(table.visible().collect::<Vec<_>>(), ["5", "6", "7"]);
+
+        // a window scrolled up stays put as rows are added
+        table.push("15".to_string());
+        assert_eq!(table.visible().collect::<Vec<_>>(), ["6", "7", "8"]);
+
+        // never fewer rows are kept than fill the window
+        let mut table = ScrollTable::new([String::new(), String::new()], 1, 3);
+        for i in 0..2 {
+            table.push(i.to_string());
+        }
+        assert_eq!(table.visible().collect::<Vec<_>>(), ["0", "1"]);
+        assert_eq!(table.end_row(), 5);
+        assert!(!table.scroll(1));
+    }
+
+    #[test]
+    fn table_layout_fits_the_terminal() {
+        // A 40 column terminal squeezes the columns, cutting long values short
+        let layout = TableLayout::new(7, 40);
+        assert_eq!(layout.width, 4);
+        assert_eq!(layout.rule().len(), 35);
+        assert_eq!(layout.row(["i", "x", "alpha", "beta", "y", "gamma", "delta"]), "i   | x  |alp…|beta| y  |gam…|del…|");
+        assert_eq!(layout.row([12u64, 5011, 123456, 0, 7, 8, 9]).chars().count(), 35);
+
+        let layout = TableLayout::new(5, 40);
+        assert_eq!(layout.width, 7);
+        assert_eq!(layout.rule().len(), 40);
+
+        // A 120 column terminal widens them, up to the width of the largest u64
+        let layout = TableLayout::new(7, 120);
+        assert_eq!(layout.width, 16);
+        assert_eq!(layout.rule().len(), 119);
+        assert_eq!(layout.cell(0, 42), format!("{:<16}", 42));
+        assert_eq!(layout.cell(3, 42), format!("{:^16}", 42));
+
+        let layout = TableLayout::new(5, 120);
+        assert_eq!(layout.width, MAX_COLUMN_WIDTH);
+        assert_eq!(layout.rule().len(), 115);
+        assert_eq!(layout.cell(1, u64::MAX).trim(), u64::MAX.to_string());
+
+        // The layout of an 80 column terminal, and never narrower than the minimum
+        assert_eq!(TableLayout::new(7, 80).width, 10);
+        assert_eq!(TableLayout::new(7, 10).width, MIN_COLUMN_WIDTH);
+
+        // Highlighted cells keep their padding
+        let mut cells = TableLayout::new(2, 40).cells([1, 2]);
+        cells[1] = format!("*{}*", cells[1]);
+        assert_eq!(TableLayout::join(cells), format!("{:<19}|*{:^19}*|", 1, 2));
+    }
+
+    #[test]
+    fn progress_test() {
+        // sqrt(5011) is a little under 71
+        let progress = Progress::new(5011);
+        assert_eq!(progress.percent(0), 0.0);
+        assert!((progress.percent(1) - 100.0 / 71.0).abs() < 1e-9);
+        assert_eq!(progress.percent(71), 100.0);
+        assert_eq!(progress.percent(142), 200.0);
+        assert_eq!(progress.line(35), "step 35 of ~71 (49.3%)");
+
+        // An RSA walk is measured against sqrt(n)
+        let progress = Progress::new(1782886219);
+        assert_eq!(progress.line(4223), "step 4223 of ~42225 (10.0%)");
+
+        // A modulus too small to have a square root above 1 still expects a step
+        assert_eq!(Progress::new(0).line(3), "step 3 of ~1 (300.0%)");
+
+        let mut out = Vec::new();
+        progress.draw(&mut out, 7, 0).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("step 0 of ~42225 (0.0%)"));
+    }
+
+    #[test]
+    fn csv_writer_test() {
+        let mut csv = CsvWriter::new(Vec::new());
+        csv.write_log_header().unwrap();
+        csv.write_log_item(&PollardsLogItem { i: 1, xi: 2, ai: 0, bi: 1, yi: 4, gi: 0, di: 2 }).unwrap();
+        csv.write_log_item(&PollardsLogItem { i: 2, xi: 8, ai: 1, bi: 1, yi: 8, gi: 2, di: 4 }).unwrap();
+        csv.write_rsa_header().unwrap();
+        csv.write_rsa_item(&PollardsRSAFactItem { i: 1, xi: 2, yi: 5, g: 1, n: 15, c: 1 }).unwrap();
+        assert_eq!(String::from_utf8(csv.out).unwrap(), "\
+i,x,alpha,beta,y,gamma,delta
+1,2,0,1,4,0,2
+2,8,1,1,8,2,4
+i,x,y,g,c
+1,2,5,1,1
+");
+    }
+
+    #[test]
+    fn verify_log_test() {
+        let response = Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 60, p: 71, ratio: 0.012839, iterations: 42, performance: Performance::Fast, batch_index: None };
+        let mut tag = response.serialize();
+        match Response::deserialize(&tag).unwrap() {
+            Response::SuccessfulLog { log, g, h, p, .. } => assert!(utils::verify_log(log, g, h, p)),
+            _ => panic!("expected `SuccessfulLog` response"),
+        }
+
+        // corrupt the low byte of the log
+        tag[5] ^= 1;
+        match Response::deserialize(&tag).unwrap() {
+            Response::SuccessfulLog { log, g, h, p, .. } => {
+                assert_eq!(log, 10);
+                assert!(!utils::verify_log(log, g, h, p));
+            }
+            _ => panic!("expected `SuccessfulLog` response"),
+        }
+
+        // a zeroed modulus must not panic
+        assert!(!utils::verify_log(11, 2, 60, 0));
+    }
+}
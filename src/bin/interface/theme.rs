@@ -0,0 +1,104 @@
+//! The colors the terminal interface is drawn in.
+use std::fmt;
+use termion::color::{self, Color};
+
+/// A color of a `Theme`, either a true color or the terminal's own foreground color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeColor {
+    Rgb(u8, u8, u8),
+    Default,
+}
+
+impl Color for ThemeColor {
+    fn write_fg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThemeColor::Rgb(r, g, b) => color::Rgb(r, g, b).write_fg(f),
+            ThemeColor::Default => color::Reset.write_fg(f),
+        }
+    }
+
+    fn write_bg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThemeColor::Rgb(r, g, b) => color::Rgb(r, g, b).write_bg(f),
+            ThemeColor::Default => color::Reset.write_bg(f),
+        }
+    }
+}
+
+/// The colors of the interface, by what they are used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// Titles and table headers
+    pub header: ThemeColor,
+
+    /// Ordinary text
+    pub text: ThemeColor,
+
+    /// Values picked out from the text around them, such as the colliding steps of Pollard's rho
+    pub highlight: ThemeColor,
+
+    /// Notices that need the user's attention, such as invalid input or a request that was refused
+    pub warning: ThemeColor,
+
+    /// Errors reported by the server
+    pub error: ThemeColor,
+}
+
+impl Theme {
+    /// The interface's original colors, for a dark terminal.
+    pub const DEFAULT: Theme = Theme {
+        header: ThemeColor::Rgb(92, 209, 193),
+        text: ThemeColor::Rgb(225, 247, 244),
+        highlight: ThemeColor::Rgb(31, 207, 31),
+        warning: ThemeColor::Rgb(242, 217, 104),
+        error: ThemeColor::Rgb(227, 61, 61),
+    };
+
+    /// No colors at all, everything is drawn in the terminal's own foreground color.
+    pub const MONO: Theme = Theme {
+        header: ThemeColor::Default,
+        text: ThemeColor::Default,
+        highlight: ThemeColor::Default,
+        warning: ThemeColor::Default,
+        error: ThemeColor::Default,
+    };
+
+    /// Fully saturated colors on white text, for readability.
+    pub const HIGH_CONTRAST: Theme = Theme {
+        header: ThemeColor::Rgb(0, 255, 255),
+        text: ThemeColor::Rgb(255, 255, 255),
+        highlight: ThemeColor::Rgb(0, 255, 0),
+        warning: ThemeColor::Rgb(255, 255, 0),
+        error: ThemeColor::Rgb(255, 0, 0),
+    };
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::DEFAULT
+    }
+}
+
+/// The themes selectable with `--theme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ThemeName {
+    /// The original colors, for a dark terminal
+    Default,
+
+    /// No colors, for terminals without true color support
+    Mono,
+
+    /// Fully saturated colors on white text
+    #[value(name = "highcontrast")]
+    HighContrast,
+}
+
+impl From<ThemeName> for Theme {
+    fn from(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Default => Theme::DEFAULT,
+            ThemeName::Mono => Theme::MONO,
+            ThemeName::HighContrast => Theme::HIGH_CONTRAST,
+        }
+    }
+}
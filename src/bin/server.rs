@@ -1,56 +1,265 @@
 //! The executable for running the server
 use std::fmt::{Debug, Display};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::process::ExitCode;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::num::NonZeroUsize;
+use std::time::Duration;
 use clap::Parser;
 use rand;
 use rand::Rng;
-use tokio::net::{ToSocketAddrs, TcpStream, TcpListener};
+use tokio::net::{lookup_host, ToSocketAddrs, TcpStream, TcpListener};
 use tokio_stream::wrappers::{TcpListenerStream, ReceiverStream, UnboundedReceiverStream};
-use tokio::sync::{mpsc::{self, channel, unbounded_channel, UnboundedSender, UnboundedReceiver, Receiver, Sender}};
+use tokio::sync::{Semaphore, mpsc::{self, channel, unbounded_channel, UnboundedSender, UnboundedReceiver, Receiver, Sender}};
 use tokio::task::{self, JoinError, JoinHandle};
-use tokio::io::{AsyncWriteExt, AsyncWrite};
-use tokio::runtime::Builder;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, AsyncWrite, BufReader};
+use tokio::runtime::{Builder, Runtime};
+use tokio::time::{self, Instant};
 use tokio_util::sync::{CancellationToken, DropGuard};
 use tracing::{instrument, error, debug, info, warn, Level};
 use futures::{stream::{Stream, StreamExt, FusedStream}, select, future::{FutureExt, FusedFuture, Fuse}, stream};
-use rand::thread_rng;
-use tokio::net::tcp::OwnedWriteHalf;
+use rand::{rngs::StdRng, SeedableRng};
 use uuid::Uuid;
-use tracing_subscriber::{self, EnvFilter};
-use discrete_log_server::algo::{miller_rabin, PollardsLog, PollardsRSAFact};
+use tracing_subscriber::{self, EnvFilter, fmt::MakeWriter};
+use tokio_rustls::{rustls, TlsAcceptor};
+use discrete_log_server::algo::{bsgs, MAX_BABY_STEPS, composite_witness, factorize, fast_power, is_prime, pohlig_hellman_with_steps, mult_order, primitive_root, AlgoError, Performance, miller_rabin, mod_inverse, solovay_strassen, trial_divide, PollardsLog, PollardsRSAFact, PollardsRSAFactBrent, PollardsRSAFactItem};
 
 use discrete_log_server::prelude::*;
 
+/// The version of the wire protocol spoken by the server, sent to clients when they connect.
+const PROTOCOL_VERSION: u8 = 2;
+
+/// The largest modulus the Pollard's rho walks can handle without their intermediate products overflowing.
+const DEFAULT_MAX_INPUT: u64 = 1 << 32;
+
+/// Keys with a prime factor below this bound are factored by trial division instead of Pollard's rho.
+const TRIAL_DIVISION_LIMIT: u64 = 10_000;
+
+/// A prime `p` is solved with Pohlig-Hellman by `LogMethod::Auto` when no prime factor of `p - 1` exceeds this bound.
+const SMOOTHNESS_BOUND: u64 = 1 << 16;
+
+/// Otherwise a modulus up to this bound is solved with baby-step giant-step by `LogMethod::Auto`, keeping the table
+/// of baby steps to at most a thousand or so entries.
+const AUTO_BSGS_LIMIT: u64 = 1 << 20;
+
+/// The largest modulus solved with baby-step giant-step on request, whose table of baby steps then holds at most
+/// `MAX_BABY_STEPS` entries.
+const BSGS_LIMIT: u64 = MAX_BABY_STEPS * MAX_BABY_STEPS;
+
+/// The number of random starting points a Pollard's rho discrete log is retried from after the walk from 1 fails.
+const LOG_RETRIES: u32 = 3;
+
+/// The number of primality test rounds run on each prime check unless configured otherwise.
+const DEFAULT_MR_ROUNDS: u32 = 20;
+
+/// The operations supported by this build of the server, factoring RSA keys requires the `rsa` feature.
+const SUPPORTED_CAPABILITIES: u32 = if cfg!(feature = "rsa") {
+    capabilities::ALL
+} else {
+    capabilities::ALL & !capabilities::RSA
+};
+
+/// The probabilistic primality test run by the server on prime checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum PrimalityTest {
+    /// The Miller-Rabin test, fooled by a composite with probability at most 1/4 per round
+    MillerRabin,
+
+    /// The Solovay-Strassen test, fooled by a composite with probability at most 1/2 per round
+    SolovayStrassen,
+}
+
+impl PrimalityTest {
+    /// Runs a single round of the test, returning `true` if the base `a` proves `n` composite.
+    fn is_witness(self, n: u64, a: u64) -> bool {
+        match self {
+            PrimalityTest::MillerRabin => miller_rabin(n, a),
+            PrimalityTest::SolovayStrassen => solovay_strassen(n, a),
+        }
+    }
+
+    /// The probability that a number passing `rounds` rounds of the test with random bases is prime.
+    fn probability(self, rounds: u32) -> f64 {
+        let fooled = match self {
+            PrimalityTest::MillerRabin => 0.25,
+            PrimalityTest::SolovayStrassen => 0.5,
+        };
+        1.0 - f64::powi(fooled, rounds.min(i32::MAX as u32) as i32)
+    }
+}
+
+/// Settings that control how the server handles its clients.
+#[derive(Debug, Clone)]
+struct ServerConfig {
+    /// The size of the channel buffers
+    buf_size: usize,
+
+    /// Whether RSA keys are factored using Brent's variant of Pollard's rho
+    brent: bool,
+
+    /// How long a single Pollard's rho computation may run before it is abandoned
+    timeout: Duration,
+
+    /// How long a client may go without starting a frame before it is disconnected
+    idle_timeout: Duration,
+
+    /// The largest modulus accepted for discrete log and RSA requests
+    max_input: u64,
+
+    /// The number of clients that may be connected at once, further clients are rejected
+    max_clients: usize,
+
+    /// The number of requests per second a single client may make
+    rate_limit: u32,
+
+    /// The number of solved discrete logs, and separately RSA factorizations, kept to answer repeated requests
+    cache_size: usize,
+
+    /// The number of primality test rounds with random bases run on each prime check
+    mr_rounds: u32,
+
+    /// The primality test run on each prime check
+    primality_test: PrimalityTest,
+
+    /// The operations the server supports, reported to clients and checked before serving a request
+    capabilities: u32,
+
+    /// Where each handled request is recorded along with its outcome, if anywhere
+    audit: Option<AuditLog>,
+
+    /// The TLS configuration client connections are wrapped in, connections are plaintext without one
+    tls: Option<Arc<rustls::ServerConfig>>,
+
+    /// The key clients must prove they hold before their requests are served, every client is served without one
+    auth_key: Option<AuthKey>,
+
+    /// The number of steps of a single computation streamed to the client, further steps are not sent
+    max_stream_items: Option<usize>,
+
+    /// Whether the time taken by each discrete log, RSA and prime computation is recorded and reported in the stats
+    benchmark: bool,
+
+    /// The number of Pollard's rho walks raced on separate threads to factor a single RSA key, with 1 the key is
+    /// factored by a single walk whose steps are streamed to the client
+    rho_workers: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            buf_size: 1000,
+            brent: false,
+            timeout: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(300),
+            max_input: DEFAULT_MAX_INPUT,
+            max_clients: 1000,
+            rate_limit: 50,
+            cache_size: 1024,
+            mr_rounds: DEFAULT_MR_ROUNDS,
+            primality_test: PrimalityTest::MillerRabin,
+            capabilities: SUPPORTED_CAPABILITIES,
+            audit: None,
+            tls: None,
+            auth_key: None,
+            max_stream_items: None,
+            benchmark: false,
+            rho_workers: 1,
+        }
+    }
+}
+
 /// The main accept loop for the server. Takes an address for the server will be bound to,
 /// listens for incoming connections from clients and handles newly connected clients.
 ///
 /// # Parameters
 /// `server_addrs`, The address the server will be spawned to
+/// `config`, The settings for handling clients
 ///
 /// # Returns
 /// `Result<(), ServerError>`, `Ok(())` in the success case, otherwise `Err(ServerError)`.
 #[instrument(ret, err)]
-async fn accept_loop(server_addrs: impl ToSocketAddrs + Debug + Clone, buf_size: usize) -> Result<(), ServerError> {
+async fn accept_loop(server_addrs: impl ToSocketAddrs + Debug + Clone, config: ServerConfig) -> Result<(), ServerError> {
     // Bind to the given server address
-    let mut listener = TcpListenerStream::new(TcpListener::bind(server_addrs)
-        .await
-        .map_err(|e| ServerError::Connection(e))?);
-    debug!("bound to address successfully");
+    let listener = bind(server_addrs).await.map_err(|e| ServerError::Connection(e))?;
+    debug!(local_addr = ?listener.local_addr(), "bound to address successfully");
+
+    serve(TcpListenerStream::new(listener), config).await
+}
+
+/// Resolves `server_addrs` and binds a listener to the first of the resolved addresses that can be bound,
+/// so a host name resolving to both an IPv6 and an IPv4 address is served on whichever is available.
+///
+/// # Returns
+/// `Result<TcpListener, std::io::Error>`, the bound listener, or the error from the last address tried.
+async fn bind(server_addrs: impl ToSocketAddrs) -> Result<TcpListener, std::io::Error> {
+    let mut last_err = None;
+    for addr in lookup_host(server_addrs).await? {
+        match TcpListener::bind(addr).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) => {
+                warn!(?addr, error = %e, "unable to bind address, trying the next one");
+                last_err = Some(e);
+            }
+        }
+    }
+    Err(last_err.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "address did not resolve to any socket address")))
+}
+
+/// Handles clients from a stream of incoming connections, shutting down gracefully once the stream ends.
+///
+/// # Parameters
+/// `listener`, The stream of incoming client connections
+/// `config`, The settings for handling clients
+///
+/// # Returns
+/// `Result<(), ServerError>`, `Ok(())` in the success case, otherwise `Err(ServerError)`.
+#[instrument(ret, err, skip(listener))]
+async fn serve<S>(mut listener: S, config: ServerConfig) -> Result<(), ServerError>
+where
+    S: Stream<Item = std::io::Result<TcpStream>> + Unpin
+{
 
     // Channel for connecting to main broker task
-    let (broker_send, broker_recv) = channel::<Event>(buf_size);
+    let (broker_send, broker_recv) = channel::<Event>(config.buf_size);
 
     // Spawn broker task
-    let mut broker_handle = task::spawn(main_broker(broker_recv, buf_size));
+    let mut broker_handle = task::spawn(main_broker(broker_recv, config.clone()));
     debug!("broker task spawned");
 
+    // Limits the number of clients connected at once
+    let client_permits = Arc::new(Semaphore::new(config.max_clients));
+    let tls = config.tls.clone().map(TlsAcceptor::from);
+
     // Accept loop
     while let Some(socket_res) = listener.next().await {
         // Parse the result
         match socket_res {
-            Ok(socket) => {
-                info!(peer_addr = ?socket.peer_addr(), "Accepting {:?}", socket.peer_addr());
-                task::spawn(client_read_task(socket, broker_send.clone()));
+            Ok(mut socket) => {
+                // Each connected client holds a permit until its read task finishes
+                match client_permits.clone().try_acquire_owned() {
+                    Ok(permit) => {
+                        info!(peer_addr = ?socket.peer_addr(), "Accepting {:?}", socket.peer_addr());
+                        let broker_send = broker_send.clone();
+                        let tls = tls.clone();
+                        task::spawn(async move {
+                            let _permit = permit;
+                            client_read_task(socket, tls, broker_send, config.idle_timeout).await
+                        });
+                    }
+                    Err(_) => {
+                        warn!(peer_addr = ?socket.peer_addr(), max_clients = config.max_clients, "rejecting client, server is busy");
+                        task::spawn(async move {
+                            let busy = Response::ServerBusy { request_id: 0 };
+                            if let Err(e) = busy.to_writer(&mut socket).await {
+                                error!(error = ?e, "unable to send `ServerBusy` response");
+                            }
+                        });
+                    }
+                }
             }
             Err(e) => error!(error = ?e, "Unable to accept client"),
         }
@@ -68,25 +277,54 @@ async fn accept_loop(server_addrs: impl ToSocketAddrs + Debug + Clone, buf_size:
 
 /// The task that reads packets sent from the client.
 ///
-/// Takes a socket and a sending half of a channel. Informs the broker of a new client connection and then begins
-/// listening for incoming packets sent by the client.
+/// Takes a socket and a sending half of a channel. Completes the TLS handshake if the server uses TLS, informs the
+/// broker of a new client connection and then begins listening for incoming packets sent by the client. A client
+/// whose connection closes, or that starts no frame within `idle_timeout`, is treated as having quit.
 ///
 /// # Parameters
 /// `socket`, The socket that the client will send packets over
+/// `tls`, The acceptor the connection is wrapped in, if the server uses TLS
 /// `broker_send`, The sending half of the channel to send parsed events to
+/// `idle_timeout`, How long to wait for the client to start its next frame, or to complete the TLS handshake
 ///
 /// # Returns
 /// `Result<(), ServerError>`, `Ok(())` in the success case otherwise `Err(ServerError)`.
-#[instrument(ret, err, skip(broker_send), fields(peer_addr = ?socket.peer_addr()))]
-async fn client_read_task(socket: TcpStream, broker_send: Sender<Event>) -> Result<(), ServerError> {
-    // Split the socket into reader and writer
-    let (mut client_reader, client_writer) = socket.into_split();
+#[instrument(ret, err, skip(tls, broker_send), fields(peer_addr = ?socket.peer_addr()))]
+async fn client_read_task(socket: TcpStream, tls: Option<TlsAcceptor>, broker_send: Sender<Event>, idle_timeout: Duration) -> Result<(), ServerError> {
+    // Split the socket into reader and writer, by way of a TLS session if the server uses one
+    let (client_reader, client_writer): (Box<dyn AsyncRead + Send + Unpin>, Box<dyn ClientWriter>) = match tls {
+        Some(acceptor) => {
+            let handshake = time::timeout(idle_timeout, acceptor.accept(socket))
+                .await
+                .unwrap_or_else(|_| Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "TLS handshake timed out")));
+            match handshake {
+                Ok(stream) => {
+                    let (reader, writer) = tokio::io::split(stream);
+                    (Box::new(reader), Box::new(writer))
+                }
+                Err(e) => {
+                    // The client never connected, so there is nothing to tell the broker
+                    warn!(error = %e, "TLS handshake with client failed");
+                    return Ok(());
+                }
+            }
+        }
+        None => {
+            let (reader, writer) = socket.into_split();
+            (Box::new(reader), Box::new(writer))
+        }
+    };
+    let mut client_reader = BufReader::new(client_reader);
     // unique id for the client
     let peer_id = Uuid::new_v4();
     // Cancellation token for graceful shutdown
     let token = CancellationToken::new();
     let shutdown_token = token.child_token();
+    // The broker cancels the shutdown token itself if it rejects the client
+    let rejected = shutdown_token.clone();
     let _token = token.drop_guard();
+    // A session may only be resumed by the first frame the client sends, besides its answer to the challenge
+    let mut resumable = true;
 
     // Create new client event to inform broker a new client has connected
     let event = Event::NewClient {
@@ -101,20 +339,84 @@ async fn client_read_task(socket: TcpStream, broker_send: Sender<Event>) -> Resu
         .map_err(|_e| ServerError::ChannelSend(format!("Client {} unable to send event to broker", peer_id)))?;
 
     loop {
-        let frame = Frame::from_reader(&mut client_reader)
-            .await
-            .map_err(|e| ServerError::Read(e))?;
+        // Only the wait for the first byte counts towards the idle timeout, a frame that has started is read in full
+        let read = async {
+            let started = time::timeout(idle_timeout, client_reader.fill_buf()).await.is_ok();
+            if started {
+                Some(Frame::from_reader(&mut client_reader).await)
+            } else {
+                None
+            }
+        };
+        let frame = select! {
+            frame = read.fuse() => frame,
+            _ = rejected.cancelled().fuse() => {
+                warn!(peer_id = ?peer_id, "client {} was rejected by the broker, read task is exiting", peer_id);
+                break;
+            }
+        };
+        let Some(frame) = frame else {
+            info!(peer_id = ?peer_id, ?idle_timeout, "client {} was idle for too long, disconnecting it", peer_id);
+            broker_send.send(Event::Quit { peer_id })
+                .await
+                .map_err(|_e| ServerError::ChannelSend(format!("Client {} unable to send event to main broker", peer_id)))?;
+            break;
+        };
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                // The whole tag was consumed, so the stream is still aligned and only this frame is dropped
+                warn!(peer_id = ?peer_id, error = %e, "client {} sent an invalid frame, dropping it", peer_id);
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                // The connection dropped without a `Quit`, so quit on the client's behalf
+                info!(peer_id = ?peer_id, "client {} disconnected", peer_id);
+                broker_send.send(Event::Quit { peer_id })
+                    .await
+                    .map_err(|_e| ServerError::ChannelSend(format!("Client {} unable to send event to main broker", peer_id)))?;
+                break;
+            }
+            Err(e) => return Err(ServerError::Read(e)),
+        };
 
         // Match on frame
+        let first_frame = match frame {
+            Frame::Auth { .. } => resumable,
+            _ => std::mem::replace(&mut resumable, false),
+        };
         let event = match frame {
-            Frame::Log { g, h, p } => Event::Log { peer_id, g, h, p },
-            Frame::RSA { n, e} => Event::RSA { peer_id, n },
-            Frame::Prime { p} => Event::Prime { peer_id, p },
-            Frame::Quit => {
+            Frame::Log { request_id, g, h, p, method } => Event::Log { peer_id, request_id, g, h, p, batch_index: None, method },
+            Frame::RSA { request_id, n, e } => Event::RSA { peer_id, request_id, n, e },
+            Frame::Prime { request_id, p, seed } => Event::Prime { peer_id, request_id, p, seed },
+            Frame::Bsgs { request_id, g, h, p } => Event::Bsgs { peer_id, request_id, g, h, p },
+            Frame::Stats { request_id } => Event::Stats { peer_id, request_id },
+            Frame::Cancel { request_id } => Event::Cancel { peer_id, request_id },
+            Frame::PrimitiveRoot { request_id, p } => Event::PrimitiveRoot { peer_id, request_id, p },
+            Frame::Capabilities { request_id } => Event::Capabilities { peer_id, request_id },
+            Frame::Ping { request_id, nonce } => Event::Ping { peer_id, request_id, nonce },
+            Frame::Resume { request_id, session } if first_frame => Event::Resume { peer_id, request_id, session },
+            Frame::Resume { .. } => {
+                warn!(peer_id = ?peer_id, "client {} tried to resume a session after its first frame, dropping it", peer_id);
+                continue;
+            }
+            Frame::Auth { request_id, hmac } => Event::Auth { peer_id, request_id, hmac },
+            Frame::ListPeers { request_id } => Event::ListPeers { peer_id, request_id },
+            Frame::Kick { request_id, peer_id: target } => Event::Kick { peer_id, request_id, target },
+            Frame::Batch { request_id, items } => {
+                // Each query of the batch is handled by the broker as its own log request
+                for (i, (g, h, p)) in items.into_iter().enumerate() {
+                    broker_send.send(Event::Log { peer_id, request_id, g, h, p, batch_index: Some(i as u32), method: LogMethod::PollardsRho })
+                        .await
+                        .map_err(|_e| ServerError::ChannelSend(format!("Client {} unable to send event to main broker", peer_id)))?;
+                }
+                continue;
+            }
+            Frame::Quit { .. } => {
                 // The client is quitting the application, so break
                 broker_send.send(Event::Quit { peer_id })
                     .await
-                    .map_err(|_e| ServerError::ChannelSend("Client {} unable to send event to main broker".to_string()))?;
+                    .map_err(|_e| ServerError::ChannelSend(format!("Client {} unable to send event to main broker", peer_id)))?;
                 info!(peer_id = ?peer_id, "Client {} read task is exiting loop", peer_id);
                 break;
             },
@@ -123,7 +425,7 @@ async fn client_read_task(socket: TcpStream, broker_send: Sender<Event>) -> Resu
         // Send the event to the broker
         broker_send.send(event)
             .await
-            .map_err(|_e| ServerError::ChannelSend("Client {} unable to send event to main broker".to_string()))?;
+            .map_err(|_e| ServerError::ChannelSend(format!("Client {} unable to send event to main broker", peer_id)))?;
     }
 
     // _token will be dropped after task finishes, sending a shutdown signal to the write task
@@ -141,11 +443,14 @@ async fn client_read_task(socket: TcpStream, broker_send: Sender<Event>) -> Resu
 /// `client_writer`, The write half of the client's socket
 /// `broker_recv`, The receiving half of the channel connecting this task with the main broker
 /// `token`, The `CancellationToken` that informs this task to shutdown
+/// `audit`, The audit log the outcome of each request is recorded in once it is written, if any
+/// `max_stream_items`, The number of steps of each streamed computation written to the client, if limited. The
+/// computation still runs to completion and its result is written regardless
 ///
 /// # Returns
 /// `Result<(), ServerError>`, In the success case a `Ok(())` will be returned, otherwise `Err(ServerError)`.
-#[instrument(ret, err, skip(client_writer, broker_recv, token))]
-async fn client_write_task(peer_id: Uuid, client_writer: &mut OwnedWriteHalf, broker_recv: &mut Receiver<Response>, token: CancellationToken) -> Result<(), ServerError> {
+#[instrument(ret, err, skip(client_writer, broker_recv, token, audit))]
+async fn client_write_task(peer_id: Uuid, client_writer: &mut Box<dyn ClientWriter>, broker_recv: &mut Receiver<Reply>, token: CancellationToken, audit: Option<AuditLog>, max_stream_items: Option<usize>) -> Result<(), ServerError> {
     debug!(peer_id = ?peer_id, "inside client write task");
     // Get mutable versions for writing
     let mut client_writer = client_writer;
@@ -174,65 +479,84 @@ async fn client_write_task(peer_id: Uuid, client_writer: &mut OwnedWriteHalf, br
         info!(response = ?response, peer_id = ?peer_id, "client write task received response from main broker");
 
         match response {
-            Response::ConnectionOk => {
-                client_writer.write_all(&Response::ConnectionOk.serialize())
-                    .await
-                    .map_err(|e| ServerError::Write(e))?;
-            }
-            Response::NotPrime { p } => {
-                client_writer.write_all(&Response::NotPrime{ p }.serialize() )
-                    .await
-                    .map_err(|e| ServerError::Write(e))?;
-            }
-            Response::Prime { p, prob } => {
-                client_writer.write_all(&Response::Prime { p, prob }.serialize())
-                    .await
-                    .map_err(|e| ServerError::Write(e))?;
-            }
-            Response::Log { mut pollards } => {
-                while let Some(log_item) = StreamExt::next(&mut pollards).await {
-                    client_writer.write_all(&Response::LogItem { item: log_item }.serialize())
-                        .await
-                        .map_err(|e| ServerError::Write(e))?;
-                }
-                // Check if the discrete log is solvable
-                if let Some(log) = pollards.solve() {
-                    info!(peer_id = ?peer_id, "discrete logarithm solved successfully");
-                    let ratio = pollards.steps_to_sqrt_mod_ratio();
-                    client_writer.write_all(&Response::SuccessfulLog { log, g: pollards.g, h: pollards.h, p: pollards.p, ratio }.serialize())
+            Reply::Stream { request_id, batch_index, mut responses, timeout, cancel } => {
+                // The token is also cancelled once the stream is relayed, so the broker can forget it
+                let _relayed = cancel.clone().drop_guard();
+                // Relay the steps of the computation in order, dropping `responses` stops the computation
+                let deadline = Instant::now() + timeout;
+                let mut steps = 0;
+                let stopped = loop {
+                    // A computation that keeps up with the writer is always ready, so check the deadline explicitly
+                    if Instant::now() >= deadline {
+                        break Some(Response::Timeout { request_id, millis: timeout.as_millis() as u64, batch_index });
+                    }
+                    let response = select! {
+                        r = time::timeout_at(deadline, responses.recv()).fuse() => match r {
+                            Ok(Some(r)) => r,
+                            Ok(None) => break None,
+                            Err(_) => break Some(Response::Timeout { request_id, millis: timeout.as_millis() as u64, batch_index }),
+                        },
+                        _ = cancel.cancelled().fuse() => break Some(Response::Cancelled { request_id, batch_index }),
+                        _ = shutdown_signal => {
+                            info!(peer_id = ?peer_id, "client {} write task received shutdown signal", peer_id);
+                            return Ok(());
+                        }
+                    };
+                    // Steps past the limit are dropped, but the computation keeps running towards its result
+                    if response.is_step() {
+                        steps += 1;
+                        match max_stream_items {
+                            Some(max) if steps == max + 1 => {
+                                debug!(peer_id = ?peer_id, request_id, max, "stream to client {} reached its limit, dropping further steps", peer_id);
+                                continue;
+                            }
+                            Some(max) if steps > max => continue,
+                            _ => {}
+                        }
+                    }
+                    response.to_writer(&mut client_writer)
                         .await
                         .map_err(|e| ServerError::Write(e))?;
-                } else {
-                    info!(peer_id = ?peer_id, "discrete logarithm not solved");
-                    // We need to inform the client that solving the logarithm was unsuccessful
-                    client_writer.write_all(&Response::UnsuccessfulLog { g: pollards.g, h: pollards.h, p: pollards.p }.serialize())
+                    if let Some(audit) = &audit {
+                        audit.outcome(peer_id, &response);
+                    }
+                };
+
+                if let Some(response) = stopped {
+                    if let Response::Timeout { .. } = response {
+                        warn!(peer_id = ?peer_id, "computation for client {} timed out after {:?}", peer_id, timeout);
+                    } else {
+                        info!(peer_id = ?peer_id, request_id, "computation for client {} cancelled", peer_id);
+                    }
+                    drop(responses);
+                    response.to_writer(&mut client_writer)
                         .await
                         .map_err(|e| ServerError::Write(e))?;
+                    if let Some(audit) = &audit {
+                        audit.outcome(peer_id, &response);
+                    }
                 }
             }
-            Response::RSA { mut pollards } => {
-                while let Some(rsa_item) = StreamExt::next(&mut pollards).await {
-                    client_writer.write_all(&Response::RSAItem { item: rsa_item }.serialize())
-                        .await
-                        .map_err(|e| ServerError::Write(e))?;
+            Reply::Response(r) => {
+                r.to_writer(&mut client_writer)
+                    .await
+                    .map_err(|e| ServerError::Write(e))?;
+                if let Some(audit) = &audit {
+                    audit.outcome(peer_id, &r);
                 }
-                // Check if we were able to factor the public key
-                if let Some(p) = pollards.factor() {
-                    info!(peer_id = ?peer_id, "public key factored successfully");
-                    let q = pollards.n / p;
-                    let ratio = pollards.steps_to_sqrt_mod_ratio();
-                    client_writer.write_all(&Response::SuccessfulRSA { p, q, ratio }.serialize())
-                        .await
-                        .map_err(|e| ServerError::Write(e))?;
-                } else {
-                    info!(peer_id = ?peer_id, "public key not factored successfully");
-                    // Otherwise we need to inform client factorization was unsuccessful
-                    client_writer.write_all(&Response::UnsuccessfulRSA { n: pollards.n }.serialize())
-                        .await
-                        .map_err(|e| ServerError::Write(e))?;
+            }
+            Reply::Close(r) => {
+                r.to_writer(&mut client_writer)
+                    .await
+                    .map_err(|e| ServerError::Write(e))?;
+                if let Some(audit) = &audit {
+                    audit.outcome(peer_id, &r);
                 }
+                // Stops the client's read task as well, the connection is closed once the broker harvests it
+                info!(peer_id = ?peer_id, "client {} write task closing the connection", peer_id);
+                token.cancel();
+                break;
             }
-            r => return Err(ServerError::IllegalResponse(peer_id, r))
         }
     }
 
@@ -240,11 +564,30 @@ async fn client_write_task(peer_id: Uuid, client_writer: &mut OwnedWriteHalf, br
 }
 
 #[instrument(ret, err, skip(events))]
-async fn main_broker(events: Receiver<Event>, buf_size: usize) -> Result<(), ServerError> {
+async fn main_broker(events: Receiver<Event>, config: ServerConfig) -> Result<(), ServerError> {
+    let ServerConfig { buf_size, brent, timeout, max_input, rate_limit, cache_size, mr_rounds, primality_test, capabilities, audit, auth_key, max_stream_items, benchmark, rho_workers, .. } = config;
     // For mapping from client id's to sending channels
-    let mut clients: HashMap<Uuid, Sender<Response>> = HashMap::new();
+    let mut clients: HashMap<Uuid, Sender<Reply>> = HashMap::new();
+    // For limiting the rate of each client's requests
+    let mut buckets: HashMap<Uuid, TokenBucket> = HashMap::new();
+    // For cancelling each client's streamed computations
+    let mut streams: HashMap<Uuid, StreamTokens> = HashMap::new();
+    // For resuming sessions, from each session token to the client holding it
+    let mut sessions: HashMap<u128, Uuid> = HashMap::new();
+    // For closing the connection of a client whose session is taken over
+    let mut connections: HashMap<Uuid, Connection> = HashMap::new();
+    // For charging each batch to the rate limit once, the request id of each client's last batch and whether it
+    // was admitted
+    let mut batches: HashMap<Uuid, (u32, bool)> = HashMap::new();
+    // For reporting the work done by the server, and how long it took if benchmarking
+    let mut stats = ServerStats::default();
+    if benchmark {
+        stats.timings = Some(ComputationTimings::default());
+    }
+    // For answering repeated requests without recomputing them, filled in by the blocking threads
+    let cache = Arc::new(Mutex::new(SolvedCache::new(cache_size)));
     // For harvesting disconnected clients
-    let (shutdown_send, shutdown_recv) = unbounded_channel::<(Uuid, OwnedWriteHalf, Receiver<Response>)>();
+    let (shutdown_send, shutdown_recv) = unbounded_channel::<(Uuid, Box<dyn ClientWriter>, Receiver<Reply>)>();
 
     // Convert to stream and fuse for selecting
     let mut shutdown_recv = UnboundedReceiverStream::new(shutdown_recv).fuse();
@@ -267,20 +610,139 @@ async fn main_broker(events: Receiver<Event>, buf_size: usize) -> Result<(), Ser
             (peer_id, client_socket, client_recv) = shutdown_recv.select_next_some().fuse() => {
                 info!(peer_id = ?peer_id, "main broker harvesting client {}", peer_id);
                 clients.remove(&peer_id).ok_or(ServerError::IllegalState(format!("client with id {} should exist", peer_id)))?;
+                buckets.remove(&peer_id);
+                streams.remove(&peer_id);
+                batches.remove(&peer_id);
+                // Its write task has stopped, so whatever the client is still waiting on is never answered
+                if let Some(audit) = &audit {
+                    audit.disconnect(peer_id);
+                }
+                if let Some(connection) = connections.remove(&peer_id) {
+                    // A session that was taken over now belongs to another client
+                    if sessions.get(&connection.session) == Some(&peer_id) {
+                        sessions.remove(&connection.session);
+                    }
+                }
                 continue;
             }
         };
 
+        // A client that has yet to answer its challenge is served nothing else, anything but the right answer fails
+        // the handshake and closes the connection
+        match connections.get_mut(&event.peer_id()) {
+            Some(connection) => match (&connection.auth, &event) {
+                (Auth::Authenticated, Event::Auth { peer_id, .. }) => {
+                    debug!(peer_id = ?peer_id, "client {} answered a challenge it did not need to, ignoring it", peer_id);
+                    continue;
+                }
+                (_, Event::NewClient { .. } | Event::Quit { .. }) | (Auth::Authenticated, _) => {}
+                (Auth::Failed, _) => continue,
+                (Auth::Pending(challenge), event) => {
+                    let peer_id = event.peer_id();
+                    let key = auth_key.as_ref()
+                        .ok_or(ServerError::IllegalState(format!("client {} should only be challenged by a server with a key", peer_id)))?;
+                    match *event {
+                        Event::Auth { hmac, .. } if key.verify(challenge, &hmac) => {
+                            info!(peer_id = ?peer_id, "client {} authenticated", peer_id);
+                            connection.auth = Auth::Authenticated;
+                        }
+                        _ => {
+                            warn!(peer_id = ?peer_id, "client {} failed to authenticate, closing its connection", peer_id);
+                            connection.auth = Auth::Failed;
+                            let request_id = match *event {
+                                Event::Auth { request_id, .. } => request_id,
+                                _ => 0,
+                            };
+                            let client_write = clients.get_mut(&peer_id)
+                                .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                            client_write.send(Reply::Close(Response::AuthFailed { request_id }))
+                                .await
+                                .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `AuthFailed` response to client {} write task", peer_id)))?;
+                        }
+                    }
+                    continue;
+                }
+            },
+            // Only a harvested client has no connection, whatever it sent before its read task stopped is dropped
+            None if !matches!(event, Event::NewClient { .. }) => {
+                debug!(peer_id = ?event.peer_id(), "dropping event from disconnected client {}", event.peer_id());
+                continue;
+            }
+            None => {}
+        }
+
+        // Requests are recorded as they arrive, their outcomes once the write task sends them
+        if let Some(audit) = &audit {
+            audit.request(&event);
+        }
+
+        // Requests beyond the client's rate limit are dropped, a batch is charged once for all of its queries
+        if let Event::Log { peer_id, request_id, .. }
+            | Event::RSA { peer_id, request_id, .. }
+            | Event::Prime { peer_id, request_id, .. }
+            | Event::Bsgs { peer_id, request_id, .. }
+            | Event::PrimitiveRoot { peer_id, request_id, .. } = &event {
+            let (peer_id, request_id) = (*peer_id, *request_id);
+            let batch_index = match event {
+                Event::Log { batch_index, .. } => batch_index,
+                _ => None,
+            };
+            let bucket = buckets.get_mut(&peer_id)
+                .ok_or(ServerError::IllegalState(format!("client {} should exist in buckets hashmap", peer_id)))?;
+            // The queries of a batch reach the broker in order, so the first one decides for the rest
+            let admitted = match (batch_index, batches.get(&peer_id)) {
+                (Some(i), Some(&(batch, admitted))) if i > 0 && batch == request_id => admitted,
+                _ => bucket.try_take(Instant::now()),
+            };
+            if batch_index == Some(0) {
+                batches.insert(peer_id, (request_id, admitted));
+            }
+            if !admitted {
+                warn!(peer_id = ?peer_id, "client {} exceeded its rate limit, dropping request", peer_id);
+                let client_write = clients.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                client_write.send(Reply::from(Response::RateLimited { request_id, batch_index }))
+                    .await
+                    .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `RateLimited` response to client {} write task", peer_id)))?;
+                continue;
+            }
+        }
+
         // Match on the event and generate the correct response
         match event {
             Event::NewClient { peer_id, mut socket, token } => {
+                // Ids are random, but a clash would hand the live client's responses to the new one, so the new
+                // client is turned away and its read task stopped
+                if clients.contains_key(&peer_id) {
+                    warn!(peer_id = ?peer_id, "client id {} is already in use, rejecting the new client", peer_id);
+                    token.cancel();
+                    task::spawn(async move {
+                        let busy = Response::ServerBusy { request_id: 0 };
+                        if let Err(e) = busy.to_writer(&mut socket).await {
+                            error!(error = ?e, "unable to send `ServerBusy` response");
+                        }
+                    });
+                    continue;
+                }
+
                 // Create new channel for communicating with new client's write task
-                let (client_write_send, mut client_write_recv) = channel::<Response>(buf_size);
+                let (client_write_send, mut client_write_recv) = channel::<Reply>(buf_size);
                 let mut shutdown_send = shutdown_send.clone();
+                let audit = audit.clone();
                 clients.insert(peer_id, client_write_send.clone());
+                buckets.insert(peer_id, TokenBucket::new(rate_limit, Instant::now()));
+                streams.insert(peer_id, StreamTokens::default());
+                let session = Uuid::new_v4().as_u128();
+                sessions.insert(session, peer_id);
+                let challenge: [u8; AUTH_LEN] = rand::random();
+                let auth = match auth_key {
+                    Some(_) => Auth::Pending(challenge),
+                    None => Auth::Authenticated,
+                };
+                connections.insert(peer_id, Connection { session, token: token.clone(), auth });
 
                 task::spawn(async move {
-                    let res = client_write_task(peer_id, &mut socket, &mut client_write_recv, token).await;
+                    let res = client_write_task(peer_id, &mut socket, &mut client_write_recv, token, audit, max_stream_items).await;
                     // Client's write task has finished, send signal back to broker
                     if let Err(e) = shutdown_send.send((peer_id, socket, client_write_recv)) {
                         error!(e = ?e, peer_id = ?peer_id,  "error sending shutdown signal to main broker");
@@ -291,93 +753,1207 @@ async fn main_broker(events: Receiver<Event>, buf_size: usize) -> Result<(), Ser
                 });
 
                 // Send the new client a ConnectionOk response
-                client_write_send.send(Response::ConnectionOk)
+                client_write_send.send(Reply::from(Response::ConnectionOk { request_id: 0, version: PROTOCOL_VERSION, session, challenge }))
                     .await
                     .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send client {} `ConnectionOk` response after spawning", peer_id)))?;
             }
-            Event::Prime { peer_id, p } => {
+            Event::Prime { peer_id, request_id, p, seed } => {
+                stats.primes += 1;
                 // First get the client from the map
                 let client_write = clients.get_mut(&peer_id)
                     .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
 
-                // Run the miller rabin test
-                let (prime_flag, prob) = task::spawn_blocking(move || {
-                    let mut rng = thread_rng();
-                    let mut i = 0;
-                    let mut prime_flag = true;
-                    while i < 20 {
-                        let a = rng.gen_range(2..p);
-                        if miller_rabin(p, a) {
-                            prime_flag = false;
+                // Random bases are drawn from 2..p, which is empty for p = 2 and holds a single base for p = 3, so
+                // small and even numbers are answered without running any rounds
+                let verdict = match p {
+                    0 | 1 => Some(Response::NotPrime { request_id, p, witness: 0 }),
+                    2 | 3 => Some(Response::Prime { request_id, p, prob: 1.0, rounds: 0 }),
+                    _ if p % 2 == 0 => Some(Response::NotPrime { request_id, p, witness: 2 }),
+                    _ => None,
+                };
+                if let Some(response) = verdict {
+                    client_write.send(Reply::from(response))
+                        .await
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Prime` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                // Run the primality test on a blocking thread, streaming each round back and stopping at the first
+                // base that proves p composite. A seeded check draws the same bases every time
+                let (responses_send, responses) = channel::<Response>(buf_size);
+                let timing = stats.timings.as_ref().map(|timings| timings.primes.clone());
+                task::spawn_blocking(move || {
+                    let timer = timing.map(Timer::start);
+                    let mut rng = match seed {
+                        Some(seed) => StdRng::seed_from_u64(seed),
+                        None => StdRng::from_entropy(),
+                    };
+                    let mut verdict = None;
+                    for round in 1..=mr_rounds {
+                        let base = rng.gen_range(2..p);
+                        let passed = !primality_test.is_witness(p, base);
+                        if responses_send.blocking_send(Response::PrimeRound { request_id, round, base, passed }).is_err() {
+                            debug!("responses receiver dropped, abandoning prime check");
+                            return;
+                        }
+                        if !passed {
+                            verdict = Some(Response::NotPrime { request_id, p, witness: base });
                             break;
                         }
-                        i += 1;
-                    }
-                    if prime_flag {
-                        (prime_flag, 1.0 - f32::powi(0.25, 20))
-                    } else {
-                        (prime_flag, 0.0)
                     }
-                })
+                    let verdict = verdict.unwrap_or_else(|| {
+                        let prob = primality_test.probability(mr_rounds);
+                        Response::Prime { request_id, p, prob: prob as f32, rounds: mr_rounds }
+                    });
+                    // Stop timing before the verdict is sent, so stats requested once it is read include the check
+                    drop(timer);
+                    let _ = responses_send.blocking_send(verdict);
+                });
+
+                let cancel = streams.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in streams hashmap", peer_id)))?
+                    .register(request_id);
+                client_write.send(Reply::Stream { request_id, batch_index: None, responses, timeout, cancel })
                     .await
-                    .map_err(|e| ServerError::Task(e))?;
+                    .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Prime` response to client {} write task", peer_id)))?;
+            }
+            Event::Bsgs { peer_id, request_id, g, h, p } => {
+                stats.logs += 1;
+                let client_write = clients.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
 
-                // Send the correct response accordingly
-                if prime_flag {
-                    client_write.send(Response::Prime { p, prob })
+                if p > max_input || p > BSGS_LIMIT {
+                    warn!(peer_id = ?peer_id, p, max_input, "client {} sent an input that is too large", peer_id);
+                    client_write.send(Reply::from(Response::InputTooLarge { request_id, value: p, batch_index: None }))
                         .await
-                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Prime` response to client {} write task", peer_id)))?;
-                } else {
-                    client_write.send(Response::NotPrime { p })
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `InputTooLarge` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+                if p < 2 {
+                    warn!(peer_id = ?peer_id, p, "client {} sent a modulus below 2", peer_id);
+                    let message = format!("the modulus {p} is below 2, so there is no group to solve the logarithm in");
+                    client_write.send(Reply::from(Response::Error { request_id, code: error_code::INVALID_MODULUS, message, batch_index: None }))
+                        .await
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Error` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                // Baby-step giant-step is deterministic, so there are no intermediate steps to stream, only its result
+                let responses = spawn_computation(peer_id, request_id, None, buf_size, move |responses| {
+                    let _ = responses.blocking_send(bsgs_result(peer_id, request_id, g, h, p, None));
+                });
+                let cancel = streams.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in streams hashmap", peer_id)))?
+                    .register(request_id);
+                client_write.send(Reply::Stream { request_id, batch_index: None, responses, timeout, cancel })
+                    .await
+                    .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Bsgs` response to client {} write task", peer_id)))?;
+            }
+            Event::PrimitiveRoot { peer_id, request_id, p } => {
+                let client_write = clients.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+
+                if p > max_input {
+                    warn!(peer_id = ?peer_id, p, max_input, "client {} sent an input that is too large", peer_id);
+                    client_write.send(Reply::from(Response::InputTooLarge { request_id, value: p, batch_index: None }))
                         .await
-                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `NotPrime` response to client {} write task", peer_id)))?;
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `InputTooLarge` response to client {} write task", peer_id)))?;
+                    continue;
                 }
+
+                // Factoring p - 1 is done by trial division, so keep it off the runtime
+                let responses = spawn_computation(peer_id, request_id, None, buf_size, move |responses| {
+                    let response = match primitive_root(p).ok_or_else(|| composite_witness(p)) {
+                        Ok(g) => Response::PrimitiveRoot { request_id, p, g },
+                        Err(witness) => Response::NotPrime { request_id, p, witness: witness.unwrap_or(0) },
+                    };
+                    let _ = responses.blocking_send(response);
+                });
+                let cancel = streams.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in streams hashmap", peer_id)))?
+                    .register(request_id);
+                client_write.send(Reply::Stream { request_id, batch_index: None, responses, timeout, cancel })
+                    .await
+                    .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `PrimitiveRoot` response to client {} write task", peer_id)))?;
             }
-            Event::Log { peer_id,  g, h, p } => {
+            Event::Log { peer_id, request_id, g, h, p, batch_index, method } => {
+                stats.logs += 1;
                 let mut client_write = clients.get_mut(&peer_id)
                     .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
-                client_write.send(Response::Log { pollards: PollardsLog::new(p, g, h) })
+
+                if p > max_input || (method == LogMethod::Bsgs && p > BSGS_LIMIT) {
+                    warn!(peer_id = ?peer_id, p, max_input, "client {} sent an input that is too large", peer_id);
+                    client_write.send(Reply::from(Response::InputTooLarge { request_id, value: p, batch_index }))
+                        .await
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `InputTooLarge` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                // Both g and h must be nonzero residues modulo p, anything else has no meaningful log
+                let reason = if g == 0 || g >= p {
+                    Some(InvalidReason::BaseOutOfRange)
+                } else if h == 0 || h >= p {
+                    Some(InvalidReason::TargetOutOfRange)
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    warn!(peer_id = ?peer_id, g, h, p, %reason, "client {} sent invalid discrete logarithm parameters", peer_id);
+                    client_write.send(Reply::from(Response::InvalidParameters { request_id, reason, batch_index }))
+                        .await
+                        .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `InvalidParameters` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                // The logs of 1 and of g itself are known without taking a single step
+                let trivial = match h {
+                    1 => Some(0),
+                    _ if h == g => Some(1),
+                    _ => None,
+                };
+                if let Some(log) = trivial {
+                    info!(peer_id = ?peer_id, g, h, p, log, "discrete logarithm solved without computing it");
+                    client_write.send(Reply::from(Response::SuccessfulLog { request_id, log, g, h, p, ratio: 0.0, iterations: 0, performance: Performance::from_ratio(0.0), batch_index }))
+                        .await
+                        .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `Log` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                // Picking a method factors p - 1 and every method is CPU bound, so solve the log on a blocking thread
+                // and stream its result back, along with the steps of Pollard's rho
+                let iterations = stats.iterations.clone();
+                let cache = cache.clone();
+                let timing = stats.timings.as_ref().map(|timings| timings.logs.clone());
+                let responses = spawn_computation(peer_id, request_id, batch_index, buf_size, move |responses| {
+                    let auto = method == LogMethod::Auto;
+                    let method = match method {
+                        LogMethod::Auto => {
+                            let method = auto_log_method(p);
+                            info!(peer_id = ?peer_id, p, ?method, "picked {:?} to solve the discrete logarithm modulo {}", method, p);
+                            method
+                        }
+                        method => method,
+                    };
+                    // Only Pollard's rho has steps to stream, the other methods just send their result
+                    let response = match method {
+                        LogMethod::Bsgs => bsgs_result(peer_id, request_id, g, h, p, batch_index),
+                        LogMethod::PohligHellman => match pohlig_hellman_result(peer_id, request_id, g, h, p, batch_index) {
+                            // A method picked by the server is not left to give up on its own, it falls back to the
+                            // method it would have picked for a modulus with p - 1 that is not smooth
+                            Response::UnsuccessfulLog { .. } if auto && p <= AUTO_BSGS_LIMIT => {
+                                info!(peer_id = ?peer_id, p, "Pohlig-Hellman gave up, falling back to baby-step giant-step");
+                                bsgs_result(peer_id, request_id, g, h, p, batch_index)
+                            }
+                            Response::UnsuccessfulLog { .. } if auto => {
+                                info!(peer_id = ?peer_id, p, "Pohlig-Hellman gave up, falling back to Pollard's rho");
+                                return rho_log(peer_id, request_id, g, h, p, batch_index, &cache, timing, iterations, responses);
+                            }
+                            response => response,
+                        },
+                        _ => return rho_log(peer_id, request_id, g, h, p, batch_index, &cache, timing, iterations, responses),
+                    };
+                    let _ = responses.blocking_send(response);
+                });
+
+                let cancel = streams.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in streams hashmap", peer_id)))?
+                    .register(request_id);
+                client_write.send(Reply::Stream { request_id, batch_index, responses, timeout, cancel })
                     .await
                     .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Log` response to client {} write task", peer_id)))?;
             }
-            Event::RSA { peer_id, n} => {
+            Event::RSA { peer_id, request_id, n, e } => {
+                stats.rsas += 1;
                 let mut client_write = clients.get_mut(&peer_id)
                     .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
-                client_write.send(Response::RSA { pollards: PollardsRSAFact::new(n) })
+
+                if capabilities & capabilities::RSA == 0 {
+                    warn!(peer_id = ?peer_id, "client {} requested RSA factoring, which this server does not support", peer_id);
+                    client_write.send(Reply::from(Response::UnsuccessfulRSA { request_id, n }))
+                        .await
+                        .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `UnsuccessfulRSA` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                if n > max_input {
+                    warn!(peer_id = ?peer_id, n, max_input, "client {} sent an input that is too large", peer_id);
+                    client_write.send(Reply::from(Response::InputTooLarge { request_id, value: n, batch_index: None }))
+                        .await
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `InputTooLarge` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                // Small factors are found instantly by trial division
+                if let Some(p) = trial_divide(n, TRIAL_DIVISION_LIMIT) {
+                    info!(peer_id = ?peer_id, "public key factored by trial division");
+                    let item = PollardsRSAFactItem { i: 1, xi: 0, yi: 0, g: p, n, c: 0 };
+                    client_write.send(Reply::from(Response::RSAItem { request_id, item }))
+                        .await
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `RSAItem` response to client {} write task", peer_id)))?;
+                    let ratio = 1.0 / f64::sqrt(n as f64);
+                    for response in rsa_factored(request_id, p, n / p, e, ratio, 1) {
+                        client_write.send(Reply::from(response))
+                            .await
+                            .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `SuccessfulRSA` response to client {} write task", peer_id)))?;
+                    }
+                    continue;
+                }
+
+                let cached = cache.lock().expect("cache lock poisoned").rsas.get(&n);
+                if let Some((p, ratio, iterations)) = cached {
+                    info!(peer_id = ?peer_id, n, "cache hit, serving factored public key without recomputing");
+                    for response in rsa_factored(request_id, p, n / p, e, ratio, iterations) {
+                        client_write.send(Reply::from(response))
+                            .await
+                            .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send cached `RSA` response to client {} write task", peer_id)))?;
+                    }
+                    continue;
+                }
+
+                // Pollard's rho is CPU bound, so run it on a blocking thread and stream its steps back
+                let (responses_send, responses) = channel::<Response>(buf_size);
+                let iterations = stats.iterations.clone();
+                let cache = cache.clone();
+                let timing = stats.timings.as_ref().map(|timings| timings.rsas.clone());
+                let res = if brent {
+                    PollardsRSAFactBrent::try_new(n).map(|pollards| {
+                        task::spawn_blocking(move || {
+                            let timer = timing.map(Timer::start);
+                            stream_steps(
+                                pollards,
+                                |item| Some(Response::RSAItem { request_id, item }),
+                                |mut pollards| {
+                                    let responses = rsa_result(peer_id, request_id, n, e, pollards.factor(), pollards.steps_to_sqrt_mod_ratio(), pollards.steps(), &cache);
+                                    drop(timer);
+                                    responses
+                                },
+                                responses_send,
+                                iterations,
+                            )
+                        });
+                    })
+                } else if rho_workers > 1 {
+                    // The walks race on blocking threads of their own, so only the result is sent
+                    PollardsRSAFact::try_new(n).map(|_| {
+                        task::spawn(async move {
+                            let timer = timing.map(Timer::start);
+                            let race = factor_parallel(n, rho_workers, iterations).fuse();
+                            let closed = responses_send.closed().fuse();
+                            futures::pin_mut!(race, closed);
+                            let (factor, steps) = select! {
+                                won = race => won.unzip(),
+                                // Dropping the race cancels its walks
+                                _ = closed => return,
+                            };
+                            let steps = steps.unwrap_or(0);
+                            let ratio = steps as f64 / f64::sqrt(n as f64);
+                            let responses = rsa_result(peer_id, request_id, n, e, factor, ratio, steps, &cache);
+                            drop(timer);
+                            for response in responses {
+                                if responses_send.send(response).await.is_err() {
+                                    return;
+                                }
+                            }
+                        });
+                    })
+                } else {
+                    PollardsRSAFact::try_new(n).map(|pollards| {
+                        task::spawn_blocking(move || {
+                            let timer = timing.map(Timer::start);
+                            stream_steps(
+                                pollards,
+                                |item| Some(Response::RSAItem { request_id, item }),
+                                |mut pollards| {
+                                    let responses = rsa_result(peer_id, request_id, n, e, pollards.factor(), pollards.steps_to_sqrt_mod_ratio(), pollards.steps(), &cache);
+                                    drop(timer);
+                                    responses
+                                },
+                                responses_send,
+                                iterations,
+                            )
+                        });
+                    })
+                };
+                if let Err(e) = res {
+                    warn!(peer_id = ?peer_id, error = %e, "unable to factor public key");
+                    let response = match e {
+                        AlgoError::Overflow => Response::InputTooLarge { request_id, value: n, batch_index: None },
+                        _ => Response::UnsuccessfulRSA { request_id, n },
+                    };
+                    client_write.send(Reply::from(response))
+                        .await
+                        .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `RSA` response to client {} write task", peer_id)))?;
+                    continue;
+                }
+
+                let cancel = streams.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in streams hashmap", peer_id)))?
+                    .register(request_id);
+                client_write.send(Reply::Stream { request_id, batch_index: None, responses, timeout, cancel })
                     .await
                     .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Log` response to client {} write task", peer_id)))?;
             }
+            Event::Stats { peer_id, request_id } => {
+                let connected = clients.len() as u64;
+                let client_write = clients.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                let response = Response::Stats {
+                    request_id,
+                    clients: connected,
+                    logs: stats.logs,
+                    rsas: stats.rsas,
+                    primes: stats.primes,
+                    iterations: stats.iterations.load(Ordering::Relaxed),
+                    timings: stats.timings.as_ref().map(ComputationTimings::summary),
+                };
+                client_write.send(Reply::from(response))
+                    .await
+                    .map_err(|e| ServerError::ChannelSend(format!("main broker unable to send `Stats` response to client {} write task", peer_id)))?;
+            }
+            Event::ListPeers { peer_id, request_id } => {
+                let client_write = clients.get(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                // Clients only get this far once authenticated, but without a shared key anyone is let in
+                let response = if auth_key.is_some() {
+                    let mut ids = clients.keys().copied().collect::<Vec<_>>();
+                    ids.sort();
+                    ids.truncate(MAX_PEER_LIST_IDS as usize);
+                    Response::PeerList { request_id, ids }
+                } else {
+                    warn!(peer_id = ?peer_id, "client {} asked for the connected peers, which only admins may list", peer_id);
+                    Response::Error { request_id, code: error_code::FORBIDDEN, message: "listing peers requires authenticating with the server's key".to_string(), batch_index: None }
+                };
+                client_write.send(Reply::from(response))
+                    .await
+                    .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `PeerList` response to client {} write task", peer_id)))?;
+            }
+            Event::Kick { peer_id, request_id, target } => {
+                let client_write = clients.get(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                let response = if auth_key.is_none() {
+                    warn!(peer_id = ?peer_id, "client {} asked to disconnect client {}, which only admins may do", peer_id, target);
+                    Response::Error { request_id, code: error_code::FORBIDDEN, message: "kicking peers requires authenticating with the server's key".to_string(), batch_index: None }
+                } else if let Some(connection) = connections.get(&target) {
+                    // Both of the client's tasks stop, after which it is harvested like any other disconnected client
+                    info!(peer_id = ?peer_id, target = ?target, "client {} disconnected client {}", peer_id, target);
+                    connection.token.cancel();
+                    Response::Kicked { request_id, peer_id: target }
+                } else {
+                    Response::Error { request_id, code: error_code::UNKNOWN_PEER, message: format!("no client {} is connected", target), batch_index: None }
+                };
+                client_write.send(Reply::from(response))
+                    .await
+                    .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `Kick` response to client {} write task", peer_id)))?;
+            }
+            Event::Ping { peer_id, request_id, nonce } => {
+                // Liveness probes are neither rate limited nor recorded, only echoed back
+                let client_write = clients.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                client_write.send(Reply::from(Response::Pong { request_id, nonce }))
+                    .await
+                    .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `Pong` response to client {} write task", peer_id)))?;
+            }
+            Event::Capabilities { peer_id, request_id } => {
+                let client_write = clients.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                client_write.send(Reply::from(Response::Capabilities { request_id, flags: capabilities }))
+                    .await
+                    .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `Capabilities` response to client {} write task", peer_id)))?;
+            }
+            Event::Cancel { peer_id, request_id } => {
+                let cancelled = streams.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in streams hashmap", peer_id)))?
+                    .cancel(request_id);
+                if cancelled {
+                    info!(peer_id = ?peer_id, request_id, "client {} cancelled its request", peer_id);
+                } else {
+                    debug!(peer_id = ?peer_id, request_id, "client {} cancelled a request that is not running", peer_id);
+                }
+            }
+            Event::Resume { peer_id, request_id, session } => {
+                let client_write = clients.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in clients hashmap", peer_id)))?;
+                let connection = connections.get_mut(&peer_id)
+                    .ok_or(ServerError::IllegalState(format!("client {} should exist in connections hashmap", peer_id)))?;
+
+                match sessions.get(&session).copied() {
+                    Some(previous) if previous != peer_id => {
+                        info!(peer_id = ?peer_id, previous = ?previous, "client {} resumed the session of client {}", peer_id, previous);
+                        // The client takes over the rate limit of the session, its fresh bucket goes to the stale
+                        // connection, which is closed but may still have requests on their way to the broker
+                        if let (Some(resumed), Some(fresh)) = (buckets.remove(&previous), buckets.remove(&peer_id)) {
+                            buckets.insert(peer_id, resumed);
+                            buckets.insert(previous, fresh);
+                        }
+                        sessions.remove(&connection.session);
+                        sessions.insert(session, peer_id);
+                        connection.session = session;
+                        if let Some(stale) = connections.get(&previous) {
+                            stale.token.cancel();
+                        }
+                    }
+                    Some(_) => debug!(peer_id = ?peer_id, "client {} resumed the session it already holds", peer_id),
+                    None => warn!(peer_id = ?peer_id, "client {} tried to resume an unknown session, keeping its new session", peer_id),
+                }
+
+                let session = connections.get(&peer_id).map_or(session, |connection| connection.session);
+                // The client has already authenticated, so there is nothing left to challenge it with
+                client_write.send(Reply::from(Response::ConnectionOk { request_id, version: PROTOCOL_VERSION, session, challenge: [0; AUTH_LEN] }))
+                    .await
+                    .map_err(|_e| ServerError::ChannelSend(format!("main broker unable to send `ConnectionOk` response to client {} write task", peer_id)))?;
+            }
+            Event::Auth { peer_id, .. } => debug!(peer_id = ?peer_id, "client {} answered its challenge", peer_id),
             Event::Quit { peer_id } => info!(peer_id = ?peer_id, "main broker received `Quit` event from client {}", peer_id),
         }
     }
 
     info!("main broker draining shutdown receiver");
+    // Only the write tasks of connected clients should keep the shutdown channel open
+    drop(shutdown_send);
 
     while let Some((peer_id, client_socket, client_recv)) = shutdown_recv.next().await {
         info!(peer_id = ?peer_id, "main broker harvesting client {}", peer_id);
         clients.remove(&peer_id).ok_or(ServerError::IllegalState(format!("client with id {} should exist", peer_id)))?;
+        buckets.remove(&peer_id);
+        streams.remove(&peer_id);
+        connections.remove(&peer_id);
+        if let Some(audit) = &audit {
+            audit.disconnect(peer_id);
+        }
     }
 
     Ok(())
 }
 
+/// A record of the audit log, a request as it arrives, the outcome sent in answer to it, or the client that sent
+/// it disconnecting.
 #[derive(Debug)]
-pub enum ServerError<> {
-    Connection(std::io::Error),
-    ChannelSend(String),
-    ChannelReceive(String),
-    IllegalFrame(Uuid, Frame),
-    IllegalResponse(Uuid, Response),
-    IllegalState(String),
-    Read(std::io::Error),
-    Task(JoinError),
-    Write(std::io::Error),
+enum AuditRecord {
+    Request { timestamp_ms: u128, peer_id: Uuid, request_id: u32, kind: &'static str, params: String, batch_index: Option<u32> },
+    Outcome { peer_id: Uuid, request_id: u32, batch_index: Option<u32>, outcome: &'static str, iterations: Option<u64> },
+    Disconnect { peer_id: Uuid },
 }
 
-impl Display for ServerError {
+/// The sending half of the audit log, an append-only file with a line for each request handled by the server.
+///
+/// Records are written by a dedicated blocking thread, so recording never blocks the broker or the write tasks.
+/// A request is written once its outcome is sent, as the tab separated fields
+/// `timestamp_ms  peer_id  request_id  kind  params  outcome  iterations`, with `-` for a request without iterations.
+/// Requests still waiting for their outcome when their client disconnects are written with the outcome `abandoned`.
+#[derive(Debug, Clone)]
+struct AuditLog(UnboundedSender<AuditRecord>);
+
+impl AuditLog {
+    /// Spawns the thread writing the audit log to `out`, it finishes once every `AuditLog` is dropped.
+    fn spawn<W: std::io::Write + Send + 'static>(out: W) -> (AuditLog, JoinHandle<std::io::Result<()>>) {
+        let (records_send, records_recv) = unbounded_channel();
+        (AuditLog(records_send), task::spawn_blocking(move || audit_task(out, records_recv)))
+    }
+
+    /// Records the request made by `event`, events that are not requests are ignored.
+    fn request(&self, event: &Event) {
+        let (peer_id, request_id, kind, params, batch_index) = match *event {
+            Event::Log { peer_id, request_id, g, h, p, batch_index, .. } => (peer_id, request_id, "log", format!("g={g} h={h} p={p}"), batch_index),
+            Event::RSA { peer_id, request_id, n, e } => (peer_id, request_id, "rsa", format!("n={n} e={e}"), None),
+            Event::Prime { peer_id, request_id, p, seed: Some(seed) } => (peer_id, request_id, "prime", format!("p={p} seed={seed}"), None),
+            Event::Prime { peer_id, request_id, p, seed: None } => (peer_id, request_id, "prime", format!("p={p}"), None),
+            Event::Bsgs { peer_id, request_id, g, h, p } => (peer_id, request_id, "bsgs", format!("g={g} h={h} p={p}"), None),
+            Event::PrimitiveRoot { peer_id, request_id, p } => (peer_id, request_id, "root", format!("p={p}"), None),
+            Event::Stats { peer_id, request_id } => (peer_id, request_id, "stats", String::new(), None),
+            Event::Capabilities { peer_id, request_id } => (peer_id, request_id, "capabilities", String::new(), None),
+            Event::ListPeers { peer_id, request_id } => (peer_id, request_id, "peers", String::new(), None),
+            Event::Kick { peer_id, request_id, target } => (peer_id, request_id, "kick", format!("peer={target}"), None),
+            Event::NewClient { .. } | Event::Cancel { .. } | Event::Ping { .. } | Event::Resume { .. } | Event::Auth { .. } | Event::Quit { .. } => return,
+        };
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        self.send(AuditRecord::Request { timestamp_ms, peer_id, request_id, kind, params, batch_index });
+    }
+
+    /// Records `response` as the outcome of the request it answers, if it is the response that ends the request.
+    fn outcome(&self, peer_id: Uuid, response: &Response) {
+        let (outcome, iterations, batch_index) = match *response {
+            Response::Prime { rounds, .. } => ("prime", Some(rounds as u64), None),
+            Response::NotPrime { .. } => ("composite", None, None),
+            Response::SuccessfulLog { iterations, batch_index, .. } => ("solved", Some(iterations), batch_index),
+            Response::UnsuccessfulLog { batch_index, .. } => ("unsolved", None, batch_index),
+            Response::LogDoesNotExist { batch_index, .. } => ("no-log", None, batch_index),
+            // The `RSAKey` or `InvalidExponent` that follows belongs to the same request
+            Response::SuccessfulRSA { iterations, .. } => ("factored", Some(iterations), None),
+            Response::UnsuccessfulRSA { .. } => ("unfactored", None, None),
+            Response::PrimitiveRoot { .. } => ("root", None, None),
+            Response::Stats { .. } => ("stats", None, None),
+            Response::Capabilities { .. } => ("capabilities", None, None),
+            Response::PeerList { .. } => ("peers", None, None),
+            Response::Kicked { .. } => ("kicked", None, None),
+            Response::InputTooLarge { .. } => ("input-too-large", None, None),
+            Response::InvalidParameters { .. } => ("invalid-parameters", None, None),
+            Response::RateLimited { .. } => ("rate-limited", None, None),
+            Response::Timeout { .. } => ("timeout", None, None),
+            Response::Cancelled { .. } => ("cancelled", None, None),
+            Response::AuthFailed { .. } => ("auth-failed", None, None),
+            Response::Error { .. } => ("error", None, None),
+            _ => return,
+        };
+        self.send(AuditRecord::Outcome { peer_id, request_id: response.request_id(), batch_index, outcome, iterations });
+    }
+
+    /// Records that `peer_id` has disconnected, so none of its requests still waiting for an outcome will get one.
+    fn disconnect(&self, peer_id: Uuid) {
+        self.send(AuditRecord::Disconnect { peer_id });
+    }
+
+    fn send(&self, record: AuditRecord) {
+        if self.0.send(record).is_err() {
+            warn!("audit log task has stopped, dropping record");
+        }
+    }
+}
+
+/// Writes the records received over `records` to `out`, pairing each request with its outcome and flushing every line.
+fn audit_task<W: std::io::Write>(mut out: W, mut records: UnboundedReceiver<AuditRecord>) -> std::io::Result<()> {
+    // Requests waiting for their outcome, the queries of a batch share a request id and are told apart by their index
+    let mut pending: HashMap<(Uuid, u32), Vec<(u128, &'static str, String, Option<u32>)>> = HashMap::new();
+    while let Some(record) = records.blocking_recv() {
+        match record {
+            AuditRecord::Request { timestamp_ms, peer_id, request_id, kind, params, batch_index } => {
+                pending.entry((peer_id, request_id)).or_default().push((timestamp_ms, kind, params, batch_index));
+            }
+            AuditRecord::Outcome { peer_id, request_id, batch_index, outcome, iterations } => {
+                let Some(requests) = pending.get_mut(&(peer_id, request_id)) else {
+                    debug!(peer_id = ?peer_id, request_id, "outcome of an unrecorded request, skipping");
+                    continue;
+                };
+                let i = requests.iter().position(|request| batch_index.is_none() || request.3 == batch_index).unwrap_or(0);
+                let (timestamp_ms, kind, params, _) = requests.remove(i);
+                if requests.is_empty() {
+                    pending.remove(&(peer_id, request_id));
+                }
+                let iterations = iterations.map_or("-".to_string(), |iterations| iterations.to_string());
+                writeln!(out, "{timestamp_ms}\t{peer_id}\t{request_id}\t{kind}\t{params}\t{outcome}\t{iterations}")?;
+                out.flush()?;
+            }
+            AuditRecord::Disconnect { peer_id } => {
+                // Written in the order the requests arrived, since the map keeps none
+                let keys: Vec<_> = pending.keys().filter(|&&(peer, _)| peer == peer_id).copied().collect();
+                let mut abandoned: Vec<_> = keys.into_iter()
+                    .flat_map(|key| pending.remove(&key).unwrap_or_default().into_iter().map(move |request| (key.1, request)))
+                    .collect();
+                abandoned.sort_by_key(|&(request_id, (timestamp_ms, ..))| (timestamp_ms, request_id));
+                for (request_id, (timestamp_ms, kind, params, _)) in abandoned {
+                    writeln!(out, "{timestamp_ms}\t{peer_id}\t{request_id}\t{kind}\t{params}\tabandoned\t-")?;
+                }
+                out.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Counters describing the work done by the server, reported to clients on request.
+#[derive(Debug, Default)]
+struct ServerStats {
+    /// Discrete log requests served, with either Pollard's rho or baby-step giant-step
+    logs: u64,
+
+    /// RSA factoring requests served
+    rsas: u64,
+
+    /// Primality checks served
+    primes: u64,
+
+    /// Pollard's rho steps computed, shared with the blocking threads running the computations
+    iterations: Arc<AtomicU64>,
+
+    /// How long the computations took, recorded by the blocking threads running them if the server is benchmarking
+    timings: Option<ComputationTimings>,
+}
+
+/// Histograms of the wall-clock time taken by each type of computation, shared with the blocking threads.
+#[derive(Debug, Clone, Default)]
+struct ComputationTimings {
+    logs: Arc<Mutex<Histogram>>,
+    rsas: Arc<Mutex<Histogram>>,
+    primes: Arc<Mutex<Histogram>>,
+}
+
+impl ComputationTimings {
+    /// The shortest, median and longest time taken by each type of computation so far.
+    fn summary(&self) -> Timings {
+        let summary = |histogram: &Mutex<Histogram>| histogram.lock().expect("timing lock poisoned").summary();
+        Timings { logs: summary(&self.logs), rsas: summary(&self.rsas), primes: summary(&self.primes) }
+    }
+}
+
+/// A histogram of durations in nanoseconds, bucketed by powers of two so it takes the same space however many
+/// durations are recorded.
+///
+/// The shortest and longest durations are kept exactly, the median is the upper bound of the bucket it falls in.
+#[derive(Debug, Clone)]
+struct Histogram {
+    /// The number of durations of each bit length, bucket `k` holds durations from `2^(k - 1)` to `2^k - 1`
+    buckets: [u64; 65],
+    count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram { buckets: [0; 65], count: 0, min: u64::MAX, max: 0 }
+    }
+}
+
+impl Histogram {
+    fn record(&mut self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[(u64::BITS - nanos.leading_zeros()) as usize] += 1;
+        self.count += 1;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    /// The shortest, median and longest durations recorded, all zero if none were.
+    fn summary(&self) -> Timing {
+        if self.count == 0 {
+            return Timing::default();
+        }
+        let half = (self.count + 1) / 2;
+        let mut seen = 0;
+        let bucket = self.buckets.iter()
+            .position(|&n| {
+                seen += n;
+                seen >= half
+            })
+            .expect("the buckets should hold every recorded duration");
+        let upper = 1u64.checked_shl(bucket as u32).map_or(u64::MAX, |bound| bound - 1);
+        Timing { min: self.min, median: upper.clamp(self.min, self.max), max: self.max }
+    }
+}
+
+/// Records the time from its start until it is dropped in a histogram, timing a computation however it ends.
+///
+/// Computations drop their timer before sending their result, so the time is recorded by the time it is read.
+struct Timer {
+    histogram: Arc<Mutex<Histogram>>,
+    started: std::time::Instant,
+}
+
+impl Timer {
+    fn start(histogram: Arc<Mutex<Histogram>>) -> Self {
+        Timer { histogram, started: std::time::Instant::now() }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.histogram.lock().expect("timing lock poisoned").record(self.started.elapsed());
+    }
+}
+
+/// The results of successfully solved requests, used to answer identical requests without recomputing them.
+#[derive(Debug)]
+struct SolvedCache {
+    /// The discrete log, ratio and number of iterations, keyed by `(g, h, p)`
+    logs: LruCache<(u64, u64, u64), (u64, f64, u64)>,
+
+    /// A factor of the public key, the ratio and number of iterations, keyed by the public key `n`
+    rsas: LruCache<u64, (u64, f64, u64)>,
+}
+
+impl SolvedCache {
+    /// Creates an empty cache holding up to `capacity` discrete logs and `capacity` factorizations.
+    fn new(capacity: usize) -> Self {
+        SolvedCache { logs: LruCache::new(capacity), rsas: LruCache::new(capacity) }
+    }
+}
+
+/// A cache holding up to `capacity` entries, evicting the least recently used entry when it is full.
+///
+/// Recency is tracked with a queue that is searched linearly, which is cheap next to the computations it saves.
+/// A capacity of 0 disables the cache.
+#[derive(Debug)]
+struct LruCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, V>,
+    /// Keys ordered from least to most recently used
+    order: VecDeque<K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        LruCache { capacity, entries: HashMap::new(), order: VecDeque::new() }
+    }
+
+    /// Returns a copy of the value for `key`, marking it as the most recently used entry.
+    fn get(&mut self, key: &K) -> Option<V> {
+        let value = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(value)
+    }
+
+    /// Inserts `value` for `key` as the most recently used entry, evicting the least recently used entry if full.
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.clone(), value).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(idx) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(idx) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
+
+/// The session held by a connected client, along with the token that closes its connection.
+///
+/// Sessions are identified by a random 128 bit token sent to the client in `ConnectionOk`. A client that reconnects
+/// while its old connection has not yet been harvested can present the token in a `Frame::Resume` to take the
+/// session over, closing the old connection.
+#[derive(Debug)]
+struct Connection {
+    session: u128,
+    token: CancellationToken,
+    auth: Auth,
+}
+
+/// How far a client is through the handshake proving it holds the server's shared key.
+#[derive(Debug)]
+enum Auth {
+    /// The client has yet to answer the challenge sent in its `ConnectionOk`
+    Pending([u8; AUTH_LEN]),
+
+    /// The client answered its challenge, or the server has no key, so its requests are served
+    Authenticated,
+
+    /// The client answered wrongly and its connection is being closed, anything else it sends is dropped
+    Failed,
+}
+
+/// The cancellation tokens of a client's streamed computations, by the id of the request they answer.
+///
+/// A client's write task cancels the token of a stream once it has relayed it, so finished computations are
+/// forgotten the next time a computation is registered.
+#[derive(Default)]
+struct StreamTokens {
+    tokens: HashMap<u32, Vec<CancellationToken>>,
+}
+
+impl StreamTokens {
+    /// Creates the token for a new computation answering `request_id`.
+    fn register(&mut self, request_id: u32) -> CancellationToken {
+        self.tokens.retain(|_, tokens| {
+            tokens.retain(|token| !token.is_cancelled());
+            !tokens.is_empty()
+        });
+        let token = CancellationToken::new();
+        self.tokens.entry(request_id).or_default().push(token.clone());
+        token
+    }
+
+    /// Cancels every computation answering `request_id`.
+    ///
+    /// # Returns
+    /// `bool`, whether any of them were still running.
+    fn cancel(&mut self, request_id: u32) -> bool {
+        let tokens = self.tokens.remove(&request_id).unwrap_or_default();
+        let running = tokens.iter().any(|token| !token.is_cancelled());
+        tokens.iter().for_each(CancellationToken::cancel);
+        running
+    }
+}
+
+/// A token bucket limiting how many requests a single client may make per second.
+///
+/// The bucket holds up to one second's worth of requests, allowing short bursts, and refills continuously.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket allowing `rate` requests per second.
+    fn new(rate: u32, now: Instant) -> Self {
+        TokenBucket { capacity: rate as f64, tokens: rate as f64, last_refill: now }
+    }
+
+    /// Refills the bucket for the time elapsed since the last refill, then takes a token if one is available.
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = f64::min(self.capacity, self.tokens + elapsed * self.capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Drives a step-wise computation to completion on the current thread, sending a response for every step
+/// and finally the responses produced by `finish`.
+///
+/// Intended to be run with `task::spawn_blocking`. The computation is abandoned as soon as the receiving
+/// half of `responses` is dropped.
+///
+/// # Parameters
+/// `steps`, The iterator over the steps of the computation
+/// `step`, Converts a single step into a `Response`, or `None` if the step should not be sent
+/// `finish`, Produces the final responses once the computation has finished
+/// `responses`, The sending half of the channel the responses are streamed over
+/// `iterations`, The server wide count of steps computed, incremented for every step
+fn stream_steps<I, T>(
+    mut steps: I,
+    step: impl Fn(T) -> Option<Response>,
+    finish: impl FnOnce(I) -> Vec<Response>,
+    responses: Sender<Response>,
+    iterations: Arc<AtomicU64>,
+)
+where
+    I: Iterator<Item = T>
+{
+    while let Some(item) = Iterator::next(&mut steps) {
+        iterations.fetch_add(1, Ordering::Relaxed);
+        if let Some(response) = step(item) {
+            if responses.blocking_send(response).is_err() {
+                debug!("responses receiver dropped, abandoning computation");
+                return;
+            }
+        } else if responses.is_closed() {
+            debug!("responses receiver dropped, abandoning computation");
+            return;
+        }
+    }
+    for response in finish(steps) {
+        if responses.blocking_send(response).is_err() {
+            return;
+        }
+    }
+}
+
+/// Solves the log of `h` to the base `g` modulo `p` with Pollard's rho, answering request `request_id` over
+/// `responses`. A log solved before is served from `cache`, otherwise the steps of the walk are streamed, unless
+/// the query is part of a batch, followed by its result.
+///
+/// A solved log is stored in `cache`, and every step taken is counted in `iterations`.
+fn rho_log(
+    peer_id: Uuid,
+    request_id: u32,
+    g: u64,
+    h: u64,
+    p: u64,
+    batch_index: Option<u32>,
+    cache: &Mutex<SolvedCache>,
+    timing: Option<Arc<Mutex<Histogram>>>,
+    iterations: Arc<AtomicU64>,
+    responses: Sender<Response>,
+) {
+    let cached = cache.lock().expect("cache lock poisoned").logs.get(&(g, h, p));
+    if let Some((log, ratio, iterations)) = cached {
+        info!(peer_id = ?peer_id, g, h, p, "cache hit, serving solved discrete logarithm without recomputing");
+        let _ = responses.blocking_send(Response::SuccessfulLog { request_id, log, g, h, p, ratio, iterations, performance: Performance::from_ratio(ratio), batch_index });
+        return;
+    }
+
+    let pollards = match PollardsLog::try_new(p, g, h) {
+        Ok(pollards) => pollards,
+        Err(e) => {
+            warn!(peer_id = ?peer_id, error = %e, "unable to solve discrete logarithm");
+            let response = match e {
+                AlgoError::Overflow => Response::InputTooLarge { request_id, value: p, batch_index },
+                _ => Response::UnsuccessfulLog { request_id, g, h, p, batch_index },
+            };
+            let _ = responses.blocking_send(response);
+            return;
+        }
+    };
+
+    let timer = timing.map(Timer::start);
+    // A log only exists if h lies in the subgroup generated by g, that is if h^ord(g) = 1
+    if let Some(order) = order_excluding(g, h, p) {
+        info!(peer_id = ?peer_id, g, h, p, order, "discrete logarithm does not exist, h is not a power of g");
+        drop(timer);
+        let _ = responses.blocking_send(Response::LogDoesNotExist { request_id, g, h, p, order, batch_index });
+        return;
+    }
+    let retry_iterations = iterations.clone();
+    stream_steps(
+        pollards,
+        |item| batch_index.is_none().then(|| Response::LogItem { request_id, item }),
+        |pollards| {
+            // The collision is marked along with the steps, so queries from a batch skip it too
+            let mut responses: Vec<Response> = pollards.collision()
+                .filter(|_| batch_index.is_none())
+                .map(|(i, value)| Response::Collision { request_id, i, value })
+                .into_iter()
+                .collect();
+            let (log, retry_steps) = match pollards.solve() {
+                Ok(Some(log)) => (Some(log), 0),
+                Ok(None) => retry_log(peer_id, g, h, p, &retry_iterations),
+                Err(e) => {
+                    warn!(peer_id = ?peer_id, g, h, p, error = %e, "unable to solve discrete logarithm");
+                    (None, 0)
+                }
+            };
+            if let Some(log) = log {
+                info!(peer_id = ?peer_id, "discrete logarithm solved successfully");
+                // Steps taken by retries count towards the cost of the solve
+                let steps = pollards.steps() + retry_steps;
+                let ratio = steps as f64 / f64::sqrt(p as f64);
+                cache.lock().expect("cache lock poisoned").logs.insert((g, h, p), (log, ratio, steps));
+                responses.push(Response::SuccessfulLog { request_id, log, g: pollards.g, h: pollards.h, p: pollards.p, ratio, iterations: steps, performance: Performance::from_ratio(ratio), batch_index });
+            } else {
+                info!(peer_id = ?peer_id, "discrete logarithm not solved");
+                responses.push(Response::UnsuccessfulLog { request_id, g: pollards.g, h: pollards.h, p: pollards.p, batch_index });
+            }
+            drop(timer);
+            responses
+        },
+        responses,
+        iterations,
+    )
+}
+
+/// Retries a Pollard's rho walk for the log of `h` to the base `g` modulo `p` that failed from the default start,
+/// walking from up to `LOG_RETRIES` random starting points until one solves it. Returns the log, if found, along
+/// with the number of steps the retries took, which are also added to `iterations`.
+fn retry_log(peer_id: Uuid, g: u64, h: u64, p: u64, iterations: &AtomicU64) -> (Option<u64>, u64) {
+    let mut rng = rand::thread_rng();
+    let mut steps = 0;
+    for attempt in 1..=LOG_RETRIES {
+        let (a0, b0) = (rng.gen_range(0..p - 1), rng.gen_range(0..p - 1));
+        debug!(peer_id = ?peer_id, attempt, a0, b0, "retrying discrete logarithm from g^{} h^{}", a0, b0);
+        let mut pollards = match PollardsLog::with_start(p, g, h, a0, b0) {
+            Ok(pollards) => pollards,
+            Err(e) => {
+                warn!(peer_id = ?peer_id, attempt, error = %e, "unable to retry discrete logarithm");
+                break;
+            }
+        };
+        for _ in &mut pollards {}
+        steps += pollards.steps();
+        iterations.fetch_add(pollards.steps(), Ordering::Relaxed);
+        match pollards.solve() {
+            Ok(Some(log)) => return (Some(log), steps),
+            Ok(None) => {}
+            Err(e) => warn!(peer_id = ?peer_id, attempt, error = %e, "retry of discrete logarithm failed"),
+        }
+    }
+    (None, steps)
+}
+
+/// Factors `n` by racing `workers` Pollard's rho walks on blocking threads, counting their steps in `iterations`.
+/// Returns the first nontrivial factor found along with the number of steps its walk took, cancelling the walks
+/// still running, or `None` if every walk gives up or `n` cannot be factored without overflow.
+async fn factor_parallel(n: u64, workers: usize, iterations: Arc<AtomicU64>) -> Option<(u64, u64)> {
+    race_walks(rho_walks(n, workers).ok()?, iterations).await
+}
+
+/// The `workers` Pollard's rho walks raced by `race_walks` to factor `n`, the `i`th walking `x -> x^2 + i` from
+/// `i + 1`. Each yields the nontrivial factor of `n` found by its last step, if any.
+///
+/// Returns an `AlgoError` if `n` cannot be factored without overflow.
+fn rho_walks(n: u64, workers: usize) -> Result<Vec<impl Iterator<Item = Option<u64>> + Send + 'static>, AlgoError> {
+    (1..=workers as u64)
+        .map(|c| {
+            PollardsRSAFact::with_poly(n, c, c + 1)
+                .map(|walk| Iterator::map(walk, |item| (item.g != 1 && item.g != item.n).then_some(item.g)))
+        })
+        .collect()
+}
+
+/// Runs each of `walks` on its own blocking thread, counting its steps in `iterations`, until one of them yields a
+/// factor. Returns that factor along with the number of steps its walk took, or `None` if every walk finishes
+/// without one.
+///
+/// The walks still running are cancelled once a factor is found, or when the returned future is dropped.
+async fn race_walks<W>(walks: Vec<W>, iterations: Arc<AtomicU64>) -> Option<(u64, u64)>
+where
+    W: Iterator<Item = Option<u64>> + Send + 'static
+{
+    let cancel = CancellationToken::new();
+    let _guard = cancel.clone().drop_guard();
+    let mut walks = walks.into_iter()
+        .map(|walk| {
+            let cancel = cancel.clone();
+            let iterations = iterations.clone();
+            task::spawn_blocking(move || {
+                for (steps, found) in (1..).zip(walk) {
+                    if cancel.is_cancelled() {
+                        return None;
+                    }
+                    iterations.fetch_add(1, Ordering::Relaxed);
+                    if let Some(factor) = found {
+                        return Some((factor, steps));
+                    }
+                }
+                None
+            })
+        })
+        .collect::<stream::FuturesUnordered<_>>();
+    while let Some(res) = walks.next().await {
+        match res {
+            Ok(Some(won)) => return Some(won),
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "Pollard's rho walk failed"),
+        }
+    }
+    None
+}
+
+/// The response to request `request_id`, or to its query `batch_index` of a batch, when the blocking task computing
+/// it fails, e.g. by panicking, so that the broker keeps serving the client rather than shutting down.
+fn task_failed(peer_id: Uuid, request_id: u32, batch_index: Option<u32>, e: JoinError) -> Response {
+    error!(peer_id = ?peer_id, request_id, error = %e, "computation for client {} failed", peer_id);
+    Response::Error { request_id, code: error_code::INTERNAL, message: "the server failed to compute the result".to_string(), batch_index }
+}
+
+/// Runs `compute` for request `request_id` on a blocking thread, handing it the sending half of the channel, holding
+/// up to `buf_size` responses, that its responses are sent over. Returns the receiving half, to be relayed as a
+/// `Reply::Stream` so the computation is bound by the same deadline and cancellation as any other stream.
+///
+/// Should `compute` fail, e.g. by panicking, the client is sent an `Error` in place of its result, carrying
+/// `batch_index` if the computation answers a query of a batch.
+fn spawn_computation(peer_id: Uuid, request_id: u32, batch_index: Option<u32>, buf_size: usize, compute: impl FnOnce(Sender<Response>) + Send + 'static) -> Receiver<Response> {
+    let (responses_send, responses) = channel::<Response>(buf_size);
+    let failed = responses_send.clone();
+    task::spawn(async move {
+        if let Err(e) = task::spawn_blocking(move || compute(responses_send)).await {
+            let _ = failed.send(task_failed(peer_id, request_id, batch_index, e)).await;
+        }
+    });
+    responses
+}
+
+/// Solves a discrete log with baby-step giant-step, the iterations and ratio of a solved log report the size of
+/// the baby-step table, absolute and relative to `sqrt(p)`.
+fn bsgs_result(peer_id: Uuid, request_id: u32, g: u64, h: u64, p: u64, batch_index: Option<u32>) -> Response {
+    match bsgs(g, h, p) {
+        Some(log) => {
+            info!(peer_id = ?peer_id, "discrete logarithm solved successfully with baby-step giant-step");
+            let iterations = f64::sqrt((p - 1) as f64).ceil();
+            let ratio = iterations / f64::sqrt(p as f64);
+            Response::SuccessfulLog { request_id, log, g, h, p, ratio, iterations: iterations as u64, performance: Performance::from_ratio(ratio), batch_index }
+        }
+        None => {
+            info!(peer_id = ?peer_id, "discrete logarithm not solved with baby-step giant-step");
+            unsolved_log(request_id, g, h, p, batch_index)
+        }
+    }
+}
+
+/// Solves a discrete log with Pohlig-Hellman, the iterations of a solved log are the baby-step giant-step steps
+/// taken in the subgroups of the factors of `p - 1`.
+fn pohlig_hellman_result(peer_id: Uuid, request_id: u32, g: u64, h: u64, p: u64, batch_index: Option<u32>) -> Response {
+    match pohlig_hellman_with_steps(g, h, p) {
+        Some((log, iterations)) => {
+            info!(peer_id = ?peer_id, "discrete logarithm solved successfully with Pohlig-Hellman");
+            let ratio = iterations as f64 / f64::sqrt(p as f64);
+            Response::SuccessfulLog { request_id, log, g, h, p, ratio, iterations, performance: Performance::from_ratio(ratio), batch_index }
+        }
+        None => {
+            info!(peer_id = ?peer_id, "discrete logarithm not solved with Pohlig-Hellman");
+            unsolved_log(request_id, g, h, p, batch_index)
+        }
+    }
+}
+
+/// Returns the order of `g` modulo `p` if `h` lies outside the subgroup it generates, that is if `h^ord(g) != 1`
+/// and `g^x = h` has no solution.
+fn order_excluding(g: u64, h: u64, p: u64) -> Option<u64> {
+    mult_order(g, p).filter(|&order| fast_power(h, order, p) != 1)
+}
+
+/// The response to a discrete log a method failed to solve, `LogDoesNotExist` if there is no solution to find
+/// and `UnsuccessfulLog` if the method gave up.
+fn unsolved_log(request_id: u32, g: u64, h: u64, p: u64, batch_index: Option<u32>) -> Response {
+    match order_excluding(g, h, p) {
+        Some(order) => Response::LogDoesNotExist { request_id, g, h, p, order, batch_index },
+        None => Response::UnsuccessfulLog { request_id, g, h, p, batch_index },
+    }
+}
+
+/// Picks the method `LogMethod::Auto` solves a discrete log modulo `p` with.
+///
+/// Pohlig-Hellman if `p` is a prime with `p - 1` free of prime factors above `SMOOTHNESS_BOUND`, since it only
+/// searches the subgroups of those factors. Otherwise baby-step giant-step if `p` is at most `AUTO_BSGS_LIMIT`, and
+/// Pollard's rho, which needs no table, beyond that.
+fn auto_log_method(p: u64) -> LogMethod {
+    let smooth = p > 2 && is_prime(p) && factorize(p - 1).last().is_some_and(|&(q, _)| q <= SMOOTHNESS_BOUND);
+    if smooth {
+        LogMethod::PohligHellman
+    } else if p <= AUTO_BSGS_LIMIT {
+        LogMethod::Bsgs
+    } else {
+        LogMethod::PollardsRho
+    }
+}
+
+/// Builds the final responses to request `request_id`, an attempt to factor the RSA public key `(n, e)`.
+///
+/// A successful factorization is stored in `cache`.
+fn rsa_result(peer_id: Uuid, request_id: u32, n: u64, e: u64, factor: Option<u64>, ratio: f64, iterations: u64, cache: &Mutex<SolvedCache>) -> Vec<Response> {
+    if let Some(p) = factor {
+        info!(peer_id = ?peer_id, "public key factored successfully");
+        cache.lock().expect("cache lock poisoned").rsas.insert(n, (p, ratio, iterations));
+        rsa_factored(request_id, p, n / p, e, ratio, iterations)
+    } else {
+        info!(peer_id = ?peer_id, "public key not factored successfully");
+        vec![Response::UnsuccessfulRSA { request_id, n }]
+    }
+}
+
+/// Builds the responses to request `request_id` for the RSA public key `(p * q, e)` once it has been factored,
+/// the `SuccessfulRSA` followed by the `RSAKey` carrying the private exponent `d = e^-1 mod (p - 1)(q - 1)`.
+///
+/// A key whose `e` is 1, or has no inverse because it shares a factor with `p - 1` or `q - 1`, is malformed and
+/// answered with `InvalidExponent` in place of the `RSAKey`.
+fn rsa_factored(request_id: u32, p: u64, q: u64, e: u64, ratio: f64, iterations: u64) -> Vec<Response> {
+    let factored = Response::SuccessfulRSA { request_id, p, q, ratio, iterations, performance: Performance::from_ratio(ratio) };
+    let key = match mod_inverse(e, (p - 1) * (q - 1)) {
+        Some(d) if e > 1 => Response::RSAKey { request_id, p, q, d },
+        _ => {
+            warn!(n = p * q, e, "public exponent has no private exponent, the key is malformed");
+            Response::InvalidExponent { request_id, n: p * q, e }
+        }
+    };
+    vec![factored, key]
+}
+
+#[derive(Debug)]
+pub enum ServerError<> {
+    Connection(std::io::Error),
+    ChannelSend(String),
+    ChannelReceive(String),
+    IllegalFrame(Uuid, Frame),
+    IllegalResponse(Uuid, Response),
+    IllegalState(String),
+    Read(std::io::Error),
+    Task(JoinError),
+    Write(std::io::Error),
+}
+
+impl Display for ServerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ServerError::Connection(e) => write!(f, "{:?}", e),
+            ServerError::Connection(e) => write!(f, "unable to bind server address: {e}"),
             ServerError::ChannelSend(s) => write!(f, "{s}"),
             ServerError::ChannelReceive(s) => write!(f, "{s}"),
             ServerError::IllegalFrame(id, frame) => write!(f, "illegal frame from client {}: {:?}", id, frame),
@@ -394,43 +1970,1899 @@ impl std::error::Error for ServerError {}
 
 #[derive(Parser)]
 struct Cli {
-    /// The address that the server will listen for incoming clients
-    #[arg(short, long)]
-    address: String,
-
-    /// The port for the address
-    #[arg(short, long)]
-    port: u16,
+    /// The address, as `host:port`, that the server will listen for incoming clients on. The host may be an IPv4
+    /// address, an IPv6 address in brackets such as `[::1]`, or a host name
+    #[arg(short, long, default_value = "0.0.0.0:8080")]
+    addr: String,
 
     /// The size of the channel buffer
-    #[arg(short, long)]
+    #[arg(short, long, default_value_t = 1000)]
     buf_size: usize,
 
+    /// Factor RSA keys using Brent's variant of Pollard's rho instead of Floyd's
+    #[arg(long)]
+    brent: bool,
+
+    /// The number of seconds a single discrete log or RSA computation may run before it is abandoned
+    #[arg(short, long, default_value_t = 30)]
+    timeout: u64,
+
+    /// The number of seconds a client may go without sending a request before it is disconnected
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_timeout: u64,
+
+    /// The largest modulus accepted for discrete log and RSA requests
+    #[arg(long, default_value_t = DEFAULT_MAX_INPUT)]
+    max_input: u64,
+
+    /// The number of clients that may be connected at once
+    #[arg(short, long, default_value_t = 1000)]
+    max_clients: usize,
+
+    /// The number of requests per second a single client may make
+    #[arg(short, long, default_value_t = 50)]
+    rate_limit: u32,
+
+    /// The number of solved discrete logs, and separately RSA factorizations, cached for repeated requests, 0 disables the cache
+    #[arg(long, default_value_t = 1024)]
+    cache_size: usize,
+
+    /// The number of primality test rounds run on each prime check
+    #[arg(long, default_value_t = DEFAULT_MR_ROUNDS, value_parser = clap::value_parser!(u32).range(1..))]
+    mr_rounds: u32,
+
+    /// The primality test run on each prime check
+    #[arg(long, value_enum, default_value_t = PrimalityTest::MillerRabin)]
+    primality_test: PrimalityTest,
+
+    /// Append a line for each handled request, with its parameters and outcome, to the file at PATH
+    #[arg(long, value_name = "PATH")]
+    audit_log: Option<PathBuf>,
+
+    /// Serve clients over TLS, presenting the PEM encoded certificate chain at PATH
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM encoded private key of the certificate given with `--tls-cert`
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Only serve clients that prove they hold the shared key KEY, by answering the challenge sent when they connect
+    #[arg(long, value_name = "KEY")]
+    auth_key: Option<String>,
+
+    /// The number of steps of a single discrete log, RSA or prime computation streamed to the client, the result is
+    /// sent once the computation finishes regardless. Unlimited by default
+    #[arg(long, value_name = "N")]
+    max_stream_items: Option<usize>,
+
+    /// Time every discrete log, RSA and prime computation, reporting the shortest, median and longest times of each
+    /// in the stats
+    #[arg(long)]
+    benchmark: bool,
+
+    /// The number of worker threads the server runs on, the number of CPUs by default
+    #[arg(long, value_name = "N")]
+    threads: Option<NonZeroUsize>,
+
+    /// The number of Pollard's rho walks with different polynomials raced to factor a single RSA key, only the
+    /// result is sent when more than one is raced
+    #[arg(long, value_name = "N", default_value = "1")]
+    rho_workers: NonZeroUsize,
 }
 
-#[instrument]
-fn main() {
-    let _ = tracing_subscriber::fmt()
+/// Builds the multi-threaded runtime the server runs on, with `threads` worker threads.
+fn runtime(threads: usize) -> std::io::Result<Runtime> {
+    Builder::new_multi_thread()
+        .worker_threads(threads)
+        .enable_all()
+        .build()
+}
+
+/// Loads the PEM encoded certificate chain at `cert` and the private key for it at `key` into a TLS configuration.
+///
+/// # Returns
+/// `Result<Arc<rustls::ServerConfig>, std::io::Error>`, an error of kind `InvalidData` if either file does not
+/// hold what it should or the key does not match the certificate.
+fn load_tls_config(cert: &Path, key: &Path) -> Result<Arc<rustls::ServerConfig>, std::io::Error> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(File::open(cert)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(invalid(format!("no certificates found in {}", cert.display())));
+    }
+    let private_key = rustls_pemfile::private_key(&mut std::io::BufReader::new(File::open(key)?))?
+        .ok_or_else(|| invalid(format!("no private key found in {}", key.display())))?;
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, private_key)
+        .map_err(|e| invalid(format!("unable to use certificate {}: {e}", cert.display())))?;
+    Ok(Arc::new(config))
+}
+
+/// Builds the subscriber for the server's logs, filtered by `filter` and written to `writer`.
+fn subscriber<W>(filter: EnvFilter, writer: W) -> impl tracing::Subscriber + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static
+{
+    tracing_subscriber::fmt()
         .with_level(true)
-        .with_env_filter(EnvFilter::from_default_env())
+        .with_env_filter(filter)
         .with_file(true)
         .with_line_number(true)
-        .init();
+        .with_writer(writer)
+        .finish()
+}
+
+#[instrument]
+fn main() -> ExitCode {
+    // The level is controlled by `RUST_LOG`, e.g. `RUST_LOG=debug`
+    tracing::subscriber::set_global_default(subscriber(EnvFilter::from_default_env(), std::io::stdout))
+        .expect("unable to set global tracing subscriber");
 
     let cli = Cli::parse();
-    debug!(address = cli.address, port = cli.port, buf_size = cli.buf_size, "Cli arguments parsed");
+    debug!(addr = ?cli.addr, buf_size = cli.buf_size, brent = cli.brent, timeout = cli.timeout, idle_timeout = cli.idle_timeout, max_input = cli.max_input, max_clients = cli.max_clients, rate_limit = cli.rate_limit, cache_size = cli.cache_size, mr_rounds = cli.mr_rounds, primality_test = ?cli.primality_test, audit_log = ?cli.audit_log, tls_cert = ?cli.tls_cert, auth = cli.auth_key.is_some(), max_stream_items = ?cli.max_stream_items, benchmark = cli.benchmark, threads = ?cli.threads, rho_workers = cli.rho_workers, "Cli arguments parsed");
+    let audit_file = match cli.audit_log.as_ref().map(|path| OpenOptions::new().create(true).append(true).open(path)).transpose() {
+        Ok(file) => file,
+        Err(e) => {
+            error!(e = ?e, "unable to open audit log");
+            eprintln!("unable to open audit log: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let tls = match (&cli.tls_cert, &cli.tls_key) {
+        (Some(cert), Some(key)) => match load_tls_config(cert, key) {
+            Ok(tls) => Some(tls),
+            Err(e) => {
+                error!(e = ?e, "unable to load TLS certificate");
+                eprintln!("unable to load TLS certificate: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => None,
+    };
+    let mut config = ServerConfig {
+        buf_size: cli.buf_size,
+        brent: cli.brent,
+        timeout: Duration::from_secs(cli.timeout),
+        idle_timeout: Duration::from_secs(cli.idle_timeout),
+        max_input: cli.max_input,
+        max_clients: cli.max_clients,
+        rate_limit: cli.rate_limit,
+        cache_size: cli.cache_size,
+        mr_rounds: cli.mr_rounds,
+        primality_test: cli.primality_test,
+        capabilities: SUPPORTED_CAPABILITIES,
+        audit: None,
+        tls,
+        auth_key: cli.auth_key.map(AuthKey::new),
+        max_stream_items: cli.max_stream_items,
+        benchmark: cli.benchmark,
+        rho_workers: cli.rho_workers.get(),
+    };
 
-    let mut rt = Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .expect("unable to build runtime");
+    let threads = cli.threads
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let rt = runtime(threads).expect("unable to build runtime");
 
-    let res = rt.block_on(accept_loop((cli.address.as_str(), cli.port), cli.buf_size));
+    let res = rt.block_on(async {
+        // The audit log is written on a blocking thread, so it must be spawned within the runtime
+        config.audit = audit_file.map(|file| AuditLog::spawn(file).0);
+        accept_loop(cli.addr.as_str(), config).await
+    });
     if let Err(e) = res {
         error!(e = ?e, "error running server");
-    } else {
-        info!("server shutting down")
+        eprintln!("{e}");
+        return ExitCode::FAILURE;
     }
+    info!("server shutting down");
+    ExitCode::SUCCESS
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use discrete_log_server::algo::mod_pow;
 
+    /// Serves `config` on an ephemeral port of a fresh runtime and connects to it, reading the `ConnectionOk`.
+    fn connect_to_test_server(config: ServerConfig) -> (Runtime, TcpStream) {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let socket = rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            task::spawn(serve(TcpListenerStream::new(listener), config));
+
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut socket).await.unwrap();
+            socket
+        });
+        (rt, socket)
+    }
+
+    /// Reads the streamed rounds of a prime check from `socket`, followed by its verdict.
+    async fn prime_check(socket: &mut TcpStream) -> (Vec<Response>, Response) {
+        let mut rounds = vec![];
+        loop {
+            match Response::from_reader(&mut *socket).await.unwrap() {
+                r @ Response::PrimeRound { .. } => rounds.push(r),
+                r => return (rounds, r),
+            }
+        }
+    }
+
+    #[test]
+    fn serve_accepts_connection_on_ephemeral_port() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            task::spawn(serve(TcpListenerStream::new(listener), ServerConfig { buf_size: 16, ..Default::default() }));
+
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::ConnectionOk { request_id: 0, version: PROTOCOL_VERSION, .. }));
+
+            socket.write_all(&Frame::Quit { request_id: 0 }.as_bytes()).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn serve_shuts_down_when_listener_ends() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            // The listener stream ends after accepting a single client
+            let server = task::spawn(serve(TcpListenerStream::new(listener).take(1), ServerConfig { buf_size: 16, ..Default::default() }));
+
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(response.is_connection_ok());
+            socket.write_all(&Frame::Quit { request_id: 0 }.as_bytes()).await.unwrap();
+            drop(socket);
+
+            assert!(server.await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn audit_log_records_each_request() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("audit_{}.log", std::process::id()));
+
+        rt.block_on(async {
+            let (audit, writer) = AuditLog::spawn(std::fs::File::create(&path).unwrap());
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let config = ServerConfig { buf_size: 16, mr_rounds: 5, audit: Some(audit), ..Default::default() };
+            let server = task::spawn(serve(TcpListenerStream::new(listener).take(1), config));
+
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut socket).await.unwrap();
+
+            socket.write_all(&Frame::Log { request_id: 1, g: 2, h: 2495, p: 5011, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            loop {
+                let response = Response::from_reader(&mut socket).await.unwrap();
+                if !response.is_step() && !response.is_collision() {
+                    assert!(response.is_successful_log());
+                    break;
+                }
+            }
+            socket.write_all(&Frame::Prime { request_id: 2, p: 7919, seed: Some(7) }.as_bytes()).await.unwrap();
+            assert!(prime_check(&mut socket).await.1.is_prime());
+
+            socket.write_all(&Frame::Quit { request_id: 0 }.as_bytes()).await.unwrap();
+            drop(socket);
+            assert!(server.await.unwrap().is_ok());
+            // The writer finishes once the server has dropped every handle to the log
+            writer.await.unwrap().unwrap();
+        });
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<Vec<&str>> = log.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(lines.len(), 2);
+        for line in &lines {
+            assert_eq!(line.len(), 7);
+            assert!(line[0].parse::<u128>().is_ok());
+            assert!(Uuid::parse_str(line[1]).is_ok());
+        }
+        assert_eq!(lines[0][2..6], ["1", "log", "g=2 h=2495 p=5011", "solved"]);
+        assert!(lines[0][6].parse::<u64>().is_ok());
+        assert_eq!(lines[1][2..], ["2", "prime", "p=7919 seed=7", "prime", "5"]);
+        assert_eq!(lines[0][1], lines[1][1]);
+    }
+
+    #[test]
+    fn audit_log_abandons_requests_of_disconnected_clients() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("audit_abandoned_{}.log", std::process::id()));
+
+        rt.block_on(async {
+            let (audit, writer) = AuditLog::spawn(std::fs::File::create(&path).unwrap());
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let config = ServerConfig { buf_size: 16, mr_rounds: 5, audit: Some(audit), max_stream_items: Some(1), ..Default::default() };
+            let server = task::spawn(serve(TcpListenerStream::new(listener).take(1), config));
+
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut socket).await.unwrap();
+
+            socket.write_all(&Frame::Prime { request_id: 1, p: 7919, seed: Some(7) }.as_bytes()).await.unwrap();
+            assert!(prime_check(&mut socket).await.1.is_prime());
+            // The client drops its connection once the log is under way, long before the walk could finish
+            socket.write_all(&Frame::Log { request_id: 2, g: 2, h: 5, p: 4294967291, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            assert!(Response::from_reader(&mut socket).await.unwrap().is_step());
+            drop(socket);
+            assert!(server.await.unwrap().is_ok());
+            writer.await.unwrap().unwrap();
+        });
+        rt.shutdown_timeout(Duration::from_secs(5));
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<Vec<&str>> = log.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][2..], ["1", "prime", "p=7919 seed=7", "prime", "5"]);
+        assert_eq!(lines[1][2..], ["2", "log", "g=2 h=5 p=4294967291", "abandoned", "-"]);
+    }
+
+    #[test]
+    fn ping_is_answered_with_its_nonce() {
+        // A rate limit of one request per second does not hold back pings
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, rate_limit: 1, ..Default::default() });
+
+        rt.block_on(async {
+            for (request_id, nonce) in [(1, 0), (2, 0xdead_beef), (3, u64::MAX)] {
+                socket.write_all(&Frame::Ping { request_id, nonce }.as_bytes()).await.unwrap();
+                assert_eq!(Response::from_reader(&mut socket).await.unwrap(), Response::Pong { request_id, nonce });
+            }
+        });
+    }
+
+    #[test]
+    fn out_of_range_log_parameters_are_rejected() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            let cases = [
+                (0, 2495, InvalidReason::BaseOutOfRange),
+                (5011, 2495, InvalidReason::BaseOutOfRange),
+                (2, 0, InvalidReason::TargetOutOfRange),
+                (2, 5011, InvalidReason::TargetOutOfRange),
+            ];
+            for (request_id, (g, h, reason)) in (1..).zip(cases) {
+                for method in [LogMethod::PollardsRho, LogMethod::Bsgs] {
+                    socket.write_all(&Frame::Log { request_id, g, h, p: 5011, method }.as_bytes()).await.unwrap();
+                    assert_eq!(Response::from_reader(&mut socket).await.unwrap(), Response::InvalidParameters { request_id, reason, batch_index: None });
+                }
+            }
+
+            // The connection is still usable afterwards
+            socket.write_all(&Frame::Log { request_id: 5, g: 2, h: 2495, p: 5011, method: LogMethod::Bsgs }.as_bytes()).await.unwrap();
+            assert!(Response::from_reader(&mut socket).await.unwrap().is_successful_log());
+        });
+    }
+
+    #[test]
+    fn reconnecting_with_session_token_resumes_session() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            task::spawn(serve(TcpListenerStream::new(listener), ServerConfig { buf_size: 16, rate_limit: 1, ..Default::default() }));
+
+            async fn connect(addr: std::net::SocketAddr) -> (TcpStream, u128) {
+                let mut socket = TcpStream::connect(addr).await.unwrap();
+                let Response::ConnectionOk { session, .. } = Response::from_reader(&mut socket).await.unwrap() else {
+                    panic!("expected `ConnectionOk`");
+                };
+                (socket, session)
+            }
+
+            // The first connection uses up the single request its session may make this second
+            let (mut stale, session) = connect(addr).await;
+            stale.write_all(&Frame::Bsgs { request_id: 1, g: 2, h: 2495, p: 5011 }.as_bytes()).await.unwrap();
+            assert!(Response::from_reader(&mut stale).await.unwrap().is_successful_log());
+
+            // Reconnecting with the token hands the session to the new connection and closes the stale one
+            let (mut socket, fresh) = connect(addr).await;
+            assert_ne!(fresh, session);
+            socket.write_all(&Frame::Resume { request_id: 2, session }.as_bytes()).await.unwrap();
+            assert_eq!(Response::from_reader(&mut socket).await.unwrap(), Response::ConnectionOk { request_id: 2, version: PROTOCOL_VERSION, session, challenge: [0; AUTH_LEN] });
+            let err = Response::from_reader(&mut stale).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+            // The rate limit of the session carries over, so the request is dropped
+            socket.write_all(&Frame::Bsgs { request_id: 3, g: 2, h: 2495, p: 5011 }.as_bytes()).await.unwrap();
+            assert_eq!(Response::from_reader(&mut socket).await.unwrap(), Response::RateLimited { request_id: 3, batch_index: None });
+
+            // Only the first frame may resume a session, later attempts are dropped
+            socket.write_all(&Frame::Resume { request_id: 4, session: fresh }.as_bytes()).await.unwrap();
+            socket.write_all(&Frame::Ping { request_id: 5, nonce: 1 }.as_bytes()).await.unwrap();
+            assert_eq!(Response::from_reader(&mut socket).await.unwrap(), Response::Pong { request_id: 5, nonce: 1 });
+
+            // An unknown token leaves the client with the session it was issued
+            let (mut other, issued) = connect(addr).await;
+            other.write_all(&Frame::Resume { request_id: 6, session: fresh }.as_bytes()).await.unwrap();
+            assert_eq!(Response::from_reader(&mut other).await.unwrap(), Response::ConnectionOk { request_id: 6, version: PROTOCOL_VERSION, session: issued, challenge: [0; AUTH_LEN] });
+        });
+    }
+
+    #[test]
+    fn clients_must_answer_challenge_with_shared_key() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let key = AuthKey::new("shared secret");
+            task::spawn(serve(TcpListenerStream::new(listener), ServerConfig { buf_size: 16, auth_key: Some(key.clone()), ..Default::default() }));
+
+            async fn connect(addr: std::net::SocketAddr) -> (TcpStream, [u8; AUTH_LEN]) {
+                let mut socket = TcpStream::connect(addr).await.unwrap();
+                let Response::ConnectionOk { challenge, .. } = Response::from_reader(&mut socket).await.unwrap() else {
+                    panic!("expected `ConnectionOk`");
+                };
+                (socket, challenge)
+            }
+
+            // A client holding the key is served once it answers its challenge
+            let (mut socket, challenge) = connect(addr).await;
+            socket.write_all(&Frame::Auth { request_id: 1, hmac: key.sign(&challenge) }.as_bytes()).await.unwrap();
+            socket.write_all(&Frame::Bsgs { request_id: 2, g: 2, h: 2495, p: 5011 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(response.is_successful_log());
+            assert_eq!(response.request_id(), 2);
+
+            // Every connection is sent its own challenge, so an old answer is no use
+            let (mut replayed, fresh) = connect(addr).await;
+            assert_ne!(fresh, challenge);
+            replayed.write_all(&Frame::Auth { request_id: 3, hmac: key.sign(&challenge) }.as_bytes()).await.unwrap();
+            assert_eq!(Response::from_reader(&mut replayed).await.unwrap(), Response::AuthFailed { request_id: 3 });
+            let err = Response::from_reader(&mut replayed).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+            // A wrong key fails the handshake and closes the connection
+            let (mut wrong, challenge) = connect(addr).await;
+            wrong.write_all(&Frame::Auth { request_id: 4, hmac: AuthKey::new("guess").sign(&challenge) }.as_bytes()).await.unwrap();
+            wrong.write_all(&Frame::Ping { request_id: 5, nonce: 1 }.as_bytes()).await.unwrap();
+            assert_eq!(Response::from_reader(&mut wrong).await.unwrap(), Response::AuthFailed { request_id: 4 });
+            let err = Response::from_reader(&mut wrong).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+            // As does skipping the handshake
+            let (mut skipped, _) = connect(addr).await;
+            skipped.write_all(&Frame::Ping { request_id: 6, nonce: 1 }.as_bytes()).await.unwrap();
+            assert_eq!(Response::from_reader(&mut skipped).await.unwrap(), Response::AuthFailed { request_id: 0 });
+        });
+    }
+
+    #[test]
+    fn tls_clients_are_served_over_self_signed_certificate() {
+        // The certificate and key are loaded from PEM files, as they would be from `--tls-cert` and `--tls-key`
+        let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = std::env::temp_dir().join(format!("tls_cert_{}.pem", std::process::id()));
+        let key_path = std::env::temp_dir().join(format!("tls_key_{}.pem", std::process::id()));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+        let tls = load_tls_config(&cert_path, &key_path).unwrap();
+        assert_eq!(load_tls_config(&key_path, &key_path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&cert_path).unwrap();
+        std::fs::remove_file(&key_path).unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.der().clone()).unwrap();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(
+            rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth()
+        ));
+
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            task::spawn(serve(TcpListenerStream::new(listener), ServerConfig { buf_size: 16, tls: Some(tls), ..Default::default() }));
+
+            let name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+            let mut socket = connector.connect(name, TcpStream::connect(addr).await.unwrap()).await.unwrap();
+            assert!(Response::from_reader(&mut socket).await.unwrap().is_connection_ok());
+            socket.write_all(&Frame::Bsgs { request_id: 1, g: 2, h: 2495, p: 5011 }.as_bytes()).await.unwrap();
+            socket.flush().await.unwrap();
+            assert!(Response::from_reader(&mut socket).await.unwrap().is_successful_log());
+
+            // A plaintext client fails the handshake and is never greeted
+            let mut plain = TcpStream::connect(addr).await.unwrap();
+            plain.write_all(&Frame::Ping { request_id: 2, nonce: 7 }.as_bytes()).await.unwrap();
+            assert!(Response::from_reader(&mut plain).await.is_err());
+        });
+    }
+
+    #[test]
+    fn admin_lists_connected_peers() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let key = AuthKey::new("shared secret");
+            let (events_send, events_recv) = channel::<Event>(16);
+            let broker = task::spawn(main_broker(events_recv, ServerConfig { buf_size: 16, auth_key: Some(key.clone()), ..Default::default() }));
+
+            // Two clients that both authenticate with the key
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let mut ids = vec![Uuid::new_v4(), Uuid::new_v4()];
+            let mut sockets = vec![];
+            let mut tokens = vec![];
+            for (i, &peer_id) in ids.iter().enumerate() {
+                let mut client = TcpStream::connect(addr).await.unwrap();
+                let (server_side, _) = listener.accept().await.unwrap();
+                let (_, socket) = server_side.into_split();
+                let token = CancellationToken::new();
+                events_send.send(Event::NewClient { peer_id, socket: Box::new(socket), token: token.clone() }).await.unwrap();
+                let Response::ConnectionOk { challenge, .. } = Response::from_reader(&mut client).await.unwrap() else {
+                    panic!("expected `ConnectionOk`");
+                };
+                events_send.send(Event::Auth { peer_id, request_id: i as u32, hmac: key.sign(&challenge) }).await.unwrap();
+                sockets.push(client);
+                tokens.push(token);
+            }
+
+            // Either client sees both ids
+            events_send.send(Event::ListPeers { peer_id: ids[1], request_id: 5 }).await.unwrap();
+            ids.sort();
+            assert_eq!(Response::from_reader(&mut sockets[1]).await.unwrap(), Response::PeerList { request_id: 5, ids });
+
+            for token in tokens {
+                token.cancel();
+            }
+            drop(events_send);
+            assert!(broker.await.unwrap().is_ok());
+
+            // Without a key there are no admins to serve
+            let (events_send, events_recv) = channel::<Event>(16);
+            let broker = task::spawn(main_broker(events_recv, ServerConfig { buf_size: 16, ..Default::default() }));
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let (server_side, _) = listener.accept().await.unwrap();
+            let (_, socket) = server_side.into_split();
+            let (peer_id, token) = (Uuid::new_v4(), CancellationToken::new());
+            events_send.send(Event::NewClient { peer_id, socket: Box::new(socket), token: token.clone() }).await.unwrap();
+            assert!(Response::from_reader(&mut client).await.unwrap().is_connection_ok());
+            events_send.send(Event::ListPeers { peer_id, request_id: 6 }).await.unwrap();
+            let Response::Error { request_id, code, .. } = Response::from_reader(&mut client).await.unwrap() else {
+                panic!("expected `Error`");
+            };
+            assert_eq!((request_id, code), (6, error_code::FORBIDDEN));
+
+            token.cancel();
+            drop(events_send);
+            assert!(broker.await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn kick_disconnects_only_its_target() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let key = AuthKey::new("shared secret");
+            let (events_send, events_recv) = channel::<Event>(16);
+            let broker = task::spawn(main_broker(events_recv, ServerConfig { buf_size: 16, auth_key: Some(key.clone()), ..Default::default() }));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+            let mut sockets = vec![];
+            let mut tokens = vec![];
+            for (i, &peer_id) in ids.iter().enumerate() {
+                let mut client = TcpStream::connect(addr).await.unwrap();
+                let (server_side, _) = listener.accept().await.unwrap();
+                let (_, socket) = server_side.into_split();
+                let token = CancellationToken::new();
+                events_send.send(Event::NewClient { peer_id, socket: Box::new(socket), token: token.clone() }).await.unwrap();
+                let Response::ConnectionOk { challenge, .. } = Response::from_reader(&mut client).await.unwrap() else {
+                    panic!("expected `ConnectionOk`");
+                };
+                events_send.send(Event::Auth { peer_id, request_id: i as u32, hmac: key.sign(&challenge) }).await.unwrap();
+                sockets.push(client);
+                tokens.push(token);
+            }
+
+            // The first client kicks the second
+            events_send.send(Event::Kick { peer_id: ids[0], request_id: 3, target: ids[1] }).await.unwrap();
+            assert_eq!(Response::from_reader(&mut sockets[0]).await.unwrap(), Response::Kicked { request_id: 3, peer_id: ids[1] });
+            assert!(tokens[1].is_cancelled());
+            let err = Response::from_reader(&mut sockets[1]).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+            // The others are still served, and the kicked client is gone
+            assert!(!tokens[0].is_cancelled() && !tokens[2].is_cancelled());
+            events_send.send(Event::Ping { peer_id: ids[2], request_id: 4, nonce: 9 }).await.unwrap();
+            assert_eq!(Response::from_reader(&mut sockets[2]).await.unwrap(), Response::Pong { request_id: 4, nonce: 9 });
+            events_send.send(Event::Kick { peer_id: ids[0], request_id: 5, target: ids[1] }).await.unwrap();
+            let Response::Error { request_id, code, .. } = Response::from_reader(&mut sockets[0]).await.unwrap() else {
+                panic!("expected `Error`");
+            };
+            assert_eq!((request_id, code), (5, error_code::UNKNOWN_PEER));
+
+            tokens[0].cancel();
+            tokens[2].cancel();
+            drop(events_send);
+            assert!(broker.await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn duplicate_peer_id_does_not_replace_live_client() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let (events_send, events_recv) = channel::<Event>(16);
+            let broker = task::spawn(main_broker(events_recv, ServerConfig { buf_size: 16, ..Default::default() }));
+
+            // Two clients whose read tasks happened to draw the same id
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let peer_id = Uuid::new_v4();
+            let mut sockets = vec![];
+            let mut tokens = vec![];
+            for _ in 0..2 {
+                let client = TcpStream::connect(addr).await.unwrap();
+                let (server_side, _) = listener.accept().await.unwrap();
+                let (_, socket) = server_side.into_split();
+                let token = CancellationToken::new();
+                events_send.send(Event::NewClient { peer_id, socket: Box::new(socket), token: token.clone() }).await.unwrap();
+                sockets.push(client);
+                tokens.push(token);
+            }
+
+            // The first client is still served, the second is turned away and told to stop reading
+            assert!(Response::from_reader(&mut sockets[0]).await.unwrap().is_connection_ok());
+            assert_eq!(Response::from_reader(&mut sockets[1]).await.unwrap(), Response::ServerBusy { request_id: 0 });
+            assert!(!tokens[0].is_cancelled());
+            assert!(tokens[1].is_cancelled());
+
+            events_send.send(Event::Ping { peer_id, request_id: 1, nonce: 42 }).await.unwrap();
+            assert_eq!(Response::from_reader(&mut sockets[0]).await.unwrap(), Response::Pong { request_id: 1, nonce: 42 });
+
+            tokens[0].cancel();
+            drop(events_send);
+            assert!(broker.await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn abrupt_disconnect_quits_and_is_harvested() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            // The read task quits on behalf of a client whose connection drops
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let client = TcpStream::connect(addr).await.unwrap();
+            let (socket, _) = listener.accept().await.unwrap();
+            let (events_send, mut events_recv) = channel::<Event>(16);
+            let read_task = task::spawn(client_read_task(socket, None, events_send, Duration::from_secs(300)));
+            let Some(Event::NewClient { peer_id, .. }) = events_recv.recv().await else {
+                panic!("expected a `NewClient` event");
+            };
+            drop(client);
+            assert!(matches!(events_recv.recv().await, Some(Event::Quit { peer_id: id }) if id == peer_id));
+            assert!(read_task.await.unwrap().is_ok());
+
+            // And the broker forgets the client
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            task::spawn(serve(TcpListenerStream::new(listener), ServerConfig { buf_size: 16, ..Default::default() }));
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut socket).await.unwrap();
+            let mut dropped = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut dropped).await.unwrap();
+            drop(dropped);
+
+            let mut clients = 2;
+            for request_id in 0..50 {
+                socket.write_all(&Frame::Stats { request_id }.as_bytes()).await.unwrap();
+                let Response::Stats { clients: connected, .. } = Response::from_reader(&mut socket).await.unwrap() else {
+                    panic!("expected a `Stats` response");
+                };
+                clients = connected;
+                if clients == 1 {
+                    break;
+                }
+                time::sleep(Duration::from_millis(20)).await;
+            }
+            assert_eq!(clients, 1);
+        });
+    }
+
+    #[test]
+    fn idle_clients_are_disconnected() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let config = ServerConfig { buf_size: 16, idle_timeout: Duration::from_millis(200), ..Default::default() };
+            task::spawn(serve(TcpListenerStream::new(listener), config));
+
+            // A client that stalls in the middle of a frame is not idle
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut socket).await.unwrap();
+            let ping = Frame::Ping { request_id: 1, nonce: 7 }.as_bytes();
+            socket.write_all(&ping[..3]).await.unwrap();
+            time::sleep(Duration::from_millis(400)).await;
+            socket.write_all(&ping[3..]).await.unwrap();
+            assert_eq!(Response::from_reader(&mut socket).await.unwrap(), Response::Pong { request_id: 1, nonce: 7 });
+
+            // A client that sends nothing is disconnected
+            let err = time::timeout(Duration::from_secs(5), Response::from_reader(&mut socket))
+                .await
+                .expect("idle client should have been disconnected")
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        });
+    }
+
+    #[test]
+    fn log_method_selects_algorithm() {
+        // Without a cache every method computes its own answer
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, cache_size: 0, ..Default::default() });
+
+        rt.block_on(async {
+            // 5010 = 2 * 3 * 5 * 167, so Auto picks Pohlig-Hellman
+            let mut results = HashMap::new();
+            for (request_id, method) in [(1, LogMethod::PollardsRho), (2, LogMethod::Bsgs), (3, LogMethod::PohligHellman), (4, LogMethod::Auto)] {
+                socket.write_all(&Frame::Log { request_id, g: 2, h: 2495, p: 5011, method }.as_bytes()).await.unwrap();
+                let mut steps = 0;
+                let (log, iterations) = loop {
+                    match Response::from_reader(&mut socket).await.unwrap() {
+                        Response::LogItem { .. } => steps += 1,
+                        Response::Collision { .. } => {}
+                        Response::SuccessfulLog { request_id: id, log, iterations, .. } if id == request_id => break (log, iterations),
+                        r => panic!("unexpected response {r:?}"),
+                    }
+                };
+                assert_eq!(mod_pow(2, log, 5011), 2495);
+                results.insert(method, (steps, iterations));
+            }
+
+            // Only Pollard's rho streams its steps
+            assert!(results[&LogMethod::PollardsRho].0 > 0);
+            // Baby-step giant-step reports its table of ceil(sqrt(5010)) baby steps
+            assert_eq!(results[&LogMethod::Bsgs], (0, 71));
+            assert_eq!(results[&LogMethod::PohligHellman].0, 0);
+            assert_ne!(results[&LogMethod::PohligHellman].1, 71);
+            assert_eq!(results[&LogMethod::Auto], results[&LogMethod::PohligHellman]);
+        });
+    }
+
+    #[test]
+    fn auto_picks_log_method_by_modulus() {
+        // 2^31 - 2 = 2 * 3^2 * 7 * 11 * 31 * 151 * 331 and 5010 = 2 * 3 * 5 * 167 are smooth
+        assert_eq!(auto_log_method(2147483647), LogMethod::PohligHellman);
+        assert_eq!(auto_log_method(5011), LogMethod::PohligHellman);
+        // 140122 = 2 * 70061 is not, but the modulus is small enough for a table of baby steps
+        assert_eq!(auto_log_method(140123), LogMethod::Bsgs);
+        // 2000302 = 2 * 1000151 is not smooth either, and the modulus is too large for the table
+        assert_eq!(auto_log_method(2000303), LogMethod::PollardsRho);
+        // Pohlig-Hellman needs a prime modulus
+        assert_eq!(auto_log_method(1 << 21), LogMethod::PollardsRho);
+
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // Rho is picked for the prime with a large factor in p - 1, so its steps are streamed
+            let h = mod_pow(5, 123456, 2000303);
+            socket.write_all(&Frame::Log { request_id: 1, g: 5, h, p: 2000303, method: LogMethod::Auto }.as_bytes()).await.unwrap();
+            let mut steps = 0;
+            let log = loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { .. } => steps += 1,
+                    Response::Collision { .. } => {}
+                    Response::SuccessfulLog { log, .. } => break log,
+                    r => panic!("unexpected response {r:?}"),
+                }
+            };
+            assert!(steps > 0);
+            assert_eq!(mod_pow(5, log, 2000303), h);
+
+            // While the smooth prime is answered by Pohlig-Hellman straight away
+            let h = mod_pow(7, 123456789, 2147483647);
+            socket.write_all(&Frame::Log { request_id: 2, g: 7, h, p: 2147483647, method: LogMethod::Auto }.as_bytes()).await.unwrap();
+            match Response::from_reader(&mut socket).await.unwrap() {
+                Response::SuccessfulLog { log, .. } => assert_eq!(mod_pow(7, log, 2147483647), h),
+                r => panic!("unexpected response {r:?}"),
+            }
+
+            // Bases that are not primitive roots are solved on smooth primes too, 4 has order 6 modulo 13 and 9
+            // has order 2^15 modulo 65537
+            for (request_id, g, h, p) in [(3, 4, 10, 13), (4, 9, 59049, 65537)] {
+                socket.write_all(&Frame::Log { request_id, g, h, p, method: LogMethod::Auto }.as_bytes()).await.unwrap();
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::SuccessfulLog { log, .. } => assert_eq!(mod_pow(g, log, p), h),
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn accept_loop_fails_when_address_in_use() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let res = accept_loop(addr, ServerConfig::default()).await;
+            assert!(matches!(res, Err(ServerError::Connection(_))));
+        });
+    }
+
+    #[test]
+    fn bind_falls_back_to_next_resolved_address() {
+        let rt = Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addrs = [taken.local_addr().unwrap(), SocketAddr::from(([127, 0, 0, 1], 0))];
+            let listener = bind(&addrs[..]).await.unwrap();
+            assert_ne!(listener.local_addr().unwrap(), addrs[0]);
+
+            assert!(bind("localhost:0").await.is_ok());
+            assert!(matches!(accept_loop("not-an-address", ServerConfig::default()).await, Err(ServerError::Connection(_))));
+        });
+    }
+
+    #[test]
+    fn large_log_does_not_stall_prime_check() {
+        // A single worker thread, so any CPU bound work on the runtime would starve the other client
+        let rt = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            task::spawn(serve(TcpListenerStream::new(listener), ServerConfig { buf_size: 16, ..Default::default() }));
+
+            // The first client requests a long running discrete log and only reads its first step
+            let mut big = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut big).await.unwrap();
+            big.write_all(&Frame::Log { request_id: 0, g: 7, h: 5, p: 2147483647, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut big).await.unwrap();
+            assert!(matches!(response, Response::LogItem { .. }));
+
+            // The second client's prime check is answered while the log is still being computed
+            let start = std::time::Instant::now();
+            let mut small = TcpStream::connect(addr).await.unwrap();
+            Response::from_reader(&mut small).await.unwrap();
+            small.write_all(&Frame::Prime { request_id: 0, p: 7919, seed: None }.as_bytes()).await.unwrap();
+            let response = prime_check(&mut small).await.1;
+            assert!(matches!(response, Response::Prime { request_id: 0, p: 7919, .. }));
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        });
+
+        rt.shutdown_timeout(std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn log_computation_times_out() {
+        let config = ServerConfig { buf_size: 16, timeout: Duration::from_millis(20), ..Default::default() };
+        let (rt, mut socket) = connect_to_test_server(config);
+
+        rt.block_on(async {
+            socket.write_all(&Frame::Log { request_id: 0, g: 7, h: 5, p: 2147483647, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+
+            // Steps are streamed until the computation is abandoned
+            let mut steps = 0;
+            loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { .. } => steps += 1,
+                    Response::Timeout { request_id: 0, millis, batch_index: None } => {
+                        assert_eq!(millis, 20);
+                        break;
+                    }
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+            assert!(steps < 185364);
+
+            // The connection is still usable after a timeout
+            socket.write_all(&Frame::Prime { request_id: 0, p: 7919, seed: None }.as_bytes()).await.unwrap();
+            let response = prime_check(&mut socket).await.1;
+            assert!(matches!(response, Response::Prime { request_id: 0, p: 7919, .. }));
+        });
+
+        rt.shutdown_timeout(Duration::from_secs(5));
+    }
+
+    #[test]
+    fn blocking_computations_time_out() {
+        let config = ServerConfig { buf_size: 16, timeout: Duration::ZERO, ..Default::default() };
+        let (rt, mut socket) = connect_to_test_server(config);
+
+        rt.block_on(async {
+            // Computations without steps to stream are still bound by the deadline
+            socket.write_all(&Frame::Bsgs { request_id: 1, g: 2, h: 2495, p: 5011 }.as_bytes()).await.unwrap();
+            assert!(matches!(Response::from_reader(&mut socket).await.unwrap(), Response::Timeout { request_id: 1, millis: 0, batch_index: None }));
+            socket.write_all(&Frame::PrimitiveRoot { request_id: 2, p: 2147483647 }.as_bytes()).await.unwrap();
+            assert!(matches!(Response::from_reader(&mut socket).await.unwrap(), Response::Timeout { request_id: 2, millis: 0, batch_index: None }));
+            for (request_id, method) in [(3, LogMethod::PohligHellman), (4, LogMethod::Auto)] {
+                socket.write_all(&Frame::Log { request_id, g: 2, h: 2495, p: 5011, method }.as_bytes()).await.unwrap();
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::Timeout { request_id: r, millis: 0, batch_index: None } => assert_eq!(r, request_id),
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+        });
+
+        rt.shutdown_timeout(Duration::from_secs(5));
+    }
+
+    #[test]
+    fn log_computation_is_cancelled_mid_stream() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            socket.write_all(&Frame::Log { request_id: 3, g: 7, h: 5, p: 2147483647, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            for _ in 0..10 {
+                let response = Response::from_reader(&mut socket).await.unwrap();
+                assert!(matches!(response, Response::LogItem { request_id: 3, .. }));
+            }
+
+            // Steps already sent may still arrive before the computation stops
+            socket.write_all(&Frame::Cancel { request_id: 3 }.as_bytes()).await.unwrap();
+            let mut steps = 10;
+            loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { request_id: 3, .. } => steps += 1,
+                    Response::Cancelled { request_id: 3, batch_index: None } => break,
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+            assert!(steps < 185364);
+
+            // The connection is still usable after cancelling, and cancelling a finished request does nothing
+            socket.write_all(&Frame::Cancel { request_id: 3 }.as_bytes()).await.unwrap();
+            socket.write_all(&Frame::Prime { request_id: 4, p: 7919, seed: None }.as_bytes()).await.unwrap();
+            let response = prime_check(&mut socket).await.1;
+            assert!(matches!(response, Response::Prime { request_id: 4, p: 7919, .. }));
+        });
+
+        rt.shutdown_timeout(Duration::from_secs(5));
+    }
+
+    #[test]
+    fn log_stream_marks_collision_before_result() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            socket.write_all(&Frame::Log { request_id: 1, g: 2, h: 2495, p: 5011, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let mut items = vec![];
+            let collision = loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { item, .. } => items.push(item),
+                    r => break r,
+                }
+            };
+            let last = items.last().unwrap();
+            assert_eq!(collision, Response::Collision { request_id: 1, i: last.i as u64, value: last.xi });
+            assert_eq!(last.xi, last.yi);
+            let result = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(result, Response::SuccessfulLog { request_id: 1, iterations, .. } if iterations == last.i as u64));
+        });
+    }
+
+    #[test]
+    fn failed_log_is_retried_from_random_starts() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // The walk from 1 ends on a collision that does not determine the log, a random start almost surely does
+            socket.write_all(&Frame::Log { request_id: 1, g: 2, h: 29, p: 1213, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let (mut steps, mut collision) = (0, None);
+            let result = loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { request_id: 1, .. } => steps += 1,
+                    Response::Collision { request_id: 1, i, .. } => collision = Some(i),
+                    r => break r,
+                }
+            };
+            assert_eq!(collision, Some(steps));
+            let Response::SuccessfulLog { request_id: 1, log, iterations, .. } = result else {
+                panic!("expected `SuccessfulLog`, got {result:?}");
+            };
+            assert_eq!(log, 236);
+            assert!(iterations > steps);
+        });
+    }
+
+    #[test]
+    fn capped_stream_sends_result_after_limit() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, max_stream_items: Some(5), ..Default::default() });
+
+        rt.block_on(async {
+            socket.write_all(&Frame::Log { request_id: 1, g: 7, h: 5, p: 2147483647, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let (mut steps, mut collision) = (0, None);
+            let result = loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { request_id: 1, .. } => steps += 1,
+                    Response::Collision { request_id: 1, i, .. } => collision = Some(i),
+                    r => break r,
+                }
+            };
+            assert_eq!(steps, 5);
+            let Response::SuccessfulLog { request_id: 1, log, iterations, .. } = result else {
+                panic!("expected `SuccessfulLog`, got {result:?}");
+            };
+            assert_eq!(mod_pow(7, log, 2147483647), 5);
+            assert!(iterations > 5);
+            // The collision is not a step, so it is sent past the limit
+            assert_eq!(collision, Some(iterations));
+
+            // The limit applies to each stream separately
+            socket.write_all(&Frame::Prime { request_id: 2, p: 7919, seed: Some(1) }.as_bytes()).await.unwrap();
+            let (rounds, verdict) = prime_check(&mut socket).await;
+            assert_eq!(rounds.len(), 5);
+            assert!(matches!(verdict, Response::Prime { request_id: 2, rounds: DEFAULT_MR_ROUNDS, .. }));
+        });
+
+        rt.shutdown_timeout(Duration::from_secs(5));
+    }
+
+    #[test]
+    fn stream_tokens_forget_relayed_streams() {
+        let mut streams = StreamTokens::default();
+        let first = streams.register(1);
+        let second = streams.register(2);
+
+        // The write task cancels the token of a stream it has finished relaying
+        first.cancel();
+        streams.register(3);
+        assert!(!streams.tokens.contains_key(&1));
+        assert!(!streams.cancel(1));
+
+        assert!(streams.cancel(2));
+        assert!(second.is_cancelled());
+        assert!(!streams.cancel(2));
+    }
+
+    #[test]
+    fn log_outside_subgroup_is_rejected_without_computing() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // 5 has order 5 modulo 71 and 2^5 != 1, so 2 is not a power of 5 and no steps are streamed
+            socket.write_all(&Frame::Log { request_id: 1, g: 5, h: 2, p: 71, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::LogDoesNotExist { request_id: 1, g: 5, h: 2, p: 71, order: 5, batch_index: None });
+            socket.write_all(&Frame::Stats { request_id: 2 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::Stats { request_id: 2, logs: 1, iterations: 0, .. }));
+        });
+    }
+
+    #[test]
+    fn trivial_logs_are_solved_without_computing() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // g^0 = 1 and g^1 = g, whichever method is asked for no steps are streamed
+            let trivial = [(1, 2, 1, 0, LogMethod::PollardsRho), (2, 2, 2, 1, LogMethod::PollardsRho), (3, 7, 1, 0, LogMethod::Bsgs), (4, 7, 7, 1, LogMethod::Auto)];
+            for (request_id, g, h, log, method) in trivial {
+                socket.write_all(&Frame::Log { request_id, g, h, p: 5011, method }.as_bytes()).await.unwrap();
+                let response = Response::from_reader(&mut socket).await.unwrap();
+                assert_eq!(response, Response::SuccessfulLog { request_id, log, g, h, p: 5011, ratio: 0.0, iterations: 0, performance: Performance::Fast, batch_index: None });
+            }
+            socket.write_all(&Frame::Stats { request_id: 5 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::Stats { request_id: 5, logs: 4, iterations: 0, .. }));
+        });
+    }
+
+    #[test]
+    fn invalid_modulus_is_reported_without_disconnecting() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            socket.write_all(&Frame::Bsgs { request_id: 1, g: 0, h: 0, p: 1 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            let Response::Error { request_id: 1, code: error_code::INVALID_MODULUS, message, batch_index: None } = response else {
+                panic!("expected `Error`, got {response:?}");
+            };
+            assert!(message.contains("modulus 1"));
+
+            // The connection is still served
+            socket.write_all(&Frame::Bsgs { request_id: 2, g: 2, h: 2495, p: 5011 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::SuccessfulLog { request_id: 2, .. }));
+        });
+    }
+
+    #[test]
+    fn bsgs_rejects_a_modulus_past_its_table_limit() {
+        let config = ServerConfig { buf_size: 16, max_input: u64::MAX, ..Default::default() };
+        let (rt, mut socket) = connect_to_test_server(config);
+
+        rt.block_on(async {
+            // Even with no configured bound on the input, the baby-step table is never sized past BSGS_LIMIT
+            let p = 18446744073709551557;
+            socket.write_all(&Frame::Bsgs { request_id: 1, g: 2, h: 4, p }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::InputTooLarge { request_id: 1, value, batch_index: None } if value == p));
+            socket.write_all(&Frame::Log { request_id: 2, g: 2, h: 4, p, method: LogMethod::Bsgs }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::InputTooLarge { request_id: 2, value, batch_index: None } if value == p));
+
+            socket.write_all(&Frame::Bsgs { request_id: 3, g: 2, h: 2495, p: 5011 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::SuccessfulLog { request_id: 3, .. }));
+        });
+    }
+
+    #[test]
+    fn primitive_root_requests_are_answered() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            socket.write_all(&Frame::PrimitiveRoot { request_id: 1, p: 2147483647 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::PrimitiveRoot { request_id: 1, p: 2147483647, g: 7 });
+
+            // Only primes have primitive roots
+            socket.write_all(&Frame::PrimitiveRoot { request_id: 2, p: 15 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::NotPrime { request_id: 2, p: 15, witness: 2 });
+        });
+    }
+
+    #[test]
+    fn mr_rounds_set_reported_probability() {
+        for mr_rounds in [1, 4, 20] {
+            let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, mr_rounds, ..Default::default() });
+
+            rt.block_on(async {
+                socket.write_all(&Frame::Prime { request_id: 1, p: 7919, seed: None }.as_bytes()).await.unwrap();
+                let (rounds, response) = prime_check(&mut socket).await;
+                assert_eq!(rounds.len(), mr_rounds as usize);
+                let prob = (1.0 - f64::powi(0.25, mr_rounds as i32)) as f32;
+                assert_eq!(response, Response::Prime { request_id: 1, p: 7919, prob, rounds: mr_rounds });
+            });
+        }
+        assert_eq!(PrimalityTest::MillerRabin.probability(1), 0.75);
+        assert_eq!(PrimalityTest::MillerRabin.probability(4), 0.99609375);
+        assert_eq!(PrimalityTest::SolovayStrassen.probability(1), 0.5);
+        assert_eq!(PrimalityTest::SolovayStrassen.probability(4), 0.9375);
+    }
+
+    #[test]
+    fn small_and_even_numbers_answer_prime_checks() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            let expected = [
+                Response::NotPrime { request_id: 0, p: 0, witness: 0 },
+                Response::NotPrime { request_id: 1, p: 1, witness: 0 },
+                Response::Prime { request_id: 2, p: 2, prob: 1.0, rounds: 0 },
+                Response::Prime { request_id: 3, p: 3, prob: 1.0, rounds: 0 },
+                Response::NotPrime { request_id: 4, p: 4, witness: 2 },
+                Response::NotPrime { request_id: 1000, p: 1000, witness: 2 },
+            ];
+            for response in expected {
+                let p = match response {
+                    Response::Prime { p, .. } | Response::NotPrime { p, .. } => p,
+                    _ => unreachable!(),
+                };
+                socket.write_all(&Frame::Prime { request_id: p as u32, p, seed: None }.as_bytes()).await.unwrap();
+                // None of these run any rounds, the verdict is the only response
+                assert_eq!(prime_check(&mut socket).await, (vec![], response));
+            }
+        });
+    }
+
+    #[test]
+    fn seeded_prime_checks_repeat_their_bases() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            let mut bases = vec![];
+            for seed in [7, 7, 8] {
+                socket.write_all(&Frame::Prime { request_id: 1, p: 7919, seed: Some(seed) }.as_bytes()).await.unwrap();
+                let (rounds, verdict) = prime_check(&mut socket).await;
+                assert!(verdict.is_prime());
+                let seen: Vec<u64> = rounds.iter()
+                    .map(|r| match r {
+                        Response::PrimeRound { base, .. } => *base,
+                        r => panic!("unexpected response {r:?}"),
+                    })
+                    .collect();
+                bases.push(seen);
+            }
+            assert_eq!(bases[0].len(), DEFAULT_MR_ROUNDS as usize);
+            assert_eq!(bases[0], bases[1]);
+            assert_ne!(bases[0], bases[2]);
+
+            // The same seed finds the same witness for a composite
+            let mut witnesses = vec![];
+            for _ in 0..2 {
+                socket.write_all(&Frame::Prime { request_id: 2, p: 561, seed: Some(7) }.as_bytes()).await.unwrap();
+                match prime_check(&mut socket).await.1 {
+                    Response::NotPrime { witness, .. } => witnesses.push(witness),
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+            assert_eq!(witnesses[0], witnesses[1]);
+        });
+    }
+
+    #[test]
+    fn composite_reports_miller_rabin_witness() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // 561 is a Carmichael number, so the witness must be a strong witness rather than a Fermat witness
+            socket.write_all(&Frame::Prime { request_id: 1, p: 561, seed: None }.as_bytes()).await.unwrap();
+            let (rounds, verdict) = prime_check(&mut socket).await;
+            match verdict {
+                Response::NotPrime { request_id: 1, p: 561, witness } => {
+                    assert!((2..561).contains(&witness));
+                    assert!(miller_rabin(561, witness));
+                    // The witness is the base of the last streamed round, the only one that failed
+                    let (last, passed) = rounds.split_last().unwrap();
+                    assert_eq!(last, &Response::PrimeRound { request_id: 1, round: rounds.len() as u32, base: witness, passed: false });
+                    assert!(passed.iter().all(|r| matches!(r, Response::PrimeRound { passed: true, .. })));
+                }
+                response => panic!("unexpected response {response:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn solovay_strassen_answers_prime_checks() {
+        let config = ServerConfig { buf_size: 16, mr_rounds: 4, primality_test: PrimalityTest::SolovayStrassen, ..Default::default() };
+        let (rt, mut socket) = connect_to_test_server(config);
+
+        rt.block_on(async {
+            socket.write_all(&Frame::Prime { request_id: 1, p: 7919, seed: None }.as_bytes()).await.unwrap();
+            let response = prime_check(&mut socket).await.1;
+            assert_eq!(response, Response::Prime { request_id: 1, p: 7919, prob: 0.9375, rounds: 4 });
+
+            socket.write_all(&Frame::Prime { request_id: 2, p: 561, seed: None }.as_bytes()).await.unwrap();
+            match prime_check(&mut socket).await.1 {
+                Response::NotPrime { request_id: 2, p: 561, witness } => assert!(solovay_strassen(561, witness)),
+                response => panic!("unexpected response {response:?}"),
+            }
+        });
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "rsa"), ignore = "factors RSA keys")]
+    fn oversized_inputs_are_rejected() {
+        // Allow a larger maximum than the factoring walks support, so the algorithm check is exercised too
+        let config = ServerConfig { buf_size: 16, max_input: u64::MAX - 1, ..Default::default() };
+        let (rt, mut socket) = connect_to_test_server(config);
+
+        rt.block_on(async {
+            // 2^63 + 9 has no factor below the trial division limit, so it reaches Pollard's rho
+            let n = (1 << 63) + 9;
+            socket.write_all(&Frame::RSA { request_id: 0, n, e: 65537 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::InputTooLarge { request_id: 0, value: n, batch_index: None });
+
+            socket.write_all(&Frame::Log { request_id: 0, g: 2, h: 5, p: u64::MAX, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::InputTooLarge { request_id: 0, value: u64::MAX, batch_index: None });
+        });
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "rsa"), ignore = "factors RSA keys")]
+    fn rsa_recovers_private_exponent() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // The textbook key n = 61 * 53, e = 17 has private exponent d = 2753
+            socket.write_all(&Frame::RSA { request_id: 1, n: 3233, e: 17 }.as_bytes()).await.unwrap();
+            let response = loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::RSAItem { .. } => continue,
+                    r => break r,
+                }
+            };
+            assert!(matches!(response, Response::SuccessfulRSA { request_id: 1, p: 53, q: 61, .. }));
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::RSAKey { request_id: 1, p: 53, q: 61, d: 2753 });
+
+            // phi(3233) = 52 * 60, so e = 3 shares a factor with q - 1 and e = 13 one with p - 1, while e = 1
+            // is its own inverse but leaves messages unencrypted
+            for (request_id, e) in [(2, 3), (3, 13), (4, 1)] {
+                socket.write_all(&Frame::RSA { request_id, n: 3233, e }.as_bytes()).await.unwrap();
+                let response = loop {
+                    match Response::from_reader(&mut socket).await.unwrap() {
+                        Response::RSAItem { .. } => continue,
+                        r => break r,
+                    }
+                };
+                assert!(response.is_successful_rsa());
+                let response = Response::from_reader(&mut socket).await.unwrap();
+                assert_eq!(response, Response::InvalidExponent { request_id, n: 3233, e });
+            }
+        });
+    }
+
+    #[test]
+    fn clients_over_the_limit_are_rejected() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let config = ServerConfig { buf_size: 16, max_clients: 2, ..Default::default() };
+            task::spawn(serve(TcpListenerStream::new(listener), config));
+
+            let mut clients = Vec::new();
+            for _ in 0..2 {
+                let mut socket = TcpStream::connect(addr).await.unwrap();
+                let response = Response::from_reader(&mut socket).await.unwrap();
+                assert!(response.is_connection_ok());
+                clients.push(socket);
+            }
+
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::ServerBusy { request_id: 0 });
+        });
+    }
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, start);
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+
+        // Half a second refills a single token at two requests per second
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+
+        // The bucket never holds more than its capacity
+        let much_later = later + Duration::from_secs(60);
+        assert!(bucket.try_take(much_later));
+        assert!(bucket.try_take(much_later));
+        assert!(!bucket.try_take(much_later));
+    }
+
+    #[test]
+    fn burst_of_requests_is_throttled() {
+        let config = ServerConfig { buf_size: 16, rate_limit: 2, ..Default::default() };
+        let (rt, mut socket) = connect_to_test_server(config);
+
+        rt.block_on(async {
+            let start = std::time::Instant::now();
+            for _ in 0..10 {
+                socket.write_all(&Frame::Prime { request_id: 0, p: 7919, seed: None }.as_bytes()).await.unwrap();
+            }
+
+            let (mut answered, mut throttled) = (0, 0);
+            for _ in 0..10 {
+                match prime_check(&mut socket).await.1 {
+                    Response::Prime { .. } => answered += 1,
+                    Response::RateLimited { request_id: 0, batch_index: None } => throttled += 1,
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+            // Two requests fit in the initial burst, plus at most the tokens refilled while the test ran
+            let refilled = (start.elapsed().as_secs_f64() * 2.0).ceil() as usize;
+            assert!(answered >= 2);
+            assert!(answered <= 2 + refilled);
+            assert_eq!(answered + throttled, 10);
+            assert!(throttled > 0);
+        });
+    }
+
+    #[test]
+    fn stats_count_served_requests() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // Every response echoes the id of the request it answers
+            socket.write_all(&Frame::Prime { request_id: 1, p: 7919, seed: None }.as_bytes()).await.unwrap();
+            assert_eq!(prime_check(&mut socket).await.1.request_id(), 1);
+
+            // Read the whole stream so every step has been counted before asking for stats
+            socket.write_all(&Frame::Log { request_id: 2, g: 7, h: 63, p: 71, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let mut steps = 0;
+            loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { request_id: 2, .. } => steps += 1,
+                    Response::Collision { request_id: 2, .. } => {}
+                    _ => break,
+                }
+            }
+
+            socket.write_all(&Frame::Stats { request_id: 3 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::Stats { request_id: 3, clients: 1, logs: 1, rsas: 0, primes: 1, iterations: steps, timings: None });
+            assert!(steps > 0);
+        });
+    }
+
+    #[test]
+    fn capabilities_report_supported_operations() {
+        // The default build supports RSA factoring, unless the crate is built without the `rsa` feature
+        let flags = SUPPORTED_CAPABILITIES;
+        assert_eq!(flags & capabilities::RSA != 0, cfg!(feature = "rsa"));
+        assert_eq!(flags | capabilities::RSA, capabilities::ALL);
+        for flag in [capabilities::PING, capabilities::POHLIG_HELLMAN, capabilities::AUTO_LOG, capabilities::LIST_PEERS, capabilities::KICK] {
+            assert_ne!(flags & flag, 0, "{flag:#x} is not supported");
+        }
+
+        // A server without RSA support clears its bit and refuses to factor keys
+        let without_rsa = capabilities::ALL & !capabilities::RSA;
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, capabilities: without_rsa, ..Default::default() });
+
+        rt.block_on(async {
+            socket.write_all(&Frame::Capabilities { request_id: 1 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::Capabilities { request_id: 1, flags: without_rsa });
+
+            socket.write_all(&Frame::RSA { request_id: 2, n: 1782886219, e: 65537 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert_eq!(response, Response::UnsuccessfulRSA { request_id: 2, n: 1782886219 });
+        });
+    }
+
+    #[test]
+    fn batch_results_carry_their_index() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            // 7 generates the whole group mod 2^31 - 1, while 70 = -1 mod 71 only generates {1, 70}, so only the
+            // middle query has no solution
+            let items = vec![(7, 5, 2147483647), (70, 5, 71), (7, 11, 2147483647)];
+            socket.write_all(&Frame::Batch { request_id: 9, items: items.clone() }.as_bytes()).await.unwrap();
+
+            for (i, (g, h, p)) in items.into_iter().enumerate() {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::SuccessfulLog { request_id: 9, log, batch_index, .. } => {
+                        assert_eq!(batch_index, Some(i as u32));
+                        assert_ne!(i, 1);
+                        assert_eq!(mod_pow(g, log, p), h);
+                    }
+                    Response::LogDoesNotExist { request_id: 9, order, batch_index, .. } => {
+                        assert_eq!(batch_index, Some(i as u32));
+                        assert_eq!((i, order), (1, 2));
+                    }
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn batches_are_rate_limited_as_a_whole() {
+        let config = ServerConfig { buf_size: 16, rate_limit: 1, max_input: 1 << 40, ..Default::default() };
+        let (rt, mut socket) = connect_to_test_server(config);
+
+        rt.block_on(async {
+            // A batch larger than the rate limit is charged a single request, so every query is answered, the
+            // invalid ones by responses carrying their index too
+            let items = vec![(7, 5, 71), (0, 5, 71), (7, 5, 1 << 41), (7, 71, 71), (7, 11, 71), (7, 20, 71), (7, 33, 71), (7, 50, 71)];
+            socket.write_all(&Frame::Batch { request_id: 1, items: items.clone() }.as_bytes()).await.unwrap();
+            // While the next batch is dropped as a whole, each of its queries told so by its index
+            socket.write_all(&Frame::Batch { request_id: 2, items: items.clone() }.as_bytes()).await.unwrap();
+
+            let (mut answered, mut limited) = (vec![], vec![]);
+            for _ in 0..2 * items.len() {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::InvalidParameters { request_id: 1, reason, batch_index: Some(i) } => {
+                        let expected = if i == 1 { InvalidReason::BaseOutOfRange } else { InvalidReason::TargetOutOfRange };
+                        assert!(i == 1 || i == 3);
+                        assert_eq!(reason, expected);
+                        answered.push(i);
+                    }
+                    Response::InputTooLarge { request_id: 1, value, batch_index: Some(i) } => {
+                        assert_eq!((i, value), (2, 1 << 41));
+                        answered.push(i);
+                    }
+                    Response::SuccessfulLog { request_id: 1, batch_index: Some(i), .. }
+                    | Response::UnsuccessfulLog { request_id: 1, batch_index: Some(i), .. }
+                    | Response::LogDoesNotExist { request_id: 1, batch_index: Some(i), .. } => answered.push(i),
+                    Response::RateLimited { request_id: 2, batch_index: Some(i) } => limited.push(i),
+                    r => panic!("unexpected response {r:?}"),
+                }
+            }
+            answered.sort();
+            let indices: Vec<u32> = (0..items.len() as u32).collect();
+            assert_eq!(answered, indices);
+            assert_eq!(limited, indices);
+        });
+    }
+
+    #[test]
+    fn unsolved_log_tells_missing_logs_from_failures() {
+        // 25 = 5^2 is in the subgroup of order 5 generated by 5 modulo 71, so a failure to find its log is the
+        // method giving up
+        assert_eq!(unsolved_log(1, 5, 25, 71, None), Response::UnsuccessfulLog { request_id: 1, g: 5, h: 25, p: 71, batch_index: None });
+        // 2 is not, so its log does not exist
+        assert_eq!(unsolved_log(1, 5, 2, 71, Some(3)), Response::LogDoesNotExist { request_id: 1, g: 5, h: 2, p: 71, order: 5, batch_index: Some(3) });
+        assert_eq!(order_excluding(70, 5, 71), Some(2));
+        assert_eq!(order_excluding(70, 70, 71), None);
+    }
+
+    #[test]
+    fn benchmark_times_computations() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, benchmark: true, ..Default::default() });
+
+        rt.block_on(async {
+            // Nothing has been timed yet
+            socket.write_all(&Frame::Stats { request_id: 1 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::Stats { timings: Some(timings), .. } if timings == Timings::default()));
+
+            socket.write_all(&Frame::Prime { request_id: 2, p: 7919, seed: None }.as_bytes()).await.unwrap();
+            prime_check(&mut socket).await;
+            for (request_id, h) in [(3, 63), (4, 62)] {
+                socket.write_all(&Frame::Log { request_id, g: 7, h, p: 71, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+                while matches!(Response::from_reader(&mut socket).await.unwrap(), Response::LogItem { .. } | Response::Collision { .. }) {}
+            }
+
+            socket.write_all(&Frame::Stats { request_id: 5 }.as_bytes()).await.unwrap();
+            let Response::Stats { timings: Some(Timings { logs, rsas, primes }), .. } = Response::from_reader(&mut socket).await.unwrap() else {
+                panic!("expected a `Stats` response with timings");
+            };
+            assert!(logs.min > 0 && logs.min <= logs.median && logs.median <= logs.max);
+            assert!(primes.min > 0 && primes.min == primes.max);
+            assert_eq!(rsas, Timing::default());
+        });
+    }
+
+    #[test]
+    fn histogram_summarizes_durations() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.summary(), Timing::default());
+
+        // The median of 3, 100 and 5000 falls in the bucket from 64 to 127
+        for nanos in [5000, 3, 100] {
+            histogram.record(Duration::from_nanos(nanos));
+        }
+        assert_eq!(histogram.summary(), Timing { min: 3, median: 127, max: 5000 });
+
+        // The median never lies outside of the durations recorded
+        let mut histogram = Histogram::default();
+        histogram.record(Duration::from_nanos(100));
+        assert_eq!(histogram.summary(), Timing { min: 100, median: 100, max: 100 });
+        histogram.record(Duration::MAX);
+        assert_eq!(histogram.summary(), Timing { min: 100, median: 127, max: u64::MAX });
+    }
+
+    #[test]
+    fn lru_cache_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(cache.get(&1), Some("a"));
+        // 2 is now the least recently used entry
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&3), Some("c"));
+
+        let mut disabled = LruCache::new(0);
+        disabled.insert(1, "a");
+        assert_eq!(disabled.get(&1), None);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "rsa"), ignore = "factors RSA keys")]
+    fn repeated_requests_are_served_from_cache() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, ..Default::default() });
+
+        rt.block_on(async {
+            let iterations = |response: Response| match response {
+                Response::Stats { iterations, .. } => iterations,
+                r => panic!("unexpected response {r:?}"),
+            };
+
+            socket.write_all(&Frame::Log { request_id: 1, g: 7, h: 5, p: 2147483647, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            let first = loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::LogItem { .. } | Response::Collision { .. } => continue,
+                    r => break r,
+                }
+            };
+            assert!(matches!(first, Response::SuccessfulLog { request_id: 1, .. }));
+            socket.write_all(&Frame::Stats { request_id: 2 }.as_bytes()).await.unwrap();
+            let computed = iterations(Response::from_reader(&mut socket).await.unwrap());
+            assert!(computed > 0);
+
+            // The second request skips the stream of steps entirely
+            socket.write_all(&Frame::Log { request_id: 3, g: 7, h: 5, p: 2147483647, method: LogMethod::PollardsRho }.as_bytes()).await.unwrap();
+            match (first, Response::from_reader(&mut socket).await.unwrap()) {
+                (
+                    Response::SuccessfulLog { log, ratio, iterations, .. },
+                    Response::SuccessfulLog { request_id, log: cached, ratio: cached_ratio, iterations: cached_iterations, .. },
+                ) => {
+                    assert_eq!(request_id, 3);
+                    assert_eq!(cached, log);
+                    assert_eq!(cached_ratio, ratio);
+                    assert!(iterations > 0);
+                    assert_eq!(cached_iterations, iterations);
+                }
+                r => panic!("unexpected responses {r:?}"),
+            }
+            socket.write_all(&Frame::Stats { request_id: 4 }.as_bytes()).await.unwrap();
+            assert_eq!(iterations(Response::from_reader(&mut socket).await.unwrap()), computed);
+
+            // The same holds for factoring a public key too large for trial division
+            let n = 10007 * 10009;
+            socket.write_all(&Frame::RSA { request_id: 5, n, e: 65537 }.as_bytes()).await.unwrap();
+            loop {
+                match Response::from_reader(&mut socket).await.unwrap() {
+                    Response::RSAItem { .. } => continue,
+                    r => break assert!(matches!(r, Response::SuccessfulRSA { request_id: 5, .. })),
+                }
+            }
+            assert!(matches!(Response::from_reader(&mut socket).await.unwrap(), Response::RSAKey { request_id: 5, .. }));
+            socket.write_all(&Frame::Stats { request_id: 6 }.as_bytes()).await.unwrap();
+            let computed = iterations(Response::from_reader(&mut socket).await.unwrap());
+
+            socket.write_all(&Frame::RSA { request_id: 7, n, e: 65537 }.as_bytes()).await.unwrap();
+            let response = Response::from_reader(&mut socket).await.unwrap();
+            assert!(matches!(response, Response::SuccessfulRSA { request_id: 7, p: 10007 | 10009, .. }));
+            assert!(matches!(Response::from_reader(&mut socket).await.unwrap(), Response::RSAKey { request_id: 7, d: 35910881, .. }));
+            socket.write_all(&Frame::Stats { request_id: 8 }.as_bytes()).await.unwrap();
+            assert_eq!(iterations(Response::from_reader(&mut socket).await.unwrap()), computed);
+        });
+    }
+
+    /// Collects everything written by the subscriber under test
+    #[derive(Clone, Default)]
+    struct TestWriter(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for TestWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn debug_filter_emits_server_spans() {
+        let writer = TestWriter::default();
+        let make_writer = writer.clone();
+        let subscriber = subscriber(EnvFilter::new("debug"), move || make_writer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            // A current thread runtime keeps every task on this thread, under the scoped subscriber
+            let rt = Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+
+            rt.block_on(async {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                let server = task::spawn(serve(TcpListenerStream::new(listener).take(1), ServerConfig { buf_size: 16, ..Default::default() }));
+
+                let mut socket = TcpStream::connect(addr).await.unwrap();
+                Response::from_reader(&mut socket).await.unwrap();
+                socket.write_all(&Frame::Quit { request_id: 0 }.as_bytes()).await.unwrap();
+                drop(socket);
+
+                server.await.unwrap().unwrap();
+            });
+        });
+
+        let logs = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("DEBUG"));
+        assert!(logs.contains("serve"));
+        assert!(logs.contains("main_broker"));
+        assert!(logs.contains("client_read_task"));
+    }
+
+    #[test]
+    fn cli_defaults() {
+        let cli = Cli::try_parse_from(["server"]).unwrap();
+        assert_eq!(cli.addr, "0.0.0.0:8080");
+        assert_eq!(cli.buf_size, 1000);
+        assert!(!cli.brent);
+        assert_eq!(cli.timeout, 30);
+        assert_eq!(cli.max_input, 1 << 32);
+        assert_eq!(cli.max_clients, 1000);
+        assert_eq!(cli.rate_limit, 50);
+        assert_eq!(cli.cache_size, 1024);
+        assert_eq!(cli.mr_rounds, 20);
+        assert_eq!(cli.primality_test, PrimalityTest::MillerRabin);
+        assert_eq!(cli.max_stream_items, None);
+
+        let cli = Cli::try_parse_from(["server", "--addr", "127.0.0.1:9000", "--buf-size", "10"]).unwrap();
+        assert_eq!(cli.addr, "127.0.0.1:9000");
+        assert_eq!(cli.buf_size, 10);
+
+        assert_eq!(Cli::try_parse_from(["server", "--addr", "[::1]:9000"]).unwrap().addr, "[::1]:9000");
+        assert_eq!(Cli::try_parse_from(["server", "--addr", "localhost:9000"]).unwrap().addr, "localhost:9000");
+        assert_eq!(Cli::try_parse_from(["server", "--mr-rounds", "5"]).unwrap().mr_rounds, 5);
+        assert!(Cli::try_parse_from(["server", "--mr-rounds", "0"]).is_err());
+        let cli = Cli::try_parse_from(["server", "--primality-test", "solovay-strassen"]).unwrap();
+        assert_eq!(cli.primality_test, PrimalityTest::SolovayStrassen);
+        assert_eq!(Cli::try_parse_from(["server", "--max-stream-items", "100"]).unwrap().max_stream_items, Some(100));
+        assert!(!cli.benchmark);
+        assert!(Cli::try_parse_from(["server", "--benchmark"]).unwrap().benchmark);
+        assert_eq!(cli.threads, None);
+        assert_eq!(Cli::try_parse_from(["server", "--threads", "4"]).unwrap().threads, NonZeroUsize::new(4));
+        assert!(Cli::try_parse_from(["server", "--threads", "0"]).is_err());
+        assert_eq!(cli.rho_workers.get(), 1);
+        assert_eq!(Cli::try_parse_from(["server", "--rho-workers", "4"]).unwrap().rho_workers.get(), 4);
+        assert!(Cli::try_parse_from(["server", "--rho-workers", "0"]).is_err());
+    }
+
+    /// A walk that never finds a factor, counting in `0` the walks like it that have been dropped.
+    struct EndlessWalk(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl Iterator for EndlessWalk {
+        type Item = Option<u64>;
+        fn next(&mut self) -> Option<Option<u64>> {
+            Some(None)
+        }
+    }
+
+    impl Drop for EndlessWalk {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn rho_walks_race_to_a_factor() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        for n in [1782886219, 1991010911, 2465929391] {
+            let (factor, steps) = rt.block_on(race_walks(rho_walks(n, 4).unwrap(), Arc::new(AtomicU64::new(0)))).unwrap();
+            assert!(steps > 0);
+            assert!(factor > 1 && factor < n && n % factor == 0);
+        }
+        assert!(matches!(rho_walks(u64::MAX, 4), Err(AlgoError::Overflow)));
+    }
+
+    #[test]
+    fn factor_parallel_finds_a_factor() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        for n in [1782886219, 1991010911, 2465929391] {
+            let iterations = Arc::new(AtomicU64::new(0));
+            let (factor, steps) = rt.block_on(factor_parallel(n, 4, iterations.clone())).unwrap();
+            assert!(factor > 1 && factor < n && n % factor == 0);
+            assert!(iterations.load(Ordering::Relaxed) >= steps);
+        }
+        assert_eq!(rt.block_on(factor_parallel(u64::MAX, 4, Arc::new(AtomicU64::new(0)))), None);
+    }
+
+    #[test]
+    fn race_walks_cancels_the_losers() {
+        let rt = Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            // The losers only stop once cancelled, so the race would never be decided without its winner
+            let dropped = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let mut walks: Vec<Box<dyn Iterator<Item = Option<u64>> + Send>> = (0..3)
+                .map(|_| Box::new(EndlessWalk(dropped.clone())) as Box<dyn Iterator<Item = Option<u64>> + Send>)
+                .collect();
+            walks.push(Box::new(std::iter::repeat(None).take(99).chain([Some(53)])));
+            let iterations = Arc::new(AtomicU64::new(0));
+            assert_eq!(race_walks(walks, iterations.clone()).await, Some((53, 100)));
+            assert!(iterations.load(Ordering::Relaxed) >= 100);
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while dropped.load(Ordering::SeqCst) < 3 {
+                assert!(Instant::now() < deadline, "losing walks were not cancelled");
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "rsa"), ignore = "factors RSA keys")]
+    fn rho_workers_race_to_factor() {
+        let (rt, mut socket) = connect_to_test_server(ServerConfig { buf_size: 16, rho_workers: 4, ..Default::default() });
+
+        rt.block_on(async {
+            // Only the result is sent, none of the steps of the walks
+            socket.write_all(&Frame::RSA { request_id: 1, n: 1782865951, e: 65537 }.as_bytes()).await.unwrap();
+            match Response::from_reader(&mut socket).await.unwrap() {
+                Response::SuccessfulRSA { request_id: 1, p, q, iterations, .. } => {
+                    assert_eq!(p * q, 1782865951);
+                    assert!(iterations > 0);
+                }
+                r => panic!("unexpected response {r:?}"),
+            }
+            assert!(matches!(Response::from_reader(&mut socket).await.unwrap(), Response::RSAKey { request_id: 1, .. }));
+        });
+    }
+
+    #[test]
+    fn runtime_has_the_requested_worker_threads() {
+        // Each task blocks its worker until all three are running, which only happens with three workers
+        let rt = runtime(3).unwrap();
+        let barrier = Arc::new(std::sync::Barrier::new(3));
+        let (passed_send, passed) = std::sync::mpsc::channel();
+        for _ in 0..3 {
+            let barrier = barrier.clone();
+            let passed_send = passed_send.clone();
+            rt.spawn(async move {
+                barrier.wait();
+                let _ = passed_send.send(());
+            });
+        }
+        // The workers may all be stuck at the barrier, so wait on the tasks from outside the runtime
+        let all_passed = (0..3).all(|_| passed.recv_timeout(Duration::from_secs(5)).is_ok());
+        rt.shutdown_background();
+        assert!(all_passed, "fewer than three tasks ran at once");
+    }
+}
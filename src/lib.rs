@@ -1,7 +1,9 @@
 use std::sync::Arc;
-use tokio::io::AsyncReadExt;
+use std::io;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpStream, TcpSocket};
-use tokio::net::tcp::OwnedWriteHalf;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
@@ -13,112 +15,650 @@ pub mod prelude {
     pub use super::*;
 }
 
+/// The half of a client's connection the server writes responses to, either a plain TCP stream or a TLS session
+/// over one.
+pub trait ClientWriter: AsyncWrite + std::fmt::Debug + Send + Unpin {}
+
+impl<W: AsyncWrite + std::fmt::Debug + Send + Unpin> ClientWriter for W {}
+
+/// The length in bytes of the challenge sent in `ConnectionOk`, and of the HMAC-SHA256 a client answers it with.
+pub const AUTH_LEN: usize = 32;
+
+/// A key shared between the server and its clients, which a client proves it holds by answering the challenge sent
+/// in `ConnectionOk` with the HMAC-SHA256 of the challenge under the key.
+///
+/// The key is never printed, so a configuration holding one can be logged.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AuthKey(Vec<u8>);
+
+impl AuthKey {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        AuthKey(key.into())
+    }
+
+    /// The HMAC-SHA256 of `challenge` under this key, sent by the client in a `Frame::Auth`.
+    pub fn sign(&self, challenge: &[u8; AUTH_LEN]) -> [u8; AUTH_LEN] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(challenge);
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Whether `hmac` is the HMAC-SHA256 of `challenge` under this key, compared in constant time.
+    pub fn verify(&self, challenge: &[u8; AUTH_LEN], hmac: &[u8; AUTH_LEN]) -> bool {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(challenge);
+        mac.verify_slice(hmac).is_ok()
+    }
+}
+
+impl std::fmt::Debug for AuthKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AuthKey(..)")
+    }
+}
+
 /// An event triggered by a connecting client.
+///
+/// Events created from a `Frame` carry its `request_id`, so the broker can echo it back in the `Response`.
 #[derive(Debug)]
 pub enum Event {
     /// A new client connecting to the server
-    NewClient { peer_id: Uuid, socket: OwnedWriteHalf, token: CancellationToken },
+    NewClient { peer_id: Uuid, socket: Box<dyn ClientWriter>, token: CancellationToken },
+
+    /// Variant to represent a client request to solve the discrete logarithm with `method`
+    Log { peer_id: Uuid, request_id: u32, g: u64, h: u64, p: u64, batch_index: Option<u32>, method: LogMethod },
+
+    /// Variant to represent a client request to find the RSA private key from the given public key `(n, e)`
+    RSA { peer_id: Uuid, request_id: u32, n: u64, e: u64 },
+
+    /// Variant to represent a client request to check if a number is prime or not, drawing the bases of the
+    /// test from a generator seeded with `seed` if given
+    Prime { peer_id: Uuid, request_id: u32, p: u64, seed: Option<u64> },
+
+    /// Variant to represent a client request to solve the discrete logarithm using baby-step giant-step
+    Bsgs { peer_id: Uuid, request_id: u32, g: u64, h: u64, p: u64 },
+
+    /// Variant to represent a client request for a snapshot of the server's statistics
+    Stats { peer_id: Uuid, request_id: u32 },
+
+    /// Variant to represent a client request to stop the computations answering `request_id`
+    Cancel { peer_id: Uuid, request_id: u32 },
+
+    /// Variant to represent a client request for a primitive root modulo `p`
+    PrimitiveRoot { peer_id: Uuid, request_id: u32, p: u64 },
+
+    /// Variant to represent a client request for the operations the server supports
+    Capabilities { peer_id: Uuid, request_id: u32 },
+
+    /// Variant to represent a client's liveness probe, answered straight away with the same `nonce`
+    Ping { peer_id: Uuid, request_id: u32, nonce: u64 },
+
+    /// Variant to represent a reconnecting client asking to take over the session it was issued `session` for
+    Resume { peer_id: Uuid, request_id: u32, session: u128 },
 
-    /// Variant to represent a client request to solve the discrete logarithm
-    Log { peer_id: Uuid, g: u64, h: u64, p: u64, },
+    /// Variant to represent a client answering the challenge it was sent in `ConnectionOk` with `hmac`
+    Auth { peer_id: Uuid, request_id: u32, hmac: [u8; AUTH_LEN] },
 
-    /// Variant to represent a client request to find the RSA private key from the given public key
-    RSA { peer_id: Uuid, n: u64},
+    /// Variant to represent an admin's request for the ids of the connected clients
+    ListPeers { peer_id: Uuid, request_id: u32 },
 
-    /// Variant to represent a client request to check if a number is prime or not
-    Prime { peer_id: Uuid, p: u64 },
+    /// Variant to represent an admin's request to disconnect the client `target`
+    Kick { peer_id: Uuid, request_id: u32, target: Uuid },
 
     /// Variant to represent a client disconnecting from the server, mainly for logging
     Quit { peer_id: Uuid }
 }
 
+impl Event {
+    /// Returns the id of the client that triggered the event.
+    pub fn peer_id(&self) -> Uuid {
+        match self {
+            Event::NewClient { peer_id, .. }
+            | Event::Log { peer_id, .. }
+            | Event::RSA { peer_id, .. }
+            | Event::Prime { peer_id, .. }
+            | Event::Bsgs { peer_id, .. }
+            | Event::Stats { peer_id, .. }
+            | Event::Cancel { peer_id, .. }
+            | Event::PrimitiveRoot { peer_id, .. }
+            | Event::Capabilities { peer_id, .. }
+            | Event::Ping { peer_id, .. }
+            | Event::Resume { peer_id, .. }
+            | Event::Auth { peer_id, .. }
+            | Event::ListPeers { peer_id, .. }
+            | Event::Kick { peer_id, .. }
+            | Event::Quit { peer_id } => *peer_id,
+        }
+    }
+}
+
+/// A reply queued by the main broker for a client's write task.
+///
+/// `Response` only contains variants that can be written over the wire. Computations whose steps are
+/// streamed to the client run on a blocking thread and send each step back over a channel, the write task
+/// relays them to the client in order.
+#[derive(Debug)]
+pub enum Reply {
+    /// A single response that is written to the client as is
+    Response(Response),
+
+    /// The responses produced by a computation, written to the client until the channel is closed or
+    /// `timeout` elapses, in which case the computation is abandoned and a `Timeout` for `request_id` is sent.
+    /// Likewise the computation is abandoned and a `Cancelled` sent once `cancel` is cancelled. Either carries
+    /// `batch_index`, the position of the query the computation answers within a `Frame::Batch`, if any.
+    Stream { request_id: u32, batch_index: Option<u32>, responses: tokio::sync::mpsc::Receiver<Response>, timeout: std::time::Duration, cancel: CancellationToken },
+
+    /// A last response, after which the write task closes the client's connection
+    Close(Response),
+}
+
+impl From<Response> for Reply {
+    fn from(response: Response) -> Self {
+        Reply::Response(response)
+    }
+}
+
+/// An error produced when bytes received over the wire do not form a valid message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The type byte of a `Frame` tag does not correspond to any variant
+    UnknownFrameTag(u8),
+
+    /// The type byte of a `Response` tag does not correspond to any variant
+    UnknownResponseTag(u8),
+
+    /// A `Frame::Batch` contains more than `MAX_BATCH_ITEMS` queries
+    BatchTooLarge(u32),
+
+    /// A `Response::PeerList` contains more than `MAX_PEER_LIST_IDS` ids
+    PeerListTooLarge(u32),
+
+    /// The performance byte of a successful result does not correspond to any `Performance`
+    UnknownPerformance(u8),
+
+    /// The method byte of a `Frame::Log` does not correspond to any `LogMethod`
+    UnknownLogMethod(u8),
+
+    /// The reason byte of a `Response::InvalidParameters` does not correspond to any `InvalidReason`
+    UnknownInvalidReason(u8),
+
+    /// The message of a `Response::Error` is longer than `MAX_ERROR_MESSAGE_LEN` or is not valid UTF-8
+    MalformedErrorMessage,
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::UnknownFrameTag(b) => write!(f, "unknown type byte {b} when deserializing `Frame`"),
+            ProtocolError::UnknownResponseTag(b) => write!(f, "unknown type byte {b} when deserializing `Response`"),
+            ProtocolError::BatchTooLarge(n) => write!(f, "batch of {n} items exceeds the maximum of {MAX_BATCH_ITEMS}"),
+            ProtocolError::PeerListTooLarge(n) => write!(f, "peer list of {n} ids exceeds the maximum of {MAX_PEER_LIST_IDS}"),
+            ProtocolError::UnknownPerformance(b) => write!(f, "unknown performance byte {b} when deserializing `Response`"),
+            ProtocolError::UnknownLogMethod(b) => write!(f, "unknown log method byte {b} when deserializing `Frame`"),
+            ProtocolError::UnknownInvalidReason(b) => write!(f, "unknown invalid parameters reason byte {b} when deserializing `Response`"),
+            ProtocolError::MalformedErrorMessage => write!(f, "overlong or invalid UTF-8 error message when deserializing `Response`"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<ProtocolError> for std::io::Error {
+    fn from(e: ProtocolError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// The algorithm a `Frame::Log` asks the server to solve its discrete logarithm with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LogMethod {
+    /// Pollard's rho, streaming each step of the walk
+    #[default]
+    PollardsRho,
+
+    /// Baby-step giant-step, answering with the result only
+    Bsgs,
+
+    /// Pohlig-Hellman, answering with the result only, fast when `p - 1` has only small prime factors
+    PohligHellman,
+
+    /// Whichever of the other methods the server expects to be fastest for the modulus
+    Auto,
+}
+
+impl LogMethod {
+    /// The byte used to transmit the method, Pollard's rho is 0 so a frame without the byte asks for it.
+    pub fn as_byte(self) -> u8 {
+        match self {
+            LogMethod::PollardsRho => 0,
+            LogMethod::Bsgs => 1,
+            LogMethod::PohligHellman => 2,
+            LogMethod::Auto => 3,
+        }
+    }
+
+    /// The method transmitted as `b`, or `None` if `b` is not a valid method.
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(LogMethod::PollardsRho),
+            1 => Some(LogMethod::Bsgs),
+            2 => Some(LogMethod::PohligHellman),
+            3 => Some(LogMethod::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// The shortest, median and longest wall-clock time spent on one type of computation, in nanoseconds.
+///
+/// The median is estimated to within a factor of two, see the server's `--benchmark` flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+impl std::fmt::Display for Timing {
+    /// Writes the timing as `min/median/max` in milliseconds, e.g. `0.012/0.250/3.100 ms`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |nanos: u64| nanos as f64 / 1e6;
+        write!(f, "{:.3}/{:.3}/{:.3} ms", ms(self.min), ms(self.median), ms(self.max))
+    }
+}
+
+/// The timings of the discrete log, RSA and prime computations run by a server, sent in `Response::Stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub logs: Timing,
+    pub rsas: Timing,
+    pub primes: Timing,
+}
+
+/// Why the parameters of a `Frame::Log` were refused, sent in `Response::InvalidParameters`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidReason {
+    /// The base `g` is not in the range `[1, p - 1]`
+    BaseOutOfRange,
+
+    /// The target `h` is not in the range `[1, p - 1]`
+    TargetOutOfRange,
+}
+
+impl InvalidReason {
+    /// The byte used to transmit the reason.
+    pub fn as_byte(self) -> u8 {
+        match self {
+            InvalidReason::BaseOutOfRange => 0,
+            InvalidReason::TargetOutOfRange => 1,
+        }
+    }
+
+    /// The reason transmitted as `b`, or `None` if `b` is not a valid reason.
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(InvalidReason::BaseOutOfRange),
+            1 => Some(InvalidReason::TargetOutOfRange),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for InvalidReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidReason::BaseOutOfRange => write!(f, "g must be between 1 and p - 1"),
+            InvalidReason::TargetOutOfRange => write!(f, "h must be between 1 and p - 1"),
+        }
+    }
+}
+
+/// The bits of the `flags` sent in `Response::Capabilities`, one per operation a server may support.
+///
+/// Bits not listed here are reserved and always cleared, so new operations can be added without breaking
+/// older clients.
+pub mod capabilities {
+    /// Solving discrete logarithms with Pollard's rho, `Frame::Log` and `Frame::Batch`
+    pub const LOG: u32 = 1 << 0;
+
+    /// Factoring RSA public keys, `Frame::RSA`
+    pub const RSA: u32 = 1 << 1;
+
+    /// Primality checks, `Frame::Prime`
+    pub const PRIME: u32 = 1 << 2;
+
+    /// Solving discrete logarithms with baby-step giant-step, `Frame::Bsgs`
+    pub const BSGS: u32 = 1 << 3;
+
+    /// Server statistics, `Frame::Stats`
+    pub const STATS: u32 = 1 << 4;
+
+    /// Cancelling running computations, `Frame::Cancel`
+    pub const CANCEL: u32 = 1 << 5;
+
+    /// Finding primitive roots, `Frame::PrimitiveRoot`
+    pub const PRIMITIVE_ROOT: u32 = 1 << 6;
+
+    /// Liveness probes, `Frame::Ping`
+    pub const PING: u32 = 1 << 7;
+
+    /// Solving discrete logarithms with Pohlig-Hellman, `LogMethod::PohligHellman`
+    pub const POHLIG_HELLMAN: u32 = 1 << 8;
+
+    /// Leaving the method a discrete logarithm is solved with to the server, `LogMethod::Auto`
+    pub const AUTO_LOG: u32 = 1 << 9;
+
+    /// Listing the connected clients, `Frame::ListPeers`, served to admins only
+    pub const LIST_PEERS: u32 = 1 << 10;
+
+    /// Disconnecting clients, `Frame::Kick`, served to admins only
+    pub const KICK: u32 = 1 << 11;
+
+    /// Every operation defined by this version of the protocol
+    pub const ALL: u32 = LOG | RSA | PRIME | BSGS | STATS | CANCEL | PRIMITIVE_ROOT | PING | POHLIG_HELLMAN | AUTO_LOG | LIST_PEERS | KICK;
+}
+
+/// The codes of a `Response::Error`, telling the client what kind of failure its message describes.
+pub mod error_code {
+    /// The modulus of the request cannot be computed with, such as a modulus below 2
+    pub const INVALID_MODULUS: u16 = 1;
+
+    /// The server failed while computing the result, the request may succeed if sent again
+    pub const INTERNAL: u16 = 2;
+
+    /// The request is reserved for admins, clients that authenticated with the server's shared key
+    pub const FORBIDDEN: u16 = 3;
+
+    /// The client the request refers to is not connected to the server
+    pub const UNKNOWN_PEER: u16 = 4;
+}
+
+/// The longest message a `Response::Error` carries in bytes, longer messages are truncated when serialized.
+pub const MAX_ERROR_MESSAGE_LEN: usize = 1024;
+
+/// The largest number of ids a single `Response::PeerList` may contain, keeping it within one framed payload.
+pub const MAX_PEER_LIST_IDS: u32 = 1024;
+
 /// A response generated by the server, to be sent back to the client.
+///
+/// Every variant carries the `request_id` of the `Frame` it answers. Responses that are not sent in reply to a
+/// frame, such as `ConnectionOk` and `ServerBusy`, use a `request_id` of 0.
 #[derive(Debug, PartialEq)]
 pub enum Response {
-    /// Represents a successfully established connection
-    ConnectionOk,
+    /// Represents a successfully established connection, carrying the protocol version spoken by the server
+    ///
+    /// `session` is the token of the client's session, which a client that reconnects may present in a
+    /// `Frame::Resume` to take the session over. A `ConnectionOk` also answers the `Frame::Resume`, carrying the
+    /// token of the session the client holds afterwards.
+    ///
+    /// `challenge` is random, a server with a shared key serves no request until the client has answered it with
+    /// a `Frame::Auth`. The `ConnectionOk` answering a `Frame::Resume` comes after the handshake, so its challenge
+    /// is all zeros.
+    ConnectionOk { request_id: u32, version: u8, session: u128, challenge: [u8; AUTH_LEN] },
 
     /// In case the client sends a number that is not prime
-    NotPrime { p: u64 },
-
-    /// Informs client that the number is prime with probability `prob`
-    Prime { p: u64, prob: f32 },
+    ///
+    /// `witness` is a base `a` for which the server's primality test proves `p` composite, or 0 if `p < 2`.
+    NotPrime { request_id: u32, p: u64, witness: u64 },
 
-    /// For generating the data using Pollards algorithm
-    Log { pollards: PollardsLog },
+    /// Informs client that the number is prime with probability `prob`, after `rounds` rounds of the primality test
+    Prime { request_id: u32, p: u64, prob: f32, rounds: u32 },
 
     /// The data for one step of Pollards algorithm
-    LogItem { item: PollardsLogItem },
+    LogItem { request_id: u32, item: PollardsLogItem },
 
     /// The result of successfully computing the discrete logarithm
-    SuccessfulLog { log: u64, g: u64, h: u64, p: u64, ratio: f64 },
+    ///
+    /// `iterations` is the number of steps taken, `ratio` that number relative to `sqrt(p)` and `performance` the
+    /// classification of the ratio. `batch_index` is the position of the query within a `Frame::Batch`, or `None`
+    /// for a single query.
+    SuccessfulLog { request_id: u32, log: u64, g: u64, h: u64, p: u64, ratio: f64, iterations: u64, performance: Performance, batch_index: Option<u32> },
 
     /// Informs client that algorithm was unsuccessfully able to determine the discrete log
-    UnsuccessfulLog { g: u64, h: u64, p: u64 },
+    ///
+    /// `batch_index` is the position of the query within a `Frame::Batch`, or `None` for a single query.
+    UnsuccessfulLog { request_id: u32, g: u64, h: u64, p: u64, batch_index: Option<u32> },
 
-    /// For generating the data using pollards algorithm to factor an RSA key
-    RSA { pollards: PollardsRSAFact },
-
-    /// The data generated by completing one step of Pollards algorithm for factoring RSA keys
-    RSAItem { item: PollardsRSAFactItem },
+    /// The data generated by completing one step of Pollards algorithm for factoring RSA keys, including the
+    /// polynomial constant the walk was using
+    RSAItem { request_id: u32, item: PollardsRSAFactItem },
 
     /// Informs the client that the algorithm successfully factored the RSA key
-    SuccessfulRSA { p: u64, q: u64, ratio: f64 },
+    ///
+    /// `iterations` is the number of steps taken, `ratio` that number relative to `sqrt(p * q)` and `performance`
+    /// the classification of the ratio.
+    SuccessfulRSA { request_id: u32, p: u64, q: u64, ratio: f64, iterations: u64, performance: Performance },
 
     /// Informs the client that the algorithm was unsuccessfully able to factor the RSA key
-    UnsuccessfulRSA { n: u64 }
+    UnsuccessfulRSA { request_id: u32, n: u64 },
+
+    /// Informs the client that the computation was abandoned after running for `millis` milliseconds
+    ///
+    /// `batch_index` is the position of the query within a `Frame::Batch`, or `None` for a single request.
+    Timeout { request_id: u32, millis: u64, batch_index: Option<u32> },
+
+    /// Informs the client that `value` exceeds the largest input the server accepts
+    ///
+    /// `batch_index` is the position of the query within a `Frame::Batch`, or `None` for a single request.
+    InputTooLarge { request_id: u32, value: u64, batch_index: Option<u32> },
+
+    /// Informs a connecting client that the server has reached its client limit and closed the connection
+    ServerBusy { request_id: u32 },
+
+    /// Informs the client that its request was dropped because it exceeded its rate limit
+    ///
+    /// A `Frame::Batch` is charged to the rate limit once, so either all of its queries are dropped, each answered
+    /// with its `batch_index`, or none are.
+    RateLimited { request_id: u32, batch_index: Option<u32> },
+
+    /// A snapshot of the number of connected clients, requests served per type and Pollard's rho steps computed,
+    /// along with the time taken by the computations if the server records it
+    Stats { request_id: u32, clients: u64, logs: u64, rsas: u64, primes: u64, iterations: u64, timings: Option<Timings> },
+
+    /// Informs the client that the computation was stopped at its request
+    ///
+    /// `batch_index` is the position of the query within a `Frame::Batch`, or `None` for a single request.
+    Cancelled { request_id: u32, batch_index: Option<u32> },
+
+    /// Informs the client that `g` is a primitive root modulo the prime `p`
+    PrimitiveRoot { request_id: u32, p: u64, g: u64 },
+
+    /// The outcome of one round of a primality test, `passed` is false if `base` proved the number composite
+    PrimeRound { request_id: u32, round: u32, base: u64, passed: bool },
+
+    /// The operations the server supports, one bit per operation as laid out in `capabilities`
+    Capabilities { request_id: u32, flags: u32 },
+
+    /// The private exponent `d` of a factored RSA key `n = p * q`, sent after its `SuccessfulRSA`
+    RSAKey { request_id: u32, p: u64, q: u64, d: u64 },
+
+    /// Informs the client that the factored key `(n, e)` is malformed, sent after its `SuccessfulRSA` in place of
+    /// the `RSAKey`, since `e` is 1 or shares a factor with `phi(n)` and so has no private exponent
+    InvalidExponent { request_id: u32, n: u64, e: u64 },
+
+    /// The answer to a `Frame::Ping`, echoing its `nonce`
+    Pong { request_id: u32, nonce: u64 },
+
+    /// Informs the client that the parameters of its request are out of range, so nothing was computed
+    ///
+    /// `batch_index` is the position of the query within a `Frame::Batch`, or `None` for a single request.
+    InvalidParameters { request_id: u32, reason: InvalidReason, batch_index: Option<u32> },
+
+    /// Informs the client that it did not answer the challenge of its `ConnectionOk` with the server's shared key,
+    /// after which the connection is closed
+    AuthFailed { request_id: u32 },
+
+    /// Marks the step `i` of Pollard's rho for discrete logarithms at which the tortoise and hare met at `value`,
+    /// sent after the last `LogItem` and before the result
+    Collision { request_id: u32, i: u64, value: u64 },
+
+    /// Reports a failure the server recovered from, identified by one of the `error_code`s and described by `message`
+    /// for display to the user. The connection stays open
+    ///
+    /// `batch_index` is the position of the query within a `Frame::Batch`, or `None` for a single request.
+    Error { request_id: u32, code: u16, message: String, batch_index: Option<u32> },
+
+    /// Answers an admin's `Frame::ListPeers` with the ids of the clients connected to the server
+    PeerList { request_id: u32, ids: Vec<Uuid> },
+
+    /// Answers an admin's `Frame::Kick`, the client `peer_id` has been disconnected
+    Kicked { request_id: u32, peer_id: Uuid },
+
+    /// Informs the client that the discrete log has no solution, `h` is not in the subgroup of order `order`
+    /// generated by `g` modulo `p`
+    ///
+    /// `batch_index` is the position of the query within a `Frame::Batch`, or `None` for a single query.
+    LogDoesNotExist { request_id: u32, g: u64, h: u64, p: u64, order: u64, batch_index: Option<u32> },
 }
 
 impl Response {
-    fn serialize_8_bytes(tag: &mut ResponseSerTag, idx: usize, val: u64) {
+    fn serialize_8_bytes(tag: &mut [u8], idx: usize, val: u64) {
         for i in 0..8 {
             tag[i + idx] ^= ((val >> (i * 8)) & 0xff) as u8;
         }
     }
 
-    fn deserialize_8_bytes(tag: &ResponseSerTag, idx: usize, val: &mut u64) {
+    fn deserialize_8_bytes(tag: &[u8], idx: usize, val: &mut u64) {
         for i in 0..8 {
             *val ^= (tag[idx + i] as u64) << (i * 8);
         }
     }
 
-    fn serialize_4_bytes(tag: &mut ResponseSerTag, idx: usize, val: u32) {
+    fn serialize_4_bytes(tag: &mut [u8], idx: usize, val: u32) {
         for i in 0..4 {
             tag[i + idx] ^= ((val >> (i * 8)) & 0xff) as u8;
         }
     }
 
-    fn deserialize_4_bytes(tag: &ResponseSerTag, idx: usize, val: &mut u32) {
+    fn deserialize_4_bytes(tag: &[u8], idx: usize, val: &mut u32) {
         for i in 0..4 {
             *val ^= (tag[i + idx] as u32) << (i * 8);
         }
     }
 
-    pub fn is_log(&self) -> bool {
-        match self {
-            Response::Log { pollards } => true,
-            _ => false
+    /// Writes the optional batch index of a response to a query as a flag byte at index 45 followed by a `u32`.
+    fn serialize_batch_index(tag: &mut [u8], batch_index: Option<u32>) {
+        Response::serialize_batch_index_at(tag, 45, batch_index);
+    }
+
+    fn deserialize_batch_index(tag: &[u8]) -> Option<u32> {
+        Response::deserialize_batch_index_at(tag, 45)
+    }
+
+    /// Writes the optional batch index as a flag byte at index `idx` followed by a `u32`.
+    fn serialize_batch_index_at(tag: &mut [u8], idx: usize, batch_index: Option<u32>) {
+        if let Some(batch_index) = batch_index {
+            tag[idx] ^= 1;
+            Response::serialize_4_bytes(tag, idx + 1, batch_index);
         }
     }
 
-    pub fn is_rsa(&self) -> bool {
-        match self {
-            Response::RSA { pollards} => true,
-            _ => false,
+    fn deserialize_batch_index_at(tag: &[u8], idx: usize) -> Option<u32> {
+        if tag[idx] == 0 {
+            return None;
         }
+        let mut batch_index = 0;
+        Response::deserialize_4_bytes(tag, idx + 1, &mut batch_index);
+        Some(batch_index)
     }
 
-    pub fn is_connection_ok(&self) -> bool {
+    /// Returns the id of the request this response answers.
+    pub fn request_id(&self) -> u32 {
         match self {
-            Response::ConnectionOk => true,
-            _ => false,
+            Response::ConnectionOk { request_id, .. }
+            | Response::NotPrime { request_id, .. }
+            | Response::Prime { request_id, .. }
+            | Response::LogItem { request_id, .. }
+            | Response::SuccessfulLog { request_id, .. }
+            | Response::UnsuccessfulLog { request_id, .. }
+            | Response::RSAItem { request_id, .. }
+            | Response::SuccessfulRSA { request_id, .. }
+            | Response::UnsuccessfulRSA { request_id, .. }
+            | Response::Timeout { request_id, .. }
+            | Response::InputTooLarge { request_id, .. }
+            | Response::ServerBusy { request_id }
+            | Response::RateLimited { request_id, .. }
+            | Response::Stats { request_id, .. }
+            | Response::Cancelled { request_id, .. }
+            | Response::PrimitiveRoot { request_id, .. }
+            | Response::PrimeRound { request_id, .. }
+            | Response::Capabilities { request_id, .. }
+            | Response::RSAKey { request_id, .. }
+            | Response::InvalidExponent { request_id, .. }
+            | Response::Pong { request_id, .. }
+            | Response::InvalidParameters { request_id, .. }
+            | Response::AuthFailed { request_id }
+            | Response::Collision { request_id, .. }
+            | Response::Error { request_id, .. }
+            | Response::PeerList { request_id, .. }
+            | Response::Kicked { request_id, .. }
+            | Response::LogDoesNotExist { request_id, .. } => *request_id,
         }
     }
 
-    pub async fn from_reader<R: AsyncReadExt + Unpin>(mut reader: R) -> Result<Self, std::io::Error> {
-        let mut tag = [0u8; 57];
-        reader.read_exact(&mut tag).await?;
-        Ok(Self::deserialize(&tag))
+    /// Returns `true` for the `ConnectionOk` greeting sent by the server.
+    pub fn is_connection_ok(&self) -> bool {
+        matches!(self, Response::ConnectionOk { .. })
+    }
+
+    /// Returns `true` if this response reports a number as prime.
+    pub fn is_prime(&self) -> bool {
+        matches!(self, Response::Prime { .. })
+    }
+
+    /// Returns `true` if this response reports a number as composite.
+    pub fn is_not_prime(&self) -> bool {
+        matches!(self, Response::NotPrime { .. })
+    }
+
+    /// Returns `true` if this response carries a solved discrete logarithm.
+    pub fn is_successful_log(&self) -> bool {
+        matches!(self, Response::SuccessfulLog { .. })
+    }
+
+    /// Returns `true` if this response reports a discrete logarithm that could not be found.
+    pub fn is_unsuccessful_log(&self) -> bool {
+        matches!(self, Response::UnsuccessfulLog { .. })
+    }
+
+    /// Returns `true` if this response carries the factors of an RSA modulus.
+    pub fn is_successful_rsa(&self) -> bool {
+        matches!(self, Response::SuccessfulRSA { .. })
+    }
+
+    /// Returns `true` if this response reports an RSA modulus that could not be factored.
+    pub fn is_unsuccessful_rsa(&self) -> bool {
+        matches!(self, Response::UnsuccessfulRSA { .. })
+    }
+
+    /// Returns `true` for the collision marking where a discrete logarithm's walk ended.
+    pub fn is_collision(&self) -> bool {
+        matches!(self, Response::Collision { .. })
+    }
+
+    /// Returns `true` for the intermediate steps streamed while a computation runs, rather than its result.
+    pub fn is_step(&self) -> bool {
+        matches!(self, Response::LogItem { .. } | Response::RSAItem { .. } | Response::PrimeRound { .. })
+    }
+
+    /// Reads a `Response` from `reader`, framed with the default codec.
+    ///
+    /// A stream that ends before a whole frame has been read fails with `io::ErrorKind::UnexpectedEof`.
+    pub async fn from_reader<R: AsyncReadExt + Unpin>(reader: R) -> Result<Self, std::io::Error> {
+        Self::from_reader_with(FramedCodec::default(), reader).await
+    }
+
+    /// Reads a `Response` from `reader` that was framed using `codec`.
+    pub async fn from_reader_with<R: AsyncReadExt + Unpin>(codec: FramedCodec, mut reader: R) -> Result<Self, std::io::Error> {
+        let payload = read_framed(&mut reader).await?;
+        let tag = codec.verify(&payload)?.to_vec();
+        Ok(Self::deserialize(&tag)?)
+    }
+
+    /// Writes this `Response` to `writer`, framed with the default codec, and flushes it.
+    pub async fn to_writer<W: AsyncWriteExt + Unpin>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        writer.write_all(&self.as_bytes()).await?;
+        writer.flush().await
     }
 }
 
@@ -126,61 +666,190 @@ impl BytesSer for Response {
     type SerTag = ResponseSerTag;
 
     fn serialize(&self) -> Self::SerTag {
-        let mut tag = [0u8; 57];
+        let mut tag = vec![0u8; RESPONSE_TAG_LEN];
+        Response::serialize_4_bytes(&mut tag, 1, self.request_id());
         match self {
-            Response::ConnectionOk => tag[0] ^= 1,
-            Response::NotPrime {p} => {
+            Response::ConnectionOk { version, session, challenge, .. } => {
+                tag[0] ^= 1;
+                tag[5] ^= *version;
+                Response::serialize_8_bytes(&mut tag, 6, *session as u64);
+                Response::serialize_8_bytes(&mut tag, 14, (*session >> 64) as u64);
+                tag[22..22 + AUTH_LEN].copy_from_slice(challenge);
+            }
+            Response::NotPrime { p, witness, .. } => {
                 tag[0] ^= 2;
-                Response::serialize_8_bytes(&mut tag, 1, *p);
+                Response::serialize_8_bytes(&mut tag, 5, *p);
+                Response::serialize_8_bytes(&mut tag, 13, *witness);
             }
-            Response::Prime {p, prob} => {
+            Response::Prime { p, prob, rounds, .. } => {
                 tag[0] ^= 3;
-                Response::serialize_8_bytes(&mut tag, 1, *p);
-                Response::serialize_4_bytes(&mut tag, 9, (*prob).to_bits())
+                Response::serialize_8_bytes(&mut tag, 5, *p);
+                Response::serialize_4_bytes(&mut tag, 13, (*prob).to_bits());
+                Response::serialize_4_bytes(&mut tag, 17, *rounds);
             }
-            Response::LogItem { item} => {
+            Response::LogItem { item, .. } => {
                 tag[0] ^= 4;
-                Response::serialize_8_bytes(&mut tag, 1, item.i as u64);
-                Response::serialize_8_bytes(&mut tag, 9, item.xi);
-                Response::serialize_8_bytes(&mut tag, 17, item.ai);
-                Response::serialize_8_bytes(&mut tag, 25, item.bi);
-                Response::serialize_8_bytes(&mut tag, 33, item.yi);
-                Response::serialize_8_bytes(&mut tag, 41, item.gi);
-                Response::serialize_8_bytes(&mut tag, 49, item.di);
-            }
-            Response::SuccessfulLog { log, g, h, p, ratio} => {
+                Response::serialize_8_bytes(&mut tag, 5, item.i as u64);
+                Response::serialize_8_bytes(&mut tag, 13, item.xi);
+                Response::serialize_8_bytes(&mut tag, 21, item.ai);
+                Response::serialize_8_bytes(&mut tag, 29, item.bi);
+                Response::serialize_8_bytes(&mut tag, 37, item.yi);
+                Response::serialize_8_bytes(&mut tag, 45, item.gi);
+                Response::serialize_8_bytes(&mut tag, 53, item.di);
+            }
+            Response::SuccessfulLog { log, g, h, p, ratio, iterations, performance, batch_index, .. } => {
                 tag[0] ^= 5;
-                Response::serialize_8_bytes(&mut tag, 1, *log);
-                Response::serialize_8_bytes(&mut tag, 9, *g);
-                Response::serialize_8_bytes(&mut tag, 17, *h);
-                Response::serialize_8_bytes(&mut tag, 25, *p);
-                Response::serialize_8_bytes(&mut tag, 33, ratio.to_bits());
+                Response::serialize_8_bytes(&mut tag, 5, *log);
+                Response::serialize_8_bytes(&mut tag, 13, *g);
+                Response::serialize_8_bytes(&mut tag, 21, *h);
+                Response::serialize_8_bytes(&mut tag, 29, *p);
+                Response::serialize_8_bytes(&mut tag, 37, ratio.to_bits());
+                Response::serialize_batch_index(&mut tag, *batch_index);
+                Response::serialize_8_bytes(&mut tag, 50, *iterations);
+                tag[58] ^= performance.as_byte();
             }
-            Response::UnsuccessfulLog { g, h, p} => {
+            Response::UnsuccessfulLog { g, h, p, batch_index, .. } => {
                 tag[0] ^= 6;
-                Response::serialize_8_bytes(&mut tag, 1, *g);
-                Response::serialize_8_bytes(&mut tag, 9, *h);
-                Response::serialize_8_bytes(&mut tag, 17, *p);
+                Response::serialize_8_bytes(&mut tag, 5, *g);
+                Response::serialize_8_bytes(&mut tag, 13, *h);
+                Response::serialize_8_bytes(&mut tag, 21, *p);
+                Response::serialize_batch_index(&mut tag, *batch_index);
             }
-            Response::RSAItem { item} => {
+            Response::RSAItem { item, .. } => {
                 tag[0] ^= 7;
-                Response::serialize_8_bytes(&mut tag, 1, item.i as u64);
-                Response::serialize_8_bytes(&mut tag, 9, item.xi);
-                Response::serialize_8_bytes(&mut tag, 17, item.yi);
-                Response::serialize_8_bytes(&mut tag, 25, item.g);
-                Response::serialize_8_bytes(&mut tag, 33, item.n);
+                Response::serialize_8_bytes(&mut tag, 5, item.i as u64);
+                Response::serialize_8_bytes(&mut tag, 13, item.xi);
+                Response::serialize_8_bytes(&mut tag, 21, item.yi);
+                Response::serialize_8_bytes(&mut tag, 29, item.g);
+                Response::serialize_8_bytes(&mut tag, 37, item.n);
+                Response::serialize_8_bytes(&mut tag, 45, item.c);
             }
-            Response::SuccessfulRSA { p, q, ratio } => {
+            Response::SuccessfulRSA { p, q, ratio, iterations, performance, .. } => {
                 tag[0] ^= 8;
-                Response::serialize_8_bytes(&mut tag, 1, *p);
-                Response::serialize_8_bytes(&mut tag, 9, *q);
-                Response::serialize_8_bytes(&mut tag, 17, ratio.to_bits());
+                Response::serialize_8_bytes(&mut tag, 5, *p);
+                Response::serialize_8_bytes(&mut tag, 13, *q);
+                Response::serialize_8_bytes(&mut tag, 21, ratio.to_bits());
+                Response::serialize_8_bytes(&mut tag, 29, *iterations);
+                tag[37] ^= performance.as_byte();
             }
-            Response::UnsuccessfulRSA {n} => {
+            Response::UnsuccessfulRSA { n, .. } => {
                 tag[0] ^= 9;
-                Response::serialize_8_bytes(&mut tag, 1, *n);
+                Response::serialize_8_bytes(&mut tag, 5, *n);
+            }
+            Response::Timeout { millis, batch_index, .. } => {
+                tag[0] ^= 10;
+                Response::serialize_8_bytes(&mut tag, 5, *millis);
+                Response::serialize_batch_index(&mut tag, *batch_index);
+            }
+            Response::InputTooLarge { value, batch_index, .. } => {
+                tag[0] ^= 11;
+                Response::serialize_8_bytes(&mut tag, 5, *value);
+                Response::serialize_batch_index(&mut tag, *batch_index);
+            }
+            Response::ServerBusy { .. } => tag[0] ^= 12,
+            Response::RateLimited { batch_index, .. } => {
+                tag[0] ^= 13;
+                Response::serialize_batch_index(&mut tag, *batch_index);
+            }
+            Response::Stats { clients, logs, rsas, primes, iterations, timings, .. } => {
+                tag[0] ^= 14;
+                Response::serialize_8_bytes(&mut tag, 5, *clients);
+                Response::serialize_8_bytes(&mut tag, 13, *logs);
+                Response::serialize_8_bytes(&mut tag, 21, *rsas);
+                Response::serialize_8_bytes(&mut tag, 29, *primes);
+                Response::serialize_8_bytes(&mut tag, 37, *iterations);
+                // A flag for whether timings follow, then 24 bytes per type of computation
+                if let Some(timings) = timings {
+                    tag[45] ^= 1;
+                    tag.resize(STATS_TIMINGS_END, 0);
+                    for (i, timing) in [timings.logs, timings.rsas, timings.primes].into_iter().enumerate() {
+                        Response::serialize_8_bytes(&mut tag, 46 + 24 * i, timing.min);
+                        Response::serialize_8_bytes(&mut tag, 54 + 24 * i, timing.median);
+                        Response::serialize_8_bytes(&mut tag, 62 + 24 * i, timing.max);
+                    }
+                }
+            }
+            Response::Cancelled { batch_index, .. } => {
+                tag[0] ^= 15;
+                Response::serialize_batch_index(&mut tag, *batch_index);
+            }
+            Response::PrimitiveRoot { p, g, .. } => {
+                tag[0] ^= 16;
+                Response::serialize_8_bytes(&mut tag, 5, *p);
+                Response::serialize_8_bytes(&mut tag, 13, *g);
+            }
+            Response::PrimeRound { round, base, passed, .. } => {
+                tag[0] ^= 17;
+                Response::serialize_4_bytes(&mut tag, 5, *round);
+                Response::serialize_8_bytes(&mut tag, 9, *base);
+                tag[17] ^= *passed as u8;
+            }
+            Response::Capabilities { flags, .. } => {
+                tag[0] ^= 18;
+                Response::serialize_4_bytes(&mut tag, 5, *flags);
+            }
+            Response::RSAKey { p, q, d, .. } => {
+                tag[0] ^= 19;
+                Response::serialize_8_bytes(&mut tag, 5, *p);
+                Response::serialize_8_bytes(&mut tag, 13, *q);
+                Response::serialize_8_bytes(&mut tag, 21, *d);
+            }
+            Response::InvalidExponent { n, e, .. } => {
+                tag[0] ^= 20;
+                Response::serialize_8_bytes(&mut tag, 5, *n);
+                Response::serialize_8_bytes(&mut tag, 13, *e);
+            }
+            Response::Pong { nonce, .. } => {
+                tag[0] ^= 21;
+                Response::serialize_8_bytes(&mut tag, 5, *nonce);
+            }
+            Response::InvalidParameters { reason, batch_index, .. } => {
+                tag[0] ^= 22;
+                tag[5] ^= reason.as_byte();
+                Response::serialize_batch_index(&mut tag, *batch_index);
+            }
+            Response::AuthFailed { .. } => tag[0] ^= 23,
+            Response::Collision { i, value, .. } => {
+                tag[0] ^= 24;
+                Response::serialize_8_bytes(&mut tag, 5, *i);
+                Response::serialize_8_bytes(&mut tag, 13, *value);
+            }
+            Response::Error { code, message, batch_index, .. } => {
+                tag[0] ^= 25;
+                tag[5..7].copy_from_slice(&code.to_le_bytes());
+                // The message is prefixed with its length, so trailing zero bytes trimmed from the payload are restored
+                let mut len = message.len().min(MAX_ERROR_MESSAGE_LEN);
+                while !message.is_char_boundary(len) {
+                    len -= 1;
+                }
+                tag[7..9].copy_from_slice(&(len as u16).to_le_bytes());
+                // The batch index comes before the message, whose length it does not depend on
+                Response::serialize_batch_index_at(&mut tag, 9, *batch_index);
+                tag.truncate(ERROR_MESSAGE_START);
+                tag.extend_from_slice(&message.as_bytes()[..len]);
+            }
+            Response::PeerList { ids, .. } => {
+                // The number of ids followed by 16 bytes per id, ids past `MAX_PEER_LIST_IDS` are dropped
+                let ids = &ids[..ids.len().min(MAX_PEER_LIST_IDS as usize)];
+                tag[0] ^= 26;
+                Response::serialize_4_bytes(&mut tag, 5, ids.len() as u32);
+                tag.truncate(9);
+                for id in ids {
+                    tag.extend_from_slice(id.as_bytes());
+                }
+            }
+            Response::LogDoesNotExist { g, h, p, order, batch_index, .. } => {
+                tag[0] ^= 28;
+                Response::serialize_8_bytes(&mut tag, 5, *g);
+                Response::serialize_8_bytes(&mut tag, 13, *h);
+                Response::serialize_8_bytes(&mut tag, 21, *p);
+                Response::serialize_8_bytes(&mut tag, 29, *order);
+                Response::serialize_batch_index(&mut tag, *batch_index);
+            }
+            Response::Kicked { peer_id, .. } => {
+                tag[0] ^= 27;
+                tag[5..21].copy_from_slice(peer_id.as_bytes());
             }
-            _ => panic!("`Response` variant cannot be serialized.")
         }
         tag
     }
@@ -188,20 +857,42 @@ impl BytesSer for Response {
 
 impl BytesDeser for Response {
     type DeserTag = Response;
-    fn deserialize(tag: &Self::SerTag) -> Response {
-        match tag[0] {
-            1 => Response::ConnectionOk,
+    fn deserialize(tag: &Self::SerTag) -> Result<Response, ProtocolError> {
+        // Trimmed payloads are padded back out to the fixed part of the tag
+        let mut padded;
+        let tag = if tag.len() < RESPONSE_TAG_LEN {
+            padded = tag.clone();
+            padded.resize(RESPONSE_TAG_LEN, 0);
+            &padded
+        } else {
+            tag
+        };
+        // Bytes 1-4 hold the request id for every variant
+        let mut request_id = 0;
+        Response::deserialize_4_bytes(tag, 1, &mut request_id);
+        let response = match tag[0] {
+            1 => {
+                let (mut low, mut high) = (0, 0);
+                Response::deserialize_8_bytes(tag, 6, &mut low);
+                Response::deserialize_8_bytes(tag, 14, &mut high);
+                let mut challenge = [0; AUTH_LEN];
+                challenge.copy_from_slice(&tag[22..22 + AUTH_LEN]);
+                Response::ConnectionOk { request_id, version: tag[5], session: (high as u128) << 64 | low as u128, challenge }
+            }
             2 => {
-                let mut p = 0;
-                Response::deserialize_8_bytes(tag, 1, &mut p);
-                Response::NotPrime { p }
+                let (mut p, mut witness) = (0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut p);
+                Response::deserialize_8_bytes(tag, 13, &mut witness);
+                Response::NotPrime { request_id, p, witness }
             }
             3 => {
                 let mut p = 0;
                 let mut prob = 0;
-                Response::deserialize_8_bytes(tag, 1, &mut p);
-                Response::deserialize_4_bytes(tag, 9, &mut prob);
-                Response::Prime { p, prob: f32::from_bits(prob) }
+                Response::deserialize_8_bytes(tag, 5, &mut p);
+                Response::deserialize_4_bytes(tag, 13, &mut prob);
+                let mut rounds = 0;
+                Response::deserialize_4_bytes(tag, 17, &mut rounds);
+                Response::Prime { request_id, p, prob: f32::from_bits(prob), rounds }
             }
             4 => {
                 let mut i = 0;
@@ -211,14 +902,14 @@ impl BytesDeser for Response {
                 let mut yi = 0;
                 let mut gi = 0;
                 let mut di = 0;
-                Response::deserialize_8_bytes(tag, 1, &mut i);
-                Response::deserialize_8_bytes(tag, 9, &mut xi);
-                Response::deserialize_8_bytes(tag, 17, &mut ai);
-                Response::deserialize_8_bytes(tag, 25, &mut bi);
-                Response::deserialize_8_bytes(tag, 33, &mut yi);
-                Response::deserialize_8_bytes(tag, 41, &mut gi);
-                Response::deserialize_8_bytes(tag, 49, &mut di);
-                Response::LogItem { item: PollardsLogItem {
+                Response::deserialize_8_bytes(tag, 5, &mut i);
+                Response::deserialize_8_bytes(tag, 13, &mut xi);
+                Response::deserialize_8_bytes(tag, 21, &mut ai);
+                Response::deserialize_8_bytes(tag, 29, &mut bi);
+                Response::deserialize_8_bytes(tag, 37, &mut yi);
+                Response::deserialize_8_bytes(tag, 45, &mut gi);
+                Response::deserialize_8_bytes(tag, 53, &mut di);
+                Response::LogItem { request_id, item: PollardsLogItem {
                     i: i as usize,
                     xi,
                     ai,
@@ -234,93 +925,346 @@ impl BytesDeser for Response {
                 let mut h = 0;
                 let mut p = 0;
                 let mut ratio_bits = 0;
-                Response::deserialize_8_bytes(tag, 1, &mut log);
-                Response::deserialize_8_bytes(tag, 9, &mut g);
-                Response::deserialize_8_bytes(tag, 17, &mut h);
-                Response::deserialize_8_bytes(tag, 25, &mut p);
-                Response::deserialize_8_bytes(tag, 33, &mut ratio_bits);
+                Response::deserialize_8_bytes(tag, 5, &mut log);
+                Response::deserialize_8_bytes(tag, 13, &mut g);
+                Response::deserialize_8_bytes(tag, 21, &mut h);
+                Response::deserialize_8_bytes(tag, 29, &mut p);
+                Response::deserialize_8_bytes(tag, 37, &mut ratio_bits);
                 // let ratio = unsafe { std::mem::transmute::<u64, f64>(ratio_bits) };
                 let ratio = f64::from_bits(ratio_bits);
-                Response::SuccessfulLog { log, g, h, p, ratio }
+                let batch_index = Response::deserialize_batch_index(tag);
+                let mut iterations = 0;
+                Response::deserialize_8_bytes(tag, 50, &mut iterations);
+                let performance = Performance::from_byte(tag[58]).ok_or(ProtocolError::UnknownPerformance(tag[58]))?;
+                Response::SuccessfulLog { request_id, log, g, h, p, ratio, iterations, performance, batch_index }
             }
             6 => {
                 let (mut g, mut h, mut p) = (0, 0, 0);
-                Response::deserialize_8_bytes(tag, 1, &mut g);
-                Response::deserialize_8_bytes(tag, 9, &mut h);
-                Response::deserialize_8_bytes(tag, 17, &mut p);
-                Response::UnsuccessfulLog { g, h, p }
+                Response::deserialize_8_bytes(tag, 5, &mut g);
+                Response::deserialize_8_bytes(tag, 13, &mut h);
+                Response::deserialize_8_bytes(tag, 21, &mut p);
+                let batch_index = Response::deserialize_batch_index(tag);
+                Response::UnsuccessfulLog { request_id, g, h, p, batch_index }
             }
             7 => {
-                let (mut i, mut xi, mut yi, mut g, mut n) = (0, 0, 0, 0, 0);
-                Response::deserialize_8_bytes(tag, 1, &mut i);
-                Response::deserialize_8_bytes(tag, 9, &mut xi);
-                Response::deserialize_8_bytes(tag, 17, &mut yi);
-                Response::deserialize_8_bytes(tag, 25, &mut g);
-                Response::deserialize_8_bytes(tag, 33, &mut n);
-                Response::RSAItem { item: PollardsRSAFactItem { i: i as usize, xi, yi, g, n }}
+                let (mut i, mut xi, mut yi, mut g, mut n, mut c) = (0, 0, 0, 0, 0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut i);
+                Response::deserialize_8_bytes(tag, 13, &mut xi);
+                Response::deserialize_8_bytes(tag, 21, &mut yi);
+                Response::deserialize_8_bytes(tag, 29, &mut g);
+                Response::deserialize_8_bytes(tag, 37, &mut n);
+                Response::deserialize_8_bytes(tag, 45, &mut c);
+                Response::RSAItem { request_id, item: PollardsRSAFactItem { i: i as usize, xi, yi, g, n, c }}
             }
             8 => {
-                let (mut p, mut q, mut ratio_bits) = (0, 0, 0);
-                Response::deserialize_8_bytes(tag, 1, &mut p);
-                Response::deserialize_8_bytes(tag, 9, &mut q);
-                Response::deserialize_8_bytes(tag, 17, &mut ratio_bits);
+                let (mut p, mut q, mut ratio_bits, mut iterations) = (0, 0, 0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut p);
+                Response::deserialize_8_bytes(tag, 13, &mut q);
+                Response::deserialize_8_bytes(tag, 21, &mut ratio_bits);
+                Response::deserialize_8_bytes(tag, 29, &mut iterations);
                 let ratio = f64::from_bits(ratio_bits);
-                Response::SuccessfulRSA { p, q, ratio }
+                let performance = Performance::from_byte(tag[37]).ok_or(ProtocolError::UnknownPerformance(tag[37]))?;
+                Response::SuccessfulRSA { request_id, p, q, ratio, iterations, performance }
             }
             9 => {
                 let mut n = 0;
-                Response::deserialize_8_bytes(tag, 1, &mut n);
-                Response::UnsuccessfulRSA { n }
+                Response::deserialize_8_bytes(tag, 5, &mut n);
+                Response::UnsuccessfulRSA { request_id, n }
             }
-            _ => panic!("Invalid type byte detected when deserializing `Response`")
-        }
+            10 => {
+                let mut millis = 0;
+                Response::deserialize_8_bytes(tag, 5, &mut millis);
+                let batch_index = Response::deserialize_batch_index(tag);
+                Response::Timeout { request_id, millis, batch_index }
+            }
+            11 => {
+                let mut value = 0;
+                Response::deserialize_8_bytes(tag, 5, &mut value);
+                let batch_index = Response::deserialize_batch_index(tag);
+                Response::InputTooLarge { request_id, value, batch_index }
+            }
+            12 => Response::ServerBusy { request_id },
+            13 => Response::RateLimited { request_id, batch_index: Response::deserialize_batch_index(tag) },
+            14 => {
+                let (mut clients, mut logs, mut rsas, mut primes, mut iterations) = (0, 0, 0, 0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut clients);
+                Response::deserialize_8_bytes(tag, 13, &mut logs);
+                Response::deserialize_8_bytes(tag, 21, &mut rsas);
+                Response::deserialize_8_bytes(tag, 29, &mut primes);
+                Response::deserialize_8_bytes(tag, 37, &mut iterations);
+                let timings = (tag[45] != 0).then(|| {
+                    // Trailing zero bytes of the timings are trimmed on the wire, so missing bytes are read as zero
+                    let mut bytes = tag.to_vec();
+                    bytes.resize(STATS_TIMINGS_END, 0);
+                    let timing = |i: usize| {
+                        let mut timing = Timing::default();
+                        Response::deserialize_8_bytes(&bytes, 46 + 24 * i, &mut timing.min);
+                        Response::deserialize_8_bytes(&bytes, 54 + 24 * i, &mut timing.median);
+                        Response::deserialize_8_bytes(&bytes, 62 + 24 * i, &mut timing.max);
+                        timing
+                    };
+                    Timings { logs: timing(0), rsas: timing(1), primes: timing(2) }
+                });
+                Response::Stats { request_id, clients, logs, rsas, primes, iterations, timings }
+            }
+            15 => Response::Cancelled { request_id, batch_index: Response::deserialize_batch_index(tag) },
+            16 => {
+                let (mut p, mut g) = (0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut p);
+                Response::deserialize_8_bytes(tag, 13, &mut g);
+                Response::PrimitiveRoot { request_id, p, g }
+            }
+            17 => {
+                let (mut round, mut base) = (0, 0);
+                Response::deserialize_4_bytes(tag, 5, &mut round);
+                Response::deserialize_8_bytes(tag, 9, &mut base);
+                Response::PrimeRound { request_id, round, base, passed: tag[17] != 0 }
+            }
+            18 => {
+                let mut flags = 0;
+                Response::deserialize_4_bytes(tag, 5, &mut flags);
+                Response::Capabilities { request_id, flags }
+            }
+            19 => {
+                let (mut p, mut q, mut d) = (0, 0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut p);
+                Response::deserialize_8_bytes(tag, 13, &mut q);
+                Response::deserialize_8_bytes(tag, 21, &mut d);
+                Response::RSAKey { request_id, p, q, d }
+            }
+            20 => {
+                let (mut n, mut e) = (0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut n);
+                Response::deserialize_8_bytes(tag, 13, &mut e);
+                Response::InvalidExponent { request_id, n, e }
+            }
+            21 => {
+                let mut nonce = 0;
+                Response::deserialize_8_bytes(tag, 5, &mut nonce);
+                Response::Pong { request_id, nonce }
+            }
+            22 => {
+                let reason = InvalidReason::from_byte(tag[5]).ok_or(ProtocolError::UnknownInvalidReason(tag[5]))?;
+                let batch_index = Response::deserialize_batch_index(tag);
+                Response::InvalidParameters { request_id, reason, batch_index }
+            }
+            23 => Response::AuthFailed { request_id },
+            24 => {
+                let (mut i, mut value) = (0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut i);
+                Response::deserialize_8_bytes(tag, 13, &mut value);
+                Response::Collision { request_id, i, value }
+            }
+            25 => {
+                let code = u16::from_le_bytes([tag[5], tag[6]]);
+                let len = u16::from_le_bytes([tag[7], tag[8]]) as usize;
+                let batch_index = Response::deserialize_batch_index_at(tag, 9);
+                if len > MAX_ERROR_MESSAGE_LEN {
+                    return Err(ProtocolError::MalformedErrorMessage);
+                }
+                // Trailing zero bytes of the message are trimmed on the wire like any others, so pad it back out
+                let mut bytes = tag[ERROR_MESSAGE_START..tag.len().min(ERROR_MESSAGE_START + len)].to_vec();
+                bytes.resize(len, 0);
+                let message = String::from_utf8(bytes).map_err(|_| ProtocolError::MalformedErrorMessage)?;
+                Response::Error { request_id, code, message, batch_index }
+            }
+            26 => {
+                let mut len = 0;
+                Response::deserialize_4_bytes(tag, 5, &mut len);
+                if len > MAX_PEER_LIST_IDS {
+                    return Err(ProtocolError::PeerListTooLarge(len));
+                }
+                // Trailing zero bytes of the last id are trimmed on the wire, so missing bytes are read as zero
+                let ids = (0..len as usize)
+                    .map(|i| Uuid::from_bytes(core::array::from_fn(|j| tag.get(9 + 16 * i + j).copied().unwrap_or(0))))
+                    .collect();
+                Response::PeerList { request_id, ids }
+            }
+            27 => {
+                let peer_id = Uuid::from_bytes(core::array::from_fn(|i| tag[5 + i]));
+                Response::Kicked { request_id, peer_id }
+            }
+            28 => {
+                let (mut g, mut h, mut p, mut order) = (0, 0, 0, 0);
+                Response::deserialize_8_bytes(tag, 5, &mut g);
+                Response::deserialize_8_bytes(tag, 13, &mut h);
+                Response::deserialize_8_bytes(tag, 21, &mut p);
+                Response::deserialize_8_bytes(tag, 29, &mut order);
+                let batch_index = Response::deserialize_batch_index(tag);
+                Response::LogDoesNotExist { request_id, g, h, p, order, batch_index }
+            }
+            b => return Err(ProtocolError::UnknownResponseTag(b)),
+        };
+        Ok(response)
+    }
+}
+
+impl AsBytes for Response {
+    fn as_bytes(&self) -> Vec<u8> {
+        // Variable length responses are capped when serialized, so every tag fits in a frame
+        FramedCodec::default().encode(&self.serialize()).expect("serialized response fits in a frame")
     }
 }
 
 /// The type of serialization tag for a `Response`.
-pub type ResponseSerTag = [u8; 57];
+///
+/// One byte for the type, 4 bytes for the request id and up to 56 bytes for the transmitted data, except for
+/// `Response::Error` which takes 2 bytes for its code, 2 for the length of its message and 5 for its batch index,
+/// followed by the message,
+/// and `Response::PeerList` which takes 4 bytes for the number of ids and 16 bytes per id. A `Response::Stats`
+/// carrying timings runs to `STATS_TIMINGS_END` bytes.
+pub type ResponseSerTag = Vec<u8>;
 
-impl SerializationTag for ResponseSerTag {}
+/// The length of the fixed part of a `ResponseSerTag`, shorter tags are padded with zero bytes on deserialization.
+const RESPONSE_TAG_LEN: usize = 61;
+
+/// The index of the first byte of the message of a `Response::Error`, after its code, length and batch index.
+const ERROR_MESSAGE_START: usize = 14;
+
+/// The length of the tag of a `Response::Stats` carrying timings, the flag at byte 45 and three `Timing`s after it.
+const STATS_TIMINGS_END: usize = 46 + 3 * 24;
 
 impl DeserializationTag for Response {}
 
 /// Data that is read from a client's socket
+///
+/// Every variant carries a `request_id` chosen by the client, which the server echoes back in each `Response`
+/// it sends for the frame. This lets a client that pipelines requests tell the streamed responses apart.
 #[derive(Debug, PartialEq)]
 pub enum Frame {
-    /// A client request to solve the discrete logarithm
-    Log { g: u64, h: u64, p: u64 },
+    /// A client request to solve the discrete logarithm with `method`
+    Log { request_id: u32, g: u64, h: u64, p: u64, method: LogMethod },
 
     /// A client request to decrypt the RSA private key from the give public key
-    RSA { n: u64, e: u64 },
+    RSA { request_id: u32, n: u64, e: u64 },
 
     /// A client request to check if a number is prime or not
-    Prime { p: u64 },
+    ///
+    /// If `seed` is given the random bases of the test are drawn from a generator seeded with it, so repeating
+    /// the request repeats the same rounds.
+    Prime { request_id: u32, p: u64, seed: Option<u64> },
 
     /// A client request to disconnect from the server
-    Quit,
+    Quit { request_id: u32 },
+
+    /// A client request to solve the discrete logarithm using baby-step giant-step
+    Bsgs { request_id: u32, g: u64, h: u64, p: u64 },
+
+    /// A client request for a snapshot of the server's statistics
+    Stats { request_id: u32 },
+
+    /// A client request to solve several discrete logarithms `(g, h, p)` at once
+    ///
+    /// The result of each query is answered with a `SuccessfulLog` or `UnsuccessfulLog` carrying the index of
+    /// the query in `items` as its `batch_index`.
+    Batch { request_id: u32, items: Vec<(u64, u64, u64)> },
+
+    /// A client request to stop the computations answering the request `request_id`
+    Cancel { request_id: u32 },
+
+    /// A client request for a primitive root modulo the prime `p`
+    PrimitiveRoot { request_id: u32, p: u64 },
+
+    /// A client request for the operations the server supports, answered with `Response::Capabilities`
+    Capabilities { request_id: u32 },
+
+    /// A liveness probe, answered with a `Response::Pong` carrying the same `nonce` without running any computation
+    Ping { request_id: u32, nonce: u64 },
+
+    /// A reconnecting client's request to take over the session it was issued `session` for in `ConnectionOk`,
+    /// only honoured as the first frame sent over a connection
+    Resume { request_id: u32, session: u128 },
+
+    /// A client's answer to the challenge sent in `ConnectionOk`, the HMAC-SHA256 of the challenge under the
+    /// server's shared key
+    Auth { request_id: u32, hmac: [u8; AUTH_LEN] },
+
+    /// An admin's request for the ids of the connected clients, answered with `Response::PeerList`
+    ///
+    /// Only clients that authenticated with the server's shared key are admins, anyone else is answered with a
+    /// `Response::Error` with the code `error_code::FORBIDDEN`.
+    ListPeers { request_id: u32 },
+
+    /// An admin's request to disconnect the client `peer_id`, answered with `Response::Kicked`
+    ///
+    /// Like `Frame::ListPeers` it is only served to admins. A client that is not connected is answered with a
+    /// `Response::Error` with the code `error_code::UNKNOWN_PEER`.
+    Kick { request_id: u32, peer_id: Uuid },
 }
 
 impl Eq for Frame {}
 
 impl Frame {
+    /// Creates a `Frame::Batch`, returning `ProtocolError::BatchTooLarge` if there are more than `MAX_BATCH_ITEMS`
+    /// items, which could not be sent in a single frame.
+    pub fn batch(request_id: u32, items: Vec<(u64, u64, u64)>) -> Result<Frame, ProtocolError> {
+        if items.len() > MAX_BATCH_ITEMS as usize {
+            return Err(ProtocolError::BatchTooLarge(u32::try_from(items.len()).unwrap_or(u32::MAX)));
+        }
+        Ok(Frame::Batch { request_id, items })
+    }
+
     /// Implementation detail of `Frame`, a helper method to aid in serializing into bytes
-    fn serialize_8_bytes(tag: &mut [u8; 25], idx: usize, val: u64) {
+    fn serialize_8_bytes(tag: &mut [u8], idx: usize, val: u64) {
         for i in 0..8 {
             tag[i + idx] ^= ((val >> (8 * i)) & 0xff) as u8;
         }
     }
 
     /// Implementation detail of `Frame`, a helper method to aid in deserializing the tag from bytes
-    fn deserialize_8_bytes(tag: &[u8; 25], idx: usize, val: &mut u64) {
+    ///
+    /// Bytes past the end of `tag` are read as zero, since trailing zero bytes are trimmed on the wire.
+    fn deserialize_8_bytes(tag: &[u8], idx: usize, val: &mut u64) {
         for i in 0..8 {
-            *val ^= (tag[i + idx] as u64) << (i * 8);
+            *val ^= (tag.get(i + idx).copied().unwrap_or(0) as u64) << (i * 8);
+        }
+    }
+
+    /// Implementation detail of `Frame`, reads a little endian `u32` starting at `idx`, treating missing bytes as zero
+    fn deserialize_4_bytes(tag: &[u8], idx: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = tag.get(idx + i).copied().unwrap_or(0);
+        }
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Returns the id the client chose for this request.
+    pub fn request_id(&self) -> u32 {
+        match self {
+            Frame::Log { request_id, .. }
+            | Frame::RSA { request_id, .. }
+            | Frame::Prime { request_id, .. }
+            | Frame::Quit { request_id }
+            | Frame::Bsgs { request_id, .. }
+            | Frame::Stats { request_id }
+            | Frame::Batch { request_id, .. }
+            | Frame::Cancel { request_id }
+            | Frame::PrimitiveRoot { request_id, .. }
+            | Frame::Capabilities { request_id }
+            | Frame::Ping { request_id, .. }
+            | Frame::Resume { request_id, .. }
+            | Frame::Auth { request_id, .. }
+            | Frame::ListPeers { request_id }
+            | Frame::Kick { request_id, .. } => *request_id,
         }
     }
 
     pub async fn from_reader<R: AsyncReadExt + Unpin>(reader: &mut R) -> Result<Self, std::io::Error> {
-        let mut buf = [0u8; 25];
-        reader.read_exact(&mut buf).await?;
-        Ok(Frame::deserialize(&buf))
+        Frame::from_reader_with(FramedCodec::default(), reader).await
+    }
+
+    /// Reads a `Frame` from `reader` that was framed using `codec`.
+    pub async fn from_reader_with<R: AsyncReadExt + Unpin>(codec: FramedCodec, reader: &mut R) -> Result<Self, std::io::Error> {
+        let payload = read_framed(reader).await?;
+        let tag = codec.verify(&payload)?.to_vec();
+        Ok(Frame::deserialize(&tag)?)
+    }
+
+    /// Writes this `Frame` to `writer`, framed with the default codec, and flushes it.
+    pub async fn to_writer<W: AsyncWriteExt + Unpin>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        writer.write_all(&self.as_bytes()).await?;
+        writer.flush().await
     }
 }
 
@@ -328,24 +1272,78 @@ impl BytesSer for Frame {
     type SerTag = FrameSerTag;
 
     fn serialize(&self) -> Self::SerTag {
-        let mut tag = [0; 25];
+        let mut tag = vec![0; 29];
+        tag[1..5].copy_from_slice(&self.request_id().to_le_bytes());
         match self {
-            Frame::Log { g, h, p } => {
+            Frame::Log { g, h, p, method, .. } => {
+                // The method byte follows the 24 bytes of data
+                tag.push(method.as_byte());
                 tag[0] ^= 1;
-                Frame::serialize_8_bytes(&mut tag, 1, *g);
-                Frame::serialize_8_bytes(&mut tag, 9, *h);
-                Frame::serialize_8_bytes(&mut tag, 17, *p);
+                Frame::serialize_8_bytes(&mut tag, 5, *g);
+                Frame::serialize_8_bytes(&mut tag, 13, *h);
+                Frame::serialize_8_bytes(&mut tag, 21, *p);
             },
-            Frame::RSA { n, e} => {
+            Frame::RSA { n, e, .. } => {
                 tag[0] ^= 2;
-                Frame::serialize_8_bytes(&mut tag, 1, *n);
-                Frame::serialize_8_bytes(&mut tag, 9, *e);
+                Frame::serialize_8_bytes(&mut tag, 5, *n);
+                Frame::serialize_8_bytes(&mut tag, 13, *e);
             }
-            Frame::Prime { p } => {
+            Frame::Prime { p, seed, .. } => {
                 tag[0] ^= 3;
-                Frame::serialize_8_bytes(&mut tag, 1, *p);
+                Frame::serialize_8_bytes(&mut tag, 5, *p);
+                // A flag byte marks whether a seed follows
+                if let Some(seed) = seed {
+                    tag[13] ^= 1;
+                    Frame::serialize_8_bytes(&mut tag, 14, *seed);
+                }
+            }
+            Frame::Quit { .. } => tag[0] ^= 4,
+            Frame::Bsgs { g, h, p, .. } => {
+                tag[0] ^= 5;
+                Frame::serialize_8_bytes(&mut tag, 5, *g);
+                Frame::serialize_8_bytes(&mut tag, 13, *h);
+                Frame::serialize_8_bytes(&mut tag, 21, *p);
+            }
+            Frame::Stats { .. } => tag[0] ^= 6,
+            Frame::Batch { items, .. } => {
+                // The number of items followed by 24 bytes per item. A batch over the limit is sent without its
+                // items, so that it still fits in a frame and is rejected by the receiver as too large
+                let len = u32::try_from(items.len()).unwrap_or(u32::MAX);
+                let items = if len > MAX_BATCH_ITEMS { &items[..0] } else { &items[..] };
+                tag.resize(9 + 24 * items.len(), 0);
+                tag[0] ^= 7;
+                tag[5..9].copy_from_slice(&len.to_le_bytes());
+                for (i, (g, h, p)) in items.iter().enumerate() {
+                    Frame::serialize_8_bytes(&mut tag, 9 + 24 * i, *g);
+                    Frame::serialize_8_bytes(&mut tag, 17 + 24 * i, *h);
+                    Frame::serialize_8_bytes(&mut tag, 25 + 24 * i, *p);
+                }
+            }
+            Frame::Cancel { .. } => tag[0] ^= 8,
+            Frame::PrimitiveRoot { p, .. } => {
+                tag[0] ^= 9;
+                Frame::serialize_8_bytes(&mut tag, 5, *p);
+            }
+            Frame::Capabilities { .. } => tag[0] ^= 10,
+            Frame::Ping { nonce, .. } => {
+                tag[0] ^= 11;
+                Frame::serialize_8_bytes(&mut tag, 5, *nonce);
+            }
+            Frame::Resume { session, .. } => {
+                tag[0] ^= 12;
+                Frame::serialize_8_bytes(&mut tag, 5, *session as u64);
+                Frame::serialize_8_bytes(&mut tag, 13, (*session >> 64) as u64);
+            }
+            Frame::Auth { hmac, .. } => {
+                tag.resize(5 + AUTH_LEN, 0);
+                tag[0] ^= 13;
+                tag[5..].copy_from_slice(hmac);
+            }
+            Frame::ListPeers { .. } => tag[0] ^= 14,
+            Frame::Kick { peer_id, .. } => {
+                tag[0] ^= 15;
+                tag[5..21].copy_from_slice(peer_id.as_bytes());
             }
-            Frame::Quit => tag[0] ^= 4,
         }
         tag
     }
@@ -354,47 +1352,234 @@ impl BytesSer for Frame {
 impl BytesDeser for Frame {
     type DeserTag = Frame;
 
-    fn deserialize(tag: &Self::SerTag) -> Self::DeserTag {
-        // Bytes 1-3 may represent different pieces of data depending on the variant of self
-        let type_byte= tag[0];
+    fn deserialize(tag: &Self::SerTag) -> Result<Self::DeserTag, ProtocolError> {
+        // Bytes 1-4 hold the request id, the remaining bytes represent different pieces of data depending on the
+        // variant of self
+        let type_byte= tag.first().copied().unwrap_or(0);
+        let request_id = Frame::deserialize_4_bytes(tag, 1);
         if type_byte ^ 1 == 0 {
             let (mut g, mut h, mut p) = (0u64, 0u64, 0u64);
-            Frame::deserialize_8_bytes(&tag, 1, &mut g);
-            Frame::deserialize_8_bytes(&tag, 9, &mut h);
-            Frame::deserialize_8_bytes(&tag, 17, &mut p);
-            Frame::Log { g, h, p}
+            Frame::deserialize_8_bytes(&tag, 5, &mut g);
+            Frame::deserialize_8_bytes(&tag, 13, &mut h);
+            Frame::deserialize_8_bytes(&tag, 21, &mut p);
+            let b = tag.get(29).copied().unwrap_or(0);
+            let method = LogMethod::from_byte(b).ok_or(ProtocolError::UnknownLogMethod(b))?;
+            Ok(Frame::Log { request_id, g, h, p, method })
         } else if type_byte ^ 2 == 0 {
             let (mut n, mut e) = (0u64, 0u64);
-            Frame::deserialize_8_bytes(&tag, 1, &mut n);
-            Frame::deserialize_8_bytes(&tag, 9, &mut e);
-            Frame::RSA { n, e }
+            Frame::deserialize_8_bytes(&tag, 5, &mut n);
+            Frame::deserialize_8_bytes(&tag, 13, &mut e);
+            Ok(Frame::RSA { request_id, n, e })
         } else if type_byte ^ 3 == 0 {
             let mut p = 0;
-            Frame::deserialize_8_bytes(tag, 1, &mut p);
-            Frame::Prime { p }
+            Frame::deserialize_8_bytes(tag, 5, &mut p);
+            let seed = (tag.get(13).copied().unwrap_or(0) != 0).then(|| {
+                let mut seed = 0;
+                Frame::deserialize_8_bytes(tag, 14, &mut seed);
+                seed
+            });
+            Ok(Frame::Prime { request_id, p, seed })
         } else if type_byte ^ 4 == 0 {
-            Frame::Quit
+            Ok(Frame::Quit { request_id })
+        } else if type_byte ^ 5 == 0 {
+            let (mut g, mut h, mut p) = (0u64, 0u64, 0u64);
+            Frame::deserialize_8_bytes(&tag, 5, &mut g);
+            Frame::deserialize_8_bytes(&tag, 13, &mut h);
+            Frame::deserialize_8_bytes(&tag, 21, &mut p);
+            Ok(Frame::Bsgs { request_id, g, h, p })
+        } else if type_byte ^ 6 == 0 {
+            Ok(Frame::Stats { request_id })
+        } else if type_byte ^ 7 == 0 {
+            let len = Frame::deserialize_4_bytes(tag, 5);
+            if len > MAX_BATCH_ITEMS {
+                return Err(ProtocolError::BatchTooLarge(len));
+            }
+            let items = (0..len as usize)
+                .map(|i| {
+                    let (mut g, mut h, mut p) = (0u64, 0u64, 0u64);
+                    Frame::deserialize_8_bytes(tag, 9 + 24 * i, &mut g);
+                    Frame::deserialize_8_bytes(tag, 17 + 24 * i, &mut h);
+                    Frame::deserialize_8_bytes(tag, 25 + 24 * i, &mut p);
+                    (g, h, p)
+                })
+                .collect();
+            Ok(Frame::Batch { request_id, items })
+        } else if type_byte ^ 8 == 0 {
+            Ok(Frame::Cancel { request_id })
+        } else if type_byte ^ 9 == 0 {
+            let mut p = 0;
+            Frame::deserialize_8_bytes(tag, 5, &mut p);
+            Ok(Frame::PrimitiveRoot { request_id, p })
+        } else if type_byte ^ 10 == 0 {
+            Ok(Frame::Capabilities { request_id })
+        } else if type_byte ^ 11 == 0 {
+            let mut nonce = 0;
+            Frame::deserialize_8_bytes(tag, 5, &mut nonce);
+            Ok(Frame::Ping { request_id, nonce })
+        } else if type_byte ^ 12 == 0 {
+            let (mut low, mut high) = (0, 0);
+            Frame::deserialize_8_bytes(tag, 5, &mut low);
+            Frame::deserialize_8_bytes(tag, 13, &mut high);
+            Ok(Frame::Resume { request_id, session: (high as u128) << 64 | low as u128 })
+        } else if type_byte ^ 13 == 0 {
+            let mut hmac = [0; AUTH_LEN];
+            for (i, b) in hmac.iter_mut().enumerate() {
+                *b = tag.get(5 + i).copied().unwrap_or(0);
+            }
+            Ok(Frame::Auth { request_id, hmac })
+        } else if type_byte ^ 14 == 0 {
+            Ok(Frame::ListPeers { request_id })
+        } else if type_byte ^ 15 == 0 {
+            let peer_id = Uuid::from_bytes(core::array::from_fn(|i| tag.get(5 + i).copied().unwrap_or(0)));
+            Ok(Frame::Kick { request_id, peer_id })
         } else {
-            panic!("invalid type byte detected when deserializing `Frame`.");
+            Err(ProtocolError::UnknownFrameTag(type_byte))
         }
     }
 }
 
 impl AsBytes for Frame {
     fn as_bytes(&self) -> Vec<u8> {
-        self.serialize().to_vec()
+        // Batches over `MAX_BATCH_ITEMS` are sent without their items, so every tag fits in a frame
+        FramedCodec::default().encode(&self.serialize()).expect("serialized frame fits in a frame")
     }
 }
 
 /// The serialization tag for `Frame`
 ///
-/// One byte for the type and up to 24 bytes for the transmitted data.
-pub type FrameSerTag = [u8; 25];
+/// One byte for the type, 4 bytes for the request id and up to 24 bytes for the transmitted data, except for
+/// `Frame::Batch` which takes 4 bytes for the number of items and 24 bytes per item, `Frame::Log` which takes a
+/// 25th byte for its method and `Frame::Auth` which takes 32 bytes for its HMAC.
+pub type FrameSerTag = Vec<u8>;
+
+/// The largest number of queries a single `Frame::Batch` may contain, keeping it within one framed payload.
+pub const MAX_BATCH_ITEMS: u32 = 256;
 
 impl SerializationTag for FrameSerTag {}
 
 impl DeserializationTag for Frame {}
 
+/// Length prefixed framing for messages sent over the wire.
+///
+/// Each message is written as a little endian `u16` length followed by that many payload bytes. The payload
+/// is the fixed size serialization tag with its trailing zero bytes trimmed, so small messages stay small and
+/// new variants are free to use larger tags without changing the transport.
+///
+/// When `checksummed` is set the payload is followed by a little endian CRC32 of the payload bytes, which is
+/// verified on decode. `FramedCodec::default()` enables it when the crate is built with the `checksummed` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramedCodec {
+    pub checksummed: bool,
+}
+
+impl Default for FramedCodec {
+    fn default() -> Self {
+        FramedCodec { checksummed: cfg!(feature = "checksummed") }
+    }
+}
+
+impl FramedCodec {
+    /// Encodes a serialization tag as a length prefixed payload.
+    ///
+    /// Returns an `io::Error` of kind `InvalidData` if the payload is too long for its length to fit the `u16` prefix.
+    pub fn encode(&self, tag: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let len = tag.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        let checksum_len = if self.checksummed { 4 } else { 0 };
+        let prefix = u16::try_from(len + checksum_len).map_err(|_| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("payload of {} bytes is too large to frame", len + checksum_len)
+        ))?;
+        let mut bytes = Vec::with_capacity(2 + len + checksum_len);
+        bytes.extend_from_slice(&prefix.to_le_bytes());
+        bytes.extend_from_slice(&tag[..len]);
+        if self.checksummed {
+            bytes.extend_from_slice(&crc32(&tag[..len]).to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes a payload returned by `read_framed` into `tag`, padding it with zero bytes.
+    ///
+    /// Returns an `io::Error` of kind `InvalidData` if the payload does not fit into `tag`, or if the codec is
+    /// checksummed and the checksum is missing or does not match.
+    pub fn decode(&self, payload: &[u8], tag: &mut [u8]) -> Result<(), io::Error> {
+        let payload = self.verify(payload)?;
+        if payload.len() > tag.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("payload of {} bytes is larger than the {} byte tag", payload.len(), tag.len())
+            ));
+        }
+        tag[..payload.len()].copy_from_slice(payload);
+        tag[payload.len()..].fill(0);
+        Ok(())
+    }
+
+    /// Strips the checksum from a payload returned by `read_framed`, verifying it if the codec is checksummed.
+    ///
+    /// Returns an `io::Error` of kind `InvalidData` if the checksum is missing or does not match.
+    pub fn verify<'a>(&self, payload: &'a [u8]) -> Result<&'a [u8], io::Error> {
+        if self.checksummed {
+            if payload.len() < 4 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "payload is too short to contain a checksum"));
+            }
+            let (payload, checksum) = payload.split_at(payload.len() - 4);
+            let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+            let actual = crc32(payload);
+            if actual != expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("checksum mismatch, expected {expected:#010x} but computed {actual:#010x}")
+                ));
+            }
+            Ok(payload)
+        } else {
+            Ok(payload)
+        }
+    }
+}
+
+/// Computes the CRC32 (IEEE 802.3) checksum of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Reads a single length prefixed payload written by `FramedCodec::encode` from `reader`.
+///
+/// A stream that ends part way through fails with `io::ErrorKind::UnexpectedEof`, reporting how many bytes of
+/// the frame arrived, e.g. `got 10 of 25 bytes before EOF`.
+pub async fn read_framed<R: AsyncReadExt + Unpin>(reader: &mut R) -> Result<Vec<u8>, io::Error> {
+    let mut len = [0u8; 2];
+    read_counted(reader, &mut len, 0).await?;
+    let mut payload = vec![0u8; u16::from_le_bytes(len) as usize];
+    read_counted(reader, &mut payload, len.len()).await?;
+    Ok(payload)
+}
+
+/// Fills `buf` from `reader`, where `received` bytes of the frame were read before it.
+async fn read_counted<R: AsyncReadExt + Unpin>(reader: &mut R, buf: &mut [u8], received: usize) -> Result<(), io::Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("got {} of {} bytes before EOF", received + filled, received + buf.len())
+            ));
+        }
+        filled += n;
+    }
+    Ok(())
+}
+
 /// An interface for any type that can be serialized into bytes.
 pub trait BytesSer {
     /// Associated type for the tag `self` will serialize as.
@@ -411,8 +1596,8 @@ pub trait BytesDeser: BytesSer {
     type DeserTag: DeserializationTag;
 
     /// Required method,
-    /// takes a reference to `Self::SerTag` and returns a `Self::DeSerTag`
-    fn deserialize(tag: &Self::SerTag) -> Self::DeserTag;
+    /// takes a reference to `Self::SerTag` and returns a `Self::DeSerTag`, or a `ProtocolError` if the tag is invalid
+    fn deserialize(tag: &Self::SerTag) -> Result<Self::DeserTag, ProtocolError>;
 }
 
 /// Marker trait. Intended to be implemented by any type that is a `SerTag`.
@@ -423,7 +1608,7 @@ pub trait DeserializationTag {}
 
 /// An interface for any type that can be serialized into bytes and deserialized from bytes
 pub trait AsBytes: BytesDeser {
-    /// Required method, takes a `self` shared reference and returns the byte representation
+    /// Required method, takes a `self` shared reference and returns the framed byte representation sent over the wire
     fn as_bytes(&self) -> Vec<u8>;
 }
 
@@ -436,227 +1621,845 @@ mod tests {
         // let frame = Frame::Connect;
         // let tag = frame.serialize();
         // println!("{:?}", tag);
-        // assert_eq!(tag, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        // assert_eq!(tag, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let frame = Frame::Log { g: 3, h: 2, p: 7 };
+        let frame = Frame::Log { request_id: 0, g: 3, h: 2, p: 7, method: LogMethod::PollardsRho };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [1, 3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [1, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let frame = Frame::Log { g: 627, h: 390, p: 941 };
+        let frame = Frame::Log { request_id: 0, g: 627, h: 390, p: 941, method: LogMethod::PollardsRho };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [1, 115, 2, 0, 0, 0, 0, 0, 0, 134, 1, 0, 0, 0, 0, 0, 0, 173, 3, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [1, 0, 0, 0, 0, 115, 2, 0, 0, 0, 0, 0, 0, 134, 1, 0, 0, 0, 0, 0, 0, 173, 3, 0, 0, 0, 0, 0, 0, 0]);
 
-        let frame = Frame::RSA { n: 1794677960, e: 525734818};
+        let frame = Frame::RSA { request_id: 0, n: 1794677960, e: 525734818};
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [2, 200, 156, 248, 106, 0, 0, 0, 0, 162, 19, 86, 31, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [2, 0, 0, 0, 0, 200, 156, 248, 106, 0, 0, 0, 0, 162, 19, 86, 31, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let frame = Frame::RSA { n: 38749709, e: 10988423 };
+        let frame = Frame::RSA { request_id: 0, n: 38749709, e: 10988423 };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [2, 13, 70, 79, 2, 0, 0, 0, 0, 135, 171, 167, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [2, 0, 0, 0, 0, 13, 70, 79, 2, 0, 0, 0, 0, 135, 171, 167, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let frame = Frame::Prime { p: 15239131 };
+        let frame = Frame::Prime { request_id: 0, p: 15239131, seed: None };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [3, 219, 135, 232, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [3, 0, 0, 0, 0, 219, 135, 232, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let frame = Frame::Quit;
+        let frame = Frame::Quit { request_id: 0 };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let frame = Frame::Bsgs { request_id: 0, g: 2, h: 2495, p: 5011 };
+        let tag = frame.serialize();
+        println!("{:?}", tag);
+        assert_eq!(tag, [5, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 191, 9, 0, 0, 0, 0, 0, 0, 147, 19, 0, 0, 0, 0, 0, 0]);
     }
 
     #[test]
     fn deserialize_frame_should_work() {
-        let frame = Frame::Log { g: 3, h: 2, p: 7 };
+        let frame = Frame::Log { request_id: 0, g: 3, h: 2, p: 7, method: LogMethod::PollardsRho };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [1, 3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [1, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_frame = Frame::deserialize(&tag);
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_frame);
         assert_eq!(deserialized_frame, frame);
 
-        let frame = Frame::Log { g: 627, h: 390, p: 941 };
+        let frame = Frame::Log { request_id: 0, g: 627, h: 390, p: 941, method: LogMethod::PollardsRho };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [1, 115, 2, 0, 0, 0, 0, 0, 0, 134, 1, 0, 0, 0, 0, 0, 0, 173, 3, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [1, 0, 0, 0, 0, 115, 2, 0, 0, 0, 0, 0, 0, 134, 1, 0, 0, 0, 0, 0, 0, 173, 3, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_frame = Frame::deserialize(&tag);
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_frame);
         assert_eq!(deserialized_frame, frame);
 
-        let frame = Frame::RSA { n: 1794677960, e: 525734818};
+        let frame = Frame::RSA { request_id: 0, n: 1794677960, e: 525734818};
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [2, 200, 156, 248, 106, 0, 0, 0, 0, 162, 19, 86, 31, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [2, 0, 0, 0, 0, 200, 156, 248, 106, 0, 0, 0, 0, 162, 19, 86, 31, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_frame = Frame::deserialize(&tag);
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_frame);
         assert_eq!(deserialized_frame, frame);
 
-        let frame = Frame::RSA { n: 38749709, e: 10988423 };
+        let frame = Frame::RSA { request_id: 0, n: 38749709, e: 10988423 };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [2, 13, 70, 79, 2, 0, 0, 0, 0, 135, 171, 167, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [2, 0, 0, 0, 0, 13, 70, 79, 2, 0, 0, 0, 0, 135, 171, 167, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_frame = Frame::deserialize(&tag);
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_frame);
         assert_eq!(deserialized_frame, frame);
 
-        let frame = Frame::Prime { p: 15239131 };
+        let frame = Frame::Prime { request_id: 0, p: 15239131, seed: None };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [3, 219, 135, 232, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [3, 0, 0, 0, 0, 219, 135, 232, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_frame = Frame::deserialize(&tag);
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_frame);
         assert_eq!(frame, deserialized_frame);
 
-        let frame = Frame::Quit;
+        let frame = Frame::Quit { request_id: 0 };
         let tag = frame.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_frame = Frame::deserialize(&tag);
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_frame);
         assert_eq!(deserialized_frame, frame);
+
+        let frame = Frame::Bsgs { request_id: 0, g: 2, h: 2495, p: 5011 };
+        let tag = frame.serialize();
+        println!("{:?}", tag);
+        assert_eq!(tag, [5, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 191, 9, 0, 0, 0, 0, 0, 0, 147, 19, 0, 0, 0, 0, 0, 0]);
+
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
+        println!("{:?}", deserialized_frame);
+        assert_eq!(deserialized_frame, frame);
+
+        let frame = Frame::Stats { request_id: 0 };
+        let tag = frame.serialize();
+        println!("{:?}", tag);
+        assert_eq!(tag, [6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
+        println!("{:?}", deserialized_frame);
+        assert_eq!(deserialized_frame, frame);
+
+        let frame = Frame::Batch { request_id: 0, items: vec![(2, 63, 71), (7, 5, 257)] };
+        let tag = frame.serialize();
+        println!("{:?}", tag);
+        assert_eq!(tag, [
+            7, 0, 0, 0, 0, 2, 0, 0, 0,
+            2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0,
+            7, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0,
+        ]);
+
+        let deserialized_frame = Frame::deserialize(&tag).unwrap();
+        println!("{:?}", deserialized_frame);
+        assert_eq!(deserialized_frame, frame);
+    }
+
+    #[test]
+    fn deserialize_invalid_frame_should_error() {
+        let mut tag = vec![0u8; 25];
+        tag[0] = 200;
+        assert_eq!(Frame::deserialize(&tag), Err(ProtocolError::UnknownFrameTag(200)));
+        assert_eq!(Frame::deserialize(&vec![]), Err(ProtocolError::UnknownFrameTag(0)));
+
+        let mut tag = vec![7u8, 0, 0, 0, 0];
+        tag.extend_from_slice(&(MAX_BATCH_ITEMS + 1).to_le_bytes());
+        assert_eq!(Frame::deserialize(&tag), Err(ProtocolError::BatchTooLarge(MAX_BATCH_ITEMS + 1)));
+
+        // Reading the same tag from a stream should fail with `InvalidData` rather than panic
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let bytes = FramedCodec::default().encode(&tag).unwrap();
+        let err = rt.block_on(Frame::from_reader(&mut &bytes[..])).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn batch_size_is_limited_when_serializing() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let items: Vec<_> = (0..MAX_BATCH_ITEMS as u64).map(|i| (u64::MAX - i, i + 1, u64::MAX)).collect();
+
+        // A batch at the limit round trips, through a tag and through a stream
+        let frame = Frame::batch(3, items.clone()).unwrap();
+        assert_eq!(Frame::deserialize(&frame.serialize()).unwrap(), frame);
+        let bytes = frame.as_bytes();
+        assert_eq!(rt.block_on(Frame::from_reader(&mut &bytes[..])).unwrap(), frame);
+
+        // One more item is rejected when constructing the batch
+        let mut over = items;
+        over.push((2, 5, 11));
+        assert_eq!(Frame::batch(3, over.clone()), Err(ProtocolError::BatchTooLarge(MAX_BATCH_ITEMS + 1)));
+
+        // and a batch built directly is sent without its items, for the receiver to reject
+        let frame = Frame::Batch { request_id: 3, items: over };
+        assert_eq!(frame.serialize().len(), 9);
+        assert_eq!(Frame::deserialize(&frame.serialize()), Err(ProtocolError::BatchTooLarge(MAX_BATCH_ITEMS + 1)));
+        let bytes = frame.as_bytes();
+        let err = rt.block_on(Frame::from_reader(&mut &bytes[..])).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
     }
 
     #[test]
     fn serialize_response_should_work() {
-        let response = Response::ConnectionOk;
+        let response = Response::ConnectionOk { request_id: 0, version: 1, session: 0, challenge: [0; AUTH_LEN] };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let response = Response::NotPrime { p: 8 };
+        let response = Response::NotPrime { request_id: 0, p: 8, witness: 3 };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [2, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [2, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
 
-        let response = Response::Prime { p: 31, prob: 0.9942 };
+        let response = Response::Prime { request_id: 0, p: 31, prob: 0.9942, rounds: 20 };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [3, 31, 0, 0, 0, 0, 0, 0, 0, 228, 131, 126, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [3, 0, 0, 0, 0, 31, 0, 0, 0, 0, 0, 0, 0, 228, 131, 126, 63, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let response = Response::LogItem { item: PollardsLogItem { i: 3, xi: 127, yi: 64, ai: 128, bi: 32, gi: 55, di: 89}};
+        let response = Response::LogItem { request_id: 0, item: PollardsLogItem { i: 3, xi: 127, yi: 64, ai: 128, bi: 32, gi: 55, di: 89}};
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [4, 3, 0, 0, 0, 0, 0, 0, 0, 127, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0, 55, 0, 0, 0, 0, 0, 0, 0, 89, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [4, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 127, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0, 55, 0, 0, 0, 0, 0, 0, 0, 89, 0, 0, 0, 0, 0, 0, 0]);
 
-        let response = Response::SuccessfulLog { log: 11, g: 2, h: 63, p: 71, ratio: 0.012839 };
+        let response = Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 63, p: 71, ratio: 0.012839, iterations: 42, performance: Performance::Fast, batch_index: None };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [5, 11, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [5, 0, 0, 0, 0, 11, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 0, 0, 0, 0, 0, 42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let response = Response::UnsuccessfulLog { g: 2, h: 63, p: 71 };
+        let response = Response::UnsuccessfulLog { request_id: 0, g: 2, h: 63, p: 71, batch_index: None };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [6, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,]);
+        assert_eq!(tag, [6, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,]);
 
-        let response = Response::RSAItem { item: PollardsRSAFactItem { i: 1, xi: 2, yi: 3, g: 1, n: 15}};
+        let response = Response::RSAItem { request_id: 0, item: PollardsRSAFactItem { i: 1, xi: 2, yi: 3, g: 1, n: 15, c: 4 }};
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [7, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [7, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let response = Response::SuccessfulRSA { p: 3, q: 5, ratio: 0.012839 };
+        let response = Response::SuccessfulRSA { request_id: 0, p: 3, q: 5, ratio: 0.012839, iterations: 42, performance: Performance::Fast };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [8, 3, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [8, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let response = Response::UnsuccessfulRSA { n: 15 };
+        let response = Response::UnsuccessfulRSA { request_id: 0, n: 15 };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [9, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [9, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
     }
 
     #[test]
     fn deserialize_response_should_work() {
-        let response = Response::ConnectionOk;
+        let response = Response::ConnectionOk { request_id: 0, version: 1, session: 0, challenge: [0; AUTH_LEN] };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::NotPrime { p: 8 };
+        let response = Response::NotPrime { request_id: 0, p: 8, witness: 3 };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [2, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [2, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::Prime { p: 31, prob: 0.9942 };
+        let response = Response::Prime { request_id: 0, p: 31, prob: 0.9942, rounds: 20 };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [3, 31, 0, 0, 0, 0, 0, 0, 0, 228, 131, 126, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [3, 0, 0, 0, 0, 31, 0, 0, 0, 0, 0, 0, 0, 228, 131, 126, 63, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::LogItem { item: PollardsLogItem { i: 3, xi: 127, yi: 64, ai: 128, bi: 32, gi: 55, di: 89}};
+        let response = Response::LogItem { request_id: 0, item: PollardsLogItem { i: 3, xi: 127, yi: 64, ai: 128, bi: 32, gi: 55, di: 89}};
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [4, 3, 0, 0, 0, 0, 0, 0, 0, 127, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0, 55, 0, 0, 0, 0, 0, 0, 0, 89, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [4, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 127, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0, 55, 0, 0, 0, 0, 0, 0, 0, 89, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::SuccessfulLog { log: 11, g: 2, h: 63, p: 71, ratio:  0.012839 };
+        let response = Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 63, p: 71, ratio:  0.012839, iterations: 42, performance: Performance::Fast, batch_index: None };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [5, 11, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [5, 0, 0, 0, 0, 11, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 0, 0, 0, 0, 0, 42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::UnsuccessfulLog { g: 2, h: 63, p: 71 };
+        let response = Response::UnsuccessfulLog { request_id: 0, g: 2, h: 63, p: 71, batch_index: None };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [6, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,]);
+        assert_eq!(tag, [6, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0, 0, 0, 71, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::RSAItem { item: PollardsRSAFactItem { i: 1, xi: 2, yi: 3, g: 1, n: 15}};
+        let response = Response::RSAItem { request_id: 0, item: PollardsRSAFactItem { i: 1, xi: 2, yi: 3, g: 1, n: 15, c: 4 }};
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [7, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [7, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::SuccessfulRSA { p: 3, q: 5, ratio: 0.012839 };
+        let response = Response::SuccessfulRSA { request_id: 0, p: 3, q: 5, ratio: 0.012839, iterations: 42, performance: Performance::Fast };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [8, 3, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [8, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 230, 32, 232, 104, 85, 75, 138, 63, 42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
 
-        let response = Response::UnsuccessfulRSA { n: 15 };
+        let response = Response::UnsuccessfulRSA { request_id: 0, n: 15 };
         let tag = response.serialize();
         println!("{:?}", tag);
-        assert_eq!(tag, [9, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag, [9, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-        let deserialized_response = Response::deserialize(&tag);
+        let deserialized_response = Response::deserialize(&tag).unwrap();
+        println!("{:?}", deserialized_response);
+        assert_eq!(deserialized_response, response);
+
+        let response = Response::Timeout { request_id: 0, millis: 30000, batch_index: None };
+        let tag = response.serialize();
+        println!("{:?}", tag);
+        assert_eq!(tag, [10, 0, 0, 0, 0, 48, 117, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let deserialized_response = Response::deserialize(&tag).unwrap();
         println!("{:?}", deserialized_response);
         assert_eq!(deserialized_response, response);
     }
-}
 
+    #[test]
+    fn batch_index_should_round_trip() {
+        let responses = [
+            Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 63, p: 71, ratio: 0.5, iterations: 42, performance: Performance::Typical, batch_index: Some(0) },
+            Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 63, p: 71, ratio: 0.5, iterations: 42, performance: Performance::Slow, batch_index: Some(u32::MAX) },
+            Response::UnsuccessfulLog { request_id: 0, g: 2, h: 63, p: 71, batch_index: Some(2) },
+            Response::UnsuccessfulLog { request_id: 0, g: 2, h: 63, p: 71, batch_index: None },
+            Response::LogDoesNotExist { request_id: 0, g: 5, h: 2, p: 71, order: 5, batch_index: Some(7) },
+            Response::LogDoesNotExist { request_id: 0, g: 5, h: 2, p: 71, order: 5, batch_index: None },
+            // Every response a query of a batch may be answered with carries its index
+            Response::RateLimited { request_id: 1, batch_index: Some(255) },
+            Response::InputTooLarge { request_id: 1, value: u64::MAX, batch_index: Some(3) },
+            Response::InvalidParameters { request_id: 1, reason: InvalidReason::BaseOutOfRange, batch_index: Some(4) },
+            Response::Timeout { request_id: 1, millis: 20, batch_index: Some(5) },
+            Response::Cancelled { request_id: 1, batch_index: Some(0) },
+            Response::Error { request_id: 1, code: error_code::INTERNAL, message: "failed".to_string(), batch_index: Some(6) },
+            Response::Error { request_id: 1, code: error_code::INTERNAL, message: String::new(), batch_index: Some(u32::MAX) },
+        ];
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        for response in responses {
+            let tag = response.serialize();
+            assert_eq!(Response::deserialize(&tag).unwrap(), response);
+            assert_eq!(rt.block_on(Response::from_reader(&response.as_bytes()[..])).unwrap(), response);
+        }
+    }
+
+    #[test]
+    fn iterations_should_round_trip() {
+        for iterations in [0, 1, 185364, u64::MAX] {
+            let responses = [
+                Response::SuccessfulLog { request_id: 3, log: 11, g: 2, h: 63, p: 71, ratio: 0.5, iterations, performance: Performance::Typical, batch_index: None },
+                Response::SuccessfulLog { request_id: 3, log: 11, g: 2, h: 63, p: 71, ratio: 0.5, iterations, performance: Performance::Typical, batch_index: Some(u32::MAX) },
+                Response::SuccessfulRSA { request_id: 3, p: 3, q: 5, ratio: 0.5, iterations, performance: Performance::Typical },
+            ];
+            for response in responses {
+                let tag = response.serialize();
+                assert_eq!(Response::deserialize(&tag).unwrap(), response);
+            }
+        }
+    }
+
+    #[test]
+    fn performance_should_round_trip() {
+        for performance in [Performance::Fast, Performance::Typical, Performance::Slow] {
+            let responses = [
+                Response::SuccessfulLog { request_id: 3, log: 11, g: 2, h: 63, p: 71, ratio: 0.5, iterations: 42, performance, batch_index: Some(7) },
+                Response::SuccessfulRSA { request_id: 3, p: 3, q: 5, ratio: 0.5, iterations: 42, performance },
+            ];
+            for response in responses {
+                let tag = response.serialize();
+                assert_eq!(Response::deserialize(&tag).unwrap(), response);
+            }
+        }
+
+        let mut tag = Response::SuccessfulRSA { request_id: 3, p: 3, q: 5, ratio: 0.5, iterations: 42, performance: Performance::Fast }.serialize();
+        tag[37] = 3;
+        assert_eq!(Response::deserialize(&tag), Err(ProtocolError::UnknownPerformance(3)));
+    }
+
+    #[test]
+    fn deserialize_invalid_response_should_error() {
+        // A zeroed tag, e.g. from a truncated packet
+        assert_eq!(Response::deserialize(&vec![0u8; 61]), Err(ProtocolError::UnknownResponseTag(0)));
+        assert_eq!(Response::deserialize(&vec![99u8; 61]), Err(ProtocolError::UnknownResponseTag(99)));
+
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let bytes = FramedCodec::default().encode(&[0u8; 61]).unwrap();
+        let err = rt.block_on(Response::from_reader(&bytes[..])).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unknown type byte 0 when deserializing `Response`");
+    }
+
+    #[test]
+    fn framed_codec_should_trim_payload() {
+        let codec = FramedCodec { checksummed: false };
+        let bytes = codec.encode(&Frame::Quit { request_id: 0 }.serialize()).unwrap();
+        assert_eq!(bytes, vec![1, 0, 4]);
+
+        let bytes = codec.encode(&Response::NotPrime { request_id: 0, p: 15, witness: 2 }.serialize()).unwrap();
+        assert_eq!(bytes, vec![14, 0, 2, 0, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+        let mut tag = [0u8; 25];
+        assert_eq!(codec.decode(&[1u8; 26], &mut tag).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn framed_codec_should_reject_oversized_payload() {
+        // The longest payload whose length fits the u16 prefix is framed, one byte more is an error
+        let tag = vec![1u8; u16::MAX as usize];
+        let bytes = FramedCodec { checksummed: false }.encode(&tag).unwrap();
+        assert_eq!(&bytes[..2], &u16::MAX.to_le_bytes());
+        let tag = vec![1u8; u16::MAX as usize + 1];
+        assert_eq!(FramedCodec { checksummed: false }.encode(&tag).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        // The checksum counts towards the length
+        let tag = vec![1u8; u16::MAX as usize - 3];
+        assert_eq!(FramedCodec { checksummed: true }.encode(&tag).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        // A peer list too long to frame is capped instead
+        let ids = (0..2 * MAX_PEER_LIST_IDS as u128).map(Uuid::from_u128).collect::<Vec<_>>();
+        let response = Response::PeerList { request_id: 0, ids: ids.clone() };
+        let Response::PeerList { ids: sent, .. } = Response::deserialize(&response.serialize()).unwrap() else {
+            panic!("expected a `PeerList`");
+        };
+        assert_eq!(sent, ids[..MAX_PEER_LIST_IDS as usize]);
+        assert!(!response.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn framed_frame_round_trip_should_work() {
+        let frames = [
+            Frame::Log { request_id: 0, g: 2, h: 5, p: 11, method: LogMethod::PollardsRho },
+            Frame::Log { request_id: 0, g: 2, h: 5, p: 11, method: LogMethod::Auto },
+            Frame::RSA { request_id: 0, n: 1782886219, e: 65537 },
+            Frame::Prime { request_id: 0, p: 7919, seed: None },
+            Frame::Prime { request_id: 0, p: 7919, seed: Some(0) },
+            Frame::Prime { request_id: 0, p: 7919, seed: Some(u64::MAX) },
+            Frame::Quit { request_id: 0 },
+            Frame::Bsgs { request_id: 0, g: 2, h: 2495, p: 5011 },
+            Frame::Stats { request_id: 0 },
+            Frame::Batch { request_id: 0, items: vec![(2, 63, 71), (3, 0, 0), (0, 0, 0)] },
+            Frame::Batch { request_id: 0, items: vec![] },
+            Frame::Cancel { request_id: 0 },
+            Frame::PrimitiveRoot { request_id: 0, p: 7919 },
+            Frame::Capabilities { request_id: 0 },
+            Frame::Ping { request_id: 0, nonce: u64::MAX },
+            Frame::Resume { request_id: 0, session: u128::MAX },
+            Frame::Resume { request_id: 0, session: 1 << 64 },
+            Frame::Auth { request_id: 0, hmac: [0xff; AUTH_LEN] },
+            Frame::Auth { request_id: 0, hmac: [0; AUTH_LEN] },
+            Frame::ListPeers { request_id: 0 },
+            Frame::Kick { request_id: 0, peer_id: Uuid::from_u128(1) },
+        ];
+        let bytes: Vec<u8> = frames.iter().flat_map(|f| f.as_bytes()).collect();
+
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let mut reader = &bytes[..];
+        for frame in frames {
+            let read = rt.block_on(Frame::from_reader(&mut reader)).unwrap();
+            assert_eq!(read, frame);
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn framed_response_round_trip_should_work() {
+        let responses = [
+            Response::ConnectionOk { request_id: 0, version: 1, session: u128::MAX, challenge: [0xa5; AUTH_LEN] },
+            Response::NotPrime { request_id: 0, p: 15, witness: 2 },
+            Response::Prime { request_id: 0, p: 31, prob: 0.99609375, rounds: 4 },
+            Response::LogItem { request_id: 0, item: PollardsLogItem { i: 3, xi: 127, yi: 64, ai: 128, bi: 32, gi: 55, di: 89 } },
+            Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 63, p: 71, ratio: 0.012839, iterations: 42, performance: Performance::Fast, batch_index: None },
+            Response::UnsuccessfulLog { request_id: 0, g: 2, h: 63, p: 71, batch_index: None },
+            Response::RSAItem { request_id: 0, item: PollardsRSAFactItem { i: 1, xi: 2, yi: 3, g: 1, n: 15, c: 1 } },
+            Response::SuccessfulRSA { request_id: 0, p: 3, q: 5, ratio: 0.012839, iterations: 42, performance: Performance::Fast },
+            Response::UnsuccessfulRSA { request_id: 0, n: 15 },
+            Response::Timeout { request_id: 0, millis: 30000, batch_index: None },
+            Response::InputTooLarge { request_id: 0, value: u64::MAX, batch_index: None },
+            Response::ServerBusy { request_id: 0 },
+            Response::RateLimited { request_id: 0, batch_index: None },
+            Response::Stats { request_id: 0, clients: 3, logs: 10, rsas: 2, primes: 7, iterations: 123456, timings: None },
+            // No prime checks were timed, so the timings end in zero bytes, which are trimmed from the payload
+            Response::Stats {
+                request_id: 0, clients: 3, logs: 10, rsas: 2, primes: 0, iterations: 123456,
+                timings: Some(Timings {
+                    logs: Timing { min: 1, median: 1 << 20, max: u64::MAX },
+                    rsas: Timing { min: 850_000, median: 1_200_000, max: 9_000_000 },
+                    primes: Timing::default(),
+                }),
+            },
+            Response::Stats { request_id: 0, clients: 0, logs: 0, rsas: 0, primes: 0, iterations: 0, timings: Some(Timings::default()) },
+            Response::Cancelled { request_id: 0, batch_index: None },
+            Response::PrimitiveRoot { request_id: 0, p: 7919, g: 7 },
+            Response::PrimeRound { request_id: 0, round: 3, base: 2, passed: false },
+            Response::PrimeRound { request_id: 0, round: u32::MAX, base: u64::MAX, passed: true },
+            Response::Capabilities { request_id: 0, flags: capabilities::ALL },
+            Response::RSAKey { request_id: 0, p: 42209, q: 42239, d: 1605556097 },
+            Response::InvalidExponent { request_id: 0, n: 3233, e: 3 },
+            Response::Pong { request_id: 0, nonce: u64::MAX },
+            Response::InvalidParameters { request_id: 0, reason: InvalidReason::BaseOutOfRange, batch_index: None },
+            Response::InvalidParameters { request_id: 0, reason: InvalidReason::TargetOutOfRange, batch_index: None },
+            Response::AuthFailed { request_id: 0 },
+            Response::Collision { request_id: 0, i: 97, value: u64::MAX },
+            Response::Error { request_id: 0, code: error_code::INTERNAL, message: "the server failed to compute the result".to_string(), batch_index: None },
+            Response::Error { request_id: 0, code: u16::MAX, message: String::new(), batch_index: None },
+            // The last id ends in zero bytes, which are trimmed from the payload
+            Response::PeerList { request_id: 0, ids: vec![Uuid::from_u128(u128::MAX), Uuid::from_u128(1 << 120)] },
+            Response::PeerList { request_id: 0, ids: vec![] },
+            Response::Kicked { request_id: 0, peer_id: Uuid::from_u128(u128::MAX) },
+            Response::LogDoesNotExist { request_id: 0, g: 5, h: 2, p: 71, order: 5, batch_index: None },
+        ];
+        // Every variant can be written, `as_bytes` frames its serialized tag
+        for response in &responses {
+            assert_eq!(response.as_bytes(), FramedCodec::default().encode(&response.serialize()).unwrap());
+        }
+        let bytes: Vec<u8> = responses.iter().flat_map(|r| r.as_bytes()).collect();
+
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let mut reader = &bytes[..];
+        for response in responses {
+            let read = rt.block_on(Response::from_reader(&mut reader)).unwrap();
+            assert_eq!(read, response);
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn response_predicates_should_match_only_their_variants() {
+        let responses = [
+            Response::ConnectionOk { request_id: 0, version: 1, session: 0, challenge: [0; AUTH_LEN] },
+            Response::NotPrime { request_id: 0, p: 15, witness: 2 },
+            Response::Prime { request_id: 0, p: 31, prob: 0.5, rounds: 1 },
+            Response::LogItem { request_id: 0, item: PollardsLogItem { i: 3, xi: 127, yi: 64, ai: 128, bi: 32, gi: 55, di: 89 } },
+            Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 63, p: 71, ratio: 0.5, iterations: 42, performance: Performance::Fast, batch_index: None },
+            Response::UnsuccessfulLog { request_id: 0, g: 2, h: 63, p: 71, batch_index: None },
+            Response::RSAItem { request_id: 0, item: PollardsRSAFactItem { i: 1, xi: 2, yi: 3, g: 1, n: 15, c: 1 } },
+            Response::SuccessfulRSA { request_id: 0, p: 3, q: 5, ratio: 0.5, iterations: 42, performance: Performance::Fast },
+            Response::UnsuccessfulRSA { request_id: 0, n: 15 },
+            Response::Timeout { request_id: 0, millis: 30000, batch_index: None },
+            Response::InputTooLarge { request_id: 0, value: u64::MAX, batch_index: None },
+            Response::ServerBusy { request_id: 0 },
+            Response::RateLimited { request_id: 0, batch_index: None },
+            Response::Stats { request_id: 0, clients: 3, logs: 10, rsas: 2, primes: 7, iterations: 123456, timings: None },
+            Response::Cancelled { request_id: 0, batch_index: None },
+            Response::PrimitiveRoot { request_id: 0, p: 7919, g: 7 },
+            Response::PrimeRound { request_id: 0, round: 3, base: 2, passed: false },
+            Response::Capabilities { request_id: 0, flags: capabilities::ALL },
+            Response::RSAKey { request_id: 0, p: 3, q: 5, d: 3 },
+            Response::InvalidExponent { request_id: 0, n: 15, e: 2 },
+            Response::Pong { request_id: 0, nonce: 7 },
+            Response::InvalidParameters { request_id: 0, reason: InvalidReason::TargetOutOfRange, batch_index: None },
+            Response::AuthFailed { request_id: 0 },
+            Response::Collision { request_id: 0, i: 97, value: 42 },
+        ];
+        // Each predicate paired with the indices into `responses` it should hold for
+        let predicates: [(fn(&Response) -> bool, &[usize]); 9] = [
+            (Response::is_connection_ok, &[0]),
+            (Response::is_not_prime, &[1]),
+            (Response::is_prime, &[2]),
+            (Response::is_successful_log, &[4]),
+            (Response::is_unsuccessful_log, &[5]),
+            (Response::is_successful_rsa, &[7]),
+            (Response::is_unsuccessful_rsa, &[8]),
+            (Response::is_step, &[3, 6, 16]),
+            (Response::is_collision, &[23]),
+        ];
+        for (predicate, expected) in predicates {
+            for (i, response) in responses.iter().enumerate() {
+                assert_eq!(predicate(response), expected.contains(&i), "{response:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn to_writer_should_round_trip_over_duplex() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (mut client, mut server) = tokio::io::duplex(64);
+        rt.block_on(async {
+            let frames = [
+                Frame::Log { request_id: 1, g: 2, h: 5, p: 11, method: LogMethod::PollardsRho },
+                Frame::Batch { request_id: 2, items: vec![(2, 63, 71), (3, 0, 0)] },
+                Frame::Quit { request_id: 3 },
+            ];
+            for frame in frames {
+                frame.to_writer(&mut client).await.unwrap();
+                assert_eq!(Frame::from_reader(&mut server).await.unwrap(), frame);
+            }
+
+            let responses = [
+                Response::ConnectionOk { request_id: 0, version: 1, session: 0, challenge: [0; AUTH_LEN] },
+                Response::SuccessfulRSA { request_id: 4, p: 3, q: 5, ratio: 0.5, iterations: 42, performance: Performance::Typical },
+                Response::Stats { request_id: 5, clients: 3, logs: 10, rsas: 2, primes: 7, iterations: 123456, timings: None },
+            ];
+            for response in responses {
+                response.to_writer(&mut server).await.unwrap();
+                assert_eq!(Response::from_reader(&mut client).await.unwrap(), response);
+            }
+        });
+    }
+
+    #[test]
+    fn auth_key_should_sign_challenges() {
+        let key = AuthKey::new("shared secret");
+        let challenge = [0x42; AUTH_LEN];
+        let hmac = key.sign(&challenge);
+        assert!(key.verify(&challenge, &hmac));
+        assert_ne!(hmac, key.sign(&[0x43; AUTH_LEN]));
+        assert!(!key.verify(&[0x43; AUTH_LEN], &hmac));
+        assert!(!AuthKey::new("wrong secret").verify(&challenge, &hmac));
+        assert_eq!(format!("{key:?}"), "AuthKey(..)");
+    }
+
+    #[test]
+    fn log_method_should_round_trip() {
+        for method in [LogMethod::PollardsRho, LogMethod::Bsgs, LogMethod::PohligHellman, LogMethod::Auto] {
+            assert_eq!(LogMethod::from_byte(method.as_byte()), Some(method));
+            let frame = Frame::Log { request_id: 1, g: 2, h: 2495, p: 5011, method };
+            let tag = frame.serialize();
+            assert_eq!(tag[29], method.as_byte());
+            assert_eq!(Frame::deserialize(&tag).unwrap(), frame);
+        }
+
+        // A frame without the method byte asks for Pollard's rho
+        let mut tag = Frame::Log { request_id: 1, g: 2, h: 2495, p: 5011, method: LogMethod::Bsgs }.serialize();
+        tag.truncate(29);
+        assert_eq!(Frame::deserialize(&tag).unwrap(), Frame::Log { request_id: 1, g: 2, h: 2495, p: 5011, method: LogMethod::PollardsRho });
+
+        tag.push(4);
+        assert_eq!(Frame::deserialize(&tag), Err(ProtocolError::UnknownLogMethod(4)));
+    }
+
+    #[test]
+    fn invalid_reason_should_reject_unknown_bytes() {
+        let mut tag = Response::InvalidParameters { request_id: 1, reason: InvalidReason::TargetOutOfRange, batch_index: None }.serialize();
+        assert_eq!(tag[5], 1);
+        tag[5] = 2;
+        assert_eq!(Response::deserialize(&tag), Err(ProtocolError::UnknownInvalidReason(2)));
+    }
+
+    #[test]
+    fn ping_nonce_should_round_trip_over_duplex() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (mut client, mut server) = tokio::io::duplex(64);
+        rt.block_on(async {
+            for nonce in [0, 42, u64::MAX] {
+                Frame::Ping { request_id: 9, nonce }.to_writer(&mut client).await.unwrap();
+                let Frame::Ping { request_id, nonce: received } = Frame::from_reader(&mut server).await.unwrap() else {
+                    panic!("expected a `Ping` frame");
+                };
+                assert_eq!((request_id, received), (9, nonce));
+
+                Response::Pong { request_id, nonce: received }.to_writer(&mut server).await.unwrap();
+                assert_eq!(Response::from_reader(&mut client).await.unwrap(), Response::Pong { request_id: 9, nonce });
+            }
+        });
+    }
+
+    #[test]
+    fn response_from_reader_should_fail_on_truncated_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        rt.block_on(async {
+            let bytes = Response::Prime { request_id: 7, p: 15239131, prob: 0.5, rounds: 1 }.as_bytes();
+            // Cut off inside the length prefix, inside the payload, and with nothing written at all
+            for len in [1, bytes.len() - 1, 0] {
+                let (mut client, mut server) = tokio::io::duplex(128);
+                server.write_all(&bytes[..len]).await.unwrap();
+                drop(server);
+                let err = Response::from_reader(&mut client).await.unwrap_err();
+                assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+                let total = if len < 2 { 2 } else { bytes.len() };
+                assert_eq!(err.to_string(), format!("got {} of {} bytes before EOF", len, total));
+            }
+        });
+    }
+
+    #[test]
+    fn frame_from_reader_should_report_bytes_received() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        rt.block_on(async {
+            let bytes = Frame::RSA { request_id: 3, n: 1782886219, e: 65537 }.as_bytes();
+            assert!(bytes.len() > 10);
+            let (mut client, mut server) = tokio::io::duplex(128);
+            // The frame arrives in pieces before the sender goes away
+            server.write_all(&bytes[..4]).await.unwrap();
+            server.write_all(&bytes[4..10]).await.unwrap();
+            drop(server);
+            let err = Frame::from_reader(&mut client).await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+            assert_eq!(err.to_string(), format!("got 10 of {} bytes before EOF", bytes.len()));
+        });
+    }
+
+    #[test]
+    fn request_id_should_round_trip() {
+        for request_id in [1, 0x1234_5678, u32::MAX] {
+            let frames = [
+                Frame::Log { request_id, g: 2, h: 5, p: 11, method: LogMethod::PollardsRho },
+                Frame::RSA { request_id, n: 1782886219, e: 65537 },
+                Frame::Prime { request_id, p: 7919, seed: Some(42) },
+                Frame::Quit { request_id },
+                Frame::Bsgs { request_id, g: 2, h: 2495, p: 5011 },
+                Frame::Stats { request_id },
+                Frame::Batch { request_id, items: vec![(2, 63, 71)] },
+                Frame::Batch { request_id, items: vec![] },
+                Frame::Cancel { request_id },
+                Frame::PrimitiveRoot { request_id, p: 7919 },
+                Frame::Capabilities { request_id },
+                Frame::Ping { request_id, nonce: 0x0123_4567_89ab_cdef },
+                Frame::Resume { request_id, session: 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210 },
+                Frame::Auth { request_id, hmac: core::array::from_fn(|i| i as u8) },
+                Frame::ListPeers { request_id },
+                Frame::Kick { request_id, peer_id: Uuid::from_u128(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210) },
+            ];
+            for frame in frames {
+                let deserialized_frame = Frame::deserialize(&frame.serialize()).unwrap();
+                assert_eq!(deserialized_frame.request_id(), request_id);
+                assert_eq!(deserialized_frame, frame);
+            }
+
+            let responses = [
+                Response::ConnectionOk { request_id, version: 1, session: 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210, challenge: [7; AUTH_LEN] },
+                Response::NotPrime { request_id, p: 15, witness: 2 },
+                Response::Prime { request_id, p: 31, prob: 0.99609375, rounds: 4 },
+                Response::LogItem { request_id, item: PollardsLogItem { i: 3, xi: 127, yi: 64, ai: 128, bi: 32, gi: 55, di: 89 } },
+                Response::SuccessfulLog { request_id, log: 11, g: 2, h: 63, p: 71, ratio: 0.012839, iterations: 42, performance: Performance::Fast, batch_index: Some(1) },
+                Response::UnsuccessfulLog { request_id, g: 2, h: 63, p: 71, batch_index: None },
+                Response::RSAItem { request_id, item: PollardsRSAFactItem { i: 1, xi: 2, yi: 3, g: 1, n: 15, c: 1 } },
+                Response::SuccessfulRSA { request_id, p: 3, q: 5, ratio: 0.012839, iterations: 42, performance: Performance::Fast },
+                Response::UnsuccessfulRSA { request_id, n: 15 },
+                Response::Timeout { request_id, millis: 30000, batch_index: None },
+                Response::InputTooLarge { request_id, value: u64::MAX, batch_index: None },
+                Response::ServerBusy { request_id },
+                Response::RateLimited { request_id, batch_index: None },
+                Response::Stats { request_id, clients: 3, logs: 10, rsas: 2, primes: 7, iterations: 123456, timings: None },
+                Response::Cancelled { request_id, batch_index: None },
+                Response::PrimitiveRoot { request_id, p: 7919, g: 7 },
+                Response::PrimeRound { request_id, round: 3, base: 2, passed: true },
+                Response::Capabilities { request_id, flags: capabilities::ALL & !capabilities::RSA },
+                Response::RSAKey { request_id, p: 3, q: 5, d: 3 },
+                Response::InvalidExponent { request_id, n: 15, e: 2 },
+                Response::Pong { request_id, nonce: 0x0123_4567_89ab_cdef },
+                Response::InvalidParameters { request_id, reason: InvalidReason::BaseOutOfRange, batch_index: None },
+                Response::AuthFailed { request_id },
+                Response::Collision { request_id, i: 1, value: 2 },
+                Response::Error { request_id, code: error_code::INVALID_MODULUS, message: "modulus 1 is below 2".to_string(), batch_index: None },
+                Response::PeerList { request_id, ids: vec![Uuid::from_u128(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210)] },
+                Response::Kicked { request_id, peer_id: Uuid::from_u128(1 << 64) },
+                Response::LogDoesNotExist { request_id, g: 5, h: 2, p: 71, order: 5, batch_index: Some(1) },
+            ];
+            for response in responses {
+                let deserialized_response = Response::deserialize(&response.serialize()).unwrap();
+                assert_eq!(deserialized_response.request_id(), request_id);
+                assert_eq!(deserialized_response, response);
+            }
+        }
+    }
+
+    #[test]
+    fn peer_list_should_carry_every_id() {
+        let ids = vec![Uuid::from_u128(0x0102), Uuid::from_u128(0x0304 << 112)];
+        let tag = Response::PeerList { request_id: 7, ids: ids.clone() }.serialize();
+        assert_eq!(tag[..9], [26, 7, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(tag.len(), 9 + 2 * 16);
+        assert_eq!(tag[9..25], *ids[0].as_bytes());
+        assert_eq!(tag[25..41], *ids[1].as_bytes());
+
+        // A count beyond the maximum is rejected rather than read
+        let mut tag = Response::PeerList { request_id: 7, ids: vec![] }.serialize();
+        tag[5..9].copy_from_slice(&(MAX_PEER_LIST_IDS + 1).to_le_bytes());
+        assert_eq!(Response::deserialize(&tag), Err(ProtocolError::PeerListTooLarge(MAX_PEER_LIST_IDS + 1)));
+    }
+
+    #[test]
+    fn error_response_should_carry_its_message() {
+        // Trailing zero bytes of the message survive the trimming of the payload, since its length is sent first
+        let response = Response::Error { request_id: 7, code: error_code::INTERNAL, message: "überlauf\0\0".to_string(), batch_index: None };
+        let tag = response.serialize();
+        assert_eq!(tag[..14], [25, 7, 0, 0, 0, 2, 0, 11, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tag.len(), 25);
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        assert_eq!(rt.block_on(Response::from_reader(&response.as_bytes()[..])).unwrap(), response);
+
+        // Long messages are cut at a character boundary
+        let message = format!("a{}", "é".repeat(MAX_ERROR_MESSAGE_LEN));
+        let response = Response::Error { request_id: 0, code: error_code::INTERNAL, message: message.clone(), batch_index: None };
+        let Response::Error { message: truncated, .. } = Response::deserialize(&response.serialize()).unwrap() else {
+            panic!("expected `Error`");
+        };
+        assert_eq!(truncated.len(), MAX_ERROR_MESSAGE_LEN - 1);
+        assert!(message.starts_with(&truncated));
+
+        // A message ending in zero bytes past the fixed part of the tag is padded back out after trimming
+        let message = format!("{}\0\0", "a".repeat(60));
+        let response = Response::Error { request_id: 3, code: error_code::INTERNAL, message, batch_index: Some(1) };
+        assert_eq!(rt.block_on(Response::from_reader(&response.as_bytes()[..])).unwrap(), response);
+        let message = "\0".repeat(60);
+        let response = Response::Error { request_id: 3, code: error_code::INTERNAL, message, batch_index: None };
+        assert_eq!(rt.block_on(Response::from_reader(&response.as_bytes()[..])).unwrap(), response);
+
+        // A message longer than the limit, or that is not UTF-8, is rejected
+        let mut tag = Response::Error { request_id: 0, code: 1, message: "a".repeat(60), batch_index: None }.serialize();
+        tag[7..9].copy_from_slice(&(MAX_ERROR_MESSAGE_LEN as u16 + 1).to_le_bytes());
+        assert_eq!(Response::deserialize(&tag), Err(ProtocolError::MalformedErrorMessage));
+        tag[7..9].copy_from_slice(&61u16.to_le_bytes());
+        tag.push(0xff);
+        assert_eq!(Response::deserialize(&tag), Err(ProtocolError::MalformedErrorMessage));
+    }
+
+    #[test]
+    fn crc32_should_work() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn checksummed_round_trip_should_work() {
+        let codec = FramedCodec { checksummed: true };
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        let frame = Frame::Log { request_id: 0, g: 2, h: 5, p: 11, method: LogMethod::PollardsRho };
+        let bytes = codec.encode(&frame.serialize()).unwrap();
+        assert_eq!(bytes.len(), 2 + 22 + 4);
+        let read = rt.block_on(Frame::from_reader_with(codec, &mut &bytes[..])).unwrap();
+        assert_eq!(read, frame);
+
+        let response = Response::SuccessfulLog { request_id: 0, log: 11, g: 2, h: 63, p: 71, ratio: 0.012839, iterations: 42, performance: Performance::Fast, batch_index: None };
+        let bytes = codec.encode(&response.serialize()).unwrap();
+        let read = rt.block_on(Response::from_reader_with(codec, &bytes[..])).unwrap();
+        assert_eq!(read, response);
+    }
+
+    #[test]
+    fn checksummed_corruption_should_be_detected() {
+        let codec = FramedCodec { checksummed: true };
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        // Flip a single bit of `h`, which would otherwise deserialize into a valid looking frame
+        let mut bytes = codec.encode(&Frame::Log { request_id: 0, g: 2, h: 5, p: 11, method: LogMethod::PollardsRho }.serialize()).unwrap();
+        bytes[2 + 13] ^= 1;
+        let err = rt.block_on(Frame::from_reader_with(codec, &mut &bytes[..])).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut bytes = codec.encode(&Response::UnsuccessfulRSA { request_id: 0, n: 15 }.serialize()).unwrap();
+        bytes[3] ^= 0x80;
+        let err = rt.block_on(Response::from_reader_with(codec, &bytes[..])).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // A payload too short to hold the checksum
+        let mut tag = [0u8; 25];
+        assert_eq!(codec.decode(&[4], &mut tag).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}